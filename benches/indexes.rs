@@ -1,18 +1,24 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
-use data_gen::{flip_bits, generate_uniform_data_with_block_size, rand_pos};
+use data_gen::datasets::{Dataset, BLOCKS_1M_BS10, BLOCKS_1M_BS1000, BLOCKS_1M_BS100000};
 use hloo::{index::Index, init_lookup};
 
 init_lookup!(LookupUtil, 256, 5, 1, 64);
 
-#[allow(unused)]
-fn generate_bad_data(n: usize, block_size: usize) -> Vec<(Bits, usize)> {
-    generate_uniform_data_with_block_size(n, block_size, false, Bits::new).collect()
+fn generate_data(dataset: &Dataset) -> Vec<(Bits, usize)> {
+    dataset
+        .generate_verified()
+        .into_iter()
+        .map(|(k, v)| (Bits::new(k), v))
+        .collect()
 }
 
-fn generate_targets(data: &[(Bits, usize)], n: usize, change_bits: usize) -> impl Iterator<Item = Bits> {
-    let src = (0..n)
-        .map(|_| Bits::new(flip_bits(data[rand_pos(data)].0.data, change_bits)))
+fn generate_targets(dataset: &Dataset, data: &[(Bits, usize)], change_bits: usize) -> impl Iterator<Item = Bits> {
+    let raw: Vec<_> = data.iter().map(|(k, v)| (k.data, *v)).collect();
+    let src = dataset
+        .targets(&raw, data.len(), change_bits, 256)
+        .into_iter()
+        .map(Bits::new)
         .collect::<Vec<_>>();
     src.into_iter().cycle()
 }
@@ -20,30 +26,29 @@ fn generate_targets(data: &[(Bits, usize)], n: usize, change_bits: usize) -> imp
 fn index_get_candidates_bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("get_candidates 1M");
 
-    let data_size = 1_000_000;
-    let block_sizes = [10, 1000, 100000];
+    let datasets = [BLOCKS_1M_BS10, BLOCKS_1M_BS1000, BLOCKS_1M_BS100000];
 
-    for block_size in block_sizes {
-        let data = generate_bad_data(data_size, block_size);
-        let mut target_iter = generate_targets(&data, data_size, 3);
+    for dataset in datasets {
+        let data = generate_data(&dataset);
+        let mut target_iter = generate_targets(&dataset, &data, 3);
         let mut index = MemIndex::new(Permutations::get_variant(0));
         index.insert(&data).unwrap();
         index.refresh();
         println!("{:?}", index.stats());
-        group.bench_function(format!("in-memory, perm_0, avg block_size = {}", block_size), |b| {
+        group.bench_function(format!("in-memory, perm_0, {}", dataset.name), |b| {
             b.iter(|| index.get_candidates(&target_iter.next().unwrap()))
         });
     }
 
-    for block_size in block_sizes {
-        let data = generate_bad_data(data_size, block_size);
-        let mut target_iter = generate_targets(&data, data_size, 3);
+    for dataset in datasets {
+        let data = generate_data(&dataset);
+        let mut target_iter = generate_targets(&dataset, &data, 3);
         let tempdir = tempfile::tempdir().unwrap();
         let mut index = MemMapIndex::new(Permutations::get_variant(0), 0, tempdir.path().join("test-index")).unwrap();
         index.insert(&data).unwrap();
         index.refresh();
         println!("{:?}", index.stats());
-        group.bench_function(format!("mem-mapped, perm_0, avg block_size = {}", block_size), |b| {
+        group.bench_function(format!("mem-mapped, perm_0, {}", dataset.name), |b| {
             b.iter(|| index.get_candidates(&target_iter.next().unwrap()))
         });
     }
@@ -1,18 +1,23 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
+use data_gen::datasets::SplitMix64;
 use hloo::index::BlockLocator;
 
+const SEED: u64 = 0x686c_6f6f_0000_1001;
+
 fn generate_data(n: usize, n_blocks: usize) -> Vec<(usize, usize)> {
+    let mut rng = SplitMix64::new(SEED);
     let mut data = Vec::with_capacity(n);
     for i in 0..n {
-        data.push((data_gen::rand_between(0, n_blocks as u64) as usize, i));
+        data.push(((rng.next_u64() % n_blocks as u64) as usize, i));
     }
     data.sort_by_key(|(k, _)| *k);
     data
 }
 
 fn generate_targets(data: &[(usize, usize)], n: usize) -> impl Iterator<Item = usize> + '_ {
-    let inputs: Vec<usize> = (0..n).map(|_| data_gen::rand_pos(data)).collect();
+    let mut rng = SplitMix64::new(!SEED);
+    let inputs: Vec<usize> = (0..n).map(|_| (rng.next_u64() % data.len() as u64) as usize).collect();
     inputs.into_iter().map(|i| data[i].0).cycle()
 }
 
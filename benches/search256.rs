@@ -2,34 +2,27 @@ use std::time::Duration;
 
 use criterion::{criterion_group, criterion_main, Criterion};
 
-use data_gen::{flip_bits, generate_uniform_data, generate_uniform_data_with_block_size, rand_pos};
+use data_gen::datasets::{Dataset, SKEWED_1M_BS10, UNIFORM_1M};
 use hloo::{
     index::naive_search,
-    lookup::lookup_impl::lookup256::{Bits, MemLookup, MemMapLookup},
+    lookup::lookup_impl::lookup256::{Bits, MemLookup, MemMapLookup, SplitMemLookup},
     Lookup,
 };
 
-fn generate_perfect_data(n: usize, _: usize) -> Vec<(Bits, usize)> {
-    generate_uniform_data(n).map(|(k, v)| (Bits::new(k), v)).collect()
-}
-
-fn generate_bad_data(n: usize, block_size: usize) -> Vec<(Bits, usize)> {
-    generate_uniform_data_with_block_size(n, block_size, true, Bits::new).collect()
-}
-
-fn generate_target(data: &[(Bits, usize)], change_bits: usize) -> Bits {
-    let pos = rand_pos(data);
-    Bits::new(flip_bits(data[pos].0.data, change_bits))
+fn generate_data(dataset: &Dataset) -> (Vec<(Bits, usize)>, Bits) {
+    let data = dataset.generate_verified();
+    let target = dataset.targets(&data, 1, 3, 256)[0];
+    (
+        data.into_iter().map(|(k, v)| (Bits::new(k), v)).collect(),
+        Bits::new(target),
+    )
 }
 
 fn search256_bench(c: &mut Criterion) {
     println!("preparing data...");
-    let all_data = [
-        ("perfect_data", generate_perfect_data(1_000_000, 10)),
-        ("bad_data", generate_bad_data(1_000_000, 10)),
-    ];
-    for (name, data) in all_data {
-        let target = generate_target(&data, 3);
+    let all_data = [("perfect_data", UNIFORM_1M), ("bad_data", SKEWED_1M_BS10)];
+    for (name, dataset) in all_data {
+        let (data, target) = generate_data(&dataset);
         let mut group = c.benchmark_group(format!("search256 1M/{}", name));
 
         group.bench_function("naive", |b| b.iter(|| naive_search(&data, target, 3)));
@@ -39,6 +32,11 @@ fn search256_bench(c: &mut Criterion) {
         lookup1.insert(&data).unwrap();
         group.bench_function("hloo in-memory", |b| b.iter(|| lookup1.search(&target, 3)));
 
+        let mut lookup_split = SplitMemLookup::default();
+        println!("inserting data into in-memory (split layout)...");
+        lookup_split.insert(&data).unwrap();
+        group.bench_function("hloo in-memory split", |b| b.iter(|| lookup_split.search(&target, 3)));
+
         let temp_dir = tempfile::tempdir().unwrap();
         println!("inserting data into mem-mapped...");
         let mut lookup2 = MemMapLookup::create(temp_dir.path()).unwrap();
@@ -2,34 +2,27 @@ use std::time::Duration;
 
 use criterion::{criterion_group, criterion_main, Criterion};
 
-use data_gen::{flip_bits, generate_uniform_data, generate_uniform_data_with_block_size, rand_pos};
+use data_gen::datasets::{Dataset, SKEWED_1M_BS10, UNIFORM_1M};
 use hloo::{
     index::naive_search,
     lookup::lookup_impl::lookup64::{Bits, MemLookup, MemMapLookup},
     Lookup,
 };
 
-fn generate_perfect_data(n: usize, _: usize) -> Vec<(Bits, usize)> {
-    generate_uniform_data(n).map(|(k, v)| (Bits::new([k[0]]), v)).collect()
-}
-
-fn generate_bad_data(n: usize, block_size: usize) -> Vec<(Bits, usize)> {
-    generate_uniform_data_with_block_size(n, block_size, true, |d| Bits::new([d[0]])).collect()
-}
-
-fn generate_target(data: &[(Bits, usize)], change_bits: usize) -> Bits {
-    let pos = rand_pos(data);
-    Bits::new(flip_bits(data[pos].0.data, change_bits))
+fn generate_data(dataset: &Dataset) -> (Vec<(Bits, usize)>, Bits) {
+    let data = dataset.generate_verified();
+    let target = dataset.targets(&data, 1, 3, 64)[0];
+    (
+        data.into_iter().map(|(k, v)| (Bits::new([k[0]]), v)).collect(),
+        Bits::new([target[0]]),
+    )
 }
 
 fn search64_bench(c: &mut Criterion) {
     println!("preparing data...");
-    let all_data = [
-        ("perfect_data", generate_perfect_data(1_000_000, 10)),
-        ("bad_data", generate_bad_data(1_000_000, 10)),
-    ];
-    for (name, data) in all_data {
-        let target = generate_target(&data, 3);
+    let all_data = [("perfect_data", UNIFORM_1M), ("bad_data", SKEWED_1M_BS10)];
+    for (name, dataset) in all_data {
+        let (data, target) = generate_data(&dataset);
         let mut group = c.benchmark_group(format!("search64 1M/{}", name));
 
         group.bench_function("naive", |b| b.iter(|| naive_search(&data, target, 3)));
@@ -0,0 +1,190 @@
+//! Versioned, seeded benchmark datasets.
+//!
+//! Every dataset is fully determined by its parameters and is generated with a self-contained PRNG, so it is the same
+//! on every machine and with every version of dependencies. Each dataset has a published checksum, which can be used
+//! to make sure benchmark numbers are computed over the same data.
+
+/// Version of the dataset generator. Bumped whenever generated data changes.
+pub const DATASETS_VERSION: u32 = 1;
+
+/// Uniformly distributed 1M keys.
+pub const UNIFORM_1M: Dataset = Dataset {
+    name: "uniform_1m",
+    seed: 0x686c_6f6f_0000_0001,
+    n: 1_000_000,
+    block_size: None,
+    really_bad_distribution: false,
+    checksum: 0x5aa8_2f48_d60f_0531,
+};
+
+/// 1M keys, with the first word skewed to create blocks of ~10 items.
+pub const SKEWED_1M_BS10: Dataset = Dataset {
+    name: "skewed_1m_bs10",
+    seed: 0x686c_6f6f_0000_0002,
+    n: 1_000_000,
+    block_size: Some(10),
+    really_bad_distribution: true,
+    checksum: 0xa4b3_4245_5558_ffc5,
+};
+
+/// 1M keys, with the first word skewed to create blocks of ~10 items in the top bits.
+pub const BLOCKS_1M_BS10: Dataset = Dataset {
+    name: "blocks_1m_bs10",
+    seed: 0x686c_6f6f_0000_0003,
+    n: 1_000_000,
+    block_size: Some(10),
+    really_bad_distribution: false,
+    checksum: 0x063e_628f_4854_e149,
+};
+
+/// 1M keys, with the first word skewed to create blocks of ~1000 items in the top bits.
+pub const BLOCKS_1M_BS1000: Dataset = Dataset {
+    name: "blocks_1m_bs1000",
+    seed: 0x686c_6f6f_0000_0004,
+    n: 1_000_000,
+    block_size: Some(1000),
+    really_bad_distribution: false,
+    checksum: 0xf155_7573_6a4a_2fd6,
+};
+
+/// 1M keys, with the first word skewed to create blocks of ~100000 items in the top bits.
+pub const BLOCKS_1M_BS100000: Dataset = Dataset {
+    name: "blocks_1m_bs100000",
+    seed: 0x686c_6f6f_0000_0005,
+    n: 1_000_000,
+    block_size: Some(100_000),
+    really_bad_distribution: false,
+    checksum: 0x64f0_dc7b_5529_f3b3,
+};
+
+/// All published datasets.
+pub const ALL_DATASETS: [Dataset; 5] = [
+    UNIFORM_1M,
+    SKEWED_1M_BS10,
+    BLOCKS_1M_BS10,
+    BLOCKS_1M_BS1000,
+    BLOCKS_1M_BS100000,
+];
+
+/// Description of a reproducible dataset of 256-bit keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dataset {
+    pub name: &'static str,
+    pub seed: u64,
+    pub n: usize,
+    /// If set, the first word of each key is restricted to produce blocks of roughly this size.
+    pub block_size: Option<usize>,
+    /// If set, all words of the key are made equal to the first one.
+    pub really_bad_distribution: bool,
+    /// Published checksum of the generated data.
+    pub checksum: u64,
+}
+
+impl Dataset {
+    /// Generate the data, sorted by key.
+    pub fn generate(&self) -> Vec<([u64; 4], usize)> {
+        let mut rng = SplitMix64::new(self.seed);
+        let mut data: Vec<_> = (0..self.n)
+            .map(|i| {
+                let mut key = [rng.next_u64(), rng.next_u64(), rng.next_u64(), rng.next_u64()];
+                if let Some(block_size) = self.block_size {
+                    let n_blocks = (self.n / block_size).max(1) as u64;
+                    key[0] = (rng.next_u64() % n_blocks) << 32;
+                    if self.really_bad_distribution {
+                        key[1] = key[0];
+                        key[2] = key[0];
+                        key[3] = key[0];
+                    }
+                }
+                (key, i)
+            })
+            .collect();
+        data.sort_by_key(|(k, _)| *k);
+        data
+    }
+
+    /// Generate `n` search targets by picking keys from `data` and flipping `change_bits` random bits among the first
+    /// `key_bits` bits in them.
+    pub fn targets(&self, data: &[([u64; 4], usize)], n: usize, change_bits: usize, key_bits: usize) -> Vec<[u64; 4]> {
+        assert!(0 < key_bits && key_bits <= 256, "key_bits should be in 1..=256");
+        let mut rng = SplitMix64::new(!self.seed);
+        (0..n)
+            .map(|_| {
+                let mut key = data[(rng.next_u64() % data.len() as u64) as usize].0;
+                for _ in 0..change_bits {
+                    let pos = (rng.next_u64() % key_bits as u64) as usize;
+                    key[pos / 64] ^= 1 << (pos % 64);
+                }
+                key
+            })
+            .collect()
+    }
+
+    /// Generate the data and verify that it matches the published checksum.
+    pub fn generate_verified(&self) -> Vec<([u64; 4], usize)> {
+        let data = self.generate();
+        let actual = checksum(&data);
+        assert_eq!(
+            actual, self.checksum,
+            "dataset {} v{} does not match its published checksum: expected {:016x}, got {:016x}",
+            self.name, DATASETS_VERSION, self.checksum, actual
+        );
+        data
+    }
+}
+
+/// Compute a FNV-1a checksum of the data.
+pub fn checksum(data: &[([u64; 4], usize)]) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for (key, value) in data {
+        for word in key.iter().copied().chain([*value as u64]) {
+            for byte in word.to_le_bytes() {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(PRIME);
+            }
+        }
+    }
+    hash
+}
+
+/// SplitMix64 generator. Used instead of `rand` generators, as those are not guaranteed to be stable across versions.
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datasets_match_published_checksums() {
+        for dataset in ALL_DATASETS {
+            let data = dataset.generate_verified();
+            assert_eq!(data.len(), dataset.n, "{}: wrong length", dataset.name);
+        }
+    }
+
+    #[test]
+    fn targets_are_reproducible() {
+        let data = BLOCKS_1M_BS10.generate();
+        let targets = BLOCKS_1M_BS10.targets(&data, 10, 3, 256);
+        assert_eq!(
+            targets,
+            BLOCKS_1M_BS10.targets(&data, 10, 3, 256),
+            "targets should not change between runs"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+pub mod datasets;
+
 use itertools::Itertools;
 
 pub use rand::random;
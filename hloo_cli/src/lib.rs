@@ -0,0 +1,390 @@
+//! Command-line tool to build, inspect and repair memory-mapped lookups (see `hloo::lookup::lookup_impl`) holding
+//! `i64` values, like the ones served by `hloo_server`.
+//!
+//! Keys are hex strings of 16 (64-bit keys) or 64 (256-bit keys) digits. Lookups are built from either:
+//!
+//! - CSV files of `key,value` lines, skipping empty lines and lines starting with `#`;
+//! - raw dumps of little-endian records (see `MemMapLookup::import_raw`).
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use hloo::{
+    Index, Lookup,
+    index::LookupValidation,
+    lookup::{
+        StatsSnapshot,
+        lookup_impl::{lookup64, lookup256},
+    },
+};
+
+pub const USAGE: &str = "usage:
+  hloo-cli build <dir> <input> [--format csv|raw]
+  hloo-cli query <dir> <key> [--distance <n>]
+  hloo-cli stats <dir>
+  hloo-cli validate <dir>
+  hloo-cli compact <dir>
+options:
+  --bits 64|256  size of keys of the lookup (default: 64)";
+
+/// Format of the input of `build`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Csv,
+    Raw,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Create a lookup in `dir` from the items in `input`.
+    Build {
+        dir: PathBuf,
+        input: PathBuf,
+        format: InputFormat,
+    },
+    /// Search the lookup in `dir` for items within `distance` of `key`.
+    Query { dir: PathBuf, key: String, distance: u32 },
+    /// Print statistics of the lookup in `dir`.
+    Stats { dir: PathBuf },
+    /// Check integrity of the lookup in `dir`.
+    Validate { dir: PathBuf },
+    /// Physically remove deleted items from the lookup in `dir`.
+    Compact { dir: PathBuf },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Args {
+    pub command: Command,
+    pub bits: u32,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let name = args.next().ok_or("missing command")?;
+    let mut positional = Vec::new();
+    let mut bits = 64;
+    let mut format = InputFormat::Csv;
+    let mut distance = 0;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bits" => {
+                let value = args.next().ok_or("--bits requires a value")?;
+                bits = value.parse().map_err(|_| format!("invalid number of bits: {value}"))?;
+            }
+            "--format" if name == "build" => {
+                format = match args.next().ok_or("--format requires a value")?.as_str() {
+                    "csv" => InputFormat::Csv,
+                    "raw" => InputFormat::Raw,
+                    value => return Err(format!("invalid format: {value}")),
+                };
+            }
+            "--distance" if name == "query" => {
+                let value = args.next().ok_or("--distance requires a value")?;
+                distance = value.parse().map_err(|_| format!("invalid distance: {value}"))?;
+            }
+            _ if !arg.starts_with("--") => positional.push(arg),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+    let mut positional = positional.into_iter();
+    let mut next = |what: &str| positional.next().ok_or(format!("missing {what}"));
+    let command = match name.as_str() {
+        "build" => Command::Build {
+            dir: next("directory")?.into(),
+            input: next("input")?.into(),
+            format,
+        },
+        "query" => Command::Query {
+            dir: next("directory")?.into(),
+            key: next("key")?,
+            distance,
+        },
+        "stats" => Command::Stats {
+            dir: next("directory")?.into(),
+        },
+        "validate" => Command::Validate {
+            dir: next("directory")?.into(),
+        },
+        "compact" => Command::Compact {
+            dir: next("directory")?.into(),
+        },
+        _ => return Err(format!("unknown command: {name}")),
+    };
+    if let Some(arg) = positional.next() {
+        return Err(format!("unexpected argument: {arg}"));
+    }
+    Ok(Args { command, bits })
+}
+
+/// Operations of the inspected lookup, with keys as hex strings.
+pub trait CliLookup {
+    /// Insert items from `key,value` lines. Returns the number of inserted items.
+    fn insert_csv(&mut self, input: &mut dyn BufRead) -> Result<usize, String>;
+
+    /// Insert all records of a raw dump. Returns the number of inserted items.
+    fn import_raw(&mut self, path: &Path) -> Result<usize, String>;
+
+    /// Distinct items within `distance` of `key`, as `(value, distance)` ordered by distance and then by value.
+    fn query(&self, key: &str, distance: u32) -> Result<Vec<(i64, u32)>, String>;
+
+    /// Statistics, after recomputing the ones of the indexes.
+    fn stats(&mut self) -> StatsSnapshot;
+
+    fn validate(&self) -> Result<LookupValidation, String>;
+
+    fn compact(&mut self) -> Result<(), String>;
+
+    fn persist(&self) -> Result<(), String>;
+}
+
+macro_rules! impl_cli_lookup {
+    ($lookup:ty, $bits:ty) => {
+        impl CliLookup for $lookup {
+            fn insert_csv(&mut self, input: &mut dyn BufRead) -> Result<usize, String> {
+                let mut items = Vec::new();
+                for (i, line) in input.lines().enumerate() {
+                    let line = line.map_err(|e| e.to_string())?;
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let parse = || {
+                        let (key, value) = line.split_once(',').ok_or("expected `key,value`")?;
+                        let key = key.trim().parse::<$bits>().map_err(|e| e.to_string())?;
+                        let value = value.trim().parse::<i64>().map_err(|e| e.to_string())?;
+                        Ok::<_, String>((key, value))
+                    };
+                    items.push(parse().map_err(|e| format!("line {}: {e}", i + 1))?);
+                }
+                Lookup::insert(self, &items).map_err(|e| e.to_string())?;
+                Ok(items.len())
+            }
+
+            fn import_raw(&mut self, path: &Path) -> Result<usize, String> {
+                <$lookup>::import_raw(self, path).map_err(|e| e.to_string())
+            }
+
+            fn query(&self, key: &str, distance: u32) -> Result<Vec<(i64, u32)>, String> {
+                let key = key.parse::<$bits>().map_err(|e| e.to_string())?;
+                let result = Lookup::search(self, &key, distance).map_err(|e| e.to_string())?;
+                // every index finds the items within the distance
+                let items: BTreeSet<_> = result
+                    .into_flat_iter()
+                    .map(|item| (item.distance(), *item.data()))
+                    .collect();
+                Ok(items
+                    .into_iter()
+                    .map(|(distance, value)| (value, distance))
+                    .collect())
+            }
+
+            fn stats(&mut self) -> StatsSnapshot {
+                for index in self.indexes_mut() {
+                    index.refresh();
+                }
+                self.stats_snapshot()
+            }
+
+            fn validate(&self) -> Result<LookupValidation, String> {
+                Lookup::validate(self).map_err(|e| e.to_string())
+            }
+
+            fn compact(&mut self) -> Result<(), String> {
+                Lookup::compact(self).map_err(|e| e.to_string())
+            }
+
+            fn persist(&self) -> Result<(), String> {
+                Lookup::persist(self).map_err(|e| e.to_string())
+            }
+        }
+    };
+}
+
+impl_cli_lookup!(lookup64::MemMapLookup<i64>, lookup64::Bits);
+impl_cli_lookup!(lookup256::MemMapLookup<i64>, lookup256::Bits);
+
+/// How to open a lookup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    Create,
+    Load,
+    ReadOnly,
+}
+
+/// Open the lookup with keys of `bits` bits in the directory `path`.
+pub fn open_lookup(path: &Path, bits: u32, mode: OpenMode) -> Result<Box<dyn CliLookup>, String> {
+    macro_rules! open {
+        ($lookup:ty) => {{
+            let lookup = match mode {
+                OpenMode::Create => <$lookup>::create(path),
+                OpenMode::Load => <$lookup>::load(path),
+                OpenMode::ReadOnly => <$lookup>::load_read_only(path),
+            };
+            Box::new(lookup.map_err(|e| e.to_string())?)
+        }};
+    }
+
+    let lookup: Box<dyn CliLookup> = match bits {
+        64 => open!(lookup64::MemMapLookup<i64>),
+        256 => open!(lookup256::MemMapLookup<i64>),
+        _ => return Err(format!("bits should be 64 or 256, got {bits}")),
+    };
+    Ok(lookup)
+}
+
+fn write_stats(out: &mut dyn Write, stats: &StatsSnapshot) -> io::Result<()> {
+    writeln!(out, "items: {}", stats.n_items)?;
+    writeln!(out, "max search distance: {}", stats.max_search_distance)?;
+    writeln!(out, "bytes on disk: {}", stats.bytes_on_disk)?;
+    for (i, index) in stats.indexes.iter().enumerate() {
+        writeln!(
+            out,
+            "index {i}: {} items in {} blocks of {}/{}/{} (min/avg/max) items, {} bytes on disk",
+            index.n_items,
+            index.n_blocks,
+            index.min_block_size,
+            index.avg_block_size,
+            index.max_block_size,
+            index.bytes_on_disk
+        )?;
+    }
+    Ok(())
+}
+
+fn write_validation(out: &mut dyn Write, report: &LookupValidation) -> io::Result<()> {
+    for (i, index) in report.indexes.iter().enumerate() {
+        let mut problems = Vec::new();
+        if let Some(pos) = index.first_unsorted {
+            problems.push(format!("item {pos} is not sorted"));
+        }
+        if let Some((expected, actual)) = index.storage_size_mismatch {
+            problems.push(format!("file is {actual} bytes, expected {expected}"));
+        }
+        if let Some((expected, actual)) = index.signature_mismatch {
+            problems.push(format!("signature is {actual:#x}, expected {expected:#x}"));
+        }
+        if problems.is_empty() {
+            problems.push("ok".to_string());
+        }
+        writeln!(out, "index {i}: {} items, {}", index.n_items, problems.join(", "))?;
+    }
+    if !report.item_counts_agree {
+        writeln!(out, "indexes hold different numbers of items")?;
+    }
+    writeln!(out, "{}", if report.is_ok() { "ok" } else { "invalid" })
+}
+
+/// Run the command, writing its output to `out`. Returns whether the lookup is valid, which is only checked by
+/// `validate`.
+pub fn run(args: &Args, out: &mut dyn Write) -> Result<bool, String> {
+    let io_error = |e: io::Error| e.to_string();
+    match &args.command {
+        Command::Build { dir, input, format } => {
+            fs::create_dir_all(dir).map_err(io_error)?;
+            if fs::read_dir(dir).map_err(io_error)?.next().is_some() {
+                return Err(format!("{} is not empty", dir.display()));
+            }
+            let mut lookup = open_lookup(dir, args.bits, OpenMode::Create)?;
+            let inserted = match format {
+                InputFormat::Csv => {
+                    let file = fs::File::open(input).map_err(io_error)?;
+                    lookup.insert_csv(&mut BufReader::new(file))?
+                }
+                InputFormat::Raw => lookup.import_raw(input)?,
+            };
+            lookup.persist()?;
+            writeln!(out, "inserted {inserted} items into {}", dir.display()).map_err(io_error)?;
+        }
+        Command::Query { dir, key, distance } => {
+            let lookup = open_lookup(dir, args.bits, OpenMode::ReadOnly)?;
+            for (value, distance) in lookup.query(key, *distance)? {
+                writeln!(out, "{value}\t{distance}").map_err(io_error)?;
+            }
+        }
+        Command::Stats { dir } => {
+            let mut lookup = open_lookup(dir, args.bits, OpenMode::ReadOnly)?;
+            write_stats(out, &lookup.stats()).map_err(io_error)?;
+        }
+        Command::Validate { dir } => {
+            let lookup = open_lookup(dir, args.bits, OpenMode::ReadOnly)?;
+            let report = lookup.validate()?;
+            write_validation(out, &report).map_err(io_error)?;
+            return Ok(report.is_ok());
+        }
+        Command::Compact { dir } => {
+            let mut lookup = open_lookup(dir, args.bits, OpenMode::Load)?;
+            lookup.compact()?;
+            lookup.persist()?;
+            writeln!(out, "compacted {}", dir.display()).map_err(io_error)?;
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_cli(args: &str) -> Result<String, String> {
+        let args = parse_args(args.split_whitespace().map(str::to_string))?;
+        let mut out = Vec::new();
+        run(&args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn lookups_are_built_queried_and_inspected() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let (dir, csv) = (tmp_dir.path().join("lookup"), tmp_dir.path().join("items.csv"));
+        let items = "# key,value\n0000000000000000,1\n0000000000000003, 2\n\nFFFFFFFFFFFFFFFF,3\n";
+        fs::write(&csv, items).unwrap();
+        let (dir, csv) = (dir.display(), csv.display());
+
+        assert_eq!(
+            run_cli(&format!("build {dir} {csv}")).unwrap(),
+            format!("inserted 3 items into {dir}\n")
+        );
+        assert!(
+            run_cli(&format!("build {dir} {csv}")).is_err(),
+            "directory is not empty"
+        );
+        assert_eq!(
+            run_cli(&format!("query {dir} 0000000000000001 --distance 1")).unwrap(),
+            "1\t1\n2\t1\n"
+        );
+        assert!(run_cli(&format!("query {dir} 0000000000000001 --bits 256")).is_err());
+        let stats = run_cli(&format!("stats {dir}")).unwrap();
+        assert!(stats.starts_with("items: 3\nmax search distance: 3\n"), "{stats}");
+        assert_eq!(stats.lines().filter(|line| line.starts_with("index")).count(), 4);
+        assert_eq!(
+            run_cli(&format!("compact {dir}")).unwrap(),
+            format!("compacted {dir}\n")
+        );
+        assert!(run_cli(&format!("validate {dir}")).unwrap().ends_with("\nok\n"));
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        assert!(
+            parse_args(["build".to_string(), "dir".to_string()]).is_err(),
+            "missing input"
+        );
+        assert!(parse_args(["stats".to_string(), "dir".to_string(), "--distance".to_string()]).is_err());
+        assert!(parse_args(["repair".to_string(), "dir".to_string()]).is_err());
+        assert_eq!(
+            parse_args(["query", "dir", "00", "--distance", "2", "--bits", "256"].map(str::to_string)),
+            Ok(Args {
+                command: Command::Query {
+                    dir: "dir".into(),
+                    key: "00".to_string(),
+                    distance: 2
+                },
+                bits: 256
+            })
+        );
+    }
+}
@@ -0,0 +1,26 @@
+//! Usage: `hloo-cli <build|query|stats|validate|compact> <dir> ... [--bits 64|256]`
+//!
+//! Builds, inspects and repairs the lookup stored in the directory `dir`. See the library documentation for the
+//! formats of the input of `build`.
+
+use std::{io, process::ExitCode};
+
+use hloo_cli::{USAGE, parse_args, run};
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&args, &mut io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
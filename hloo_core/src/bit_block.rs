@@ -1,7 +1,9 @@
+use alloc::vec::Vec;
+
 /// Returns a bit mask of length `len` starting at a given bit `pos`.
-fn compute_mask(pos: usize, len: usize, word_size: usize) -> u64 {
+fn compute_mask(pos: usize, len: usize, word_size: usize) -> u128 {
     assert!(
-        0 < word_size && word_size <= 64,
+        0 < word_size && word_size <= 128,
         "word size {word_size} is not supported"
     );
     assert!(
@@ -12,17 +14,22 @@ fn compute_mask(pos: usize, len: usize, word_size: usize) -> u64 {
         pos + len,
         word_size
     );
-    ((1 << len) - 1) << pos
+    (u128::MAX >> (128 - len)) << pos
+}
+
+/// Returns a mask of all bits of a word.
+pub(crate) fn full_mask(word_size: usize) -> u128 {
+    compute_mask(0, word_size, word_size)
 }
 
 /// Restores pos and len from a mask.
-fn unmask(mask: u64) -> (usize, usize) {
+fn unmask(mask: u128) -> (usize, usize) {
     let pos = mask.trailing_zeros();
     let len = (mask >> pos).trailing_ones();
     (pos as usize, len as usize)
 }
 
-fn combine_masks(m1: u64, m2: u64) -> Option<u64> {
+fn combine_masks(m1: u128, m2: u128) -> Option<u128> {
     let (pos1, len1) = unmask(m1);
     let (pos2, len2) = unmask(m2);
     if pos1 + len1 == pos2 || pos2 + len2 == pos1 {
@@ -133,7 +140,7 @@ impl BitBlock {
     }
 
     /// If a block is a single-word block, return the its corresponding bit mask; otherwise None.
-    pub fn mask(&self, word_size: usize) -> Option<u64> {
+    pub fn mask(&self, word_size: usize) -> Option<u128> {
         self.bit_pos(word_size)
             .map(|bit| compute_mask(bit, self.len(), word_size))
     }
@@ -149,13 +156,13 @@ impl BitBlock {
 pub enum BitOp {
     MaskShiftAndCopy {
         src_word: usize,
-        src_mask: u64,
+        src_mask: u128,
         src_shift: i64,
         dst_word: usize,
     },
     MaskAndCopy {
         src_word: usize,
-        src_mask: u64,
+        src_mask: u128,
         dst_word: usize,
     },
     Copy {
@@ -229,15 +236,15 @@ impl BitOp {
         }
     }
 
-    pub fn mask(&self) -> u64 {
+    pub fn mask(&self) -> u128 {
         match self {
             Self::MaskShiftAndCopy { src_mask, .. } => *src_mask,
             Self::MaskAndCopy { src_mask, .. } => *src_mask,
-            Self::Copy { .. } => u64::MAX,
+            Self::Copy { .. } => u128::MAX,
         }
     }
 
-    fn set_mask(&mut self, mask: u64) -> Self {
+    fn set_mask(&mut self, mask: u128) -> Self {
         match self {
             Self::MaskShiftAndCopy { src_mask, .. } => *src_mask = mask,
             Self::MaskAndCopy { src_mask, .. } => *src_mask = mask,
@@ -246,7 +253,7 @@ impl BitOp {
         *self
     }
 
-    pub fn clone_with_mask(&self, mask: u64) -> Self {
+    pub fn clone_with_mask(&self, mask: u128) -> Self {
         self.clone().set_mask(mask)
     }
 
@@ -259,8 +266,8 @@ impl BitOp {
     }
 }
 
-impl std::fmt::Display for BitOp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for BitOp {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let fmt_width = 32;
         match self {
             Self::MaskShiftAndCopy {
@@ -332,8 +339,8 @@ impl PermutedBitBlock {
     }
 }
 
-impl std::fmt::Display for PermutedBitBlock {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for PermutedBitBlock {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         if self.block.start_pos() == self.new_pos {
             write!(
                 f,
@@ -370,6 +377,12 @@ mod tests {
         assert_eq!(mask, 0b1);
         let mask = compute_mask(63, 1, 64);
         assert_eq!(mask, 0b1000000000000000000000000000000000000000000000000000000000000000);
+        let mask = compute_mask(0, 64, 64);
+        assert_eq!(mask, u64::MAX as u128);
+        let mask = compute_mask(64, 64, 128);
+        assert_eq!(mask, (u64::MAX as u128) << 64);
+        let mask = compute_mask(0, 128, 128);
+        assert_eq!(mask, u128::MAX);
     }
 
     #[test]
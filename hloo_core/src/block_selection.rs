@@ -0,0 +1,127 @@
+use alloc::{format, string::String, vec::Vec};
+
+use itertools::Itertools;
+
+/// Strategy selecting which `k` of `r` blocks are moved to the front by each permutation. Every selected combination
+/// of blocks produces one permutation, and so one index.
+///
+/// Only [`BlockSelection::AllCombinations`] guarantees that every key within distance `r - k` is found; other
+/// strategies trade recall for fewer indexes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum BlockSelection {
+    /// All k-combinations of blocks, in lexicographic order.
+    #[default]
+    AllCombinations,
+    /// `n` k-combinations of blocks chosen at random with a generator seeded by `seed`, in lexicographic order. The
+    /// same seed always selects the same combinations.
+    RandomSubset { n: usize, seed: u64 },
+    /// Explicitly listed combinations, each holding `k` distinct indexes of blocks, in the given order.
+    Custom(Vec<Vec<usize>>),
+}
+
+impl BlockSelection {
+    /// Check that combinations of `k` blocks out of `r` can be selected, i.e. that a random subset is not larger than
+    /// the number of combinations, and that every custom combination holds exactly `k` distinct indexes of blocks less
+    /// than `r`.
+    pub fn validate(&self, r: usize, k: usize) -> Result<(), String> {
+        match self {
+            Self::AllCombinations => Ok(()),
+            Self::RandomSubset { n, .. } => {
+                let total = (0..r).combinations(k).count();
+                if *n <= total {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "can't select {n} out of {total} combinations of blocks (r={r} k={k})"
+                    ))
+                }
+            }
+            Self::Custom(orders) => orders
+                .iter()
+                .find(|order| {
+                    order.len() != k
+                        || order.iter().sorted().tuple_windows().any(|(a, b)| a == b)
+                        || order.iter().any(|&i| i >= r)
+                })
+                .map_or(Ok(()), |order| {
+                    Err(format!(
+                        "combination {order:?} does not hold {k} distinct blocks out of {r}"
+                    ))
+                }),
+        }
+    }
+
+    /// Select combinations of `k` blocks out of `r`.
+    ///
+    /// # Panics
+    /// Panics if the selection is not valid for `r` and `k`, see [`BlockSelection::validate`].
+    pub fn select(&self, r: usize, k: usize) -> Vec<Vec<usize>> {
+        if let Err(e) = self.validate(r, k) {
+            panic!("{e}");
+        }
+        match self {
+            Self::AllCombinations => (0..r).combinations(k).collect(),
+            Self::RandomSubset { n, seed } => {
+                let mut combinations = (0..r).combinations(k).collect::<Vec<_>>();
+                // partial Fisher-Yates shuffle: the first n combinations are a uniform random subset
+                let mut rng = SplitMix64(*seed);
+                for i in 0..*n {
+                    let j = i + (rng.next() % (combinations.len() - i) as u64) as usize;
+                    combinations.swap(i, j);
+                }
+                combinations.truncate(*n);
+                combinations.sort_unstable();
+                combinations
+            }
+            Self::Custom(orders) => orders.clone(),
+        }
+    }
+}
+
+/// SplitMix64 generator. Used instead of `rand`, whose generators are not guaranteed to be stable across versions, as
+/// the selected combinations define the layout of persisted indexes.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_subset_is_deterministic() {
+        let selection = BlockSelection::RandomSubset { n: 10, seed: 42 };
+        let selected = selection.select(8, 2);
+        assert_eq!(selected.len(), 10);
+        assert!(selected.is_sorted() && selected.iter().all_unique());
+        assert!(selected.iter().all(|c| c.len() == 2 && c[0] < c[1] && c[1] < 8));
+        assert_eq!(selection.select(8, 2), selected);
+        assert_ne!(BlockSelection::RandomSubset { n: 10, seed: 43 }.select(8, 2), selected);
+        assert_eq!(
+            BlockSelection::RandomSubset { n: 28, seed: 42 }.select(8, 2),
+            BlockSelection::AllCombinations.select(8, 2)
+        );
+    }
+
+    #[test]
+    fn invalid_selections_are_rejected() {
+        assert!(BlockSelection::RandomSubset { n: 28, seed: 0 }.validate(8, 2).is_ok());
+        assert!(BlockSelection::RandomSubset { n: 29, seed: 0 }.validate(8, 2).is_err());
+        assert!(
+            BlockSelection::Custom(vec![vec![0, 7], vec![1, 2]])
+                .validate(8, 2)
+                .is_ok()
+        );
+        assert!(BlockSelection::Custom(vec![vec![0, 8]]).validate(8, 2).is_err());
+        assert!(BlockSelection::Custom(vec![vec![1, 1]]).validate(8, 2).is_err());
+        assert!(BlockSelection::Custom(vec![vec![0, 1, 2]]).validate(8, 2).is_err());
+    }
+}
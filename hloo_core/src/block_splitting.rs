@@ -0,0 +1,30 @@
+use alloc::{format, string::String};
+
+/// Strategy assigning bits of keys to blocks.
+///
+/// Contiguous blocks are the cheapest to permute, but when some regions of keys carry less information than others
+/// (e.g. in perceptual hashes), indexes keyed by blocks from these regions are much less selective. Strided blocks
+/// spread every block over the whole key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlockSplitting {
+    /// Every block is a single range of bits, the first blocks being one bit longer if bits can't be split evenly.
+    #[default]
+    Contiguous,
+    /// Runs of `stride` bits are assigned to blocks in turn: bits `0..stride` to block 0, `stride..2 * stride` to
+    /// block 1, and so on, going back to block 0 after the last one. `stride = 1` interleaves single bits.
+    Strided { stride: usize },
+}
+
+impl BlockSplitting {
+    /// Check that `f` bits can be split into `r` non-empty blocks.
+    pub fn validate(&self, f: usize, r: usize) -> Result<(), String> {
+        match self {
+            Self::Contiguous if f < r => Err(format!("{f} is not enough bits to split into {r} blocks")),
+            Self::Strided { stride: 0 } => Err(String::from("stride can't be 0")),
+            Self::Strided { stride } if f.div_ceil(*stride) < r => Err(format!(
+                "{f} is not enough bits to split into {r} blocks with stride {stride}"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{cmp::Ordering, fmt};
+
+use crate::{
+    BitContainer, BitOp, BitPermuter, BlockSelection, BlockSplitting, Permutation, Pod, create_permutations_with,
+};
+
+const MAX_WORDS: usize = 4;
+
+/// Word sizes supported by [`DynPermuter`].
+pub const DYN_WORD_SIZES: [usize; 4] = [8, 16, 32, 64];
+
+/// Bit sequence whose length is only known at runtime, up to [`DynBitVec::MAX_BITS`] bits. Keys and masks of
+/// [`DynPermuter`] are stored in it.
+///
+/// Bits are enumerated from the most significant bit of the first byte, like in containers generated by
+/// `make_permutations!`, and bits past the length are always zero, so bit vectors of the same length are ordered the
+/// same way as the corresponding generated containers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(C)]
+pub struct DynBitVec {
+    data: [u64; MAX_WORDS],
+    len: u64,
+}
+
+impl DynBitVec {
+    /// Maximum number of bits a `DynBitVec` can hold.
+    pub const MAX_BITS: usize = MAX_WORDS * 64;
+
+    /// Create a bit vector of `len` zero bits.
+    ///
+    /// # Panics
+    /// Panics if `len` is greater than [`Self::MAX_BITS`].
+    pub fn zeros(len: usize) -> Self {
+        assert!(
+            len <= Self::MAX_BITS,
+            "bit vector can't hold {len} bits (max={})",
+            Self::MAX_BITS
+        );
+        Self {
+            data: [0; MAX_WORDS],
+            len: len as u64,
+        }
+    }
+
+    /// Create a bit vector from big-endian bytes, i.e. the first bit is the most significant bit of the first byte.
+    ///
+    /// # Panics
+    /// Panics if `bytes` hold more than [`Self::MAX_BITS`] bits.
+    pub fn from_be_bytes(bytes: &[u8]) -> Self {
+        let mut val = Self::zeros(bytes.len() * 8);
+        for (i, chunk) in bytes.chunks(8).enumerate() {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            val.data[i] = u64::from_be_bytes(word);
+        }
+        val
+    }
+
+    /// Create a bit vector from bytes of little-endian words of `word_bits` bits, i.e. the layout produced by
+    /// `from_le_bytes` of containers generated by `make_permutations!` with the same word size. If `bytes` do not
+    /// consist of whole words, the remaining bytes are a shorter little-endian word.
+    ///
+    /// # Panics
+    /// Panics if `word_bits` is not supported, or `bytes` hold more than [`Self::MAX_BITS`] bits.
+    pub fn from_le_bytes(bytes: &[u8], word_bits: usize) -> Self {
+        assert!(
+            DYN_WORD_SIZES.contains(&word_bits),
+            "word size {word_bits} is not supported"
+        );
+        let word_bytes = word_bits / 8;
+        let be_bytes: Vec<u8> = bytes
+            .chunks(word_bytes)
+            .flat_map(|word| word.iter().rev().copied())
+            .collect();
+        Self::from_be_bytes(&be_bytes)
+    }
+
+    /// Number of bits in this vector.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Whether this vector holds no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get a single bit value.
+    ///
+    /// # Panics
+    /// Panics if `idx` is out of bounds.
+    pub fn get(&self, idx: usize) -> bool {
+        assert!(idx < self.len(), "bit {idx} is out of bounds (len={})", self.len);
+        self.data[idx / 64] & (1 << (63 - idx % 64)) != 0
+    }
+
+    /// Set a single bit value.
+    ///
+    /// # Panics
+    /// Panics if `idx` is out of bounds.
+    pub fn set(&mut self, idx: usize, value: bool) {
+        assert!(idx < self.len(), "bit {idx} is out of bounds (len={})", self.len);
+        let bit = 1 << (63 - idx % 64);
+        if value {
+            self.data[idx / 64] |= bit;
+        } else {
+            self.data[idx / 64] &= !bit;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    /// Location of `word_bits`-bit word `idx`: index in `data`, and shift of the word within it.
+    fn word_pos(idx: usize, word_bits: usize) -> (usize, usize) {
+        let pos = idx * word_bits;
+        (pos / 64, 64 - word_bits - pos % 64)
+    }
+
+    fn word_mask(word_bits: usize) -> u64 {
+        u64::MAX >> (64 - word_bits)
+    }
+
+    /// Get `word_bits`-bit word `idx`.
+    fn word(&self, idx: usize, word_bits: usize) -> u64 {
+        let (i, shift) = Self::word_pos(idx, word_bits);
+        (self.data[i] >> shift) & Self::word_mask(word_bits)
+    }
+
+    /// Replace `word_bits`-bit word `idx` with `value`.
+    fn set_word(&mut self, idx: usize, word_bits: usize, value: u64) {
+        let (i, shift) = Self::word_pos(idx, word_bits);
+        let mask = Self::word_mask(word_bits) << shift;
+        self.data[i] = (self.data[i] & !mask) | ((value << shift) & mask);
+    }
+
+    /// Create a bit vector of `len` bits stored in `words`.
+    fn from_words(words: &[u64], len: usize) -> Self {
+        let mut val = Self::zeros(len);
+        val.data[..words.len()].copy_from_slice(words);
+        val
+    }
+
+    /// Set bits of `value` in `word_bits`-bit word `idx`.
+    fn or_word(&mut self, idx: usize, word_bits: usize, value: u64) {
+        let (i, shift) = Self::word_pos(idx, word_bits);
+        self.data[i] |= (value & Self::word_mask(word_bits)) << shift;
+    }
+}
+
+impl FromIterator<bool> for DynBitVec {
+    /// Collect bits into a vector, taking at most [`DynBitVec::MAX_BITS`] of them.
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut val = Self::default();
+        for (i, el) in iter.into_iter().enumerate().take(Self::MAX_BITS) {
+            val.len += 1;
+            val.set(i, el);
+        }
+        val
+    }
+}
+
+impl fmt::Display for DynBitVec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in 0..self.len().div_ceil(4) {
+            write!(f, "{:X}", (self.data[i / 16] >> ((15 - i % 16) * 4)) & 0xF)?;
+        }
+        Ok(())
+    }
+}
+
+// SAFETY: the struct is `repr(C)` and consists of plain words, so any bit pattern is a valid value
+unsafe impl Pod for DynBitVec {}
+
+impl BitContainer for DynBitVec {
+    type Data = [u64; MAX_WORDS];
+
+    fn data(&self) -> &Self::Data {
+        &self.data
+    }
+
+    fn data_mut(&mut self) -> &mut Self::Data {
+        &mut self.data
+    }
+
+    fn bit(&self, idx: usize) -> bool {
+        self.get(idx)
+    }
+
+    fn xor_dist(&self, other: &Self) -> u32 {
+        self.data
+            .iter()
+            .zip(&other.data)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
+/// Keys stored in `u64` words, bits being enumerated from the most significant bit of the first word, which
+/// [`DynPermuter`] can permute without converting them to [`DynBitVec`]s first. Masks are still [`DynBitVec`]s.
+///
+/// Implemented by `#[derive(BitContainer)]` for structs wrapping `[u64; N]`.
+pub trait WordKey: BitContainer {
+    /// Get words of the key.
+    fn words(&self) -> &[u64];
+
+    /// Get words of the key.
+    fn words_mut(&mut self) -> &mut [u64];
+}
+
+/// Error returned when permutation parameters can't be used by [`DynPermuter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamsError {
+    /// Word size is not one of [`DYN_WORD_SIZES`].
+    UnsupportedWordSize { w: usize },
+    /// Number of bits is zero or greater than [`DynBitVec::MAX_BITS`].
+    UnsupportedWidth { f: usize },
+    /// Bits can't be split into `r` non-empty blocks, or `k` is not between 1 and `r`.
+    InvalidBlocks { f: usize, r: usize, k: usize },
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedWordSize { w } => write!(f, "word size {w} is not supported"),
+            Self::UnsupportedWidth { f: bits } => {
+                write!(f, "{bits} bits are not supported (max={} bits)", DynBitVec::MAX_BITS)
+            }
+            Self::InvalidBlocks { f: bits, r, k } => {
+                write!(f, "can't choose {k} of {r} blocks of {bits} bits")
+            }
+        }
+    }
+}
+
+impl core::error::Error for ParamsError {}
+
+/// Check that `f` bits in `w`-bit words can be split into `r` blocks, `k` of which are chosen by each permutation.
+///
+/// `f` does not have to be divisible by `w`: the last word is then only partially used.
+pub fn check_params(f: usize, r: usize, k: usize, w: usize) -> Result<(), ParamsError> {
+    if !DYN_WORD_SIZES.contains(&w) {
+        return Err(ParamsError::UnsupportedWordSize { w });
+    }
+    if f == 0 || f > DynBitVec::MAX_BITS {
+        return Err(ParamsError::UnsupportedWidth { f });
+    }
+    if r == 0 || r > f || k == 0 || k > r {
+        return Err(ParamsError::InvalidBlocks { f, r, k });
+    }
+    Ok(())
+}
+
+/// Bit permutation which interprets [`BitOp`]s of a [`Permutation`] at runtime, for parameters which are not known at
+/// compile time. Keys and masks are [`DynBitVec`]s.
+///
+/// This is slower than permuters generated by `make_permutations!`, which compile the same operations into code.
+pub struct DynPermuter {
+    word_bits: usize,
+    n_bits: usize,
+    mask_len: usize,
+    mask_bits: u32,
+    n_blocks: u32,
+    apply_ops: Vec<BitOp>,
+    revert_ops: Vec<BitOp>,
+    mask_ops: Vec<BitOp>,
+}
+
+impl DynPermuter {
+    /// Create a permuter for `perm` of keys of `n_bits` bits, operating on words of `word_bits` bits.
+    ///
+    /// # Panics
+    /// Panics if `n_bits` or `word_bits` are not supported; see [`check_params`].
+    pub fn new(perm: &Permutation, n_bits: usize, word_bits: usize) -> Self {
+        assert!(
+            DYN_WORD_SIZES.contains(&word_bits),
+            "word size {word_bits} is not supported"
+        );
+        assert!(n_bits <= DynBitVec::MAX_BITS, "{n_bits} bits are not supported");
+        let flatten = |ops: BTreeMap<usize, Vec<BitOp>>| ops.into_values().flatten().collect();
+        Self {
+            word_bits,
+            n_bits,
+            mask_len: perm.mask_words(word_bits) * word_bits,
+            mask_bits: perm.mask_bits() as u32,
+            n_blocks: perm.n_blocks() as u32,
+            apply_ops: flatten(perm.compile_apply(word_bits, true)),
+            revert_ops: flatten(perm.compile_revert(word_bits, true)),
+            mask_ops: flatten(perm.compile_top_mask(word_bits, true)),
+        }
+    }
+
+    /// Create permuters for all permutations of `f` bits split into `r` blocks, `k` of which are moved to the front,
+    /// operating on words of `w` bits. This is the runtime counterpart of `make_permutations!`.
+    pub fn create_all(f: usize, r: usize, k: usize, w: usize) -> Result<Vec<Self>, ParamsError> {
+        Self::create_all_with(f, r, k, w, &BlockSelection::AllCombinations, BlockSplitting::Contiguous)
+    }
+
+    /// Create permuters like [`DynPermuter::create_all`], for combinations of blocks selected by `selection`, with
+    /// bits assigned to blocks by `splitting`.
+    ///
+    /// # Panics
+    /// Panics if `selection` can't select blocks, see [`BlockSelection::select`].
+    pub fn create_all_with(
+        f: usize,
+        r: usize,
+        k: usize,
+        w: usize,
+        selection: &BlockSelection,
+        splitting: BlockSplitting,
+    ) -> Result<Vec<Self>, ParamsError> {
+        check_params(f, r, k, w)?;
+        if splitting.validate(f, r).is_err() {
+            return Err(ParamsError::InvalidBlocks { f, r, k });
+        }
+        Ok(create_permutations_with(f, w, r, k, selection, splitting)
+            .iter()
+            .map(|perm| Self::new(perm, f, w))
+            .collect())
+    }
+
+    /// Number of bits in keys.
+    pub fn n_bits(&self) -> usize {
+        self.n_bits
+    }
+
+    /// Length of masks in bits: [`BitPermuter::mask_bits`] rounded up to whole words.
+    pub fn mask_len(&self) -> usize {
+        self.mask_len
+    }
+
+    /// Number of blocks this permuter operates on. See [`BitPermuter::n_blocks`].
+    pub fn n_blocks(&self) -> u32 {
+        self.n_blocks
+    }
+
+    /// Number of bits of keys kept by masks. See [`BitPermuter::mask_bits`].
+    pub fn mask_bits(&self) -> u32 {
+        self.mask_bits
+    }
+
+    fn run(&self, ops: &[BitOp], key: &DynBitVec, out_bits: usize) -> DynBitVec {
+        let w = self.word_bits;
+        let mut out = DynBitVec::zeros(out_bits);
+        for op in ops {
+            match *op {
+                BitOp::MaskShiftAndCopy {
+                    src_word,
+                    src_mask,
+                    src_shift,
+                    dst_word,
+                } => {
+                    // words are at most 64 bits long, and so are their masks
+                    let masked = key.word(src_word, w) & src_mask as u64;
+                    let shifted = if src_shift < 0 {
+                        masked >> -src_shift
+                    } else {
+                        masked << src_shift
+                    };
+                    out.or_word(dst_word, w, shifted);
+                }
+                BitOp::MaskAndCopy {
+                    src_word,
+                    src_mask,
+                    dst_word,
+                } => out.or_word(dst_word, w, key.word(src_word, w) & src_mask as u64),
+                BitOp::Copy { src_word, dst_word } => out.set_word(dst_word, w, key.word(src_word, w)),
+            }
+        }
+        out
+    }
+
+    /// Copy words of `key` into a bit vector of [`DynPermuter::n_bits`] bits.
+    ///
+    /// # Panics
+    /// Panics if `key` has fewer words than needed to hold the bits, or more than a `DynBitVec` can hold.
+    fn to_bit_vec<K: WordKey>(&self, key: &K) -> DynBitVec {
+        let words = key.words();
+        assert!(
+            words.len() * 64 >= self.n_bits && words.len() <= MAX_WORDS,
+            "key of {} words can't hold {} bits",
+            words.len(),
+            self.n_bits
+        );
+        DynBitVec::from_words(words, self.n_bits)
+    }
+
+    fn run_words<K: WordKey>(&self, ops: &[BitOp], key: &K) -> K {
+        let out = self.run(ops, &self.to_bit_vec(key), self.n_bits);
+        let mut val = K::default();
+        let words = val.words_mut();
+        let n_words = words.len();
+        words.copy_from_slice(&out.data[..n_words]);
+        val
+    }
+}
+
+impl BitPermuter<DynBitVec, DynBitVec> for DynPermuter {
+    fn apply(&self, key: &DynBitVec) -> DynBitVec {
+        self.run(&self.apply_ops, key, self.n_bits)
+    }
+
+    fn revert(&self, key: &DynBitVec) -> DynBitVec {
+        self.run(&self.revert_ops, key, self.n_bits)
+    }
+
+    fn mask(&self, key: &DynBitVec) -> DynBitVec {
+        self.run(&self.mask_ops, key, self.mask_len)
+    }
+
+    fn mask_and_cmp(&self, key: &DynBitVec, other_mask: &DynBitVec) -> Ordering {
+        self.mask(key).cmp(other_mask)
+    }
+
+    fn n_blocks(&self) -> u32 {
+        self.n_blocks
+    }
+
+    fn mask_bits(&self) -> u32 {
+        self.mask_bits
+    }
+}
+
+impl<K: WordKey + Send + Sync> BitPermuter<K, DynBitVec> for DynPermuter {
+    fn apply(&self, key: &K) -> K {
+        self.run_words(&self.apply_ops, key)
+    }
+
+    fn revert(&self, key: &K) -> K {
+        self.run_words(&self.revert_ops, key)
+    }
+
+    fn mask(&self, key: &K) -> DynBitVec {
+        self.run(&self.mask_ops, &self.to_bit_vec(key), self.mask_len)
+    }
+
+    fn mask_and_cmp(&self, key: &K, other_mask: &DynBitVec) -> Ordering {
+        BitPermuter::<K, DynBitVec>::mask(self, key).cmp(other_mask)
+    }
+
+    fn n_blocks(&self) -> u32 {
+        self.n_blocks
+    }
+
+    fn mask_bits(&self) -> u32 {
+        self.mask_bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_vec_words_follow_bit_order() {
+        let mut bits = DynBitVec::from_be_bytes(&[0b1000_0001, 0xFF, 0, 0x0F, 0xAB]);
+        assert_eq!(bits.len(), 40);
+        assert!(bits.get(0) && bits.get(7) && !bits.get(1), "first byte");
+        assert_eq!(bits.word(0, 8), 0b1000_0001, "first 8-bit word");
+        assert_eq!(bits.word(1, 16), 0x000F, "second 16-bit word");
+        assert_eq!(bits.word(4, 8), 0xAB, "last 8-bit word");
+        assert_eq!(bits.to_string(), "81FF000FAB");
+
+        bits.set_word(1, 8, 0x12);
+        bits.or_word(2, 8, 0x30);
+        assert_eq!(bits.to_string(), "8112300FAB");
+        assert_eq!(
+            DynBitVec::from_le_bytes(&[0xFF, 0x12, 0x0F, 0x30], 16).to_string(),
+            "12FF300F"
+        );
+        assert_eq!(
+            DynBitVec::from_le_bytes(&[0x34, 0x12, 0xCD, 0xAB, 0x0F, 0x30], 32).to_string(),
+            "ABCD1234300F",
+            "partial last word"
+        );
+        assert_eq!(
+            DynBitVec::from_iter(bits.iter()),
+            bits,
+            "from_iter is unable to reconstruct bits"
+        );
+    }
+
+    #[test]
+    fn apply_then_revert_is_identity() {
+        let bits = DynBitVec::from_be_bytes(&[0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0, 0x0F, 0xED]);
+        let zeros = DynBitVec::zeros(80);
+        // 80 bits are not divisible into 32-bit words
+        let perms = DynPermuter::create_all(80, 7, 2, 32).unwrap();
+        assert_eq!(perms.len(), 21);
+        for (i, perm) in perms.iter().enumerate() {
+            let permuted = perm.apply(&bits);
+            assert_eq!(
+                permuted.xor_dist(&zeros),
+                bits.xor_dist(&zeros),
+                "permutation {i}: bits are lost"
+            );
+            assert_eq!(
+                perm.revert(&permuted),
+                bits,
+                "permutation {i}: failed apply-revert test"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        assert_eq!(check_params(64, 4, 1, 64), Ok(()));
+        assert_eq!(
+            check_params(64, 4, 1, 24),
+            Err(ParamsError::UnsupportedWordSize { w: 24 })
+        );
+        assert_eq!(check_params(72, 4, 1, 64), Ok(()));
+        assert_eq!(
+            check_params(512, 4, 1, 64),
+            Err(ParamsError::UnsupportedWidth { f: 512 })
+        );
+        assert_eq!(
+            check_params(64, 4, 5, 64),
+            Err(ParamsError::InvalidBlocks { f: 64, r: 4, k: 5 })
+        );
+        assert!(DynPermuter::create_all(64, 0, 1, 64).is_err());
+    }
+}
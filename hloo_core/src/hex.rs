@@ -0,0 +1,60 @@
+use core::fmt;
+
+/// Error returned when parsing a bit container from a hex string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseBitsError {
+    /// The string does not have the number of hex digits the container holds.
+    InvalidLength { expected: usize, actual: usize },
+    /// The string contains a character which is not a hex digit, at the given byte position.
+    InvalidDigit { pos: usize },
+}
+
+impl fmt::Display for ParseBitsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength { expected, actual } => {
+                write!(f, "expected {expected} hex digits, got {actual}")
+            }
+            Self::InvalidDigit { pos } => write!(f, "invalid hex digit at position {pos}"),
+        }
+    }
+}
+
+impl core::error::Error for ParseBitsError {}
+
+/// Strip an optional `0x` prefix from `s`, and check that the rest consists of exactly `n_digits` hex digits.
+///
+/// Used by `FromStr` implementations of containers generated by `make_permutations!`.
+pub fn hex_digits(s: &str, n_digits: usize) -> Result<&str, ParseBitsError> {
+    let (prefix, digits) = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(digits) => (2, digits),
+        None => (0, s),
+    };
+    if let Some(pos) = digits.bytes().position(|b| !b.is_ascii_hexdigit()) {
+        return Err(ParseBitsError::InvalidDigit { pos: prefix + pos });
+    }
+    if digits.len() != n_digits {
+        return Err(ParseBitsError::InvalidLength {
+            expected: n_digits,
+            actual: digits.len(),
+        });
+    }
+    Ok(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_digits_are_checked() {
+        assert_eq!(hex_digits("0x00ff", 4), Ok("00ff"));
+        assert_eq!(hex_digits("ABCD", 4), Ok("ABCD"));
+        assert_eq!(
+            hex_digits("0x0ff", 4),
+            Err(ParseBitsError::InvalidLength { expected: 4, actual: 3 })
+        );
+        assert_eq!(hex_digits("+0ff", 4), Err(ParseBitsError::InvalidDigit { pos: 0 }));
+        assert_eq!(hex_digits("0x0fg0", 4), Err(ParseBitsError::InvalidDigit { pos: 4 }));
+    }
+}
@@ -1,10 +1,65 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
 mod bit_block;
+mod block_selection;
+mod block_splitting;
+mod dyn_permuter;
+mod hex;
 mod permutations;
+mod verify;
 
-use std::cmp::Ordering;
+use core::cmp::Ordering;
 
 pub use bit_block::{BitBlock, BitOp, PermutedBitBlock};
-pub use permutations::{create_permutations, Permutation};
+pub use block_selection::BlockSelection;
+pub use block_splitting::BlockSplitting;
+pub use dyn_permuter::{check_params, DynBitVec, DynPermuter, ParamsError, WordKey, DYN_WORD_SIZES};
+pub use hex::{hex_digits, ParseBitsError};
+pub use permutations::{create_permutations, create_permutations_with, Permutation};
+pub use verify::{sample_bytes, verify_permuter, VerifyError};
+
+// re-exported for code generated by `make_permutations!`, which may be `no_std`
+#[doc(hidden)]
+pub extern crate alloc;
+#[cfg(feature = "arbitrary")]
+pub use arbitrary;
+#[cfg(feature = "bitvec")]
+pub use bitvec;
+#[cfg(feature = "proptest")]
+pub use proptest;
+#[cfg(feature = "rand")]
+pub use rand;
+#[cfg(feature = "serde")]
+pub use serde;
+
+/// Plain data which can be stored in memory-mapped files: any bit pattern of the right size is a valid value, and the
+/// type holds no references. Values are read from and written to files as their in-memory representation.
+///
+/// Unlike similar traits of other crates, pairs are `Pod` even if they contain padding (e.g. a 4-byte key paired with
+/// an 8-byte value), since reading them is still sound. Contents of padding bytes are unspecified, though, so files
+/// holding such pairs are not byte-for-byte reproducible.
+///
+/// # Safety
+/// Implementors must be `Copy` types without references, pointers, or invalid bit patterns (e.g. `bool`, `char` or
+/// enums), and must not be aligned to more than 64 bytes.
+pub unsafe trait Pod: Copy {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(
+            // SAFETY: primitive numbers are valid for any bit pattern
+            unsafe impl Pod for $t {}
+        )*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+// SAFETY: arrays have no padding between their elements, which are `Pod`
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+// SAFETY: both elements are `Pod`, and padding bytes may hold anything
+unsafe impl<A: Pod, B: Pod> Pod for (A, B) {}
 
 pub trait BitContainer: Default {
     type Data;
@@ -22,25 +77,33 @@ pub trait BitContainer: Default {
     fn xor_dist(&self, other: &Self) -> u32;
 }
 
-pub trait BitPermuter<B, M> {
-    /// Apply permutation to bit sequence `key`. Statically dispatched.
-    fn apply_static(key: &B) -> B
-    where
-        Self: Sized;
+/// Key types generated by `make_permutations!`, linked to their masks and permuters.
+pub trait PermutedKey: BitContainer + Pod + Ord + Send + Sync + 'static {
+    /// Masks of keys produced by the permuters.
+    type Mask: Copy + Ord + Send + Sync + 'static;
 
-    /// Apply permutation to bit sequence `key`. Statically dispatched.
-    fn revert_static(key: &B) -> B
-    where
-        Self: Sized;
+    /// Parameters the permutations were generated with: `[f, r, k, w]`.
+    const PARAMS: [usize; 4];
 
-    /// Apply permutation to bit sequence `key`. Statically dispatched.
-    fn mask_static(key: &B) -> M
-    where
-        Self: Sized;
+    /// Create permuters for all variants of the permutation, one per index.
+    fn permuters() -> alloc::vec::Vec<alloc::boxed::Box<dyn BitPermuter<Self, Self::Mask>>>;
+}
 
+/// Bit permutation. Permuters are stateless, so they are required to be shareable between threads.
+pub trait BitPermuter<B, M>: Send + Sync {
     /// Apply permutation to bit sequence `key`.
     fn apply(&self, key: &B) -> B;
 
+    /// Apply permutation to bit sequence `key` in place.
+    fn apply_inplace(&self, key: &mut B) {
+        *key = self.apply(key);
+    }
+
+    /// Apply permutation to every bit sequence of `keys`. Implementations may permute several keys at once.
+    fn apply_many(&self, keys: &[B]) -> alloc::vec::Vec<B> {
+        keys.iter().map(|key| self.apply(key)).collect()
+    }
+
     /// Revert permutation of bit sequence `key`.
     fn revert(&self, key: &B) -> B;
 
@@ -52,4 +115,19 @@ pub trait BitPermuter<B, M> {
 
     /// Get number of blocks this permuter operates on.
     fn n_blocks(&self) -> u32;
+
+    /// Get number of bits of the key kept by `mask`, i.e. total length of the blocks moved to the front.
+    fn mask_bits(&self) -> u32;
+}
+
+/// Bit permutation known at compile time, such as the ones generated by `make_permutations!`.
+pub trait StaticBitPermuter<B, M>: BitPermuter<B, M> + Sized {
+    /// Apply permutation to bit sequence `key`. Statically dispatched.
+    fn apply_static(key: &B) -> B;
+
+    /// Revert permutation of bit sequence `key`. Statically dispatched.
+    fn revert_static(key: &B) -> B;
+
+    /// Apply mask to bit sequence `key`. Statically dispatched.
+    fn mask_static(key: &B) -> M;
 }
@@ -1,24 +1,34 @@
-use std::collections::HashMap;
+use alloc::{collections::BTreeMap, vec, vec::Vec};
 
 use itertools::Itertools;
 
 pub use crate::{BitBlock, BitOp, PermutedBitBlock};
+use crate::{BlockSelection, BlockSplitting, bit_block::full_mask};
 
 pub struct Permutation {
+    /// Number of parts of blocks in the head.
     head: usize,
+    n_blocks: usize,
     blocks: Vec<PermutedBitBlock>,
 }
 
 impl Permutation {
     pub fn from_blocks(head: usize, blocks: &[BitBlock]) -> Self {
-        let permuted_blocks = create_permuted_blocks(blocks);
+        Self::from_split_blocks(head, &blocks.iter().map(|block| vec![*block]).collect::<Vec<_>>())
+    }
+
+    /// Create a permutation of blocks consisting of one or more ranges of bits each, placing their ranges one after
+    /// another. The first `head` blocks form the mask.
+    pub fn from_split_blocks(head: usize, blocks: &[Vec<BitBlock>]) -> Self {
+        let permuted_blocks = create_permuted_blocks(&blocks.concat());
         Self {
-            head,
+            head: blocks[..head].iter().map(Vec::len).sum(),
+            n_blocks: blocks.len(),
             blocks: permuted_blocks,
         }
     }
 
-    pub fn compile_apply(&self, word_size: usize, optimize: bool) -> HashMap<usize, Vec<BitOp>> {
+    pub fn compile_apply(&self, word_size: usize, optimize: bool) -> BTreeMap<usize, Vec<BitOp>> {
         compile_permutation(
             self.blocks.iter().flat_map(|block| block.to_ops(word_size)),
             word_size,
@@ -26,7 +36,7 @@ impl Permutation {
         )
     }
 
-    pub fn compile_revert(&self, word_size: usize, optimize: bool) -> HashMap<usize, Vec<BitOp>> {
+    pub fn compile_revert(&self, word_size: usize, optimize: bool) -> BTreeMap<usize, Vec<BitOp>> {
         compile_permutation(
             self.blocks.iter().flat_map(|block| block.apply().to_ops(word_size)),
             word_size,
@@ -34,7 +44,7 @@ impl Permutation {
         )
     }
 
-    pub fn compile_top_mask(&self, word_size: usize, optimize: bool) -> HashMap<usize, Vec<BitOp>> {
+    pub fn compile_top_mask(&self, word_size: usize, optimize: bool) -> BTreeMap<usize, Vec<BitOp>> {
         compile_permutation(
             self.blocks
                 .iter()
@@ -45,10 +55,29 @@ impl Permutation {
         )
     }
 
+    /// Ranges of bits of the blocks in the order they are placed. A block which is not contiguous is represented by
+    /// several ranges with the same block index.
     pub fn blocks(&self) -> &[PermutedBitBlock] {
         &self.blocks
     }
 
+    /// Number of blocks, which may be less than the number of ranges in [`Permutation::blocks`].
+    pub fn n_blocks(&self) -> usize {
+        self.n_blocks
+    }
+
+    /// Mapping of bits of the original key to their positions in the permuted key: bit `i` is moved to bit
+    /// `as_bit_mapping()[i]`. This describes the permutation independently of the word size.
+    pub fn as_bit_mapping(&self) -> Vec<usize> {
+        let mut mapping = vec![0; self.blocks.iter().map(|b| b.block.len()).sum()];
+        for b in &self.blocks {
+            for offset in 0..b.block.len() {
+                mapping[b.block.start_pos() + offset] = b.new_pos + offset;
+            }
+        }
+        mapping
+    }
+
     pub fn mask_bits(&self) -> usize {
         self.blocks[..self.head].iter().map(|b| b.block.len()).sum()
     }
@@ -63,17 +92,17 @@ fn compile_permutation(
     ops: impl Iterator<Item = BitOp>,
     word_size: usize,
     optimize: bool,
-) -> HashMap<usize, Vec<BitOp>> {
+) -> BTreeMap<usize, Vec<BitOp>> {
     let grouped_by_dst_word = ops.chunk_by(|op| (op.dst_word(), op.src_word()));
 
-    let mut result: HashMap<_, Vec<_>> = HashMap::new();
+    let mut result: BTreeMap<_, Vec<_>> = BTreeMap::new();
     for ((dst_word, src_word), mut ops) in &grouped_by_dst_word {
         let mut prev_op = ops.next().expect("empty group");
         let mut word_ops = Vec::new();
         for op in ops {
             if optimize {
                 if let Some(combined_op) = prev_op.combine(&op) {
-                    if combined_op.mask().leading_ones() == word_size as u32 {
+                    if combined_op.mask() == full_mask(word_size) {
                         prev_op = BitOp::Copy { src_word, dst_word }
                     } else {
                         prev_op = combined_op;
@@ -93,24 +122,44 @@ fn compile_permutation(
     result
 }
 
-fn split_bits_into_blocks(f: usize, r: usize) -> Vec<BitBlock> {
-    assert!(f >= r, "{f} is not enough bits to split into {r} blocks");
-    let mut blocks = Vec::with_capacity(r);
-    let mut acc = 0;
-    for i in 0..r {
-        let size = f / r + usize::from(i < f % r);
-        blocks.push(BitBlock::new(i, acc, size));
-        acc += size;
+/// Split `f` bits into `r` blocks, each consisting of one or more ranges of bits, according to `splitting`.
+fn split_bits_into_blocks(f: usize, r: usize, splitting: BlockSplitting) -> Vec<Vec<BitBlock>> {
+    if let Err(e) = splitting.validate(f, r) {
+        panic!("{e}");
+    }
+    match splitting {
+        BlockSplitting::Contiguous => {
+            let mut blocks = Vec::with_capacity(r);
+            let mut acc = 0;
+            for i in 0..r {
+                let size = f / r + usize::from(i < f % r);
+                blocks.push(vec![BitBlock::new(i, acc, size)]);
+                acc += size;
+            }
+            blocks
+        }
+        BlockSplitting::Strided { stride } => {
+            let mut blocks = vec![Vec::new(); r];
+            for (i, start) in (0..f).step_by(stride).enumerate() {
+                blocks[i % r].push(BitBlock::new(i % r, start, stride.min(f - start)));
+            }
+            blocks
+        }
     }
-    blocks
 }
 
-fn reorder_blocks(blocks: &[BitBlock], order: &[usize]) -> Vec<BitBlock> {
+fn reorder_blocks<T: Clone>(blocks: &[T], order: &[usize]) -> Vec<T> {
     let mut permuted = Vec::new();
     for pos in order {
-        permuted.push(blocks[*pos]);
+        permuted.push(blocks[*pos].clone());
     }
-    permuted.extend(blocks.iter().filter(|block| !order.contains(&block.idx())));
+    permuted.extend(
+        blocks
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !order.contains(i))
+            .map(|(_, block)| block.clone()),
+    );
     permuted
 }
 
@@ -126,27 +175,54 @@ fn create_permuted_blocks(reordered_blocks: &[BitBlock]) -> Vec<PermutedBitBlock
 
 /// Creates bit permutations from given parameters.
 ///
+/// `total_bits` does not have to be divisible by `word_bits`: the last word is then only partially used, and bits
+/// past `total_bits` are never read or written by the permutations.
+///
 /// # Panics
 /// This function panics if either of the following is true:
-/// - `total_bits` is not divisible by `word_bits`
 /// - `total_bits` < k
 /// - `r` == 0
 /// - `k` == 0
 pub fn create_permutations(total_bits: usize, word_bits: usize, r: usize, k: usize) -> Vec<Permutation> {
+    create_permutations_with(
+        total_bits,
+        word_bits,
+        r,
+        k,
+        &BlockSelection::AllCombinations,
+        BlockSplitting::Contiguous,
+    )
+}
+
+/// Creates bit permutations from given parameters, with bits assigned to blocks by `splitting`, and moving blocks
+/// selected by `selection` to the front. See [`create_permutations`].
+///
+/// # Panics
+/// This function panics in the same cases as [`create_permutations`], or if `selection` can't select blocks (see
+/// [`BlockSelection::select`]), or if `splitting` can't split bits into `r` blocks (see [`BlockSplitting::validate`]).
+pub fn create_permutations_with(
+    total_bits: usize,
+    word_bits: usize,
+    r: usize,
+    k: usize,
+    selection: &BlockSelection,
+    splitting: BlockSplitting,
+) -> Vec<Permutation> {
     assert!(
-        total_bits % word_bits == 0,
-        "total_bits has to be divisible by word_bits (tb={total_bits} wb={word_bits})"
+        0 < word_bits && word_bits <= 128,
+        "word size {word_bits} is not supported"
     );
     assert!(
         total_bits >= k,
         "total_bits must be able to fit k (tb={total_bits} k={k})",
     );
     assert!(r != 0 && k != 0, "r and k cannot be 0 (r={r} k={k})");
-    let blocks = split_bits_into_blocks(total_bits, r);
-    (0..r)
-        .combinations(k)
+    let blocks = split_bits_into_blocks(total_bits, r, splitting);
+    selection
+        .select(r, k)
+        .into_iter()
         .map(|order| reorder_blocks(&blocks, &order))
-        .map(|blocks| Permutation::from_blocks(k, &blocks))
+        .map(|blocks| Permutation::from_split_blocks(k, &blocks))
         .collect()
 }
 
@@ -156,30 +232,76 @@ mod tests {
 
     #[test]
     fn test_split_into_blocks() {
-        let res = split_bits_into_blocks(64, 5);
+        let res = split_bits_into_blocks(64, 5, BlockSplitting::Contiguous);
         let expected = vec![
-            BitBlock::new(0, 0, 13),
-            BitBlock::new(1, 13, 13),
-            BitBlock::new(2, 26, 13),
-            BitBlock::new(3, 39, 13),
-            BitBlock::new(4, 52, 12),
+            vec![BitBlock::new(0, 0, 13)],
+            vec![BitBlock::new(1, 13, 13)],
+            vec![BitBlock::new(2, 26, 13)],
+            vec![BitBlock::new(3, 39, 13)],
+            vec![BitBlock::new(4, 52, 12)],
+        ];
+        assert_eq!(res, expected);
+
+        let res = split_bits_into_blocks(64, 4, BlockSplitting::Contiguous);
+        let expected = vec![
+            vec![BitBlock::new(0, 0, 16)],
+            vec![BitBlock::new(1, 16, 16)],
+            vec![BitBlock::new(2, 32, 16)],
+            vec![BitBlock::new(3, 48, 16)],
         ];
         assert_eq!(res, expected);
+    }
 
-        let res = split_bits_into_blocks(64, 4);
+    #[test]
+    fn test_split_into_strided_blocks() {
+        let res = split_bits_into_blocks(20, 3, BlockSplitting::Strided { stride: 4 });
         let expected = vec![
-            BitBlock::new(0, 0, 16),
-            BitBlock::new(1, 16, 16),
-            BitBlock::new(2, 32, 16),
-            BitBlock::new(3, 48, 16),
+            vec![BitBlock::new(0, 0, 4), BitBlock::new(0, 12, 4)],
+            vec![BitBlock::new(1, 4, 4), BitBlock::new(1, 16, 4)],
+            vec![BitBlock::new(2, 8, 4)],
         ];
         assert_eq!(res, expected);
+
+        let res = split_bits_into_blocks(6, 2, BlockSplitting::Strided { stride: 4 });
+        assert_eq!(res, vec![vec![BitBlock::new(0, 0, 4)], vec![BitBlock::new(1, 4, 2)]]);
+    }
+
+    #[test]
+    fn test_bit_mapping() {
+        let perm = Permutation::from_blocks(
+            1,
+            &[BitBlock::new(2, 4, 2), BitBlock::new(0, 0, 3), BitBlock::new(1, 3, 1)],
+        );
+        assert_eq!(perm.as_bit_mapping(), vec![2, 3, 4, 5, 0, 1]);
+    }
+
+    #[test]
+    fn strided_permutations_move_whole_blocks() {
+        let perms = create_permutations_with(
+            64,
+            32,
+            4,
+            1,
+            &BlockSelection::AllCombinations,
+            BlockSplitting::Strided { stride: 1 },
+        );
+        assert_eq!(perms.len(), 4);
+        for (i, perm) in perms.iter().enumerate() {
+            assert_eq!((perm.n_blocks(), perm.blocks().len(), perm.mask_bits()), (4, 64, 16));
+            // the mask holds every fourth bit, starting from bit i
+            let head = &perm.blocks()[..16];
+            assert!(
+                head.iter()
+                    .enumerate()
+                    .all(|(j, b)| b.block.start_pos() == i + 4 * j && b.new_pos == j)
+            );
+        }
     }
 
     #[test]
     #[should_panic]
     fn test_split_into_blocks_not_enough_bits_panics() {
-        let _ = split_bits_into_blocks(8, 12);
+        let _ = split_bits_into_blocks(8, 12, BlockSplitting::Contiguous);
     }
 
     #[test]
@@ -0,0 +1,147 @@
+use core::{cmp::Ordering, fmt};
+
+use crate::{BitContainer, BitPermuter, block_selection::SplitMix64};
+
+/// Inconsistency of a permuter found by [`verify_permuter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// Permuter operates on a different number of blocks than expected.
+    BlockCount { expected: u32, actual: u32 },
+    /// Reverting the permutation of sample `sample` does not restore it.
+    NotReversible { sample: usize },
+    /// Permutation of sample `sample` loses or duplicates bits, i.e. changes its distance to the previous sample or
+    /// the number of set bits.
+    NotPermutation { sample: usize },
+    /// Mask of the permuted sample `sample` does not hold exactly the first `mask_bits` bits of it.
+    InconsistentMask { sample: usize },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BlockCount { expected, actual } => write!(f, "expected {expected} blocks, got {actual}"),
+            Self::NotReversible { sample } => write!(f, "permutation of sample {sample} can't be reverted"),
+            Self::NotPermutation { sample } => write!(f, "permutation of sample {sample} does not preserve bits"),
+            Self::InconsistentMask { sample } => write!(f, "mask of sample {sample} does not match its bits"),
+        }
+    }
+}
+
+impl core::error::Error for VerifyError {}
+
+/// Fill `bytes` with pseudo-random bytes determined by `seed`, e.g. to create samples for [`verify_permuter`].
+pub fn sample_bytes(seed: u64, bytes: &mut [u8]) {
+    let mut rng = SplitMix64(seed);
+    for chunk in bytes.chunks_mut(8) {
+        chunk.copy_from_slice(&rng.next().to_le_bytes()[..chunk.len()]);
+    }
+}
+
+/// Check that `permuter` operates on `n_blocks` blocks and behaves as a bit permutation on `samples`: reverting a
+/// permuted sample restores it, distances between samples are preserved, and masks hold exactly the first
+/// `mask_bits()` bits of permuted samples.
+///
+/// Permutations only move bits around, so samples with single bits set, besides a few random ones, cover every bit.
+pub fn verify_permuter<B, M>(permuter: &dyn BitPermuter<B, M>, samples: &[B], n_blocks: u32) -> Result<(), VerifyError>
+where
+    B: BitContainer + PartialEq,
+    M: BitContainer,
+{
+    if permuter.n_blocks() != n_blocks {
+        return Err(VerifyError::BlockCount {
+            expected: n_blocks,
+            actual: permuter.n_blocks(),
+        });
+    }
+    let mask_bits = permuter.mask_bits() as usize;
+    let (zero, zero_mask) = (B::default(), M::default());
+    let mut prev: Option<(&B, B)> = None;
+    for (sample, key) in samples.iter().enumerate() {
+        let permuted = permuter.apply(key);
+        if permuter.revert(&permuted) != *key {
+            return Err(VerifyError::NotReversible { sample });
+        }
+        let preserves_prev =
+            prev.is_none_or(|(prev, prev_permuted)| permuted.xor_dist(&prev_permuted) == key.xor_dist(prev));
+        if !preserves_prev || permuted.xor_dist(&zero) != key.xor_dist(&zero) {
+            return Err(VerifyError::NotPermutation { sample });
+        }
+        let mask = permuter.mask(&permuted);
+        let head_ones = (0..mask_bits).filter(|i| permuted.bit(*i)).count() as u32;
+        if (0..mask_bits).any(|i| mask.bit(i) != permuted.bit(i))
+            || mask.xor_dist(&zero_mask) != head_ones
+            || permuter.mask_and_cmp(&permuted, &mask) != Ordering::Equal
+        {
+            return Err(VerifyError::InconsistentMask { sample });
+        }
+        prev = Some((key, permuted));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DynBitVec, DynPermuter};
+
+    struct Broken(DynPermuter);
+
+    impl BitPermuter<DynBitVec, DynBitVec> for Broken {
+        fn apply(&self, key: &DynBitVec) -> DynBitVec {
+            self.0.apply(key)
+        }
+
+        fn revert(&self, key: &DynBitVec) -> DynBitVec {
+            self.0.revert(key)
+        }
+
+        fn mask(&self, key: &DynBitVec) -> DynBitVec {
+            self.0.mask(key)
+        }
+
+        fn mask_and_cmp(&self, key: &DynBitVec, other_mask: &DynBitVec) -> Ordering {
+            self.0.mask_and_cmp(key, other_mask)
+        }
+
+        fn n_blocks(&self) -> u32 {
+            self.0.n_blocks()
+        }
+
+        // claims to keep one bit more than the mask does
+        fn mask_bits(&self) -> u32 {
+            self.0.mask_bits() + 1
+        }
+    }
+
+    fn samples(n_bits: usize) -> Vec<DynBitVec> {
+        let mut bytes = vec![0; n_bits / 8];
+        let mut samples: Vec<_> = (0..n_bits)
+            .map(|i| {
+                let mut key = DynBitVec::zeros(n_bits);
+                key.set(i, true);
+                key
+            })
+            .collect();
+        for seed in 0..8 {
+            sample_bytes(seed, &mut bytes);
+            samples.push(DynBitVec::from_be_bytes(&bytes));
+        }
+        samples
+    }
+
+    #[test]
+    fn permuters_are_verified() {
+        let samples = samples(80);
+        for perm in DynPermuter::create_all(80, 7, 2, 32).unwrap() {
+            assert_eq!(verify_permuter(&perm, &samples, 7), Ok(()));
+            assert_eq!(
+                verify_permuter(&perm, &samples, 6),
+                Err(VerifyError::BlockCount { expected: 6, actual: 7 })
+            );
+            assert!(matches!(
+                verify_permuter(&Broken(perm), &samples, 7),
+                Err(VerifyError::InconsistentMask { .. })
+            ));
+        }
+    }
+}
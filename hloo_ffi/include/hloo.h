@@ -0,0 +1,53 @@
+/* C interface of the pre-defined lookups of hloo, holding int64_t values. See hloo_ffi/src/lib.rs. */
+
+#ifndef HLOO_H
+#define HLOO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Kinds of lookups. */
+#define HLOO_LOOKUP64 0u
+#define HLOO_LOOKUP256 1u
+
+/* Status codes. */
+#define HLOO_OK 0
+#define HLOO_ERR_ARGUMENT -1
+#define HLOO_ERR_DISTANCE -2
+#define HLOO_ERR_STORAGE -3
+#define HLOO_ERR_UNSUPPORTED -4
+
+typedef struct HlooLookup HlooLookup;
+
+/* Create a lookup; in memory if path is NULL, or memory-mapped in the existing directory path. NULL on error. */
+HlooLookup *hloo_create(uint32_t kind, const char *path);
+
+/* Load a memory-mapped lookup persisted in the directory path. NULL on error. */
+HlooLookup *hloo_load(uint32_t kind, const char *path);
+
+/* Release a lookup without persisting it. */
+void hloo_free(HlooLookup *lookup);
+
+/* Number of bytes of a key, or 0 if lookup is NULL. */
+size_t hloo_key_size(const HlooLookup *lookup);
+
+/* Insert n items: n big-endian keys of hloo_key_size() bytes each, and their n values. */
+int32_t hloo_insert(HlooLookup *lookup, const uint8_t *keys, const int64_t *values, size_t n);
+
+/* Search for items within distance bits of key. Writes up to cap items ordered by distance, then value, and returns
+   the total number of items found, or a negative status code. */
+int64_t hloo_search(const HlooLookup *lookup, const uint8_t *key, uint32_t distance, int64_t *out_values,
+                    uint32_t *out_distances, size_t cap);
+
+/* Persist a memory-mapped lookup; in-memory lookups return HLOO_ERR_UNSUPPORTED. */
+int32_t hloo_persist(const HlooLookup *lookup);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* HLOO_H */
@@ -0,0 +1,368 @@
+//! C interface of the pre-defined lookups of `hloo` (see `hloo::lookup::lookup_impl`), holding `int64_t` values.
+//!
+//! Lookups are opaque handles created by [`hloo_create`] or [`hloo_load`], and released by [`hloo_free`]. Keys are
+//! passed as big-endian bytes, [`hloo_key_size`] bytes each. Functions return [`HLOO_OK`] or a negative error code.
+//! See `include/hloo.h` for the declarations.
+
+use std::{
+    collections::HashSet,
+    ffi::{CStr, c_char},
+    path::Path,
+    ptr, slice,
+};
+
+use hloo::{
+    Lookup,
+    index::SearchResultItem,
+    lookup::{
+        SearchError, SearchResult,
+        lookup_impl::{lookup64, lookup256},
+    },
+};
+
+/// 64-bit keys, see `hloo::lookup::lookup_impl::lookup64`.
+pub const HLOO_LOOKUP64: u32 = 0;
+/// 256-bit keys, see `hloo::lookup::lookup_impl::lookup256`.
+pub const HLOO_LOOKUP256: u32 = 1;
+
+pub const HLOO_OK: i32 = 0;
+/// A pointer is null, or the kind of lookup is unknown.
+pub const HLOO_ERR_ARGUMENT: i32 = -1;
+/// Search distance exceeds the maximum distance of the lookup.
+pub const HLOO_ERR_DISTANCE: i32 = -2;
+/// Files of the lookup can't be read or written.
+pub const HLOO_ERR_STORAGE: i32 = -3;
+/// The operation is not supported by the lookup, e.g. persisting an in-memory lookup.
+pub const HLOO_ERR_UNSUPPORTED: i32 = -4;
+
+/// Operations of lookups behind handles, with keys as big-endian bytes.
+trait FfiLookup {
+    fn key_size(&self) -> usize;
+
+    fn insert(&mut self, keys: &[u8], values: &[i64]) -> i32;
+
+    fn search(&self, key: &[u8], distance: u32) -> Result<Vec<SearchResultItem<i64>>, i32>;
+
+    fn persist(&self) -> i32;
+}
+
+/// Deduplicate and order results of a search; every index finds the items within the distance.
+fn search_items(result: Result<SearchResult<i64>, SearchError>) -> Result<Vec<SearchResultItem<i64>>, i32> {
+    let result = result.map_err(|_| HLOO_ERR_DISTANCE)?;
+    let mut items: Vec<_> = result.into_flat_iter().collect::<HashSet<_>>().into_iter().collect();
+    items.sort_unstable_by_key(|item| (item.distance(), *item.data()));
+    Ok(items)
+}
+
+macro_rules! impl_ffi_lookup {
+    ($lookup:ty, $bits:ty, $persist:expr) => {
+        impl FfiLookup for $lookup {
+            fn key_size(&self) -> usize {
+                <$bits>::SIZE_BYTES
+            }
+
+            fn insert(&mut self, keys: &[u8], values: &[i64]) -> i32 {
+                let keys = keys.chunks(<$bits>::SIZE_BYTES).map(<$bits>::from_be_bytes);
+                let items: Vec<_> = keys.zip(values.iter().copied()).collect();
+                match Lookup::insert(self, &items) {
+                    Ok(()) => HLOO_OK,
+                    Err(_) => HLOO_ERR_STORAGE,
+                }
+            }
+
+            fn search(&self, key: &[u8], distance: u32) -> Result<Vec<SearchResultItem<i64>>, i32> {
+                search_items(Lookup::search(self, &<$bits>::from_be_bytes(key), distance))
+            }
+
+            fn persist(&self) -> i32 {
+                ($persist)(self)
+            }
+        }
+    };
+}
+
+impl_ffi_lookup!(lookup64::MemLookup<i64>, lookup64::Bits, |_| HLOO_ERR_UNSUPPORTED);
+impl_ffi_lookup!(lookup256::MemLookup<i64>, lookup256::Bits, |_| HLOO_ERR_UNSUPPORTED);
+impl_ffi_lookup!(
+    lookup64::MemMapLookup<i64>,
+    lookup64::Bits,
+    |lookup: &lookup64::MemMapLookup<i64>| {
+        match Lookup::persist(lookup) {
+            Ok(()) => HLOO_OK,
+            Err(_) => HLOO_ERR_STORAGE,
+        }
+    }
+);
+impl_ffi_lookup!(
+    lookup256::MemMapLookup<i64>,
+    lookup256::Bits,
+    |lookup: &lookup256::MemMapLookup<i64>| {
+        match Lookup::persist(lookup) {
+            Ok(()) => HLOO_OK,
+            Err(_) => HLOO_ERR_STORAGE,
+        }
+    }
+);
+
+/// Opaque handle of a lookup.
+pub struct HlooLookup(Box<dyn FfiLookup>);
+
+/// Read a path passed from C, or `None` if it is null or not valid UTF-8.
+///
+/// # Safety
+/// `path` must be null or a valid null-terminated string.
+unsafe fn read_path<'a>(path: *const c_char) -> Option<&'a Path> {
+    if path.is_null() {
+        return None;
+    }
+    // SAFETY: the caller guarantees that non-null `path` is a valid null-terminated string
+    let path = unsafe { CStr::from_ptr(path) };
+    path.to_str().ok().map(Path::new)
+}
+
+fn into_handle(lookup: Option<Box<dyn FfiLookup>>) -> *mut HlooLookup {
+    match lookup {
+        Some(lookup) => Box::into_raw(Box::new(HlooLookup(lookup))),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Create a lookup of the given kind ([`HLOO_LOOKUP64`] or [`HLOO_LOOKUP256`]). If `path` is null, the lookup is kept
+/// in memory; otherwise, its index files are created and memory-mapped in the existing directory `path`.
+///
+/// Returns null if the kind is unknown, or the files can't be created.
+///
+/// # Safety
+/// `path` must be null or a valid null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hloo_create(kind: u32, path: *const c_char) -> *mut HlooLookup {
+    // SAFETY: guaranteed by the caller
+    let path = unsafe { read_path(path) };
+    let lookup: Option<Box<dyn FfiLookup>> = match (kind, path) {
+        (HLOO_LOOKUP64, None) => Some(Box::new(lookup64::MemLookup::<i64>::default())),
+        (HLOO_LOOKUP256, None) => Some(Box::new(lookup256::MemLookup::<i64>::default())),
+        (HLOO_LOOKUP64, Some(path)) => lookup64::MemMapLookup::<i64>::create(path)
+            .ok()
+            .map(|lookup| Box::new(lookup) as Box<dyn FfiLookup>),
+        (HLOO_LOOKUP256, Some(path)) => lookup256::MemMapLookup::<i64>::create(path)
+            .ok()
+            .map(|lookup| Box::new(lookup) as Box<dyn FfiLookup>),
+        _ => None,
+    };
+    into_handle(lookup)
+}
+
+/// Load a memory-mapped lookup of the given kind from the directory `path`, where it was created and persisted.
+///
+/// Returns null if `path` is null, the kind is unknown, or the files can't be loaded.
+///
+/// # Safety
+/// `path` must be null or a valid null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hloo_load(kind: u32, path: *const c_char) -> *mut HlooLookup {
+    // SAFETY: guaranteed by the caller
+    let Some(path) = (unsafe { read_path(path) }) else {
+        return ptr::null_mut();
+    };
+    let lookup: Option<Box<dyn FfiLookup>> = match kind {
+        HLOO_LOOKUP64 => lookup64::MemMapLookup::<i64>::load(path)
+            .ok()
+            .map(|lookup| Box::new(lookup) as Box<dyn FfiLookup>),
+        HLOO_LOOKUP256 => lookup256::MemMapLookup::<i64>::load(path)
+            .ok()
+            .map(|lookup| Box::new(lookup) as Box<dyn FfiLookup>),
+        _ => None,
+    };
+    into_handle(lookup)
+}
+
+/// Release a lookup. Memory-mapped lookups are not persisted; see [`hloo_persist`].
+///
+/// # Safety
+/// `lookup` must be null or a handle which was not released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hloo_free(lookup: *mut HlooLookup) {
+    if !lookup.is_null() {
+        // SAFETY: the caller guarantees that the handle was created by `into_handle` and is not used anymore
+        drop(unsafe { Box::from_raw(lookup) });
+    }
+}
+
+/// Number of bytes of a key of the lookup, or 0 if `lookup` is null.
+///
+/// # Safety
+/// `lookup` must be null or a valid handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hloo_key_size(lookup: *const HlooLookup) -> usize {
+    // SAFETY: guaranteed by the caller
+    unsafe { lookup.as_ref() }.map_or(0, |lookup| lookup.0.key_size())
+}
+
+/// Insert `n` items into the lookup: `keys` holds `n` keys of [`hloo_key_size`] bytes each, and `values` holds their
+/// `n` values.
+///
+/// # Safety
+/// `lookup` must be null or a valid handle, and `keys` and `values` must be null or point to `n` keys and values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hloo_insert(lookup: *mut HlooLookup, keys: *const u8, values: *const i64, n: usize) -> i32 {
+    // SAFETY: guaranteed by the caller
+    let Some(lookup) = (unsafe { lookup.as_mut() }) else {
+        return HLOO_ERR_ARGUMENT;
+    };
+    if n == 0 {
+        return HLOO_OK;
+    }
+    if keys.is_null() || values.is_null() {
+        return HLOO_ERR_ARGUMENT;
+    }
+    // SAFETY: the caller guarantees that `keys` and `values` point to `n` keys and values
+    let (keys, values) = unsafe {
+        (
+            slice::from_raw_parts(keys, n * lookup.0.key_size()),
+            slice::from_raw_parts(values, n),
+        )
+    };
+    lookup.0.insert(keys, values)
+}
+
+/// Search the lookup for items within `distance` bits of `key`, which holds [`hloo_key_size`] bytes.
+///
+/// Up to `cap` distinct items are written to `out_values` and `out_distances`, ordered by distance and then by
+/// value. Returns the total number of items found, which may exceed `cap`, or a negative error code.
+///
+/// # Safety
+/// `lookup` must be null or a valid handle, `key` must be null or point to a key, and `out_values` and
+/// `out_distances` must be null or have room for `cap` items.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hloo_search(
+    lookup: *const HlooLookup,
+    key: *const u8,
+    distance: u32,
+    out_values: *mut i64,
+    out_distances: *mut u32,
+    cap: usize,
+) -> i64 {
+    // SAFETY: guaranteed by the caller
+    let Some(lookup) = (unsafe { lookup.as_ref() }) else {
+        return HLOO_ERR_ARGUMENT as i64;
+    };
+    if key.is_null() || (cap > 0 && (out_values.is_null() || out_distances.is_null())) {
+        return HLOO_ERR_ARGUMENT as i64;
+    }
+    // SAFETY: the caller guarantees that `key` points to a key
+    let key = unsafe { slice::from_raw_parts(key, lookup.0.key_size()) };
+    let items = match lookup.0.search(key, distance) {
+        Ok(items) => items,
+        Err(code) => return code as i64,
+    };
+    for (i, item) in items.iter().take(cap).enumerate() {
+        // SAFETY: the caller guarantees that the outputs have room for `cap` items
+        unsafe {
+            *out_values.add(i) = *item.data();
+            *out_distances.add(i) = item.distance();
+        }
+    }
+    items.len() as i64
+}
+
+/// Persist a memory-mapped lookup, so it can be loaded with [`hloo_load`]. In-memory lookups can't be persisted.
+///
+/// # Safety
+/// `lookup` must be null or a valid handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hloo_persist(lookup: *const HlooLookup) -> i32 {
+    // SAFETY: guaranteed by the caller
+    match unsafe { lookup.as_ref() } {
+        Some(lookup) => lookup.0.persist(),
+        None => HLOO_ERR_ARGUMENT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    fn keys(n: u64) -> Vec<u8> {
+        (0..n).flat_map(|i| (i * 0x0101_0101_0101_0101).to_be_bytes()).collect()
+    }
+
+    fn search(lookup: *const HlooLookup, key: &[u8], distance: u32, cap: usize) -> (i64, Vec<(i64, u32)>) {
+        let mut values = vec![0; cap];
+        let mut distances = vec![0; cap];
+        let n = unsafe {
+            hloo_search(
+                lookup,
+                key.as_ptr(),
+                distance,
+                values.as_mut_ptr(),
+                distances.as_mut_ptr(),
+                cap,
+            )
+        };
+        let found = values.into_iter().zip(distances).take(n.max(0) as usize).collect();
+        (n, found)
+    }
+
+    #[test]
+    fn mem_lookup_is_searched() {
+        unsafe {
+            let lookup = hloo_create(HLOO_LOOKUP64, ptr::null());
+            assert!(!lookup.is_null());
+            assert_eq!(hloo_key_size(lookup), 8);
+            let values: Vec<i64> = (0..10).collect();
+            assert_eq!(hloo_insert(lookup, keys(10).as_ptr(), values.as_ptr(), 10), HLOO_OK);
+
+            let mut key = 0x0303_0303_0303_0303u64.to_be_bytes();
+            key[7] ^= 0b11;
+            assert_eq!(search(lookup, &key, 2, 4), (1, vec![(3, 2)]));
+            // items beyond `cap` are counted, but not written
+            assert_eq!(search(lookup, &keys(2)[8..], 3, 0), (1, vec![]));
+            assert_eq!(search(lookup, &key, 4, 4).0, HLOO_ERR_DISTANCE as i64);
+            assert_eq!(hloo_persist(lookup), HLOO_ERR_UNSUPPORTED);
+            hloo_free(lookup);
+        }
+    }
+
+    #[test]
+    fn memmap_lookup_is_persisted_and_loaded() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+        let key = [0xAB; 32];
+        unsafe {
+            let lookup = hloo_create(HLOO_LOOKUP256, path.as_ptr());
+            assert!(!lookup.is_null());
+            assert_eq!(hloo_key_size(lookup), 32);
+            assert_eq!(hloo_insert(lookup, key.as_ptr(), [42].as_ptr(), 1), HLOO_OK);
+            assert_eq!(hloo_persist(lookup), HLOO_OK);
+            hloo_free(lookup);
+
+            let lookup = hloo_load(HLOO_LOOKUP256, path.as_ptr());
+            assert!(!lookup.is_null());
+            assert_eq!(search(lookup, &key, 0, 1), (1, vec![(42, 0)]));
+            hloo_free(lookup);
+        }
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        unsafe {
+            assert!(hloo_create(2, ptr::null()).is_null(), "unknown kind");
+            assert!(hloo_load(HLOO_LOOKUP64, ptr::null()).is_null(), "missing path");
+            assert_eq!(hloo_key_size(ptr::null()), 0);
+            assert_eq!(
+                hloo_insert(ptr::null_mut(), ptr::null(), ptr::null(), 0),
+                HLOO_ERR_ARGUMENT
+            );
+            assert_eq!(hloo_persist(ptr::null()), HLOO_ERR_ARGUMENT);
+
+            let lookup = hloo_create(HLOO_LOOKUP64, ptr::null());
+            assert_eq!(hloo_insert(lookup, ptr::null(), ptr::null(), 1), HLOO_ERR_ARGUMENT);
+            assert_eq!(search(lookup, &[0; 8], 0, 0).0, 0);
+            let n = hloo_search(lookup, ptr::null(), 0, ptr::null_mut(), ptr::null_mut(), 0);
+            assert_eq!(n, HLOO_ERR_ARGUMENT as i64);
+            hloo_free(lookup);
+        }
+    }
+}
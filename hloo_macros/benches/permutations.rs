@@ -1,7 +1,7 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use rand::random;
 
-use hloo_core::{BitContainer, BitPermuter};
+use hloo_core::{BitContainer, BitPermuter, Pod};
 use hloo_macros::make_permutations;
 
 make_permutations!(struct_name = "Permutations", f = 256, r = 5, k = 2, w = 64);
@@ -0,0 +1,80 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Expr, Fields, Index, Member, Type};
+
+/// Implement `BitContainer` and `WordKey` for a struct with a single `[u64; N]` field.
+pub fn derive(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let (member, len) = words_field(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics hloo_core::BitContainer for #name #ty_generics #where_clause {
+            type Data = [u64; #len];
+
+            fn data(&self) -> &Self::Data {
+                &self.#member
+            }
+
+            fn data_mut(&mut self) -> &mut Self::Data {
+                &mut self.#member
+            }
+
+            fn bit(&self, idx: usize) -> bool {
+                self.#member[idx / 64] & (1 << (63 - idx % 64)) != 0
+            }
+
+            fn xor_dist(&self, other: &Self) -> u32 {
+                self.#member
+                    .iter()
+                    .zip(&other.#member)
+                    .map(|(a, b)| (a ^ b).count_ones())
+                    .sum()
+            }
+        }
+
+        impl #impl_generics hloo_core::WordKey for #name #ty_generics #where_clause {
+            fn words(&self) -> &[u64] {
+                &self.#member
+            }
+
+            fn words_mut(&mut self) -> &mut [u64] {
+                &mut self.#member
+            }
+        }
+    })
+}
+
+/// Find the only field of the struct, checking that it is `[u64; N]`.
+fn words_field(input: &DeriveInput) -> syn::Result<(Member, Expr)> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "BitContainer can only be derived for structs",
+            ));
+        }
+    };
+    let field = match fields {
+        Fields::Named(named) if named.named.len() == 1 => &named.named[0],
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => &unnamed.unnamed[0],
+        _ => {
+            return Err(Error::new_spanned(
+                fields,
+                "BitContainer can only be derived for structs with a single `[u64; N]` field",
+            ));
+        }
+    };
+    let len = match &field.ty {
+        Type::Array(array) if matches!(&*array.elem, Type::Path(path) if path.path.is_ident("u64")) => {
+            array.len.clone()
+        }
+        ty => return Err(Error::new_spanned(ty, "expected `[u64; N]`")),
+    };
+    let member = match &field.ident {
+        Some(ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index::from(0)),
+    };
+    Ok((member, len))
+}
@@ -10,15 +10,73 @@ pub struct Bits<'a> {
     word_type_name: &'a Ident,
     word_size: usize,
     n_words: usize,
+    n_bits: usize,
+    zerocopy: bool,
 }
 
 impl<'a> Bits<'a> {
-    pub fn new(type_name: &'a Ident, word_type_name: &'a Ident, word_size: usize, n_words: usize) -> Self {
+    /// Container of `n_bits` bits stored in `n_words` words. If `n_bits` is less than the total size of the words, the
+    /// remaining (least significant) bits of the last word are padding, which is ignored by comparisons and distances.
+    pub fn new(
+        type_name: &'a Ident,
+        word_type_name: &'a Ident,
+        word_size: usize,
+        n_words: usize,
+        n_bits: usize,
+    ) -> Self {
+        assert!(
+            n_bits <= word_size * n_words && n_bits > word_size * n_words.saturating_sub(1),
+            "{n_bits} bits do not fit into {n_words} words of {word_size} bits"
+        );
         Self {
             type_name,
             word_type_name,
             word_size,
             n_words,
+            n_bits,
+            zerocopy: false,
+        }
+    }
+
+    /// Derive `zerocopy` traits, so that the container can be read from and written to byte buffers without copying.
+    pub fn with_zerocopy(self, zerocopy: bool) -> Self {
+        Self { zerocopy, ..self }
+    }
+
+    fn padding(&self) -> usize {
+        self.word_size * self.n_words - self.n_bits
+    }
+
+    /// Statements converting bytes of `raw_data` to words of `data` with `from_bytes` (`from_be_bytes` or
+    /// `from_le_bytes`). Bytes of a partial last word are placed into its most significant bytes.
+    fn words_from_bytes(&self, from_bytes: &Ident) -> proc_macro2::TokenStream {
+        let word_type_name = self.word_type_name;
+        let word_bytes = self.word_size / 8;
+        let full_words = self.n_bits / self.word_size;
+        let word_range = 0..full_words;
+        let full = quote! {
+            #(data[#word_range] = #word_type_name::#from_bytes(
+                raw_data[#word_range*#word_bytes..(#word_range + 1)*#word_bytes]
+                    .try_into()
+                    .expect("slice with incorrect length")
+            ));*;
+        };
+        if self.padding() == 0 {
+            return full;
+        }
+        let start = full_words * word_bytes;
+        let rem_bytes = (self.n_bits - full_words * self.word_size) / 8;
+        let buf_range = if from_bytes == "from_be_bytes" {
+            quote! { ..#rem_bytes }
+        } else {
+            let buf_start = word_bytes - rem_bytes;
+            quote! { #buf_start.. }
+        };
+        quote! {
+            #full
+            let mut last = [0u8; #word_bytes];
+            last[#buf_range].copy_from_slice(&raw_data[#start..]);
+            data[#full_words] = #word_type_name::#from_bytes(last);
         }
     }
 }
@@ -29,14 +87,41 @@ impl ToTokens for Bits<'_> {
         let storage_type_name = format_ident!("{}Data", type_name);
         let word_type_name = self.word_type_name;
         let iterator_name = format_ident!("{}Iterator", type_name);
-        let full_size = self.word_size * self.n_words;
+        let full_size = self.n_bits;
         let byte_size = full_size / 8;
         let word_size = self.word_size;
+        let last_word = self.n_words - 1;
+        // the last word is handled separately, as it may hold padding
+        let word_max = (0..last_word).map(|_| word_type_name.clone());
+        let word_range_xor = 0..last_word;
+        let word_range_ones = 0..last_word;
+        let from_be_words = self.words_from_bytes(&format_ident!("from_be_bytes"));
+        let from_le_words = self.words_from_bytes(&format_ident!("from_le_bytes"));
+        let padding = self.padding();
+        let word_digits = self.word_size / 4;
         let word_bytes = self.word_size / 8;
-        let word_range = 0..self.n_words;
-        let word_range_be = word_range.clone();
-        let word_range_xor = word_range.clone();
-        let word_max = word_range.clone().map(|_| word_type_name.clone());
+        // integers of the same size are converted with their most significant bit as the first bit
+        let int_conversions = [8, 16, 32, 64, 128]
+            .into_iter()
+            .filter(|bits| *bits == self.n_bits)
+            .map(|bits| {
+                let int_type = format_ident!("u{}", bits);
+                quote! {
+                    impl core::convert::From<#int_type> for #type_name {
+                        fn from(value: #int_type) -> Self {
+                            Self::from(value.to_be_bytes())
+                        }
+                    }
+
+                    impl core::convert::From<#type_name> for #int_type {
+                        fn from(value: #type_name) -> Self {
+                            #int_type::from_be_bytes(value.to_be_bytes())
+                        }
+                    }
+                }
+            });
+        let int_conversions = quote! { #(#int_conversions)* };
+        let n_digits = self.n_bits / 4;
 
         let data_type = match TypeArray::from_string(&format!("[{}; {}]", self.word_type_name, self.n_words)) {
             Ok(arr) => Type::Array(arr),
@@ -46,53 +131,359 @@ impl ToTokens for Bits<'_> {
             }
         };
 
+        // padding bits are masked out of the last word wherever they could affect the result
+        let (derives, padding_impls, last_word_mask) = if padding == 0 {
+            (
+                quote! { #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)] },
+                quote! {},
+                quote! { #word_type_name::MAX },
+            )
+        } else {
+            (
+                quote! { #[derive(Clone, Copy, Debug, Default)] },
+                quote! {
+                    impl #type_name {
+                        /// Mask of the bits of the last word which are not padding.
+                        pub const LAST_WORD_MASK: #word_type_name = #word_type_name::MAX << #padding;
+
+                        /// Data with padding bits cleared.
+                        fn significant_data(&self) -> #storage_type_name {
+                            let mut data = self.data;
+                            data[#last_word] &= Self::LAST_WORD_MASK;
+                            data
+                        }
+                    }
+
+                    impl core::cmp::PartialEq for #type_name {
+                        fn eq(&self, other: &Self) -> bool {
+                            self.significant_data() == other.significant_data()
+                        }
+                    }
+
+                    impl core::cmp::Eq for #type_name {}
+
+                    impl core::hash::Hash for #type_name {
+                        fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                            self.significant_data().hash(state);
+                        }
+                    }
+
+                    impl core::cmp::PartialOrd for #type_name {
+                        fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+                            Some(self.cmp(other))
+                        }
+                    }
+
+                    impl core::cmp::Ord for #type_name {
+                        fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+                            self.significant_data().cmp(&other.significant_data())
+                        }
+                    }
+                },
+                quote! { #type_name::LAST_WORD_MASK },
+            )
+        };
+
+        let rand_impls = if cfg!(feature = "rand") {
+            quote! {
+                /// Uniformly random bits. Padding of a partial last word is left zero.
+                impl hloo_core::rand::distr::Distribution<#type_name> for hloo_core::rand::distr::StandardUniform {
+                    fn sample<R: hloo_core::rand::Rng + ?Sized>(&self, rng: &mut R) -> #type_name {
+                        let mut data: #storage_type_name = Default::default();
+                        for word in data.iter_mut() {
+                            *word = rng.random();
+                        }
+                        data[#last_word] &= #last_word_mask;
+                        #type_name::new(data)
+                    }
+                }
+
+                impl #type_name {
+                    /// Copy of these bits with exactly `d` distinct random bits flipped, i.e. at distance `d` from
+                    /// them. Uses the thread-local generator; see `random_within_distance_with`.
+                    ///
+                    /// ## Panics
+                    /// Panics if `d` is greater than `SIZE_BITS`.
+                    pub fn random_within_distance(&self, d: u32) -> Self {
+                        self.random_within_distance_with(d, &mut hloo_core::rand::rng())
+                    }
+
+                    /// Copy of these bits with exactly `d` distinct bits flipped, chosen with `rng`.
+                    ///
+                    /// ## Panics
+                    /// Panics if `d` is greater than `SIZE_BITS`.
+                    pub fn random_within_distance_with<R: hloo_core::rand::Rng + ?Sized>(
+                        &self,
+                        d: u32,
+                        rng: &mut R,
+                    ) -> Self {
+                        assert!(
+                            d as usize <= Self::SIZE_BITS,
+                            "can't flip {} of {} bits",
+                            d,
+                            Self::SIZE_BITS
+                        );
+                        let mut bits = *self;
+                        for idx in hloo_core::rand::seq::index::sample(rng, Self::SIZE_BITS, d as usize) {
+                            let bit = (1 as #word_type_name) << (#word_size - 1 - idx % #word_size);
+                            bits.data[idx / #word_size] ^= bit;
+                        }
+                        bits
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let n_words = self.n_words;
+        let proptest_impls = if cfg!(feature = "proptest") {
+            quote! {
+                /// Uniformly random bits, shrinking towards zero bits. Padding of a partial last word is left zero.
+                impl hloo_core::proptest::arbitrary::Arbitrary for #type_name {
+                    type Parameters = ();
+                    type Strategy = hloo_core::proptest::strategy::BoxedStrategy<Self>;
+
+                    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+                        use hloo_core::proptest::strategy::Strategy;
+
+                        let words = hloo_core::proptest::arbitrary::any::<#word_type_name>();
+                        hloo_core::proptest::array::uniform::<_, #n_words>(words)
+                            .prop_map(|mut data: #storage_type_name| {
+                                data[#last_word] &= #last_word_mask;
+                                #type_name::new(data)
+                            })
+                            .boxed()
+                    }
+                }
+
+                impl #type_name {
+                    /// Strategy generating pairs of arbitrary bits and their copy with at most `d` distinct bits
+                    /// flipped, i.e. within distance `d` of them. Shrinks towards zero bits and fewer flipped bits.
+                    ///
+                    /// ## Panics
+                    /// Panics if `d` is greater than `SIZE_BITS`.
+                    pub fn pair_within_distance(d: u32) -> hloo_core::proptest::strategy::BoxedStrategy<(Self, Self)> {
+                        use hloo_core::proptest::strategy::Strategy;
+
+                        assert!(
+                            d as usize <= Self::SIZE_BITS,
+                            "can't flip {} of {} bits",
+                            d,
+                            Self::SIZE_BITS
+                        );
+                        let indexes = (0..Self::SIZE_BITS).collect::<hloo_core::alloc::vec::Vec<_>>();
+                        let flipped = hloo_core::proptest::sample::subsequence(indexes, 0..=d as usize);
+                        (hloo_core::proptest::arbitrary::any::<Self>(), flipped)
+                            .prop_map(|(bits, flipped)| {
+                                let mut other = bits;
+                                for idx in flipped {
+                                    other.flip_bit(idx);
+                                }
+                                (bits, other)
+                            })
+                            .boxed()
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let arbitrary_impls = if cfg!(feature = "arbitrary") {
+            quote! {
+                /// Bits taken from the raw data as words. Padding of a partial last word is left zero.
+                impl<'a> hloo_core::arbitrary::Arbitrary<'a> for #type_name {
+                    fn arbitrary(u: &mut hloo_core::arbitrary::Unstructured<'a>) -> hloo_core::arbitrary::Result<Self> {
+                        let mut data: #storage_type_name = u.arbitrary()?;
+                        data[#last_word] &= #last_word_mask;
+                        Ok(#type_name::new(data))
+                    }
+
+                    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+                        <#storage_type_name as hloo_core::arbitrary::Arbitrary<'a>>::size_hint(depth)
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let bitvec_impls = if cfg!(feature = "bitvec") {
+            quote! {
+                /// Bit `i` of the slice becomes bit `i` of the key (see `get`), regardless of the storage and ordering
+                /// of the slice. Bits past `SIZE_BITS` are ignored, and missing bits are left zero.
+                impl<T, O> core::convert::From<&hloo_core::bitvec::slice::BitSlice<T, O>> for #type_name
+                where
+                    T: hloo_core::bitvec::store::BitStore,
+                    O: hloo_core::bitvec::order::BitOrder,
+                {
+                    fn from(bits: &hloo_core::bitvec::slice::BitSlice<T, O>) -> Self {
+                        bits.iter().by_vals().collect()
+                    }
+                }
+
+                /// Bit `i` of the key (see `get`) becomes bit `i` of the vector, which is `SIZE_BITS` long.
+                impl<T, O> core::convert::From<#type_name> for hloo_core::bitvec::vec::BitVec<T, O>
+                where
+                    T: hloo_core::bitvec::store::BitStore,
+                    O: hloo_core::bitvec::order::BitOrder,
+                {
+                    fn from(bits: #type_name) -> Self {
+                        (0..#type_name::SIZE_BITS).map(|i| bits.get(i)).collect()
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let serde_impls = if cfg!(feature = "serde") {
+            quote! {
+                /// Serializes the bits as hex digits (see `Display`) in human-readable formats, and as big-endian bytes
+                /// otherwise.
+                impl hloo_core::serde::Serialize for #type_name {
+                    fn serialize<S: hloo_core::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                        if serializer.is_human_readable() {
+                            serializer.collect_str(self)
+                        } else {
+                            serializer.serialize_bytes(&self.to_be_bytes())
+                        }
+                    }
+                }
+
+                impl<'de> hloo_core::serde::Deserialize<'de> for #type_name {
+                    fn deserialize<D: hloo_core::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                        use hloo_core::serde::de::{Error, SeqAccess, Visitor};
+
+                        struct BitsVisitor;
+
+                        impl<'de> Visitor<'de> for BitsVisitor {
+                            type Value = #type_name;
+
+                            fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                                write!(f, "{} hex digits or {} bytes", #n_digits, #byte_size)
+                            }
+
+                            fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+                                v.parse().map_err(E::custom)
+                            }
+
+                            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                                let bytes: [u8; #byte_size] =
+                                    v.try_into().map_err(|_| E::invalid_length(v.len(), &self))?;
+                                Ok(#type_name::from(bytes))
+                            }
+
+                            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                                let mut bytes = [0u8; #byte_size];
+                                for (i, byte) in bytes.iter_mut().enumerate() {
+                                    *byte = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(i, &self))?;
+                                }
+                                if seq.next_element::<u8>()?.is_some() {
+                                    return Err(A::Error::invalid_length(#byte_size + 1, &self));
+                                }
+                                Ok(#type_name::from(bytes))
+                            }
+                        }
+
+                        if deserializer.is_human_readable() {
+                            deserializer.deserialize_str(BitsVisitor)
+                        } else {
+                            deserializer.deserialize_bytes(BitsVisitor)
+                        }
+                    }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        let zerocopy_derives = if self.zerocopy {
+            quote! {
+                #[derive(zerocopy::FromBytes, zerocopy::IntoBytes, zerocopy::Immutable, zerocopy::KnownLayout)]
+            }
+        } else {
+            quote! {}
+        };
+
         let code = quote! {
             pub type #storage_type_name = #data_type;
 
-            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+            #derives
+            #zerocopy_derives
             #[repr(C)]
             pub struct #type_name {
                 pub data: #storage_type_name,
             }
 
+            #padding_impls
+
             impl #type_name {
                 pub const SIZE_BYTES: usize = #byte_size;
                 pub const SIZE_BITS: usize = #full_size;
 
                 pub const MAX: Self = Self {
-                    data: [#( #word_max::MAX ),*]
+                    data: [#( #word_max::MAX, )* #last_word_mask]
                 };
 
+                /// All bits unset.
+                pub const ZERO: Self = Self { data: [0; #n_words] };
+
+                /// All bits set; same as `MAX`.
+                pub const ONES: Self = Self::MAX;
+
                 pub fn new(data: #storage_type_name) -> Self {
                     Self { data }
                 }
 
+                /// Create bits from big-endian bytes, i.e. the first bit is the most significant bit of the first byte.
+                /// See also `From<[u8; SIZE_BYTES]>` and `TryFrom<&[u8]>`, which do not panic.
+                ///
+                /// ## Panics
+                /// Panics if `raw_data` is not `SIZE_BYTES` long.
                 pub fn from_be_bytes(raw_data: &[u8]) -> Self {
                     if (raw_data.len() != #byte_size) {
                         panic!("should have length {}", #byte_size);
                     }
                     let mut data: #storage_type_name = Default::default();
-                    #(data[#word_range] = #word_type_name::from_be_bytes(
-                        raw_data[#word_range*#word_bytes..(#word_range + 1)*#word_bytes]
-                            .try_into()
-                            .expect("slice with incorrect length")
-                    ));*;
+                    #from_be_words
                     Self::new(data)
                 }
 
+                /// Create bits from bytes of little-endian words, i.e. every word is stored with its least significant
+                /// byte first.
+                ///
+                /// ## Panics
+                /// Panics if `raw_data` is not `SIZE_BYTES` long.
                 pub fn from_le_bytes(raw_data: &[u8]) -> Self {
                     if (raw_data.len() != #byte_size) {
                         panic!("should have length {}", #byte_size);
                     }
                     let mut data: #storage_type_name = Default::default();
-                    #(data[#word_range_be] = #word_type_name::from_le_bytes(
-                        raw_data[#word_range_be*#word_bytes..(#word_range_be + 1)*#word_bytes]
-                            .try_into()
-                            .expect("slice with incorrect length")
-                    ));*;
+                    #from_le_words
                     Self::new(data)
                 }
 
+                /// Big-endian bytes of the bits; the inverse of `from_be_bytes`.
+                pub fn to_be_bytes(&self) -> [u8; #byte_size] {
+                    let mut bytes = [0u8; #byte_size];
+                    for (chunk, word) in bytes.chunks_mut(#word_bytes).zip(self.data) {
+                        // a partial last word keeps its bytes in the most significant ones
+                        chunk.copy_from_slice(&word.to_be_bytes()[..chunk.len()]);
+                    }
+                    bytes
+                }
+
+                /// Bytes of little-endian words of the bits; the inverse of `from_le_bytes`.
+                pub fn to_le_bytes(&self) -> [u8; #byte_size] {
+                    let mut bytes = [0u8; #byte_size];
+                    for (chunk, word) in bytes.chunks_mut(#word_bytes).zip(self.data) {
+                        chunk.copy_from_slice(&word.to_le_bytes()[#word_bytes - chunk.len()..]);
+                    }
+                    bytes
+                }
+
                 pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
                     (0..Self::SIZE_BITS).map(|i| self.get(i))
                 }
@@ -100,7 +491,91 @@ impl ToTokens for Bits<'_> {
                 pub fn get(&self, idx: usize) -> bool {
                     let word = idx / #word_size;
                     let bit = (#word_size - 1) - (idx % #word_size);
-                    (self.data[word] & (1 << bit) as #word_type_name) != 0
+                    (self.data[word] & ((1 as #word_type_name) << bit)) != 0
+                }
+
+                /// Number of set bits.
+                pub fn count_ones(&self) -> u32 {
+                    let mut result = 0;
+                    #(result += self.data[#word_range_ones].count_ones();)*
+                    result + (self.data[#last_word] & #last_word_mask).count_ones()
+                }
+
+                /// Whether no bits are set.
+                pub fn is_zero(&self) -> bool {
+                    self.count_ones() == 0
+                }
+
+                /// Number of set bits; same as `count_ones`.
+                pub fn hamming_weight(&self) -> u32 {
+                    self.count_ones()
+                }
+
+                /// Number of unset bits before the first set bit, or `SIZE_BITS` if no bits are set.
+                pub fn leading_zeros(&self) -> u32 {
+                    let mut result = 0;
+                    for (i, word) in self.data.iter().enumerate() {
+                        let word = if i == #last_word { word & #last_word_mask } else { *word };
+                        if word != 0 {
+                            return result + word.leading_zeros();
+                        }
+                        result += #word_size as u32;
+                    }
+                    Self::SIZE_BITS as u32
+                }
+
+                /// Number of unset bits after the last set bit, or `SIZE_BITS` if no bits are set.
+                pub fn trailing_zeros(&self) -> u32 {
+                    let last = self.data[#last_word] & #last_word_mask;
+                    if last != 0 {
+                        return last.trailing_zeros() - #padding as u32;
+                    }
+                    let mut result = (#word_size - #padding) as u32;
+                    for word in self.data[..#last_word].iter().rev() {
+                        if *word != 0 {
+                            return result + word.trailing_zeros();
+                        }
+                        result += #word_size as u32;
+                    }
+                    Self::SIZE_BITS as u32
+                }
+
+                /// Set bit `idx` to `value`.
+                ///
+                /// ## Panics
+                /// Panics if `idx` is not less than `SIZE_BITS`.
+                pub fn set_bit(&mut self, idx: usize, value: bool) {
+                    assert!(idx < Self::SIZE_BITS, "bit {} is out of {} bits", idx, Self::SIZE_BITS);
+                    let bit = (1 as #word_type_name) << ((#word_size - 1) - (idx % #word_size));
+                    if value {
+                        self.data[idx / #word_size] |= bit;
+                    } else {
+                        self.data[idx / #word_size] &= !bit;
+                    }
+                }
+
+                /// Invert bit `idx`.
+                ///
+                /// ## Panics
+                /// Panics if `idx` is not less than `SIZE_BITS`.
+                pub fn flip_bit(&mut self, idx: usize) {
+                    assert!(idx < Self::SIZE_BITS, "bit {} is out of {} bits", idx, Self::SIZE_BITS);
+                    self.data[idx / #word_size] ^= (1 as #word_type_name) << ((#word_size - 1) - (idx % #word_size));
+                }
+
+                /// Bits `range` as an integer, with bit `range.start` as the most significant one.
+                ///
+                /// ## Panics
+                /// Panics if the range is longer than 64 bits or does not fit into `SIZE_BITS`.
+                pub fn extract_range(&self, range: core::ops::Range<usize>) -> u64 {
+                    assert!(
+                        range.start <= range.end && range.end <= Self::SIZE_BITS,
+                        "range {:?} is out of {} bits",
+                        range,
+                        Self::SIZE_BITS
+                    );
+                    assert!(range.len() <= 64, "range {:?} is longer than 64 bits", range);
+                    range.fold(0, |acc, idx| (acc << 1) | self.get(idx) as u64)
                 }
             }
 
@@ -115,7 +590,7 @@ impl ToTokens for Bits<'_> {
                 }
             }
 
-            impl std::iter::Iterator for #iterator_name {
+            impl core::iter::Iterator for #iterator_name {
                 type Item = bool;
 
                 fn next(&mut self) -> Option<Self::Item> {
@@ -129,7 +604,7 @@ impl ToTokens for Bits<'_> {
                 }
             }
 
-            impl std::iter::IntoIterator for #type_name {
+            impl core::iter::IntoIterator for #type_name {
                 type Item = bool;
                 type IntoIter = #iterator_name;
 
@@ -138,7 +613,7 @@ impl ToTokens for Bits<'_> {
                 }
             }
 
-            impl std::iter::IntoIterator for &#type_name {
+            impl core::iter::IntoIterator for &#type_name {
                 type Item = bool;
                 type IntoIter = #iterator_name;
 
@@ -147,8 +622,37 @@ impl ToTokens for Bits<'_> {
                 }
             }
 
-            impl std::iter::FromIterator<bool> for #type_name {
-                fn from_iter<I: std::iter::IntoIterator<Item = bool>>(iter: I) -> Self {
+            /// Big-endian bytes; see `from_be_bytes`.
+            impl core::convert::From<[u8; #byte_size]> for #type_name {
+                fn from(bytes: [u8; #byte_size]) -> Self {
+                    Self::from_be_bytes(&bytes)
+                }
+            }
+
+            /// Big-endian bytes; see `from_be_bytes`. Fails if the slice is not `SIZE_BYTES` long.
+            impl core::convert::TryFrom<&[u8]> for #type_name {
+                type Error = core::array::TryFromSliceError;
+
+                fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                    let bytes: [u8; #byte_size] = bytes.try_into()?;
+                    Ok(Self::from(bytes))
+                }
+            }
+
+            #int_conversions
+
+            #rand_impls
+
+            #proptest_impls
+
+            #arbitrary_impls
+
+            #bitvec_impls
+
+            #serde_impls
+
+            impl core::iter::FromIterator<bool> for #type_name {
+                fn from_iter<I: core::iter::IntoIterator<Item = bool>>(iter: I) -> Self {
                     let mut val = Self::default();
                     for (i, el) in iter.into_iter().enumerate().take(Self::SIZE_BITS) {
                         let word = i / #word_size;
@@ -159,16 +663,58 @@ impl ToTokens for Bits<'_> {
                 }
             }
 
-            impl std::string::ToString for #type_name {
-                fn to_string(&self) -> String {
-                    let mut result = String::with_capacity(#byte_size * 2);
-                    for part in self.data {
-                        result.push_str(&format!("{:016X}", part))
+            impl #type_name {
+                /// Format hex digits of the bits, most significant first. Padding of the last word is omitted.
+                fn fmt_hex(&self, f: &mut core::fmt::Formatter<'_>, upper: bool) -> core::fmt::Result {
+                    let alphabet = if upper { b"0123456789ABCDEF" } else { b"0123456789abcdef" };
+                    let mut digits = [0u8; #n_digits];
+                    for (i, digit) in digits.iter_mut().enumerate() {
+                        let shift = (#word_digits - 1 - i % #word_digits) * 4;
+                        *digit = alphabet[((self.data[i / #word_digits] >> shift) & 0xF) as usize];
                     }
-                    result
+                    f.pad_integral(true, "0x", core::str::from_utf8(&digits).expect("hex digits are ASCII"))
+                }
+            }
+
+            impl core::fmt::UpperHex for #type_name {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    self.fmt_hex(f, true)
+                }
+            }
+
+            impl core::fmt::LowerHex for #type_name {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    self.fmt_hex(f, false)
+                }
+            }
+
+            /// Formats the bits as upper case hex digits, like [`core::fmt::UpperHex`].
+            impl core::fmt::Display for #type_name {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    core::fmt::UpperHex::fmt(self, f)
                 }
             }
 
+            /// Parses exactly `SIZE_BITS / 4` hex digits, optionally prefixed with `0x`.
+            impl core::str::FromStr for #type_name {
+                type Err = hloo_core::ParseBitsError;
+
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    let digits = hloo_core::hex_digits(s, #n_digits)?;
+                    let mut data: #storage_type_name = Default::default();
+                    for (i, chunk) in digits.as_bytes().chunks(#word_digits).enumerate() {
+                        let chunk = core::str::from_utf8(chunk).expect("hex digits are ASCII");
+                        let word = #word_type_name::from_str_radix(chunk, 16).expect("hex digits are checked");
+                        // a partial last word is followed by padding
+                        data[i] = word << ((#word_digits - chunk.len()) * 4);
+                    }
+                    Ok(Self::new(data))
+                }
+            }
+
+            // SAFETY: the struct is `repr(C)` and consists of plain words, so any bit pattern is a valid value
+            unsafe impl Pod for #type_name {}
+
             impl BitContainer for #type_name {
                 type Data = #storage_type_name;
 
@@ -186,7 +732,8 @@ impl ToTokens for Bits<'_> {
 
                 fn xor_dist(&self, other: &Self) -> u32 {
                     let mut result = 0;
-                    #(result += (self.data[#word_range_xor] ^ other.data[#word_range_xor]).count_ones());*;
+                    #(result += (self.data[#word_range_xor] ^ other.data[#word_range_xor]).count_ones();)*
+                    result += ((self.data[#last_word] ^ other.data[#last_word]) & #last_word_mask).count_ones();
                     result
                 }
             }
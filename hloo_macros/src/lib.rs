@@ -1,14 +1,17 @@
+mod bit_container;
 mod bit_op;
 mod bits;
 mod permutation;
+mod simd;
 
 extern crate proc_macro;
 
 use darling::{
     Error, FromMeta,
     export::{NestedMeta, syn::Ident},
+    util::SpannedValue,
 };
-use hloo_core::create_permutations;
+use hloo_core::{BlockSelection, BlockSplitting, create_permutations_with};
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 
@@ -16,11 +19,129 @@ use crate::{bits::Bits, permutation::Permutation};
 
 #[derive(FromMeta)]
 struct PermutationParams {
-    struct_name: Ident,
-    f: usize,
-    r: usize,
-    k: usize,
-    w: Option<usize>,
+    /// Name of the permutations struct; `Permutations`, or `<key_name>Permutations` by default.
+    struct_name: Option<Ident>,
+    /// Name of the key type, `Bits` by default. The mask type is then named `<key_name>Mask` instead of `Mask`.
+    key_name: Option<Ident>,
+    f: SpannedValue<usize>,
+    r: SpannedValue<usize>,
+    k: SpannedValue<usize>,
+    w: Option<SpannedValue<usize>>,
+    /// Derive `zerocopy` traits for `Bits` and `Mask`; requires a dependency on `zerocopy` with the `derive` feature.
+    zerocopy: Option<bool>,
+    /// Implement permutations with `core::simd`, operating on all words at once; requires a nightly compiler and
+    /// `#![feature(portable_simd)]` in the calling crate. Scalar operations are still used for 128-bit words, or
+    /// when there are more words than `core::simd` supports lanes.
+    simd: Option<bool>,
+    /// Which combinations of blocks are moved to the front; all of them by default. See `hloo_core::BlockSelection`.
+    selection: Option<SpannedValue<Selection>>,
+    /// How bits are assigned to blocks; contiguous ranges by default. See `hloo_core::BlockSplitting`.
+    split: Option<SpannedValue<Split>>,
+}
+
+const WORD_SIZES: [usize; 5] = [8, 16, 32, 64, 128];
+
+impl PermutationParams {
+    /// Check that the parameters describe valid permutations, reporting every invalid one at its argument.
+    fn validate(&self) -> darling::Result<()> {
+        let mut errors = Error::accumulator();
+        let (f, r, k) = (*self.f, *self.r, *self.k);
+        if let Some(w) = &self.w
+            && !WORD_SIZES.contains(w)
+        {
+            errors.push(
+                Error::custom(format!("word size {} is not supported; use one of {WORD_SIZES:?}", **w))
+                    .with_span(&w.span()),
+            );
+        }
+        if f == 0 || f % 8 != 0 {
+            let suggestion = f.div_ceil(8).max(1) * 8;
+            errors.push(
+                Error::custom(format!(
+                    "number of bits has to be a positive multiple of 8; try f = {suggestion}"
+                ))
+                .with_span(&self.f.span()),
+            );
+        }
+        if r == 0 || r > f {
+            errors.push(
+                Error::custom(format!(
+                    "{f} bits can't be split into {r} blocks; r has to be between 1 and f"
+                ))
+                .with_span(&self.r.span()),
+            );
+        }
+        if k == 0 || k > r {
+            errors.push(
+                Error::custom(format!("can't choose {k} of {r} blocks; k has to be between 1 and r"))
+                    .with_span(&self.k.span()),
+            );
+        }
+        if let Some(split) = &self.split
+            && let Err(e) = BlockSplitting::from(&**split).validate(f, r)
+        {
+            errors.push(Error::custom(e).with_span(&split.span()));
+        }
+        if let Some(selection) = &self.selection
+            && r <= f
+            && 0 < k
+            && k <= r
+            && let Err(e) = BlockSelection::from(&**selection).validate(r, k)
+        {
+            errors.push(Error::custom(e).with_span(&selection.span()));
+        }
+        errors.finish()
+    }
+}
+
+#[derive(FromMeta)]
+enum Selection {
+    /// `selection(all)`
+    All,
+    /// `selection(random(n = 10, seed = 42))`
+    Random { n: usize, seed: u64 },
+    /// `selection(custom = "0, 1; 2, 3")`: combinations of blocks are separated by semicolons.
+    Custom(BlockOrders),
+}
+
+#[derive(FromMeta)]
+enum Split {
+    /// `split(contiguous)`
+    Contiguous,
+    /// `split(strided(stride = 4))`
+    Strided { stride: usize },
+}
+
+impl From<&Split> for BlockSplitting {
+    fn from(split: &Split) -> Self {
+        match split {
+            Split::Contiguous => Self::Contiguous,
+            Split::Strided { stride } => Self::Strided { stride: *stride },
+        }
+    }
+}
+
+struct BlockOrders(Vec<Vec<usize>>);
+
+impl FromMeta for BlockOrders {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        value
+            .split(';')
+            .map(|order| order.split(',').map(|block| block.trim().parse()).collect())
+            .collect::<Result<_, _>>()
+            .map(Self)
+            .map_err(|e| Error::custom(format!("invalid block index: {e}")))
+    }
+}
+
+impl From<&Selection> for BlockSelection {
+    fn from(selection: &Selection) -> Self {
+        match selection {
+            Selection::All => Self::AllCombinations,
+            Selection::Random { n, seed } => Self::RandomSubset { n: *n, seed: *seed },
+            Selection::Custom(BlockOrders(orders)) => Self::Custom(orders.clone()),
+        }
+    }
 }
 
 #[proc_macro]
@@ -38,44 +159,72 @@ pub fn make_permutations(item: TokenStream) -> TokenStream {
         }
     };
 
-    let word_bits = params.w.unwrap_or(64);
-    assert!(
-        [8, 16, 32, 64].contains(&word_bits),
-        "word size {word_bits} is not supported"
-    );
-    let n_words = params.f / word_bits;
-    assert!(params.f % word_bits == 0 && n_words > 0);
-
-    let struct_name = params.struct_name;
-    let data_type_name = format_ident!("Bits");
-    let mask_type_name = format_ident!("Mask");
+    if let Err(e) = params.validate() {
+        return TokenStream::from(e.write_errors());
+    }
+
+    let (f, r, k) = (*params.f, *params.r, *params.k);
+    let word_bits = params.w.map_or(64, |w| *w);
+    // if f is not divisible by the word size, the last word is padded
+    let n_words = f.div_ceil(word_bits);
+
+    let (data_type_name, mask_type_name) = match &params.key_name {
+        Some(key_name) => (key_name.clone(), format_ident!("{}Mask", key_name)),
+        None => (format_ident!("Bits"), format_ident!("Mask")),
+    };
+    let struct_name = params.struct_name.unwrap_or_else(|| match &params.key_name {
+        Some(key_name) => format_ident!("{}Permutations", key_name),
+        None => format_ident!("Permutations"),
+    });
     let word_type_name = format_ident!("u{}", word_bits);
 
-    let perms = create_permutations(params.f, word_bits, params.r, params.k);
+    let selection = params
+        .selection
+        .as_deref()
+        .map(BlockSelection::from)
+        .unwrap_or_default();
+    let split = params.split.as_deref().map(BlockSplitting::from).unwrap_or_default();
+    let perms = create_permutations_with(f, word_bits, r, k, &selection, split);
 
-    let bits_definition = Bits::new(&data_type_name, &word_type_name, word_bits, n_words);
+    let zerocopy = params.zerocopy.unwrap_or(false);
+    let bits_definition = Bits::new(&data_type_name, &word_type_name, word_bits, n_words, f).with_zerocopy(zerocopy);
 
     let mask_size = perms.iter().map(|p| p.mask_words(word_bits)).max().unwrap_or(0);
-    let mask_definition = Bits::new(&mask_type_name, &word_type_name, word_bits, mask_size);
+    let mask_definition = Bits::new(
+        &mask_type_name,
+        &word_type_name,
+        word_bits,
+        mask_size,
+        mask_size * word_bits,
+    )
+    .with_zerocopy(zerocopy);
 
+    let simd = params.simd.unwrap_or(false);
     let perms_definitions = perms
         .into_iter()
         .enumerate()
         .map(|(i, perm)| {
-            Permutation::new(
+            let permutation = Permutation::new(
                 perm,
                 format_ident!("{}{}", struct_name, i),
                 &data_type_name,
                 &mask_type_name,
                 &word_type_name,
                 word_bits,
-            )
+            );
+            if simd {
+                permutation.with_simd(n_words, mask_size)
+            } else {
+                permutation
+            }
         })
         .collect::<Vec<_>>();
 
     let variants_range = 0..perms_definitions.len();
     let variants = perms_definitions.iter().map(|p| p.struct_name.clone());
     let all_variants_range = variants_range.clone();
+    // generated code may be `no_std`, so allocations are made through `hloo_core`
+    let boxed_permuter = quote! { hloo_core::alloc::boxed::Box<dyn BitPermuter<#data_type_name, #mask_type_name>> };
 
     quote! {
         #bits_definition
@@ -85,21 +234,63 @@ pub fn make_permutations(item: TokenStream) -> TokenStream {
         pub struct #struct_name;
 
         impl #struct_name {
-            pub fn get_variant(variant: usize) -> Box<dyn BitPermuter<#data_type_name, #mask_type_name>> {
+            pub fn get_variant(variant: usize) -> #boxed_permuter {
                 match variant {
-                    #( #variants_range => Box::new(#variants {}) as Box<dyn BitPermuter<#data_type_name, #mask_type_name>> ),*,
+                    #( #variants_range => hloo_core::alloc::boxed::Box::new(#variants {}) as #boxed_permuter ),*,
                     i => panic!("permutation variant out of range: {}", i),
                 }
             }
 
-            pub fn get_all_variants() -> Vec<Box<dyn BitPermuter<#data_type_name, #mask_type_name>>> {
-                vec![
+            pub fn get_all_variants() -> hloo_core::alloc::vec::Vec<#boxed_permuter> {
+                hloo_core::alloc::vec![
                     #( Self::get_variant(#all_variants_range) ),*
                 ]
             }
+
+            /// Check that every variant behaves as a permutation of `r` blocks (see `hloo_core::verify_permuter`) on
+            /// keys with a single bit set and on `n_samples` pseudo-random keys. On failure, returns the index of the
+            /// first inconsistent variant along with the error.
+            pub fn verify(n_samples: usize) -> Result<(), (usize, hloo_core::VerifyError)> {
+                let mut samples = hloo_core::alloc::vec::Vec::with_capacity(#data_type_name::SIZE_BITS + n_samples);
+                for i in 0..#data_type_name::SIZE_BITS {
+                    let mut key = #data_type_name::default();
+                    key.set_bit(i, true);
+                    samples.push(key);
+                }
+                let mut bytes = [0u8; #data_type_name::SIZE_BYTES];
+                for seed in 0..n_samples as u64 {
+                    hloo_core::sample_bytes(seed, &mut bytes);
+                    samples.push(#data_type_name::from_be_bytes(&bytes));
+                }
+                for (variant, permuter) in Self::get_all_variants().iter().enumerate() {
+                    hloo_core::verify_permuter(permuter.as_ref(), &samples, #r as u32).map_err(|e| (variant, e))?;
+                }
+                Ok(())
+            }
+        }
+
+        impl hloo_core::PermutedKey for #data_type_name {
+            type Mask = #mask_type_name;
+
+            const PARAMS: [usize; 4] = [#f, #r, #k, #word_bits];
+
+            fn permuters() -> hloo_core::alloc::vec::Vec<#boxed_permuter> {
+                #struct_name::get_all_variants()
+            }
         }
 
         #(#perms_definitions)*
     }
     .into()
 }
+
+/// Implement `hloo_core::BitContainer` for a struct wrapping `[u64; N]`, e.g. a hash type of another crate, along with
+/// `hloo_core::WordKey`, so that `hloo_core::DynPermuter` can permute it directly. Bits are enumerated from the most
+/// significant bit of the first word, like in containers generated by `make_permutations!`.
+#[proc_macro_derive(BitContainer)]
+pub fn derive_bit_container(item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::DeriveInput);
+    bit_container::derive(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
@@ -1,7 +1,7 @@
 use darling::{export::syn::Ident, ToTokens};
 use quote::quote;
 
-use crate::bit_op::BitOp;
+use crate::{bit_op::BitOp, simd::SimdOps};
 
 pub struct Permutation<'a> {
     pub perm: hloo_core::Permutation,
@@ -10,6 +10,7 @@ pub struct Permutation<'a> {
     mask_type_name: &'a Ident,
     word_type_name: &'a Ident,
     word_size: usize,
+    simd_words: Option<(usize, usize)>,
 }
 
 impl<'a> Permutation<'a> {
@@ -28,86 +29,193 @@ impl<'a> Permutation<'a> {
             mask_type_name,
             word_type_name,
             word_size,
+            simd_words: None,
+        }
+    }
+
+    /// Use `core::simd` for `n_words` words of data and `n_mask_words` words of mask, where possible.
+    pub fn with_simd(mut self, n_words: usize, n_mask_words: usize) -> Self {
+        self.simd_words = Some((n_words, n_mask_words));
+        self
+    }
+
+    /// Documentation of the permuter, describing where blocks are moved.
+    fn layout_doc(&self) -> String {
+        let blocks = self.perm.blocks();
+        let mask_bits = self.perm.mask_bits();
+        let order = |blocks: &[hloo_core::PermutedBitBlock]| {
+            let mut order = blocks.iter().map(|b| b.block.idx().to_string()).collect::<Vec<_>>();
+            // ranges of the same block are placed one after another
+            order.dedup();
+            order.join(", ")
+        };
+        let n_head = blocks.iter().take_while(|b| b.new_pos < mask_bits).count();
+        let mut doc = format!(
+            "Bit permutation with block order {}. Masks keep the first {mask_bits} bits (blocks {}).\n\n\
+             | block | original bits | permuted bits | in mask |\n\
+             |-------|---------------|---------------|---------|\n",
+            order(blocks),
+            order(&blocks[..n_head])
+        );
+        for b in blocks {
+            let (start, end) = (b.block.start_pos(), b.block.end_pos() + 1);
+            let new_end = b.new_pos + b.block.len();
+            let in_mask = if b.new_pos < mask_bits { "yes" } else { "no" };
+            doc += &format!(
+                "| {} | {start}..{end} | {}..{new_end} | {in_mask} |\n",
+                b.block.idx(),
+                b.new_pos
+            );
+        }
+        doc
+    }
+
+    fn static_fn_body(&self, ops: Vec<hloo_core::BitOp>, is_mask: bool) -> proc_macro2::TokenStream {
+        if let Some((n_words, n_mask_words)) = self.simd_words {
+            let n_out_words = if is_mask { n_mask_words } else { n_words };
+            if let Some(simd_ops) = SimdOps::new(&ops, self.word_type_name, self.word_size, n_words, n_out_words) {
+                return quote! { #simd_ops };
+            }
+        }
+        let ops = ops.into_iter().map(|op| BitOp::new(op, self.word_type_name));
+        quote! {
+            let (inp, mut out) = (w.data(), nw.data_mut());
+            #(#ops);*;
         }
     }
 }
 
 impl ToTokens for Permutation<'_> {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-        let apply_ops = self
-            .perm
-            .compile_apply(self.word_size, true)
-            .into_iter()
-            .flat_map(|(_, ops)| ops)
-            .map(|op| BitOp::new(op, self.word_type_name))
-            .collect::<Vec<_>>();
-
-        let revert_ops = self
-            .perm
-            .compile_revert(self.word_size, true)
-            .into_iter()
-            .flat_map(|(_, ops)| ops)
-            .map(|op| BitOp::new(op, self.word_type_name))
-            .collect::<Vec<_>>();
-
-        let mask_ops = self
-            .perm
-            .compile_top_mask(self.word_size, true)
-            .into_iter()
-            .flat_map(|(_, ops)| ops)
-            .map(|op| BitOp::new(op, self.word_type_name))
-            .collect::<Vec<_>>();
+        let apply_body = self.static_fn_body(
+            self.perm
+                .compile_apply(self.word_size, true)
+                .into_iter()
+                .flat_map(|(_, ops)| ops)
+                .collect(),
+            false,
+        );
+        let revert_body = self.static_fn_body(
+            self.perm
+                .compile_revert(self.word_size, true)
+                .into_iter()
+                .flat_map(|(_, ops)| ops)
+                .collect(),
+            false,
+        );
+        let mask_body = self.static_fn_body(
+            self.perm
+                .compile_top_mask(self.word_size, true)
+                .into_iter()
+                .flat_map(|(_, ops)| ops)
+                .collect(),
+            true,
+        );
 
         let struct_name = &self.struct_name;
         let data_type_name = self.data_type_name;
         let mask_type_name = self.mask_type_name;
-        let n_blocks = self.perm.blocks().len();
+        let static_permuter = quote! { <Self as hloo_core::StaticBitPermuter<#data_type_name, #mask_type_name>> };
+        let n_blocks = self.perm.n_blocks();
+        let n_ranges = self.perm.blocks().len();
+        let n_bits: usize = self.perm.blocks().iter().map(|b| b.block.len()).sum();
+        let mask_bits = self.perm.mask_bits();
+        let block_ranges = self.perm.blocks().iter().map(|b| {
+            let (start, end) = (b.block.start_pos(), b.block.end_pos() + 1);
+            quote! { #start..#end }
+        });
+
+        let doc = self.layout_doc();
 
         let code = quote! {
+            #[doc = #doc]
             #[derive(Clone, Copy)]
             pub struct #struct_name;
 
-            impl BitPermuter<#data_type_name, #mask_type_name> for #struct_name {
+            impl #struct_name {
+                /// Blocks of the key in the order they are placed by this permutation, as ranges of bits of the
+                /// original key. Blocks are placed one after another, starting from bit 0. Blocks which are not
+                /// contiguous (see `hloo_core::BlockSplitting`) are listed as several consecutive ranges.
+                pub const BLOCKS: [core::ops::Range<usize>; #n_ranges] = [#(#block_ranges),*];
+
+                /// Number of bits in the mask, i.e. in the blocks which are compared exactly during a search.
+                pub const MASK_BITS: usize = #mask_bits;
+
+                /// Mapping of bits of the original key to their positions in the permuted key: bit `i` is moved to
+                /// bit `bit_mapping()[i]`.
+                pub fn bit_mapping() -> [usize; #n_bits] {
+                    let mut mapping = [0; #n_bits];
+                    for (dst, src) in Self::BLOCKS.into_iter().flatten().enumerate() {
+                        mapping[src] = dst;
+                    }
+                    mapping
+                }
+            }
+
+            impl hloo_core::StaticBitPermuter<#data_type_name, #mask_type_name> for #struct_name {
                 fn apply_static(w: &#data_type_name) -> #data_type_name {
                     let mut nw: #data_type_name = Default::default();
-                    let (inp, mut out) = (w.data(), nw.data_mut());
-                    #(#apply_ops);*;
+                    #apply_body
                     nw
                 }
 
                 fn revert_static(w: &#data_type_name) -> #data_type_name {
                     let mut nw: #data_type_name = Default::default();
-                    let (inp, mut out) = (w.data(), nw.data_mut());
-                    #(#revert_ops);*;
+                    #revert_body
                     nw
                 }
 
                 fn mask_static(w: &#data_type_name) -> #mask_type_name {
                     let mut nw: #mask_type_name = Default::default();
-                    let (inp, mut out) = (w.data(), nw.data_mut());
-                    #(#mask_ops);*;
+                    #mask_body
                     nw
                 }
+            }
 
+            impl BitPermuter<#data_type_name, #mask_type_name> for #struct_name {
                 fn apply(&self, w: &#data_type_name) -> #data_type_name {
-                    Self::apply_static(w)
+                    #static_permuter::apply_static(w)
+                }
+
+                fn apply_inplace(&self, w: &mut #data_type_name) {
+                    *w = #static_permuter::apply_static(w);
+                }
+
+                fn apply_many(&self, ws: &[#data_type_name]) -> hloo_core::alloc::vec::Vec<#data_type_name> {
+                    let mut permuted = hloo_core::alloc::vec::Vec::with_capacity(ws.len());
+                    // independent keys are permuted together, so that their operations can be interleaved
+                    let mut chunks = ws.chunks_exact(4);
+                    for chunk in &mut chunks {
+                        permuted.extend_from_slice(&[
+                            #static_permuter::apply_static(&chunk[0]),
+                            #static_permuter::apply_static(&chunk[1]),
+                            #static_permuter::apply_static(&chunk[2]),
+                            #static_permuter::apply_static(&chunk[3]),
+                        ]);
+                    }
+                    permuted.extend(chunks.remainder().iter().map(#static_permuter::apply_static));
+                    permuted
                 }
 
                 fn revert(&self, w: &#data_type_name) -> #data_type_name {
-                    Self::revert_static(w)
+                    #static_permuter::revert_static(w)
                 }
 
                 fn mask(&self, w: &#data_type_name) -> #mask_type_name {
-                    Self::mask_static(w)
+                    #static_permuter::mask_static(w)
                 }
 
-                fn mask_and_cmp(&self, w: &#data_type_name, other_mask: &#mask_type_name) -> std::cmp::Ordering {
-                    Self::mask_static(w).cmp(other_mask)
+                fn mask_and_cmp(&self, w: &#data_type_name, other_mask: &#mask_type_name) -> core::cmp::Ordering {
+                    #static_permuter::mask_static(w).cmp(other_mask)
                 }
 
                 fn n_blocks(&self) -> u32 {
                     #n_blocks as u32
                 }
+
+                fn mask_bits(&self) -> u32 {
+                    Self::MASK_BITS as u32
+                }
             }
         };
         tokens.extend(code);
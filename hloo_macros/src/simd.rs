@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use darling::{ToTokens, export::syn::Ident};
+use quote::quote;
+
+/// Largest number of lanes supported by `core::simd`.
+const MAX_LANES: usize = 64;
+
+/// Bit operations of a permutation, applied to all words at once with `core::simd`.
+///
+/// Operations which move bits by the same number of words and shift them by the same number of bits are merged into
+/// one: the input vector is masked with per-lane masks, shifted, and the lanes are rotated into their destination.
+pub struct SimdOps<'a> {
+    groups: Vec<SimdOpGroup>,
+    word_type_name: &'a Ident,
+    n_lanes: usize,
+    n_out_words: usize,
+}
+
+struct SimdOpGroup {
+    lane_shift: i64,
+    bit_shift: i64,
+    masks: Vec<u128>,
+}
+
+impl<'a> SimdOps<'a> {
+    /// Create SIMD operations, or `None` if containers of `n_words` words of `word_size` bits can't be represented
+    /// as `core::simd` vectors, in which case the scalar operations should be used.
+    pub fn new(
+        ops: &[hloo_core::BitOp],
+        word_type_name: &'a Ident,
+        word_size: usize,
+        n_words: usize,
+        n_out_words: usize,
+    ) -> Option<Self> {
+        let n_lanes = n_words.next_power_of_two();
+        if word_size > 64 || n_lanes > MAX_LANES {
+            return None;
+        }
+        let word_mask = u128::MAX >> (128 - word_size);
+        let mut groups: BTreeMap<(i64, i64), Vec<u128>> = BTreeMap::new();
+        for op in ops {
+            let lane_shift = op.dst_word() as i64 - op.src_word() as i64;
+            let masks = groups
+                .entry((lane_shift, op.shift()))
+                .or_insert_with(|| vec![0; n_lanes]);
+            masks[op.src_word()] |= op.mask() & word_mask;
+        }
+        let groups = groups
+            .into_iter()
+            .map(|((lane_shift, bit_shift), masks)| SimdOpGroup {
+                lane_shift,
+                bit_shift,
+                masks,
+            })
+            .collect();
+        Some(Self {
+            groups,
+            word_type_name,
+            n_lanes,
+            n_out_words,
+        })
+    }
+}
+
+impl ToTokens for SimdOps<'_> {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let word_type_name = self.word_type_name;
+        let n_lanes = self.n_lanes;
+        let n_out_words = self.n_out_words;
+        let group_ops = self.groups.iter().map(|group| {
+            let masks = &group.masks;
+            let mut value = quote! { (inp & ::core::simd::Simd::from_array([#(#masks as #word_type_name),*])) };
+            let bit_shift = group.bit_shift.unsigned_abs() as usize;
+            if group.bit_shift > 0 {
+                value = quote! { (#value << ::core::simd::Simd::splat(#bit_shift as #word_type_name)) };
+            } else if group.bit_shift < 0 {
+                value = quote! { (#value >> ::core::simd::Simd::splat(#bit_shift as #word_type_name)) };
+            }
+            let lane_shift = group.lane_shift.unsigned_abs() as usize;
+            if group.lane_shift > 0 {
+                value = quote! { #value.rotate_elements_right::<#lane_shift>() };
+            } else if group.lane_shift < 0 {
+                value = quote! { #value.rotate_elements_left::<#lane_shift>() };
+            }
+            quote! { out |= #value }
+        });
+        tokens.extend(quote! {
+            let inp = ::core::simd::Simd::<#word_type_name, #n_lanes>::load_or_default(w.data());
+            let mut out = ::core::simd::Simd::<#word_type_name, #n_lanes>::splat(0);
+            #(#group_ops;)*
+            nw.data_mut().copy_from_slice(&out.as_array()[..#n_out_words]);
+        });
+    }
+}
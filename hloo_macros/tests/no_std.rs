@@ -0,0 +1,19 @@
+//! Code generated by `make_permutations!` only needs `core` and `alloc`.
+#![no_std]
+
+use hloo_core::{BitContainer, BitPermuter, Pod};
+use hloo_macros::make_permutations;
+
+make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 2, w = 32);
+
+#[test]
+fn generated_code_does_not_need_std() {
+    let bits = Bits::new([0x01020304, 0x05060708, 0x090A0B0C]);
+    let other = Bits::from_be_bytes(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0D]);
+    for permuter in Permutations::get_all_variants() {
+        let permuted = permuter.apply(&bits);
+        assert_eq!(permuter.revert(&permuted), bits);
+        assert_eq!(permuted.xor_dist(&permuter.apply(&other)), 1);
+    }
+    assert_eq!("0102030405060708090A0B0C".parse::<Bits>(), Ok(bits));
+}
@@ -0,0 +1,52 @@
+//! Tests of permutations generated with `simd = true`, which require a nightly compiler:
+//! `cargo +nightly test -p hloo_macros --features nightly`.
+#![cfg(feature = "nightly")]
+#![feature(portable_simd)]
+
+use rand::random;
+
+use hloo_core::BitContainer;
+
+macro_rules! simd_matches_scalar {
+    ($test_name:ident, $f:literal, $r:literal, $k:literal, $w:literal) => {
+        #[test]
+        fn $test_name() {
+            mod scalar {
+                use hloo_core::{BitContainer, BitPermuter, Pod};
+                hloo_macros::make_permutations!(struct_name = "Permutations", f = $f, r = $r, k = $k, w = $w);
+            }
+            mod simd {
+                use hloo_core::{BitContainer, BitPermuter, Pod};
+                hloo_macros::make_permutations!(
+                    struct_name = "Permutations",
+                    f = $f,
+                    r = $r,
+                    k = $k,
+                    w = $w,
+                    simd = true
+                );
+            }
+            let scalar_perms = scalar::Permutations::get_all_variants();
+            let simd_perms = simd::Permutations::get_all_variants();
+            for _ in 0..100 {
+                let scalar_bits = scalar::Bits::from_be_bytes(&random::<[u8; $f / 8]>());
+                let simd_bits = simd::Bits::new(*scalar_bits.data());
+                for (scalar_perm, simd_perm) in scalar_perms.iter().zip(simd_perms.iter()) {
+                    let applied = simd_perm.apply(&simd_bits);
+                    assert_eq!(applied.data(), scalar_perm.apply(&scalar_bits).data());
+                    assert_eq!(simd_perm.revert(&applied), simd_bits);
+                    assert_eq!(
+                        simd_perm.mask(&simd_bits).data(),
+                        scalar_perm.mask(&scalar_bits).data()
+                    );
+                }
+            }
+        }
+    };
+}
+
+simd_matches_scalar!(simd_matches_scalar_u64, 256, 8, 2, 64);
+simd_matches_scalar!(simd_matches_scalar_padded_u32, 96, 5, 2, 32);
+simd_matches_scalar!(simd_matches_scalar_u8, 64, 7, 3, 8);
+simd_matches_scalar!(simd_matches_scalar_single_word, 64, 4, 1, 64);
+simd_matches_scalar!(simd_falls_back_for_u128, 256, 5, 2, 128);
@@ -1,6 +1,6 @@
 use rand::random;
 
-use hloo_core::{BitContainer, BitPermuter};
+use hloo_core::{BitContainer, BitPermuter, Pod, StaticBitPermuter};
 use hloo_macros::make_permutations;
 
 #[test]
@@ -134,6 +134,21 @@ fn apply_then_revert_is_identity() {
     }
 }
 
+#[test]
+fn apply_inplace_and_apply_many_match_apply() {
+    make_permutations!(struct_name = "Permutations", f = 64, r = 5, k = 2, w = 32);
+
+    // 7 keys are permuted in a batch of 4 and 3 separately
+    let keys: Vec<_> = (0..7).map(|_| Bits::new(random())).collect();
+    for (i, perm) in Permutations::get_all_variants().iter().enumerate() {
+        let expected: Vec<_> = keys.iter().map(|key| perm.apply(key)).collect();
+        assert_eq!(perm.apply_many(&keys), expected, "permutation {}: apply_many", i);
+        let mut key = keys[0];
+        perm.apply_inplace(&mut key);
+        assert_eq!(key, expected[0], "permutation {}: apply_inplace", i);
+    }
+}
+
 #[test]
 fn mask_works_correctly() {
     make_permutations!(struct_name = "Permutations", f = 64, r = 5, k = 2, w = 32);
@@ -193,3 +208,484 @@ fn iter_works_correctly() {
     let reconstructed = Bits::from_iter(res.into_iter().map(|(_, v)| v));
     assert_eq!(reconstructed, bits, "bits.from_iter is unable to reconstruct bits");
 }
+
+#[test]
+fn dyn_permuter_matches_generated_permutations() {
+    make_permutations!(struct_name = "Permutations", f = 64, r = 5, k = 2, w = 32);
+    let to_dyn = |bits: &[u32]| {
+        let bytes: Vec<u8> = bits.iter().flat_map(|word| word.to_be_bytes()).collect();
+        hloo_core::DynBitVec::from_be_bytes(&bytes)
+    };
+
+    let bits = Bits::new(random());
+    let dyn_perms = hloo_core::DynPermuter::create_all(64, 5, 2, 32).unwrap();
+    let perms = Permutations::get_all_variants();
+    assert_eq!(dyn_perms.len(), perms.len());
+    for (i, (perm, dyn_perm)) in perms.iter().zip(&dyn_perms).enumerate() {
+        let permuted = perm.apply(&bits);
+        let dyn_permuted = dyn_perm.apply(&to_dyn(&bits.data));
+        assert_eq!(dyn_permuted, to_dyn(&permuted.data), "permutation {}: apply differs", i);
+        assert_eq!(
+            dyn_perm.revert(&dyn_permuted),
+            to_dyn(&bits.data),
+            "permutation {}: revert differs",
+            i
+        );
+        assert_eq!(
+            dyn_perm.mask(&dyn_permuted),
+            to_dyn(&perm.mask(&permuted).data),
+            "permutation {}: mask differs",
+            i
+        );
+    }
+}
+
+#[test]
+fn dyn_permuter_permutes_derived_containers() {
+    #[derive(hloo_macros::BitContainer, Clone, Copy, Debug, Default, PartialEq)]
+    struct Hash([u64; 2]);
+
+    #[derive(hloo_macros::BitContainer, Default)]
+    struct NamedHash {
+        words: [u64; 2],
+    }
+
+    let hash = Hash(random());
+    let bytes: Vec<u8> = hash.0.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let bits = hloo_core::DynBitVec::from_be_bytes(&bytes);
+    assert_eq!((0..128).map(|i| hash.bit(i)).collect::<hloo_core::DynBitVec>(), bits);
+    assert_eq!(
+        hash.xor_dist(&Hash::default()),
+        bits.xor_dist(&hloo_core::DynBitVec::zeros(128))
+    );
+    assert_eq!(NamedHash { words: hash.0 }.data(), &hash.0);
+
+    for perm in hloo_core::DynPermuter::create_all(128, 5, 2, 32).unwrap() {
+        let permuted = perm.apply(&hash);
+        let dyn_permuted = perm.apply(&bits);
+        assert_eq!(permuted.0, dyn_permuted.data()[..2]);
+        assert_eq!(perm.revert(&permuted), hash);
+        assert_eq!(perm.mask(&permuted), perm.mask(&dyn_permuted));
+    }
+}
+
+#[test]
+fn padding_of_last_word_is_ignored() {
+    // 96 bits take one and a half 64-bit words
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 2, w = 64);
+    assert_eq!((Bits::SIZE_BITS, Bits::SIZE_BYTES), (96, 12));
+
+    let bytes: Vec<u8> = (1..=12).collect();
+    let bits = Bits::from_be_bytes(&bytes);
+    assert_eq!(bits.data, [0x0102030405060708, 0x090A0B0C00000000]);
+    assert_eq!(
+        Bits::from_le_bytes(&bytes).data,
+        [0x0807060504030201, 0x0C0B0A0900000000]
+    );
+    assert_eq!(bits.iter().count(), 96);
+    assert_eq!(
+        Bits::from_iter(bits.iter()),
+        bits,
+        "bits.from_iter is unable to reconstruct bits"
+    );
+
+    let mut padded = bits;
+    padded.data[1] |= 0xFFFF;
+    assert_eq!(padded, bits, "padding is compared");
+    assert_eq!(padded.cmp(&bits), std::cmp::Ordering::Equal, "padding is ordered");
+    assert_eq!(padded.xor_dist(&bits), 0, "padding is counted in distance");
+    assert_eq!(Bits::MAX.xor_dist(&Bits::default()), 96);
+
+    for (i, perm) in Permutations::get_all_variants().iter().enumerate() {
+        let permuted = perm.apply(&padded);
+        assert_eq!(
+            permuted.data[1] & 0xFFFFFFFF,
+            0,
+            "permutation {}: padding is permuted",
+            i
+        );
+        assert_eq!(
+            perm.revert(&permuted).data,
+            bits.data,
+            "permutation {}: failed apply-revert test!",
+            i
+        );
+    }
+}
+
+#[test]
+fn partial_last_word_is_read_from_bytes() {
+    make_permutations!(struct_name = "Permutations", f = 40, r = 4, k = 1, w = 32);
+    let bytes = [0x01, 0x02, 0x03, 0x04, 0x05];
+    assert_eq!(Bits::from_be_bytes(&bytes).data, [0x01020304, 0x05000000]);
+    assert_eq!(Bits::from_le_bytes(&bytes).data, [0x04030201, 0x05000000]);
+    assert_eq!(Bits::MAX.data, [u32::MAX, 0xFF000000]);
+}
+
+#[test]
+fn u128_words_permute_like_u64_words() {
+    mod words64 {
+        use super::*;
+        make_permutations!(struct_name = "Permutations", f = 256, r = 8, k = 2, w = 64);
+    }
+    mod words128 {
+        use super::*;
+        make_permutations!(struct_name = "Permutations", f = 256, r = 8, k = 2, w = 128);
+    }
+
+    let bytes: [u8; 32] = random();
+    let bits64 = words64::Bits::from_be_bytes(&bytes);
+    let bits128 = words128::Bits::from_be_bytes(&bytes);
+    assert_eq!(bits128.data.len(), 2);
+    assert_eq!(
+        bits128.xor_dist(&words128::Bits::default()),
+        bits64.xor_dist(&words64::Bits::default())
+    );
+
+    let perms64 = words64::Permutations::get_all_variants();
+    let perms128 = words128::Permutations::get_all_variants();
+    assert_eq!(perms128.len(), 28);
+    for (i, (perm64, perm128)) in perms64.iter().zip(&perms128).enumerate() {
+        let permuted64 = perm64.apply(&bits64);
+        let permuted128 = perm128.apply(&bits128);
+        assert!(permuted64.iter().eq(permuted128.iter()), "permutation {}: apply differs", i);
+        assert!(
+            perm64
+                .mask(&bits64)
+                .iter()
+                .eq(perm128.mask(&bits128).iter().take(words64::Mask::SIZE_BITS)),
+            "permutation {}: mask differs",
+            i
+        );
+        assert_eq!(
+            perm128.revert(&permuted128),
+            bits128,
+            "permutation {}: failed apply-revert test!",
+            i
+        );
+    }
+}
+
+#[test]
+fn zerocopy_traits_can_be_derived() {
+    use zerocopy::{FromBytes, IntoBytes};
+
+    make_permutations!(
+        struct_name = "Permutations",
+        f = 128,
+        r = 4,
+        k = 1,
+        w = 64,
+        zerocopy = true
+    );
+    let words = [0x0102030405060708u64, 0x090A0B0C0D0E0F10];
+    // a view of the words, so that the buffer is aligned
+    let buf = words.as_bytes();
+
+    let (bits, rest) = Bits::read_from_prefix(buf).expect("buffer is large enough");
+    assert!(rest.is_empty());
+    assert_eq!(bits.data, words);
+    assert_eq!(bits.as_bytes(), buf);
+
+    let keys = <[Bits]>::ref_from_bytes(buf).expect("buffer holds whole keys");
+    assert_eq!(keys, &[bits]);
+    let mask = Permutations::get_variant(0).mask(&bits);
+    assert_eq!(mask.as_bytes().len(), Mask::SIZE_BYTES);
+}
+
+#[test]
+fn bits_are_formatted_and_parsed_as_hex() {
+    make_permutations!(struct_name = "Permutations", f = 64, r = 5, k = 1, w = 32);
+    let bits = Bits::new([0x0123ABCD, 0x00000EF0]);
+    assert_eq!(bits.to_string(), "0123ABCD00000EF0");
+    assert_eq!(format!("{:x}", bits), "0123abcd00000ef0");
+    assert_eq!(format!("{:#X}", bits), "0x0123ABCD00000EF0");
+    assert_eq!("0123abcd00000ef0".parse::<Bits>(), Ok(bits));
+    assert_eq!("0x0123ABCD00000EF0".parse::<Bits>(), Ok(bits));
+    assert_eq!(
+        "0123ABCD".parse::<Bits>(),
+        Err(hloo_core::ParseBitsError::InvalidLength {
+            expected: 16,
+            actual: 8
+        })
+    );
+    assert_eq!(
+        "0123ABCD00000EFG".parse::<Bits>(),
+        Err(hloo_core::ParseBitsError::InvalidDigit { pos: 15 })
+    );
+}
+
+#[test]
+fn padded_bits_are_formatted_and_parsed_as_hex() {
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 1, w = 64);
+    let bits = Bits::new([0x0102030405060708, 0x090A0B0C00000000]);
+    assert_eq!(bits.to_string(), "0102030405060708090A0B0C");
+    assert_eq!("0102030405060708090a0b0c".parse::<Bits>(), Ok(bits));
+    let random_bits = Bits::from_be_bytes(&random::<[u8; 12]>());
+    assert_eq!(random_bits.to_string().parse::<Bits>(), Ok(random_bits));
+}
+
+#[test]
+fn bits_are_converted_to_and_from_integers_and_bytes() {
+    make_permutations!(struct_name = "Permutations", f = 64, r = 5, k = 1, w = 32);
+    let value = 0x0102030405060708u64;
+    let bits = Bits::from(value);
+    assert_eq!(bits.data, [0x01020304, 0x05060708]);
+    assert!(
+        bits.get(60) && !bits.get(63),
+        "least significant bits are the last ones"
+    );
+    assert_eq!(u64::from(bits), value);
+    assert_eq!(bits.to_be_bytes(), value.to_be_bytes());
+    assert_eq!(bits.to_le_bytes(), [4, 3, 2, 1, 8, 7, 6, 5]);
+    assert_eq!(Bits::from_le_bytes(&bits.to_le_bytes()), bits);
+    assert_eq!(Bits::from(value.to_be_bytes()), bits);
+    assert_eq!(Bits::try_from(&value.to_be_bytes()[..]).unwrap(), bits);
+    assert!(Bits::try_from(&[1u8, 2, 3][..]).is_err());
+}
+
+#[test]
+fn padded_bits_are_converted_to_and_from_bytes() {
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 1, w = 64);
+    let bytes = random::<[u8; 12]>();
+    let bits = Bits::from(bytes);
+    assert_eq!(bits.to_be_bytes(), bytes);
+    assert_eq!(Bits::from_le_bytes(&bytes).to_le_bytes(), bytes);
+}
+
+#[test]
+fn u128_bits_are_converted_to_and_from_integers() {
+    make_permutations!(struct_name = "Permutations", f = 128, r = 5, k = 1, w = 64);
+    let value: u128 = random();
+    assert_eq!(u128::from(Bits::from(value)), value);
+    assert_eq!(Bits::from(value).data, [(value >> 64) as u64, value as u64]);
+}
+
+#[test]
+fn bits_can_be_manipulated() {
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 1, w = 64);
+    let mut bits = Bits::new([0x00F0000000000000, 0x0000000100000000]);
+    assert_eq!(
+        (bits.count_ones(), bits.leading_zeros(), bits.trailing_zeros()),
+        (5, 8, 0)
+    );
+    bits.data[1] |= 1;
+    assert_eq!(bits.count_ones(), 5, "padding is not counted");
+    bits.flip_bit(95);
+    assert_eq!((bits.count_ones(), bits.trailing_zeros()), (4, 84));
+    bits.set_bit(8, false);
+    bits.set_bit(0, true);
+    bits.set_bit(0, true);
+    assert_eq!((bits.count_ones(), bits.leading_zeros()), (4, 0));
+    assert_eq!(bits.extract_range(0..12), 0b1000_0000_0111);
+    assert_eq!(bits.extract_range(60..96), 0);
+    assert_eq!(bits.extract_range(5..5), 0);
+    assert_eq!(Bits::default().leading_zeros(), 96);
+    assert_eq!(Bits::default().trailing_zeros(), 96);
+    assert_eq!(Bits::MAX.count_ones(), 96);
+}
+
+#[test]
+fn zero_and_ones_are_detected() {
+    make_permutations!(struct_name = "Permutations", f = 72, r = 5, k = 1, w = 64);
+    assert_eq!(Bits::ZERO, Bits::default());
+    assert!(Bits::ZERO.is_zero());
+    assert_eq!(Bits::ZERO.hamming_weight(), 0);
+    assert_eq!(Bits::ONES, Bits::MAX);
+    assert_eq!(Bits::ONES.hamming_weight(), 72);
+    assert!(!Bits::ONES.is_zero());
+
+    // padding of the last word is not a set bit
+    let padded = Bits::new([0, 1]);
+    assert!(padded.is_zero());
+    let mut bits = Bits::ZERO;
+    bits.set_bit(71, true);
+    assert!(!bits.is_zero());
+    assert_eq!(bits.hamming_weight(), bits.count_ones());
+    assert_eq!(Mask::ZERO.hamming_weight(), 0);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn random_bits_are_generated_within_distance() {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 1, w = 64);
+    let bits: Bits = random();
+    assert_eq!(bits.data[1] & !Bits::LAST_WORD_MASK, 0, "padding is not set");
+    for d in [0, 1, 3, 96] {
+        assert_eq!(bits.xor_dist(&bits.random_within_distance(d)), d);
+    }
+    let mut rng1 = StdRng::seed_from_u64(42);
+    let mut rng2 = StdRng::seed_from_u64(42);
+    assert_eq!(
+        bits.random_within_distance_with(5, &mut rng1),
+        bits.random_within_distance_with(5, &mut rng2)
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn bits_are_serialized_as_hex_or_bytes() {
+    use hloo_core::serde::{
+        Deserialize,
+        de::value::{BytesDeserializer, Error},
+    };
+
+    make_permutations!(struct_name = "Permutations", f = 72, r = 5, k = 1, w = 64);
+    let bits = Bits::from(random::<[u8; 9]>());
+    let json = serde_json::to_string(&bits).unwrap();
+    assert_eq!(json, format!("\"{bits}\""));
+    assert_eq!(serde_json::from_str::<Bits>(&json).unwrap(), bits);
+    assert!(serde_json::from_str::<Bits>("\"0xFF\"").is_err());
+
+    let bytes = bits.to_be_bytes();
+    assert_eq!(
+        Bits::deserialize(BytesDeserializer::<Error>::new(&bytes)).unwrap(),
+        bits
+    );
+    assert!(Bits::deserialize(BytesDeserializer::<Error>::new(&bytes[1..])).is_err());
+}
+
+#[cfg(feature = "proptest")]
+#[test]
+fn arbitrary_bits_are_generated_within_distance() {
+    use hloo_core::proptest::{prop_assert, prop_assert_eq, test_runner::TestRunner};
+
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 1, w = 64);
+    let pairs = Bits::pair_within_distance(3);
+    TestRunner::default()
+        .run(&pairs, |(bits, other)| {
+            prop_assert_eq!(bits.data[1] & !Bits::LAST_WORD_MASK, 0, "padding is not set");
+            prop_assert!(bits.xor_dist(&other) <= 3);
+            Ok(())
+        })
+        .unwrap();
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn bits_are_built_from_unstructured_data() {
+    use hloo_core::arbitrary::{Arbitrary, Unstructured};
+
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 1, w = 64);
+    assert_eq!(Bits::size_hint(0), (16, Some(16)));
+    let bytes = random::<[u8; 20]>();
+    let bits = Bits::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+    assert_eq!(bits.data[1] & !Bits::LAST_WORD_MASK, 0, "padding is not set");
+    assert_eq!(bits.data[0], u64::from_le_bytes(bytes[..8].try_into().unwrap()));
+}
+
+#[cfg(feature = "bitvec")]
+#[test]
+fn bits_are_converted_from_and_to_bit_slices() {
+    use hloo_core::bitvec::prelude::{BitVec, Lsb0, Msb0, bits};
+
+    make_permutations!(struct_name = "Permutations", f = 96, r = 5, k = 1, w = 64);
+    let bits = Bits::from_be_bytes(&random::<[u8; 12]>());
+    let lsb: BitVec<u8, Lsb0> = bits.into();
+    let msb: BitVec<u64, Msb0> = bits.into();
+    assert_eq!(lsb.len(), 96);
+    assert!((0..96).all(|i| lsb[i] == bits.get(i) && msb[i] == bits.get(i)));
+    assert_eq!(Bits::from(lsb.as_bitslice()), bits);
+    assert_eq!(Bits::from(msb.as_bitslice()), bits);
+
+    let short = Bits::from(bits![u8, Lsb0; 1, 0, 1]);
+    assert!(short.get(0) && !short.get(1) && short.get(2));
+    assert_eq!(short.count_ones(), 2, "missing bits are zero");
+}
+
+#[test]
+fn layout_metadata_describes_permutation() {
+    make_permutations!(struct_name = "Permutations", f = 64, r = 5, k = 2, w = 32);
+    // 64 / 5 = 13, 13, 13, 13, 12; the second variant places blocks 0 and 2 first
+    assert_eq!(Permutations1::BLOCKS, [0..13, 26..39, 13..26, 39..52, 52..64]);
+    assert_eq!(Permutations1::MASK_BITS, 26);
+    let mapping = Permutations1::bit_mapping();
+    assert_eq!((mapping[0], mapping[26], mapping[13], mapping[63]), (0, 13, 26, 63));
+    let perm = &hloo_core::create_permutations(64, 32, 5, 2)[1];
+    assert_eq!(perm.as_bit_mapping(), mapping);
+    let bits = Bits::from_be_bytes(&random::<[u8; 8]>());
+    let permuted = Permutations1::apply_static(&bits);
+    assert!((0..64).all(|i| permuted.get(mapping[i]) == bits.get(i)));
+}
+
+#[test]
+fn blocks_can_be_selected() {
+    mod random {
+        use hloo_core::{BitContainer, BitPermuter, Pod};
+        hloo_macros::make_permutations!(
+            struct_name = "Permutations",
+            f = 64,
+            r = 8,
+            k = 2,
+            w = 64,
+            selection(random(n = 5, seed = 42))
+        );
+    }
+    mod custom {
+        use hloo_core::{BitContainer, BitPermuter, Pod};
+        hloo_macros::make_permutations!(
+            struct_name = "Permutations",
+            f = 64,
+            r = 8,
+            k = 2,
+            w = 64,
+            selection(custom = "7, 0; 1, 2")
+        );
+    }
+    assert_eq!(random::Permutations::get_all_variants().len(), 5);
+    let selection = hloo_core::BlockSelection::RandomSubset { n: 5, seed: 42 }.select(8, 2);
+    let first_blocks = random::Permutations0::BLOCKS[..2].iter().map(|b| b.start / 8);
+    assert!(first_blocks.eq(selection[0].iter().copied()));
+    assert_eq!(custom::Permutations::get_all_variants().len(), 2);
+    assert_eq!(custom::Permutations0::BLOCKS[..3], [56..64, 0..8, 8..16]);
+    assert_eq!(custom::Permutations1::BLOCKS[..3], [8..16, 16..24, 0..8]);
+}
+
+#[test]
+fn blocks_can_be_strided() {
+    make_permutations!(
+        struct_name = "Permutations",
+        f = 64,
+        r = 4,
+        k = 1,
+        w = 32,
+        split(strided(stride = 2))
+    );
+    assert_eq!(Permutations::get_all_variants().len(), 4);
+    // block 1 takes bits 2..4, 10..12, ..., 58..60
+    assert_eq!(Permutations1::BLOCKS.len(), 32);
+    assert_eq!(Permutations1::BLOCKS[..3], [2..4, 10..12, 18..20]);
+    assert_eq!(Permutations1::MASK_BITS, 16);
+    assert_eq!(Permutations1.n_blocks(), 4);
+    let bits = Bits::from_be_bytes(&random::<[u8; 8]>());
+    let permuted = Permutations1::apply_static(&bits);
+    assert_eq!(Permutations1::revert_static(&permuted), bits);
+    let mapping = Permutations1::bit_mapping();
+    assert!((0..64).all(|i| permuted.get(mapping[i]) == bits.get(i)));
+    let mask = Permutations1::mask_static(&permuted);
+    assert!((0..16).all(|i| mask.get(i) == bits.get(2 + 8 * (i / 2) + i % 2)));
+    assert!((16..32).all(|i| !mask.get(i)));
+}
+
+#[test]
+fn generated_permutations_are_verified() {
+    mod padded {
+        use hloo_core::{BitContainer, BitPermuter, Pod};
+        hloo_macros::make_permutations!(struct_name = "Permutations", f = 96, r = 7, k = 2, w = 64);
+    }
+    mod strided {
+        use hloo_core::{BitContainer, BitPermuter, Pod};
+        hloo_macros::make_permutations!(
+            struct_name = "Permutations",
+            f = 64,
+            r = 5,
+            k = 2,
+            w = 16,
+            split(strided(stride = 3))
+        );
+    }
+    assert_eq!(padded::Permutations::verify(16), Ok(()));
+    assert_eq!(strided::Permutations::verify(16), Ok(()));
+}
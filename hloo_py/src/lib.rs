@@ -0,0 +1,328 @@
+//! Python bindings of the pre-defined lookups of `hloo` (see `hloo::lookup::lookup_impl`), holding `int64` values.
+//!
+//! The extension module is named `hloo` and is built with [maturin](https://www.maturin.rs) (see `pyproject.toml`).
+//! It provides `MemLookup` and `MemMapLookup`, both subclasses of `Lookup`, over 64-bit or 256-bit keys.
+//!
+//! Keys are passed in batches, either as `bytes` holding big-endian keys one after another, or as an array of
+//! `uint64` words (e.g. a `numpy` array of shape `(n, bits / 64)`) holding the words of keys one after another, most
+//! significant first. Values are passed as an array of `int64` or a sequence of integers.
+
+use std::{collections::HashSet, path::PathBuf};
+
+use hloo::lookup::{
+    SearchError, SearchResult,
+    lookup_impl::{lookup64, lookup256},
+};
+use pyo3::{
+    buffer::PyBuffer,
+    exceptions::{PyOSError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+
+/// Items found by a search, as `(value, distance)` pairs.
+type Found = Vec<(i64, u32)>;
+
+/// Keys passed from Python, see the module documentation.
+enum Keys {
+    Bytes(Vec<u8>),
+    Words(Vec<u64>),
+}
+
+impl Keys {
+    fn extract(keys: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if let Ok(bytes) = keys.cast::<PyBytes>() {
+            return Ok(Self::Bytes(bytes.as_bytes().to_vec()));
+        }
+        Ok(Self::Words(PyBuffer::<u64>::get(keys)?.to_vec(keys.py())?))
+    }
+
+    /// Split into keys of `size` bytes, created by `from_bytes` or `from_words`.
+    fn split<K>(
+        &self,
+        size: usize,
+        from_bytes: impl Fn(&[u8]) -> K,
+        from_words: impl Fn(&[u64]) -> K,
+    ) -> PyResult<Vec<K>> {
+        match self {
+            Self::Bytes(bytes) if bytes.len() % size == 0 => Ok(bytes.chunks(size).map(from_bytes).collect()),
+            Self::Words(words) if words.len() % (size / 8) == 0 => Ok(words.chunks(size / 8).map(from_words).collect()),
+            _ => Err(PyValueError::new_err(format!(
+                "keys should hold a whole number of {size}-byte keys"
+            ))),
+        }
+    }
+}
+
+fn extract_values(values: &Bound<'_, PyAny>) -> PyResult<Vec<i64>> {
+    match PyBuffer::<i64>::get(values) {
+        Ok(buffer) => buffer.to_vec(values.py()),
+        Err(_) => values.extract(),
+    }
+}
+
+/// Deduplicate and order results of a search; every index finds the items within the distance.
+fn found_items(result: Result<SearchResult<i64>, SearchError>) -> PyResult<Found> {
+    let result = result.map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mut items: Found = result
+        .into_flat_iter()
+        .map(|item| (*item.data(), item.distance()))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    items.sort_unstable_by_key(|&(value, distance)| (distance, value));
+    Ok(items)
+}
+
+/// Operations of lookups behind Python objects.
+trait PyLookup: Send + Sync {
+    fn key_size(&self) -> usize;
+
+    fn insert(&mut self, keys: &Keys, values: &[i64]) -> PyResult<()>;
+
+    fn search(&self, keys: &Keys, distance: u32) -> PyResult<Vec<Found>>;
+
+    fn persist(&self) -> PyResult<()>;
+}
+
+macro_rules! impl_py_lookup {
+    ($lookup:ty, $bits:ty, $persist:expr) => {
+        impl PyLookup for $lookup {
+            fn key_size(&self) -> usize {
+                <$bits>::SIZE_BYTES
+            }
+
+            fn insert(&mut self, keys: &Keys, values: &[i64]) -> PyResult<()> {
+                let keys = keys.split(<$bits>::SIZE_BYTES, <$bits>::from_be_bytes, |words| {
+                    <$bits>::new(words.try_into().expect("words are split by key"))
+                })?;
+                if keys.len() != values.len() {
+                    return Err(PyValueError::new_err(format!(
+                        "got {} keys, but {} values",
+                        keys.len(),
+                        values.len()
+                    )));
+                }
+                let items: Vec<_> = keys.into_iter().zip(values.iter().copied()).collect();
+                hloo::Lookup::insert(self, &items)
+                    .map_err(|e| PyOSError::new_err(format!("failed to insert items: {e:?}")))
+            }
+
+            fn search(&self, keys: &Keys, distance: u32) -> PyResult<Vec<Found>> {
+                let keys = keys.split(<$bits>::SIZE_BYTES, <$bits>::from_be_bytes, |words| {
+                    <$bits>::new(words.try_into().expect("words are split by key"))
+                })?;
+                keys.iter()
+                    .map(|key| found_items(hloo::Lookup::search(self, key, distance)))
+                    .collect()
+            }
+
+            fn persist(&self) -> PyResult<()> {
+                ($persist)(self)
+            }
+        }
+    };
+}
+
+impl_py_lookup!(lookup64::MemLookup<i64>, lookup64::Bits, |_| Ok(()));
+impl_py_lookup!(lookup256::MemLookup<i64>, lookup256::Bits, |_| Ok(()));
+impl_py_lookup!(
+    lookup64::MemMapLookup<i64>,
+    lookup64::Bits,
+    |lookup: &lookup64::MemMapLookup<i64>| hloo::Lookup::persist(lookup).map_err(|e| PyOSError::new_err(e.to_string()))
+);
+impl_py_lookup!(
+    lookup256::MemMapLookup<i64>,
+    lookup256::Bits,
+    |lookup: &lookup256::MemMapLookup<i64>| hloo::Lookup::persist(lookup)
+        .map_err(|e| PyOSError::new_err(e.to_string()))
+);
+
+fn unknown_bits(bits: u32) -> PyErr {
+    PyValueError::new_err(format!("bits should be 64 or 256, got {bits}"))
+}
+
+/// Base class of lookups, searching for keys within a distance.
+#[pyclass(subclass, module = "hloo")]
+pub struct Lookup(Box<dyn PyLookup>);
+
+#[pymethods]
+impl Lookup {
+    /// Number of bytes of a key.
+    #[getter]
+    fn key_size(&self) -> usize {
+        self.0.key_size()
+    }
+
+    /// Insert keys with their values. There should be as many values as there are keys.
+    fn insert(&mut self, py: Python<'_>, keys: &Bound<'_, PyAny>, values: &Bound<'_, PyAny>) -> PyResult<()> {
+        let (keys, values) = (Keys::extract(keys)?, extract_values(values)?);
+        let lookup = &mut self.0;
+        py.detach(|| lookup.insert(&keys, &values))
+    }
+
+    /// Search for items within `distance` bits of a single key. Returns `(value, distance)` pairs ordered by distance
+    /// and then by value.
+    fn search(&self, py: Python<'_>, key: &Bound<'_, PyAny>, distance: u32) -> PyResult<Found> {
+        let mut found = self.search_batch(py, key, distance)?;
+        match found.len() {
+            1 => Ok(found.remove(0)),
+            n => Err(PyValueError::new_err(format!("expected a single key, got {n}"))),
+        }
+    }
+
+    /// Search for items within `distance` bits of each of the keys, like `search`.
+    fn search_batch(&self, py: Python<'_>, keys: &Bound<'_, PyAny>, distance: u32) -> PyResult<Vec<Found>> {
+        let keys = Keys::extract(keys)?;
+        let lookup = &self.0;
+        py.detach(|| lookup.search(&keys, distance))
+    }
+}
+
+/// Lookup kept in memory.
+#[pyclass(extends = Lookup, module = "hloo")]
+pub struct MemLookup;
+
+#[pymethods]
+impl MemLookup {
+    #[new]
+    #[pyo3(signature = (bits = 64))]
+    fn new(bits: u32) -> PyResult<PyClassInitializer<Self>> {
+        let lookup: Box<dyn PyLookup> = match bits {
+            64 => Box::new(lookup64::MemLookup::<i64>::default()),
+            256 => Box::new(lookup256::MemLookup::<i64>::default()),
+            _ => return Err(unknown_bits(bits)),
+        };
+        Ok(PyClassInitializer::from(Lookup(lookup)).add_subclass(Self))
+    }
+}
+
+/// Lookup with its indexes memory-mapped from files in a directory.
+#[pyclass(extends = Lookup, module = "hloo")]
+pub struct MemMapLookup;
+
+impl MemMapLookup {
+    fn wrap(py: Python<'_>, lookup: Result<Box<dyn PyLookup>, String>) -> PyResult<Py<Self>> {
+        let lookup = lookup.map_err(PyOSError::new_err)?;
+        Py::new(py, PyClassInitializer::from(Lookup(lookup)).add_subclass(Self))
+    }
+}
+
+#[pymethods]
+impl MemMapLookup {
+    /// Create a lookup in the existing directory `path`.
+    #[staticmethod]
+    #[pyo3(signature = (path, bits = 64))]
+    fn create(py: Python<'_>, path: PathBuf, bits: u32) -> PyResult<Py<Self>> {
+        let lookup = match bits {
+            64 => lookup64::MemMapLookup::<i64>::create(&path).map(|l| Box::new(l) as Box<dyn PyLookup>),
+            256 => lookup256::MemMapLookup::<i64>::create(&path).map(|l| Box::new(l) as Box<dyn PyLookup>),
+            _ => return Err(unknown_bits(bits)),
+        };
+        Self::wrap(py, lookup.map_err(|e| e.to_string()))
+    }
+
+    /// Load a lookup from the directory `path`, where it was created and persisted.
+    #[staticmethod]
+    #[pyo3(signature = (path, bits = 64))]
+    fn load(py: Python<'_>, path: PathBuf, bits: u32) -> PyResult<Py<Self>> {
+        let lookup = match bits {
+            64 => lookup64::MemMapLookup::<i64>::load(&path).map(|l| Box::new(l) as Box<dyn PyLookup>),
+            256 => lookup256::MemMapLookup::<i64>::load(&path).map(|l| Box::new(l) as Box<dyn PyLookup>),
+            _ => return Err(unknown_bits(bits)),
+        };
+        Self::wrap(py, lookup.map_err(|e| e.to_string()))
+    }
+
+    /// Persist the lookup, so it can be loaded with `MemMapLookup.load`.
+    fn persist(slf: PyRef<'_, Self>) -> PyResult<()> {
+        let lookup = &slf.as_super().0;
+        slf.py().detach(|| lookup.persist())
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "hloo")]
+fn hloo_py(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Lookup>()?;
+    module.add_class::<MemLookup>()?;
+    module.add_class::<MemMapLookup>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    /// Run Python `code` with the module imported as `hloo`, and the given `path` as a string.
+    fn run(code: &str, path: &str) -> PyResult<()> {
+        Python::attach(|py| {
+            let module = PyModule::new(py, "hloo")?;
+            hloo_py(&module)?;
+            let globals = pyo3::types::PyDict::new(py);
+            globals.set_item("hloo", module)?;
+            globals.set_item("path", path)?;
+            py.run(&CString::new(code).unwrap(), Some(&globals), None)
+        })
+    }
+
+    #[test]
+    fn mem_lookup_is_searched() {
+        let code = r#"
+from array import array
+
+lookup = hloo.MemLookup()
+assert lookup.key_size == 8
+keys = array("Q", [i * 0x0101_0101_0101_0101 for i in range(10)])
+lookup.insert(keys, range(10))
+assert lookup.search(array("Q", [0x0303_0303_0303_0300]), 2) == [(3, 2)]
+assert lookup.search((0x0303_0303_0303_0300).to_bytes(8, "big"), 2) == [(3, 2)]
+assert lookup.search_batch(keys[:2], 0) == [[(0, 0)], [(1, 0)]]
+
+lookup = hloo.MemLookup(bits=256)
+lookup.insert(bytes([0xAB] * 32), array("q", [42]))
+assert lookup.search(array("Q", [0xABAB_ABAB_ABAB_ABAB] * 4), 1) == [(42, 0)]
+"#;
+        run(code, "").unwrap();
+    }
+
+    #[test]
+    fn memmap_lookup_is_persisted_and_loaded() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let code = r#"
+lookup = hloo.MemMapLookup.create(path, bits=256)
+assert isinstance(lookup, hloo.Lookup)
+lookup.insert(bytes([0xAB] * 32), [42])
+lookup.persist()
+del lookup
+
+lookup = hloo.MemMapLookup.load(path, bits=256)
+assert lookup.search(bytes([0xAB] * 32), 0) == [(42, 0)]
+"#;
+        run(code, tmp_dir.path().to_str().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        let code = r#"
+def raises(error, f):
+    try:
+        f()
+    except error:
+        return
+    raise AssertionError("expected " + error.__name__)
+
+raises(ValueError, lambda: hloo.MemLookup(bits=128))
+lookup = hloo.MemLookup()
+raises(ValueError, lambda: lookup.insert(bytes(12), [1]))
+raises(ValueError, lambda: lookup.insert(bytes(16), [1]))
+raises(ValueError, lambda: lookup.search(bytes(16), 0))
+raises(ValueError, lambda: lookup.search(bytes(8), 4))
+raises(TypeError, lambda: lookup.insert("key", [1]))
+raises(OSError, lambda: hloo.MemMapLookup.load(path))
+"#;
+        run(code, "/nonexistent").unwrap();
+    }
+}
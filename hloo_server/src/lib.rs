@@ -0,0 +1,292 @@
+//! Reference HTTP server for near-duplicate search over a memory-mapped lookup (see `hloo::lookup::lookup_impl`),
+//! holding `i64` values.
+//!
+//! All endpoints accept and return JSON. Keys are hex strings of 16 (64-bit keys) or 64 (256-bit keys) digits:
+//!
+//! - `POST /insert` with `{"items": [{"key": "...", "value": 42}]}` inserts items and returns `{"inserted": 1}`;
+//! - `POST /search` with `{"key": "...", "distance": 3}` returns `{"items": [{"value": 42, "distance": 1}]}`, with
+//!   distinct items ordered by distance and then by value;
+//! - `POST /persist` persists the lookup and returns `{}`.
+//!
+//! Errors are returned as `{"error": "..."}`, with status 400 for invalid requests and 500 for failures of storage.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use hloo::{
+    Lookup,
+    lookup::{
+        SearchError, SearchResult,
+        lookup_impl::{lookup64, lookup256},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct InsertRequest {
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Item {
+    pub key: String,
+    pub value: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InsertResponse {
+    pub inserted: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchRequest {
+    pub key: String,
+    pub distance: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub items: Vec<Found>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Found {
+    pub value: i64,
+    pub distance: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PersistResponse {}
+
+/// Error of a request, returned as `{"error": "..."}`.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn invalid(message: impl ToString) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message: message.to_string(),
+        }
+    }
+
+    fn storage(message: impl ToString) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct Body {
+            error: String,
+        }
+        (self.status, Json(Body { error: self.message })).into_response()
+    }
+}
+
+/// Deduplicate and order results of a search; every index finds the items within the distance.
+fn found_items(result: Result<SearchResult<i64>, SearchError>) -> Result<Vec<Found>, ApiError> {
+    let result = result.map_err(ApiError::invalid)?;
+    let mut items: Vec<_> = result
+        .into_flat_iter()
+        .map(|item| Found {
+            value: *item.data(),
+            distance: item.distance(),
+        })
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    items.sort_unstable_by_key(|item| (item.distance, item.value));
+    Ok(items)
+}
+
+/// Operations of the served lookup, with keys as hex strings.
+pub trait ServerLookup: Send + Sync {
+    fn insert(&mut self, items: &[Item]) -> Result<(), ApiError>;
+
+    fn search(&self, key: &str, distance: u32) -> Result<Vec<Found>, ApiError>;
+
+    fn persist(&self) -> Result<(), ApiError>;
+}
+
+macro_rules! impl_server_lookup {
+    ($lookup:ty, $bits:ty) => {
+        impl ServerLookup for $lookup {
+            fn insert(&mut self, items: &[Item]) -> Result<(), ApiError> {
+                let items = items
+                    .iter()
+                    .map(|item| Ok((item.key.parse::<$bits>().map_err(ApiError::invalid)?, item.value)))
+                    .collect::<Result<Vec<_>, ApiError>>()?;
+                Lookup::insert(self, &items).map_err(ApiError::storage)
+            }
+
+            fn search(&self, key: &str, distance: u32) -> Result<Vec<Found>, ApiError> {
+                let key = key.parse::<$bits>().map_err(ApiError::invalid)?;
+                found_items(Lookup::search(self, &key, distance))
+            }
+
+            fn persist(&self) -> Result<(), ApiError> {
+                Lookup::persist(self).map_err(ApiError::storage)
+            }
+        }
+    };
+}
+
+impl_server_lookup!(lookup64::MemMapLookup<i64>, lookup64::Bits);
+impl_server_lookup!(lookup256::MemMapLookup<i64>, lookup256::Bits);
+
+/// Load the lookup with keys of `bits` bits from the directory `path`, or create it there if the directory is empty.
+pub fn open_lookup(path: &Path, bits: u32) -> Result<Box<dyn ServerLookup>, String> {
+    let empty = path.read_dir().map_err(|e| e.to_string())?.next().is_none();
+    let lookup: Box<dyn ServerLookup> = match (bits, empty) {
+        (64, true) => Box::new(lookup64::MemMapLookup::<i64>::create(path).map_err(|e| e.to_string())?),
+        (64, false) => Box::new(lookup64::MemMapLookup::<i64>::load(path).map_err(|e| e.to_string())?),
+        (256, true) => Box::new(lookup256::MemMapLookup::<i64>::create(path).map_err(|e| e.to_string())?),
+        (256, false) => Box::new(lookup256::MemMapLookup::<i64>::load(path).map_err(|e| e.to_string())?),
+        _ => return Err(format!("bits should be 64 or 256, got {bits}")),
+    };
+    Ok(lookup)
+}
+
+/// Lookup shared by request handlers. Searches run concurrently, while inserts and persisting are exclusive.
+pub type SharedLookup = Arc<RwLock<Box<dyn ServerLookup>>>;
+
+/// Run `f` on a blocking thread, so that long operations on the lookup don't stall the runtime.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T, ApiError> + Send + 'static) -> Result<T, ApiError> {
+    tokio::task::spawn_blocking(f).await.map_err(ApiError::storage)?
+}
+
+async fn insert(
+    State(lookup): State<SharedLookup>,
+    Json(request): Json<InsertRequest>,
+) -> Result<Json<InsertResponse>, ApiError> {
+    blocking(move || {
+        let mut lookup = lookup.write().map_err(ApiError::storage)?;
+        lookup.insert(&request.items)?;
+        Ok(Json(InsertResponse {
+            inserted: request.items.len(),
+        }))
+    })
+    .await
+}
+
+async fn search(
+    State(lookup): State<SharedLookup>,
+    Json(request): Json<SearchRequest>,
+) -> Result<Json<SearchResponse>, ApiError> {
+    blocking(move || {
+        let lookup = lookup.read().map_err(ApiError::storage)?;
+        let items = lookup.search(&request.key, request.distance)?;
+        Ok(Json(SearchResponse { items }))
+    })
+    .await
+}
+
+async fn persist(State(lookup): State<SharedLookup>) -> Result<Json<PersistResponse>, ApiError> {
+    blocking(move || {
+        lookup.write().map_err(ApiError::storage)?.persist()?;
+        Ok(Json(PersistResponse {}))
+    })
+    .await
+}
+
+/// Create the router serving `lookup`.
+pub fn router(lookup: SharedLookup) -> Router {
+    Router::new()
+        .route("/insert", post(insert))
+        .route("/search", post(search))
+        .route("/persist", post(persist))
+        .with_state(lookup)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use http_body_util::BodyExt;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn serve(path: &Path, bits: u32) -> Router {
+        router(Arc::new(RwLock::new(open_lookup(path, bits).unwrap())))
+    }
+
+    async fn call(router: &Router, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn items_are_inserted_searched_and_persisted() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let router = serve(tmp_dir.path(), 64);
+        let items = json!({"items": [
+            {"key": "0000000000000000", "value": 1},
+            {"key": "0000000000000003", "value": 2},
+            {"key": "FFFFFFFFFFFFFFFF", "value": 3},
+        ]});
+        assert_eq!(
+            call(&router, "/insert", items).await,
+            (StatusCode::OK, json!({"inserted": 3}))
+        );
+        let (status, found) = call(&router, "/search", json!({"key": "0000000000000001", "distance": 1})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            found,
+            json!({"items": [{"value": 1, "distance": 1}, {"value": 2, "distance": 1}]})
+        );
+        assert_eq!(call(&router, "/persist", json!({})).await, (StatusCode::OK, json!({})));
+        drop(router);
+
+        let router = serve(tmp_dir.path(), 64);
+        let (_, found) = call(&router, "/search", json!({"key": "FFFFFFFFFFFFFFFF", "distance": 0})).await;
+        assert_eq!(found, json!({"items": [{"value": 3, "distance": 0}]}));
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_are_rejected() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        assert!(open_lookup(tmp_dir.path(), 128).is_err());
+        let router = serve(tmp_dir.path(), 256);
+        let (status, error) = call(&router, "/search", json!({"key": "0000000000000000", "distance": 0})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "key is too short: {error}");
+        let key = "0".repeat(64);
+        let (status, error) = call(&router, "/search", json!({"key": key, "distance": 100})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "distance is too large: {error}");
+        let items = json!({"items": [{"key": key, "value": 1}, {"key": "xyz", "value": 2}]});
+        assert_eq!(call(&router, "/insert", items).await.0, StatusCode::BAD_REQUEST);
+        let (_, found) = call(&router, "/search", json!({"key": key, "distance": 0})).await;
+        assert_eq!(found, json!({"items": []}), "nothing is inserted if any key is invalid");
+    }
+}
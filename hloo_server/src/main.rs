@@ -0,0 +1,86 @@
+//! Usage: `hloo_server <dir> [--bits 64|256] [--addr <host:port>]`
+//!
+//! Serves the lookup stored in the directory `dir`, creating it if the directory is empty. The lookup is persisted on
+//! shutdown by Ctrl-C. See the library documentation for the protocol.
+
+use std::{
+    path::PathBuf,
+    process::ExitCode,
+    sync::{Arc, RwLock},
+};
+
+use hloo_server::{open_lookup, router};
+
+const USAGE: &str = "usage: hloo_server <dir> [--bits 64|256] [--addr <host:port>]";
+
+struct Args {
+    dir: PathBuf,
+    bits: u32,
+    addr: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut dir = None;
+    let mut bits = 64;
+    let mut addr = "127.0.0.1:8080".to_string();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bits" => {
+                let value = args.next().ok_or("--bits requires a value")?;
+                bits = value.parse().map_err(|_| format!("invalid number of bits: {value}"))?;
+            }
+            "--addr" => addr = args.next().ok_or("--addr requires a value")?,
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+    let dir = dir.ok_or("missing directory")?;
+    Ok(Args { dir, bits, addr })
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let lookup = match open_lookup(&args.dir, args.bits) {
+        Ok(lookup) => Arc::new(RwLock::new(lookup)),
+        Err(e) => {
+            eprintln!("failed to open lookup in {}: {e}", args.dir.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let listener = match tokio::net::TcpListener::bind(&args.addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("failed to listen on {}: {e}", args.addr);
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!("serving {} on {}", args.dir.display(), args.addr);
+    let shutdown = async {
+        tokio::signal::ctrl_c().await.ok();
+    };
+    if let Err(e) = axum::serve(listener, router(lookup.clone()))
+        .with_graceful_shutdown(shutdown)
+        .await
+    {
+        eprintln!("server failed: {e}");
+        return ExitCode::FAILURE;
+    }
+    let persisted = lookup
+        .read()
+        .map_err(|e| e.to_string())
+        .and_then(|lookup| lookup.persist().map_err(|e| e.to_string()));
+    match persisted {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("failed to persist lookup: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
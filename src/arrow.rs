@@ -0,0 +1,256 @@
+//! Export and import of index contents as Arrow record batches and, with the `parquet` feature, Parquet files.
+//!
+//! Record batches have two non-nullable columns: [`KEY_COLUMN`], holding keys in their original (non-permuted) form
+//! as fixed-size big-endian bytes (see [`KeyBytes`]), and [`VALUE_COLUMN`], holding values as a primitive column of
+//! the matching type (see [`ArrowValue`]).
+
+use std::sync::Arc;
+
+use arrow_array::{
+    Array, ArrayRef, FixedSizeBinaryArray, PrimitiveArray, RecordBatch,
+    types::{
+        Float32Type, Float64Type, Int8Type, Int16Type, Int32Type, Int64Type, UInt8Type, UInt16Type, UInt32Type,
+        UInt64Type,
+    },
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use hloo_core::{BitContainer, BitPermuter};
+
+use crate::{
+    index::{Block, Index},
+    util::KeyBytes,
+};
+
+/// Name of the key column.
+pub const KEY_COLUMN: &str = "key";
+
+/// Name of the value column.
+pub const VALUE_COLUMN: &str = "value";
+
+/// Values which can be stored in a primitive Arrow column.
+pub trait ArrowValue: Copy {
+    /// Type of the column.
+    fn data_type() -> DataType;
+
+    /// Collect values into a column.
+    fn to_array(values: impl IntoIterator<Item = Self>) -> ArrayRef;
+
+    /// Get values of a column, or `None` if the column is of another type.
+    fn from_array(array: &dyn Array) -> Option<Vec<Self>>;
+}
+
+macro_rules! impl_arrow_value {
+    ($($t:ty => $arrow:ty),*) => {
+        $(
+            impl ArrowValue for $t {
+                fn data_type() -> DataType {
+                    <$arrow as arrow_array::ArrowPrimitiveType>::DATA_TYPE
+                }
+
+                fn to_array(values: impl IntoIterator<Item = Self>) -> ArrayRef {
+                    Arc::new(PrimitiveArray::<$arrow>::from_iter_values(values))
+                }
+
+                fn from_array(array: &dyn Array) -> Option<Vec<Self>> {
+                    let array = array.as_any().downcast_ref::<PrimitiveArray<$arrow>>()?;
+                    Some(array.values().to_vec())
+                }
+            }
+        )*
+    };
+}
+
+impl_arrow_value!(
+    i8 => Int8Type, i16 => Int16Type, i32 => Int32Type, i64 => Int64Type,
+    u8 => UInt8Type, u16 => UInt16Type, u32 => UInt32Type, u64 => UInt64Type,
+    f32 => Float32Type, f64 => Float64Type
+);
+
+/// Schema of record batches holding keys `K` and values `V`.
+pub fn schema<K: KeyBytes, V: ArrowValue>() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(KEY_COLUMN, DataType::FixedSizeBinary(K::SIZE as i32), false),
+        Field::new(VALUE_COLUMN, V::data_type(), false),
+    ]))
+}
+
+/// Collect `items` into a record batch. Keys are stored as they are, so they should be in their original form.
+pub fn to_record_batch<'a, K, V>(items: impl IntoIterator<Item = (&'a K, &'a V)>) -> Result<RecordBatch, ArrowError>
+where
+    K: KeyBytes + 'a,
+    V: ArrowValue + 'a,
+{
+    let (keys, values): (Vec<_>, Vec<_>) = items.into_iter().map(|(k, v)| (k.to_be_vec(), *v)).unzip();
+    let keys = FixedSizeBinaryArray::try_from_sparse_iter_with_size(keys.into_iter().map(Some), K::SIZE as i32)?;
+    RecordBatch::try_new(schema::<K, V>(), vec![Arc::new(keys), V::to_array(values)])
+}
+
+/// Get items stored in a record batch, e.g. created by [`to_record_batch`].
+pub fn from_record_batch<K, V>(batch: &RecordBatch) -> Result<Vec<(K, V)>, ArrowError>
+where
+    K: KeyBytes,
+    V: ArrowValue,
+{
+    let column = |name: &str| {
+        let column = batch
+            .column_by_name(name)
+            .ok_or_else(|| ArrowError::SchemaError(format!("column {name} is missing")))?;
+        if column.null_count() > 0 {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "column {name} contains nulls"
+            )));
+        }
+        Ok(column)
+    };
+    let keys = column(KEY_COLUMN)?
+        .as_any()
+        .downcast_ref::<FixedSizeBinaryArray>()
+        .filter(|keys| keys.value_length() as usize == K::SIZE)
+        .ok_or_else(|| ArrowError::SchemaError(format!("column {KEY_COLUMN} should hold {} bytes", K::SIZE)))?;
+    let values = V::from_array(column(VALUE_COLUMN)?).ok_or_else(|| {
+        ArrowError::SchemaError(format!("column {VALUE_COLUMN} should be of type {}", V::data_type()))
+    })?;
+    Ok(keys.iter().flatten().map(K::from_be_slice).zip(values).collect())
+}
+
+/// Export all items of `index` into a record batch. Keys are reverted to their original form, so every index of a
+/// lookup produces the same items, although in a different order.
+pub fn export_index<K, V, M>(index: &impl Index<K, V, M>) -> Result<RecordBatch, ArrowError>
+where
+    K: KeyBytes + Copy + Ord + BitContainer,
+    V: ArrowValue,
+    M: Ord,
+{
+    export_block(index.permuter(), index.data())
+}
+
+fn export_block<K, V, M>(permuter: &dyn BitPermuter<K, M>, block: Block<'_, K, V>) -> Result<RecordBatch, ArrowError>
+where
+    K: KeyBytes,
+    V: ArrowValue,
+{
+    let keys: Vec<_> = block.keys().map(|k| permuter.revert(k)).collect();
+    to_record_batch(keys.iter().zip(block.iter().map(|(_, v)| v)))
+}
+
+#[cfg(feature = "parquet")]
+pub use parquet_io::{ParquetError, read_parquet, write_parquet};
+
+#[cfg(feature = "parquet")]
+mod parquet_io {
+    use std::{fs::File, path::Path};
+
+    use parquet::arrow::{ArrowWriter, arrow_reader::ParquetRecordBatchReaderBuilder};
+    use thiserror::Error;
+
+    use super::*;
+
+    /// Number of items per record batch written to or read from Parquet files.
+    const BATCH_LEN: usize = 1 << 16;
+
+    #[derive(Debug, Error)]
+    pub enum ParquetError {
+        #[error("arrow error: {0}")]
+        Arrow(#[from] ArrowError),
+        #[error("parquet error: {0}")]
+        Parquet(#[from] parquet::errors::ParquetError),
+        #[error("i/o error: {0}")]
+        IoError(#[from] std::io::Error),
+    }
+
+    /// Export all items of `index` into a new Parquet file at `path`, like [`export_index`].
+    pub fn write_parquet<K, V, M>(index: &impl Index<K, V, M>, path: &Path) -> Result<(), ParquetError>
+    where
+        K: KeyBytes + Copy + Ord + BitContainer,
+        V: ArrowValue,
+        M: Ord,
+    {
+        let data = index.data();
+        let mut writer = ArrowWriter::try_new(File::create(path)?, schema::<K, V>(), None)?;
+        for start in (0..data.len()).step_by(BATCH_LEN) {
+            let block = data.slice(start..data.len().min(start + BATCH_LEN));
+            writer.write(&export_block(index.permuter(), block)?)?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Read all items of a Parquet file at `path`, e.g. created by [`write_parquet`], to be inserted into a lookup.
+    pub fn read_parquet<K, V>(path: &Path) -> Result<Vec<(K, V)>, ParquetError>
+    where
+        K: KeyBytes,
+        V: ArrowValue,
+    {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?
+            .with_batch_size(BATCH_LEN)
+            .build()?;
+        let mut items = Vec::new();
+        for batch in reader {
+            items.extend(from_record_batch(&batch?)?);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Lookup,
+        lookup::lookup_impl::lookup64::{Bits, MemLookup},
+    };
+
+    use super::*;
+
+    fn lookup_with_data() -> (MemLookup<i64>, Vec<(Bits, i64)>) {
+        let data: Vec<_> = (0..100)
+            .map(|i| (Bits::new([i * 0x0123_4567_89AB_CDEF]), i as i64))
+            .collect();
+        let mut lookup = MemLookup::default();
+        lookup.insert(&data).unwrap();
+        (lookup, data)
+    }
+
+    fn sorted(mut items: Vec<(Bits, i64)>) -> Vec<(Bits, i64)> {
+        items.sort_unstable();
+        items
+    }
+
+    #[test]
+    fn indexes_are_exported_to_record_batches() {
+        let (lookup, data) = lookup_with_data();
+        let batch = export_index(&lookup.indexes()[1]).unwrap();
+        assert_eq!(batch.schema(), schema::<Bits, i64>());
+        assert_eq!(batch.num_rows(), data.len());
+        let keys = batch.column(0).as_any().downcast_ref::<FixedSizeBinaryArray>().unwrap();
+        assert!(
+            keys.iter().flatten().any(|key| key == data[1].0.to_be_bytes()),
+            "keys are not permuted"
+        );
+        assert_eq!(sorted(from_record_batch(&batch).unwrap()), sorted(data));
+
+        assert!(from_record_batch::<Bits, u64>(&batch).is_err(), "value type differs");
+        let projected = batch.project(&[0]).unwrap();
+        assert!(
+            from_record_batch::<Bits, i64>(&projected).is_err(),
+            "value column is missing"
+        );
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn indexes_are_exported_to_parquet_files() {
+        let (lookup, data) = lookup_with_data();
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("index.parquet");
+        write_parquet(&lookup.indexes()[0], &path).unwrap();
+        let items = read_parquet::<Bits, i64>(&path).unwrap();
+        assert_eq!(sorted(items.clone()), sorted(data));
+
+        let mut imported = MemLookup::default();
+        imported.insert(&items).unwrap();
+        assert_eq!(
+            imported.indexes()[0].data().to_vec(),
+            lookup.indexes()[0].data().to_vec()
+        );
+    }
+}
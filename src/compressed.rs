@@ -0,0 +1,326 @@
+//! Read-only, zstd-compressed counterpart of [`MmVec`].
+//!
+//! Items are split into segments of a fixed number of items, and each segment is compressed separately. Accessing an
+//! item only requires decompressing the segment containing it.
+
+use std::{
+    fs::{File, remove_file},
+    io::{self, BufWriter, Write},
+    marker::PhantomData,
+    mem::size_of,
+    ops::Range,
+    path::{Path, PathBuf},
+    ptr, slice,
+};
+
+use memmap2::Mmap;
+
+use crate::mmvec::{MmVec, MmVecError, Pod};
+
+/// Default number of items per segment.
+pub const DEFAULT_SEGMENT_LEN: usize = 4096;
+
+/// Default zstd compression level.
+pub const DEFAULT_LEVEL: i32 = 3;
+
+/// Compressed, read-only vector.
+///
+/// File layout: header (signature, length, segment length, number of segments; all `u64`), followed by the table of
+/// `n_segments + 1` segment offsets (`u64`, relative to the end of the table), followed by the compressed segments.
+pub struct CompressedVec<T>
+where
+    T: Copy,
+{
+    mapped: Mmap,
+    path: PathBuf,
+    len: usize,
+    segment_len: usize,
+    n_segments: usize,
+    dummy: PhantomData<T>,
+}
+
+impl<T> CompressedVec<T>
+where
+    T: Copy,
+{
+    const HEADER_SIZE: usize = 32;
+
+    /// Compress `slice` into a new file at `path`, in segments of `segment_len` items, using the given zstd level.
+    ///
+    /// ## Safety
+    /// Unsafe since items are compressed as raw bytes, so `T` must not contain padding.
+    pub unsafe fn from_slice(
+        sig: u64,
+        slice: &[T],
+        segment_len: usize,
+        level: i32,
+        path: PathBuf,
+    ) -> Result<Self, MmVecError> {
+        assert!(segment_len > 0, "segment length should be positive");
+        let n_segments = slice.len().div_ceil(segment_len);
+        let mut compressed = Vec::with_capacity(n_segments);
+        for segment in slice.chunks(segment_len) {
+            // Safety: see the safety section of this function
+            let bytes = unsafe { slice::from_raw_parts(segment.as_ptr().cast::<u8>(), size_of_val(segment)) };
+            compressed.push(zstd::bulk::compress(bytes, level)?);
+        }
+
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for field in [sig, slice.len() as u64, segment_len as u64, n_segments as u64] {
+            writer.write_all(&field.to_ne_bytes())?;
+        }
+        let mut offset = 0u64;
+        writer.write_all(&offset.to_ne_bytes())?;
+        for segment in &compressed {
+            offset += segment.len() as u64;
+            writer.write_all(&offset.to_ne_bytes())?;
+        }
+        for segment in &compressed {
+            writer.write_all(segment)?;
+        }
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+
+        Self::from_path(sig, path)
+    }
+
+    /// Open a compressed vector at the given path. Returns an error if the signature does not match, or if the file
+    /// is malformed.
+    pub fn from_path(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        let file = File::open(&path)?;
+        // Safety: the file is only read from, and its layout is checked below
+        let mapped = unsafe { Mmap::map(&file)? };
+        #[cfg(unix)]
+        mapped.advise(memmap2::Advice::Random).ok();
+
+        let read_u64 = |offset: usize| -> Result<u64, MmVecError> {
+            let bytes = mapped.get(offset..offset + 8).ok_or_else(malformed)?;
+            Ok(u64::from_ne_bytes(
+                bytes.try_into().expect("slice has the right length"),
+            ))
+        };
+        let actual = read_u64(0)?;
+        if actual != sig {
+            return Err(MmVecError::SignatureMismatch { expected: sig, actual });
+        }
+        let len = read_u64(8)? as usize;
+        let segment_len = read_u64(16)? as usize;
+        let n_segments = read_u64(24)? as usize;
+        if segment_len == 0 || n_segments != len.div_ceil(segment_len) {
+            return Err(malformed().into());
+        }
+
+        let vec = Self {
+            mapped,
+            path,
+            len,
+            segment_len,
+            n_segments,
+            dummy: PhantomData,
+        };
+        let data_size = vec.mapped.len().checked_sub(vec.data_start()).ok_or_else(malformed)?;
+        if vec.offset(n_segments)? != data_size as u64 {
+            return Err(malformed().into());
+        }
+        Ok(vec)
+    }
+
+    /// Path to the backing file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Signature of this vector.
+    #[must_use]
+    pub fn sig(&self) -> u64 {
+        u64::from_ne_bytes(self.mapped[..8].try_into().expect("slice has the right length"))
+    }
+
+    /// Length of this vector.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether this vector is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of items per segment. The last segment may be shorter.
+    #[must_use]
+    pub fn segment_len(&self) -> usize {
+        self.segment_len
+    }
+
+    /// Number of segments.
+    #[must_use]
+    pub fn n_segments(&self) -> usize {
+        self.n_segments
+    }
+
+    /// Size of the backing file in bytes.
+    #[must_use]
+    pub fn storage_size(&self) -> u64 {
+        self.mapped.len() as u64
+    }
+
+    /// Decompress the segment with the given number.
+    ///
+    /// ## Panics
+    /// Panics if the segment number is out of bounds.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn segment(&self, segment: usize) -> Result<Vec<T>, MmVecError> {
+        assert!(segment < self.n_segments, "segment is out of bounds");
+        let start = self.data_start() + self.offset(segment)? as usize;
+        let end = self.data_start() + self.offset(segment + 1)? as usize;
+        let compressed = self.mapped.get(start..end).ok_or_else(malformed)?;
+
+        let n_items = self.segment_len.min(self.len - segment * self.segment_len);
+        let bytes = zstd::bulk::decompress(compressed, n_items * size_of::<T>())?;
+        if bytes.len() != n_items * size_of::<T>() {
+            return Err(malformed().into());
+        }
+        let mut items = Vec::<T>::with_capacity(n_items);
+        // Safety: the buffer holds exactly `n_items` Ts, and Ts are `Copy`
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), items.as_mut_ptr().cast::<u8>(), bytes.len());
+            items.set_len(n_items);
+        }
+        Ok(items)
+    }
+
+    /// Decompress items in the given range. Only the segments overlapping the range are decompressed.
+    ///
+    /// ## Panics
+    /// Panics if the range is out of bounds.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn range(&self, range: Range<usize>) -> Result<Vec<T>, MmVecError> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range is out of bounds"
+        );
+        let mut items = Vec::with_capacity(range.len());
+        if range.is_empty() {
+            return Ok(items);
+        }
+        for segment in range.start / self.segment_len..=(range.end - 1) / self.segment_len {
+            let segment_start = segment * self.segment_len;
+            let decompressed = unsafe { self.segment(segment)? };
+            let start = range.start.saturating_sub(segment_start);
+            let end = (range.end - segment_start).min(decompressed.len());
+            items.extend_from_slice(&decompressed[start..end]);
+        }
+        Ok(items)
+    }
+
+    /// Decompress the whole vector.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn to_vec(&self) -> Result<Vec<T>, MmVecError> {
+        unsafe { self.range(0..self.len) }
+    }
+
+    /// Decompress the whole vector into a new [`MmVec`] at `path`.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn decompress_to(&self, path: PathBuf) -> Result<MmVec<T>, MmVecError>
+    where
+        T: Pod,
+    {
+        let items = unsafe { self.to_vec()? };
+        MmVec::from_slice(self.sig(), &items, path)
+    }
+
+    /// Destroys self, removing the underlying file.
+    pub fn destroy(self) -> Result<(), MmVecError> {
+        let Self { mapped, path, .. } = self;
+        drop(mapped);
+        remove_file(path)?;
+        Ok(())
+    }
+
+    fn data_start(&self) -> usize {
+        Self::HEADER_SIZE + (self.n_segments + 1) * size_of::<u64>()
+    }
+
+    /// Offset of the segment relative to the start of the compressed data.
+    fn offset(&self, segment: usize) -> Result<u64, MmVecError> {
+        let at = Self::HEADER_SIZE + segment * size_of::<u64>();
+        let bytes = self.mapped.get(at..at + size_of::<u64>()).ok_or_else(malformed)?;
+        Ok(u64::from_ne_bytes(
+            bytes.try_into().expect("slice has the right length"),
+        ))
+    }
+}
+
+impl<T> MmVec<T>
+where
+    T: Pod,
+{
+    /// Compress contents of this vector into a new [`CompressedVec`] at `path`. See [`CompressedVec::from_slice`].
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the mmapped file truly contains T, and since `T` must not contain padding.
+    pub unsafe fn compress_to(
+        &self,
+        segment_len: usize,
+        level: i32,
+        path: PathBuf,
+    ) -> Result<CompressedVec<T>, MmVecError> {
+        unsafe { CompressedVec::from_slice(self.sig(), self.as_slice(), segment_len, level, path) }
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "compressed vector file is malformed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_vec_roundtrip() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let items: Vec<(u64, u64)> = (0..1000u64).map(|i| (i / 3, i * 7)).collect();
+        let vec = MmVec::from_slice(42, &items, tempdir.path().join("data.bin")).expect("failed to create memvec");
+        unsafe {
+            let compressed = vec
+                .compress_to(64, DEFAULT_LEVEL, tempdir.path().join("data.zst"))
+                .unwrap();
+            assert_eq!(compressed.n_segments(), 16, "segment count");
+            assert!(
+                compressed.storage_size() < vec.storage_size().unwrap(),
+                "compressed file should be smaller"
+            );
+            assert_eq!(compressed.segment(15).unwrap(), &items[960..], "last, partial segment");
+            assert_eq!(
+                compressed.range(100..300).unwrap(),
+                &items[100..300],
+                "range spanning segments"
+            );
+            assert!(compressed.range(5..5).unwrap().is_empty(), "empty range");
+            drop(compressed);
+
+            let compressed = CompressedVec::<(u64, u64)>::from_path(42, tempdir.path().join("data.zst")).unwrap();
+            assert_eq!(compressed.to_vec().unwrap(), items, "contents after reopening");
+            let decompressed = compressed.decompress_to(tempdir.path().join("restored.bin")).unwrap();
+            assert_eq!(decompressed.as_slice(), &items[..], "decompressed contents");
+            assert!(matches!(
+                CompressedVec::<(u64, u64)>::from_path(0, tempdir.path().join("data.zst")),
+                Err(MmVecError::SignatureMismatch { .. })
+            ));
+        }
+    }
+}
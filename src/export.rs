@@ -0,0 +1,309 @@
+//! Stable export format of lookups, written by [`Lookup::export`](crate::Lookup::export) and read by
+//! [`Lookup::import`](crate::Lookup::import).
+//!
+//! Index files follow the in-memory layout of items, which depends on the parameters of the lookup and may change
+//! between versions of this crate. An export only holds the items, with keys in their original (non-permuted) form, in
+//! a documented layout which does not depend on the platform. It can be imported into a lookup with any parameters, as
+//! long as its keys and values are of the same size, and read by other tools.
+//!
+//! ## Layout (version 1)
+//!
+//! All integers of the header are unsigned and little-endian.
+//!
+//! | Offset | Size            | Field                                   |
+//! |--------|-----------------|-----------------------------------------|
+//! | 0      | 8               | magic: the ASCII bytes `HLOOEXPT`       |
+//! | 8      | 4               | format version                          |
+//! | 12     | 4               | key size in bytes, `KS`                 |
+//! | 16     | 4               | value size in bytes, `VS`               |
+//! | 20     | 4               | reserved, zero                          |
+//! | 24     | 8               | number of items, `N`                    |
+//! | 32     | `N * (KS + VS)` | items                                   |
+//!
+//! An item is its key as big-endian bytes, i.e. the first bit of the key is the most significant bit of the first byte
+//! (see [`KeyBytes`]), followed by its value as little-endian bytes (see [`ToLeBytes`]; a pair is its first element
+//! followed by its second one). Items are in no particular order, and nothing follows them.
+//!
+//! ## Compatibility
+//!
+//! The layout of a version never changes once released. A new version is only introduced for changes which readers of
+//! older versions can't handle, and readers accept every version up to their own, so exports written by this release
+//! can be read by later ones. Exports of later versions are rejected with [`ExportError::UnsupportedVersion`].
+
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+};
+
+use thiserror::Error;
+
+use crate::util::{FromLeBytes, KeyBytes, ToLeBytes};
+
+/// Magic bytes every export starts with.
+pub const MAGIC: [u8; 8] = *b"HLOOEXPT";
+
+/// Version of the format written by this release, and the latest one it reads.
+pub const VERSION: u32 = 1;
+
+/// Size of the header in bytes; items start right after it.
+pub const HEADER_SIZE: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("i/o error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("not an export: magic bytes don't match")]
+    InvalidMagic,
+    #[error("export is of version {0}, but only versions up to {VERSION} are supported")]
+    UnsupportedVersion(u32),
+    #[error("{what} size mismatch: export has {actual} bytes, but {expected} were expected")]
+    SizeMismatch {
+        what: &'static str,
+        expected: usize,
+        actual: u32,
+    },
+    #[error("export declares {expected} items, but {actual} were written")]
+    ItemCountMismatch { expected: u64, actual: u64 },
+}
+
+/// Error of [`Lookup::import`](crate::Lookup::import): either the export can't be read, or its items can't be inserted.
+#[derive(Debug, Error)]
+pub enum ImportError<E> {
+    #[error(transparent)]
+    Export(#[from] ExportError),
+    #[error("failed to insert items: {0:?}")]
+    Insert(E),
+}
+
+/// Header of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportHeader {
+    pub version: u32,
+    pub key_size: u32,
+    pub value_size: u32,
+    pub n_items: u64,
+}
+
+impl ExportHeader {
+    /// Read and check the header at the start of `reader`. Sizes of keys and values are not checked.
+    pub fn read(reader: &mut impl Read) -> Result<Self, ExportError> {
+        let mut bytes = [0; HEADER_SIZE];
+        reader.read_exact(&mut bytes)?;
+        if bytes[0..8] != MAGIC {
+            return Err(ExportError::InvalidMagic);
+        }
+        let u32_at = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let version = u32_at(8);
+        if version == 0 || version > VERSION {
+            return Err(ExportError::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            version,
+            key_size: u32_at(12),
+            value_size: u32_at(16),
+            n_items: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+        })
+    }
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..8].copy_from_slice(&MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.key_size.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.value_size.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.n_items.to_le_bytes());
+        writer.write_all(&bytes)
+    }
+}
+
+/// Writer of an export with a known number of items.
+pub struct ExportWriter<W, K, V> {
+    writer: W,
+    n_items: u64,
+    written: u64,
+    buf: Vec<u8>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<W, K, V> ExportWriter<W, K, V>
+where
+    W: Write,
+    K: KeyBytes,
+    V: ToLeBytes,
+{
+    /// Write the header of an export of `n_items` items.
+    pub fn new(mut writer: W, n_items: u64) -> Result<Self, ExportError> {
+        let header = ExportHeader {
+            version: VERSION,
+            key_size: K::SIZE as u32,
+            value_size: V::SIZE as u32,
+            n_items,
+        };
+        header.write(&mut writer)?;
+        Ok(Self {
+            writer,
+            n_items,
+            written: 0,
+            buf: vec![0; K::SIZE + V::SIZE],
+            _marker: PhantomData,
+        })
+    }
+
+    /// Write an item, with `key` in its original (non-permuted) form.
+    pub fn write_item(&mut self, key: &K, value: &V) -> Result<(), ExportError> {
+        if self.written == self.n_items {
+            return Err(ExportError::ItemCountMismatch {
+                expected: self.n_items,
+                actual: self.written + 1,
+            });
+        }
+        let (key_bytes, value_bytes) = self.buf.split_at_mut(K::SIZE);
+        key_bytes.copy_from_slice(&key.to_be_vec());
+        value.write_le_slice(value_bytes);
+        self.writer.write_all(&self.buf)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Check that all items were written, flush and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, ExportError> {
+        if self.written != self.n_items {
+            return Err(ExportError::ItemCountMismatch {
+                expected: self.n_items,
+                actual: self.written,
+            });
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reader of an export, iterating over its items.
+pub struct ExportReader<R, K, V> {
+    reader: R,
+    header: ExportHeader,
+    remaining: u64,
+    buf: Vec<u8>,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<R, K, V> ExportReader<R, K, V>
+where
+    R: Read,
+    K: KeyBytes,
+    V: FromLeBytes,
+{
+    /// Read the header, and check that keys and values of the export are of the sizes of `K` and `V`.
+    pub fn new(mut reader: R) -> Result<Self, ExportError> {
+        let header = ExportHeader::read(&mut reader)?;
+        for (what, expected, actual) in [("key", K::SIZE, header.key_size), ("value", V::SIZE, header.value_size)] {
+            if expected as u64 != u64::from(actual) {
+                return Err(ExportError::SizeMismatch { what, expected, actual });
+            }
+        }
+        Ok(Self {
+            reader,
+            header,
+            remaining: header.n_items,
+            buf: vec![0; K::SIZE + V::SIZE],
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn header(&self) -> &ExportHeader {
+        &self.header
+    }
+}
+
+impl<R, K, V> Iterator for ExportReader<R, K, V>
+where
+    R: Read,
+    K: KeyBytes,
+    V: FromLeBytes,
+{
+    type Item = Result<(K, V), ExportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        if let Err(e) = self.reader.read_exact(&mut self.buf) {
+            // a truncated export can't be read any further
+            self.remaining = 0;
+            return Some(Err(e.into()));
+        }
+        let (key, value) = self.buf.split_at(K::SIZE);
+        Some(Ok((K::from_be_slice(key), V::from_le_slice(value))))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, usize::try_from(self.remaining).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lookup::lookup_impl::lookup64::Bits;
+
+    #[test]
+    fn layout_is_stable() {
+        let mut writer = ExportWriter::<_, Bits, i32>::new(Vec::new(), 1).unwrap();
+        writer.write_item(&Bits::new([0x0102030405060708]), &-2).unwrap();
+        let bytes = writer.finish().unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            b'H', b'L', b'O', b'O', b'E', b'X', b'P', b'T',
+            1, 0, 0, 0,
+            8, 0, 0, 0,
+            4, 0, 0, 0,
+            0, 0, 0, 0,
+            1, 0, 0, 0, 0, 0, 0, 0,
+            1, 2, 3, 4, 5, 6, 7, 8,
+            0xFE, 0xFF, 0xFF, 0xFF,
+        ];
+        assert_eq!(bytes, expected);
+
+        let mut reader = ExportReader::<_, Bits, i32>::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.header().n_items, 1);
+        assert_eq!(reader.next().unwrap().unwrap(), (Bits::new([0x0102030405060708]), -2));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn invalid_exports_are_rejected() {
+        // header of two items, without them
+        let mut bytes = ExportWriter::<_, Bits, i64>::new(Vec::new(), 2).unwrap().writer;
+        assert!(matches!(
+            ExportReader::<_, Bits, i32>::new(bytes.as_slice()),
+            Err(ExportError::SizeMismatch { what: "value", .. })
+        ));
+        let items: Vec<_> = ExportReader::<_, Bits, i64>::new(bytes.as_slice()).unwrap().collect();
+        assert!(
+            matches!(items.as_slice(), [Err(ExportError::IoError(_))]),
+            "export is truncated"
+        );
+
+        bytes[8] = 2;
+        assert!(matches!(
+            ExportReader::<_, Bits, i64>::new(bytes.as_slice()),
+            Err(ExportError::UnsupportedVersion(2))
+        ));
+        bytes[0] = b'X';
+        assert!(matches!(
+            ExportReader::<_, Bits, i64>::new(bytes.as_slice()),
+            Err(ExportError::InvalidMagic)
+        ));
+
+        let mut writer = ExportWriter::<_, Bits, i64>::new(Vec::new(), 1).unwrap();
+        writer.write_item(&Bits::default(), &1).unwrap();
+        assert!(writer.write_item(&Bits::default(), &2).is_err());
+        assert!(
+            ExportWriter::<_, Bits, i64>::new(Vec::new(), 1)
+                .unwrap()
+                .finish()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,662 @@
+//! File-backed vector implementation which does not use memory maps.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions, remove_file, rename},
+    io,
+    marker::PhantomData,
+    mem::size_of,
+    ops::Range,
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use crate::{
+    index::ScanBound,
+    mmvec::{
+        CREATED_OFFSET, Corruption, FORMAT_MAGIC, FORMAT_VERSION, HEADER_SIZE, LAST_FLUSH_OFFSET, LockMode,
+        MAGIC_OFFSET, MmVecError, UPDATES_OFFSET, VERSION_OFFSET, check_format, lock_file, sync_parent_dir,
+        unix_millis_now,
+    },
+    util::{partition, sort_unstable_by_key},
+};
+
+/// Size of a block cached by [`FileVec`], in bytes.
+pub const CACHE_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Default number of blocks cached by [`FileVec`].
+pub const DEFAULT_CACHE_BLOCKS: usize = 64;
+
+/// Vector stored in a file, accessed with positional reads and writes instead of memory maps.
+///
+/// This is an alternative to [`MmVec`](crate::mmvec::MmVec) for environments where memory maps behave badly (e.g. some
+/// network mounts or container filesystems). The file format is the same, so files can be opened by either of them.
+///
+/// Random reads go through a small cache of fixed-size blocks. Modifications which change the order of items rewrite
+/// the file atomically, the same way as for `MmVec`.
+pub struct FileVec<T>
+where
+    T: Copy,
+{
+    file: File,
+    path: PathBuf,
+    sig: u64,
+    len: usize,
+    generation: u64,
+    read_only: bool,
+    cache: Mutex<BlockCache>,
+    /// Temporary file written by [`FileVec::stage_replace`], and the number of items in it.
+    staged: Option<(File, usize)>,
+    dummy: PhantomData<T>,
+}
+
+impl<T> FileVec<T>
+where
+    T: Copy,
+{
+    /// Creates a new, empty vector.
+    pub fn new_empty(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        Self::from_slice(sig, &[], path)
+    }
+
+    /// Dumps a slice into path.
+    pub fn from_slice(sig: u64, slice: &[T], path: PathBuf) -> Result<Self, MmVecError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(|err| MmVecError::accessing(&path, err))?;
+        lock_file(&file, LockMode::Exclusive).map_err(|err| MmVecError::accessing(&path, err))?;
+        write_contents(&file, sig, 0, &[], slice)?;
+        Ok(Self::new(file, path, sig, slice.len(), 0, false))
+    }
+
+    /// Try to create a vector from the given path. Returns an error if the signature does not match, or if
+    /// the vector is not completely initialized.
+    pub fn from_path(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|err| MmVecError::accessing(&path, err))?;
+        lock_file(&file, LockMode::Exclusive).map_err(|err| MmVecError::accessing(&path, err))?;
+        Self::open(file, sig, path, false)
+    }
+
+    /// Open the vector at the given path for reading only, without locking it. Changes made by the writer are picked up
+    /// with [`FileVec::refresh`].
+    pub fn open_read_only(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        let file = File::open(&path).map_err(|err| MmVecError::accessing(&path, err))?;
+        Self::open(file, sig, path, true)
+    }
+
+    fn new(file: File, path: PathBuf, sig: u64, len: usize, generation: u64, read_only: bool) -> Self {
+        Self {
+            file,
+            path,
+            sig,
+            len,
+            generation,
+            read_only,
+            cache: Mutex::new(BlockCache::new(DEFAULT_CACHE_BLOCKS)),
+            staged: None,
+            dummy: PhantomData,
+        }
+    }
+
+    fn open(file: File, sig: u64, path: PathBuf, read_only: bool) -> Result<Self, MmVecError> {
+        // on Windows, reads of a file locked by a writer fail
+        check_format::<T>(&file, &path)?;
+        let header = read_header(&file).map_err(|err| MmVecError::accessing(&path, err))?;
+        if header.sig != sig {
+            return Err(MmVecError::SignatureMismatch {
+                expected: sig,
+                actual: header.sig,
+            });
+        }
+        let expected_size = HEADER_SIZE + header.capacity * size_of::<T>() as u64;
+        if header.len > header.capacity {
+            let corruption = Corruption::LengthExceedsCapacity {
+                len: header.len,
+                capacity: header.capacity,
+            };
+            return Err(MmVecError::corrupted(&path, corruption));
+        }
+        // writers extend the file before updating the header, so the file may be larger than the header says
+        let size = file.metadata()?.len();
+        if size < expected_size || (!read_only && size != expected_size) {
+            let corruption = Corruption::SizeMismatch {
+                capacity: header.capacity,
+                expected_size,
+                actual_size: size,
+            };
+            return Err(MmVecError::corrupted(&path, corruption));
+        }
+        Ok(Self::new(
+            file,
+            path,
+            sig,
+            // no larger than the file, which fits into memory
+            header.len as usize,
+            header.generation,
+            read_only,
+        ))
+    }
+
+    /// Path to the backing file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Length of this vector.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether this vector is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Signature of this vector.
+    #[must_use]
+    pub fn sig(&self) -> u64 {
+        self.sig
+    }
+
+    /// Whether this vector was opened with [`FileVec::open_read_only`].
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Generation counter, bumped whenever the backing file is replaced.
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Actual size of the backing file in bytes.
+    pub fn storage_size(&self) -> Result<u64, MmVecError> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    /// When the backing file was last modified.
+    pub fn modified(&self) -> Result<SystemTime, MmVecError> {
+        Ok(self.file.metadata()?.modified()?)
+    }
+
+    /// Change the number of cached blocks. Setting it to 0 disables the cache.
+    pub fn set_cache_blocks(&mut self, blocks: usize) {
+        *self.cache.get_mut().unwrap_or_else(PoisonError::into_inner) = BlockCache::new(blocks);
+    }
+
+    /// Get a copy of the item at position `i`.
+    ///
+    /// ## Panics
+    /// Panics if `i` is out of bounds.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn get(&self, i: usize) -> Result<T, MmVecError> {
+        Ok(unsafe { self.read_range(i..i + 1) }?[0])
+    }
+
+    /// Read copies of the items in the given range through the block cache.
+    ///
+    /// ## Panics
+    /// Panics if the range is out of bounds.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn read_range(&self, range: Range<usize>) -> Result<Vec<T>, MmVecError> {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range is out of bounds"
+        );
+        let data_size = (self.len * size_of::<T>()) as u64;
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let items = unsafe {
+            read_items_with(range.len(), |buf| {
+                let mut offset = (range.start * size_of::<T>()) as u64;
+                let mut filled = 0;
+                while filled < buf.len() {
+                    let block = cache.get_or_load(offset / CACHE_BLOCK_SIZE as u64, |no, block| {
+                        let start = no * CACHE_BLOCK_SIZE as u64;
+                        let len = (data_size - start).min(CACHE_BLOCK_SIZE as u64) as usize;
+                        block.resize(len, 0);
+                        read_exact_at(&self.file, block, HEADER_SIZE + start)
+                    })?;
+                    let in_block = (offset % CACHE_BLOCK_SIZE as u64) as usize;
+                    let n = (block.len() - in_block).min(buf.len() - filled);
+                    buf[filled..filled + n].copy_from_slice(&block[in_block..in_block + n]);
+                    filled += n;
+                    offset += n as u64;
+                }
+                Ok(())
+            })?
+        };
+        Ok(items)
+    }
+
+    /// Read the whole vector, bypassing the block cache.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn to_vec(&self) -> Result<Vec<T>, MmVecError> {
+        Ok(unsafe { read_items_with(self.len, |buf| read_exact_at(&self.file, buf, HEADER_SIZE))? })
+    }
+
+    /// Overwrite items starting at position `start`.
+    ///
+    /// ## Panics
+    /// Panics if the items do not fit into the vector.
+    pub fn write_at(&mut self, start: usize, items: &[T]) -> Result<(), MmVecError> {
+        self.check_writable()?;
+        assert!(start + items.len() <= self.len, "items are out of bounds");
+        let offset = (start * size_of::<T>()) as u64;
+        write_all_at(&self.file, as_bytes(items), HEADER_SIZE + offset)?;
+        let end = offset + size_of_val(items) as u64;
+        self.cache_mut()
+            .invalidate(offset / CACHE_BLOCK_SIZE as u64..end.div_ceil(CACHE_BLOCK_SIZE as u64));
+        Ok(())
+    }
+
+    /// Insert items into vector, preserving sorted order. See [`MmVec::insert_sorted`](crate::mmvec::MmVec).
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn insert_sorted<O, F>(&mut self, items: &[T], sort_key: F) -> Result<(), MmVecError>
+    where
+        T: ScanBound,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+    {
+        self.check_writable()?;
+        let mut contents = unsafe { self.to_vec()? };
+        contents.extend_from_slice(items);
+        sort_unstable_by_key(&mut contents, sort_key);
+        self.replace(&contents)
+    }
+
+    /// Remove all items matching the predicate, while preserving the sorted order.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn remove_matching<O, F, S>(&mut self, predicate: F, sort_key: S) -> Result<(), MmVecError>
+    where
+        F: Fn(&T) -> bool,
+        S: Fn(&T) -> O,
+        O: Ord,
+    {
+        self.check_writable()?;
+        let mut contents = unsafe { self.to_vec()? };
+        let split = partition(&mut contents, |el| !predicate(el));
+        contents.truncate(split);
+        contents.sort_unstable_by_key(sort_key);
+        self.replace(&contents)
+    }
+
+    /// Retain only the items for which the predicate returns `true`, preserving their relative order.
+    /// The predicate receives position of the item along with the item itself.
+    ///
+    /// ## Safety
+    /// Unsafe since we can't guarantee that the file truly contains T.
+    pub unsafe fn retain<F>(&mut self, mut predicate: F) -> Result<(), MmVecError>
+    where
+        F: FnMut(usize, &T) -> bool,
+    {
+        self.check_writable()?;
+        let contents = unsafe { self.to_vec()? };
+        let retained: Vec<_> = contents
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| predicate(i, item).then_some(*item))
+            .collect();
+        self.replace(&retained)
+    }
+
+    /// Atomically replace contents of the vector: `items` are written to a temporary sibling file, which is synced to
+    /// disk and then renamed over the backing file.
+    pub fn replace(&mut self, items: &[T]) -> Result<(), MmVecError> {
+        self.stage_replace(items)?;
+        self.commit_replace()
+    }
+
+    /// Write `items` to a temporary file, which replaces the contents of the vector once [`FileVec::commit_replace`] is
+    /// called, so that the replacement doesn't need any more disk space. The replacement is abandoned by
+    /// [`FileVec::discard_replace`] or by staging another one.
+    pub fn stage_replace(&mut self, items: &[T]) -> Result<(), MmVecError> {
+        self.check_writable()?;
+        self.staged = None;
+        let tmp_path = self.tmp_path();
+        let tmp = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&tmp_path)?;
+        lock_file(&tmp, LockMode::Exclusive)?;
+        // user metadata set through `MmVec` is kept
+        let mut header = [0u8; HEADER_SIZE as usize];
+        read_exact_at(&self.file, &mut header, 0)?;
+        let updates = header_field(&header, UPDATES_OFFSET);
+        set_header_field(&mut header, UPDATES_OFFSET, updates + 1);
+        if let Err(err) = write_contents(&tmp, self.sig, self.generation + 1, &header[32..], items) {
+            drop(tmp);
+            let _ = remove_file(&tmp_path);
+            return Err(err.into());
+        }
+        self.staged = Some((tmp, items.len()));
+        Ok(())
+    }
+
+    /// Replace the contents of the vector with the items written by [`FileVec::stage_replace`], if any.
+    pub fn commit_replace(&mut self) -> Result<(), MmVecError> {
+        let Some((tmp, len)) = self.staged.take() else {
+            return Ok(());
+        };
+        // on some platforms, an open file can't be replaced; closing it also releases the lock
+        drop(std::mem::replace(&mut self.file, tmp));
+        rename(self.tmp_path(), &self.path)?;
+        sync_parent_dir(&self.path)?;
+        self.len = len;
+        self.generation += 1;
+        self.cache_mut().clear();
+        Ok(())
+    }
+
+    /// Abandon the replacement staged by [`FileVec::stage_replace`], if any, removing its temporary file.
+    pub fn discard_replace(&mut self) {
+        if self.staged.take().is_some() {
+            let _ = remove_file(self.tmp_path());
+        }
+    }
+
+    /// Re-open the vector if the writer has replaced it since it was opened or last refreshed. Returns whether the
+    /// vector has changed. Does nothing for vectors which are not read-only.
+    pub fn refresh(&mut self) -> Result<bool, MmVecError> {
+        if !self.read_only || crate::mmvec::read_generation(&self.path)? == self.generation {
+            return Ok(false);
+        }
+        *self = Self::open_read_only(self.sig, self.path.clone())?;
+        Ok(true)
+    }
+
+    /// Writes all data to disk.
+    pub fn flush(&self) -> Result<(), MmVecError> {
+        if !self.read_only {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Destroys self, removing the underlying file.
+    pub fn destroy(self) -> Result<(), MmVecError> {
+        let tmp_path = self.tmp_path();
+        let path = self.path.clone();
+        // the lock is released once the file is closed
+        drop(self);
+        remove_file(path)?;
+        // leftover of an interrupted rewrite, if any
+        let _ = remove_file(tmp_path);
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), MmVecError> {
+        if self.read_only {
+            return Err(MmVecError::ReadOnly {});
+        }
+        Ok(())
+    }
+
+    fn cache_mut(&mut self) -> &mut BlockCache {
+        self.cache.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(".tmp");
+        path.into()
+    }
+}
+
+/// Cache of fixed-size blocks of the data section, evicted in insertion order.
+struct BlockCache {
+    blocks: HashMap<u64, Vec<u8>>,
+    order: VecDeque<u64>,
+    capacity: usize,
+    // used instead of the cache if it is disabled
+    scratch: Vec<u8>,
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            blocks: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+            scratch: Vec::new(),
+        }
+    }
+
+    fn get_or_load(&mut self, no: u64, load: impl FnOnce(u64, &mut Vec<u8>) -> io::Result<()>) -> io::Result<&[u8]> {
+        if self.capacity == 0 {
+            load(no, &mut self.scratch)?;
+            return Ok(&self.scratch);
+        }
+        if !self.blocks.contains_key(&no) {
+            let mut block = if self.blocks.len() >= self.capacity {
+                let evicted = self.order.pop_front().expect("cache is not empty");
+                self.blocks.remove(&evicted).expect("evicted block is cached")
+            } else {
+                Vec::with_capacity(CACHE_BLOCK_SIZE)
+            };
+            load(no, &mut block)?;
+            self.blocks.insert(no, block);
+            self.order.push_back(no);
+        }
+        Ok(&self.blocks[&no])
+    }
+
+    fn invalidate(&mut self, blocks: Range<u64>) {
+        for no in blocks {
+            if self.blocks.remove(&no).is_some() {
+                self.order.retain(|cached| *cached != no);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.order.clear();
+    }
+}
+
+struct Header {
+    sig: u64,
+    len: u64,
+    capacity: u64,
+    generation: u64,
+}
+
+fn read_header(file: &File) -> io::Result<Header> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    read_exact_at(file, &mut header, 0)?;
+    let field = |i: usize| u64::from_ne_bytes(header[i * 8..i * 8 + 8].try_into().expect("slice has the right length"));
+    Ok(Header {
+        sig: field(0),
+        len: field(1),
+        capacity: field(2),
+        generation: field(3),
+    })
+}
+
+/// Write a complete vector (header and items) into an empty file, and sync it to disk. `rest` is the part of the
+/// header after the generation.
+fn write_contents<T: Copy>(file: &File, sig: u64, generation: u64, rest: &[u8], items: &[T]) -> io::Result<()> {
+    let len = items.len() as u64;
+    let mut header = [0u8; HEADER_SIZE as usize];
+    for (i, field) in [sig, len, len, generation].into_iter().enumerate() {
+        header[i * 8..i * 8 + 8].copy_from_slice(&field.to_ne_bytes());
+    }
+    header[32..32 + rest.len()].copy_from_slice(rest);
+    if header_field(&header, CREATED_OFFSET) == 0 {
+        set_header_field(&mut header, CREATED_OFFSET, unix_millis_now());
+    }
+    set_header_field(&mut header, LAST_FLUSH_OFFSET, unix_millis_now());
+    set_header_field(&mut header, MAGIC_OFFSET, FORMAT_MAGIC);
+    set_header_field(&mut header, VERSION_OFFSET, FORMAT_VERSION);
+    file.set_len(HEADER_SIZE + size_of_val(items) as u64)?;
+    write_all_at(file, &header, 0)?;
+    write_all_at(file, as_bytes(items), HEADER_SIZE)?;
+    file.sync_all()
+}
+
+fn header_field(header: &[u8], offset: usize) -> u64 {
+    u64::from_ne_bytes(
+        header[offset..offset + 8]
+            .try_into()
+            .expect("slice has the right length"),
+    )
+}
+
+fn set_header_field(header: &mut [u8], offset: usize, value: u64) {
+    header[offset..offset + 8].copy_from_slice(&value.to_ne_bytes());
+}
+
+fn as_bytes<T: Copy>(items: &[T]) -> &[u8] {
+    // Safety: items are stored as raw bytes, the same way as in memory-mapped vectors
+    unsafe { slice::from_raw_parts(items.as_ptr().cast::<u8>(), size_of_val(items)) }
+}
+
+/// Allocate `n` items and fill their bytes with `fill`.
+///
+/// ## Safety
+/// `fill` must initialize the bytes to valid Ts.
+unsafe fn read_items_with<T: Copy>(n: usize, fill: impl FnOnce(&mut [u8]) -> io::Result<()>) -> io::Result<Vec<T>> {
+    let mut items = Vec::<T>::with_capacity(n);
+    let n_bytes = n * size_of::<T>();
+    unsafe {
+        ptr::write_bytes(items.as_mut_ptr().cast::<u8>(), 0, n_bytes);
+        fill(slice::from_raw_parts_mut(items.as_mut_ptr().cast::<u8>(), n_bytes))?;
+        items.set_len(n);
+    }
+    Ok(items)
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_write(file, buf, offset)? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmvec::MmVec;
+
+    use super::*;
+
+    #[test]
+    fn filevec_reads_and_writes_through_cache() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("data.bin");
+        let items: Vec<u64> = (0..20_000).collect();
+        let mut vec = FileVec::from_slice(1, &items, path.clone()).expect("failed to create filevec");
+        vec.set_cache_blocks(2);
+        unsafe {
+            assert_eq!(vec.get(12_345).unwrap(), 12_345, "single item");
+            assert_eq!(
+                vec.read_range(8000..8500).unwrap(),
+                &items[8000..8500],
+                "range spanning blocks"
+            );
+            vec.write_at(8190, &[0, 0, 0, 0]).unwrap();
+            assert_eq!(
+                vec.read_range(8188..8196).unwrap(),
+                [8188, 8189, 0, 0, 0, 0, 8194, 8195],
+                "after write"
+            );
+
+            vec.retain(|_, x| x % 2 == 1).unwrap();
+            assert_eq!(vec.len(), 9_998, "length after retain");
+            vec.insert_sorted(&[0, 2], |x| *x).unwrap();
+            assert_eq!(vec.read_range(0..4).unwrap(), [0, 1, 2, 3], "after insert");
+            assert_eq!(vec.generation(), 2, "generation after rewrites");
+        }
+        drop(vec);
+
+        // the format is shared with memory-mapped vectors
+        let mmvec = MmVec::<u64>::from_path(1, path.clone()).expect("failed to load as memvec");
+        assert_eq!(&mmvec.as_slice()[..4], [0, 1, 2, 3], "contents as memvec");
+        drop(mmvec);
+        let vec = FileVec::<u64>::from_path(1, path).expect("failed to load");
+        assert_eq!(unsafe { vec.to_vec() }.unwrap().len(), 10_000, "length after reload");
+    }
+
+    #[test]
+    fn filevec_read_only_reader_follows_writer() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("data.bin");
+        let mut writer = FileVec::from_slice(0, &[1u64, 3], path.clone()).expect("failed to create filevec");
+        assert!(
+            matches!(
+                FileVec::<u64>::from_path(0, path.clone()),
+                Err(MmVecError::Locked { .. })
+            ),
+            "writer holds the lock"
+        );
+        let mut reader = FileVec::<u64>::open_read_only(0, path).expect("failed to open reader");
+        assert!(!reader.refresh().unwrap(), "nothing changed yet");
+        unsafe { writer.insert_sorted(&[2], |x| *x) }.unwrap();
+        assert!(reader.refresh().unwrap(), "file was replaced");
+        assert_eq!(
+            unsafe { reader.to_vec() }.unwrap(),
+            [1, 2, 3],
+            "reader should see new contents"
+        );
+        assert!(
+            matches!(reader.write_at(0, &[0]), Err(MmVecError::ReadOnly {})),
+            "reader can't modify"
+        );
+    }
+}
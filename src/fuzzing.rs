@@ -0,0 +1,113 @@
+//! Support for fuzzing with [`arbitrary`](https://docs.rs/arbitrary), available with the `arbitrary` feature.
+//!
+//! Keys generated by `make_permutations!` and `init_lookup!` implement `Arbitrary` with this feature, and so do
+//! [`SearchResultItem`] and [`InsertBatch`]. [`fill_lookup`] builds a lookup from any byte stream, e.g. the input of a
+//! fuzz target:
+//!
+//! ```
+//! use hloo::{fuzzing::fill_lookup, lookup::lookup_impl::lookup64::MemLookup};
+//!
+//! let data = [1u8; 100];
+//! let mut lookup = MemLookup::<u32>::default();
+//! let n_items = fill_lookup(&mut lookup, &data).unwrap();
+//! ```
+
+use hloo_core::{
+    BitContainer,
+    arbitrary::{Arbitrary, Result, Unstructured, size_hint},
+};
+
+use crate::{Lookup, index::SearchResultItem, lookup::IndexResult};
+
+/// Batch of items inserted into a lookup at once.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InsertBatch<K, V> {
+    pub items: Vec<(K, V)>,
+}
+
+impl<'a, K, V> Arbitrary<'a> for InsertBatch<K, V>
+where
+    K: Arbitrary<'a>,
+    V: Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self { items: u.arbitrary()? })
+    }
+
+    fn arbitrary_take_rest(u: Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            items: Vec::arbitrary_take_rest(u)?,
+        })
+    }
+}
+
+impl<'a, V> Arbitrary<'a> for SearchResultItem<V>
+where
+    V: Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self::new(u.arbitrary()?, u.arbitrary()?))
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        size_hint::and(V::size_hint(depth), u32::size_hint(depth))
+    }
+}
+
+/// Insert arbitrary batches built from `data` into `lookup`, until the data runs out. Returns the number of inserted
+/// items.
+///
+/// Any data builds a lookup: a trailing part which is too short for an item is ignored.
+pub fn fill_lookup<'a, K, V, M, L>(lookup: &mut L, data: &'a [u8]) -> IndexResult<usize, K, V, M, L::Index>
+where
+    K: BitContainer + Ord + Arbitrary<'a>,
+    V: Clone + Arbitrary<'a>,
+    M: Ord,
+    L: Lookup<K, V, M>,
+{
+    let mut n_items = 0;
+    let mut u = Unstructured::new(data);
+    while !u.is_empty() {
+        let Ok(batch) = InsertBatch::<K, V>::arbitrary(&mut u) else {
+            break;
+        };
+        lookup.insert(&batch.items)?;
+        n_items += batch.items.len();
+    }
+    Ok(n_items)
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use crate::{
+        SimpleLookup,
+        index::{Index, MemIndex},
+    };
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn lookups_are_filled_from_arbitrary_data() {
+        let data: Vec<u8> = (0..1000u32).map(|i| i.wrapping_mul(2654435761) as u8).collect();
+        let indexes = Permutations::get_all_variants()
+            .into_iter()
+            .map(MemIndex::new)
+            .collect();
+        let mut lookup: SimpleLookup<Bits, u16, Mask, MemIndex<_, _, _>> = SimpleLookup::new(indexes);
+        let n_items = fill_lookup(&mut lookup, &data).unwrap();
+        assert!(n_items > 0);
+        for index in lookup.indexes() {
+            assert_eq!(index.data().len(), n_items);
+        }
+        let (key, value) = lookup.indexes()[0].iter().next().map(|(k, v)| (*k, *v)).unwrap();
+        let key = lookup.indexes()[0].permuter().revert(&key);
+        assert!(lookup.search_simple(&key, 0).contains(&SearchResultItem::new(value, 0)));
+
+        assert_eq!(fill_lookup(&mut lookup, &[]).unwrap(), 0, "empty data builds nothing");
+    }
+}
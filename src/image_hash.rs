@@ -0,0 +1,81 @@
+//! Conversions between the pre-defined keys (see [`lookup_impl`](crate::lookup::lookup_impl)) and perceptual hashes
+//! of [`img_hash`].
+//!
+//! Bytes of an [`ImageHash`] are used as the big-endian bytes of a key, so distances between keys are the same as
+//! distances between the hashes. Hashes of other length, e.g. created with a hash size other than 8x8 for
+//! [`lookup64::Bits`] or 16x16 for [`lookup256::Bits`], can't be converted.
+//!
+//! Raw outputs of other pHash and aHash implementations don't need this feature: big-endian bytes convert with
+//! `From<[u8; N]>` and `TryFrom<&[u8]>`, the 64-bit hash of the pHash library converts with `From<u64>`, and arrays of
+//! bits (e.g. the `hash` of Python's `imagehash`, flattened) convert with `FromIterator<bool>`.
+
+use core::array::TryFromSliceError;
+
+use img_hash::{HashBytes, ImageHash};
+
+use crate::lookup::lookup_impl::{lookup64, lookup256};
+
+macro_rules! impl_image_hash_conversions {
+    ($bits:ty, $n_bytes:literal) => {
+        /// Fails if the hash is not `SIZE_BYTES` long.
+        impl<B: HashBytes> TryFrom<&ImageHash<B>> for $bits {
+            type Error = TryFromSliceError;
+
+            fn try_from(hash: &ImageHash<B>) -> Result<Self, Self::Error> {
+                Self::try_from(hash.as_bytes())
+            }
+        }
+
+        impl From<$bits> for ImageHash {
+            fn from(bits: $bits) -> Self {
+                ImageHash::from_bytes(&bits.to_be_bytes()).expect("boxed hashes hold any number of bytes")
+            }
+        }
+
+        impl From<$bits> for ImageHash<[u8; $n_bytes]> {
+            fn from(bits: $bits) -> Self {
+                ImageHash::from_bytes(&bits.to_be_bytes()).expect("hash has the size of the key")
+            }
+        }
+    };
+}
+
+impl_image_hash_conversions!(lookup64::Bits, 8);
+impl_image_hash_conversions!(lookup256::Bits, 32);
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::BitContainer;
+    use img_hash::{
+        HasherConfig,
+        image::{DynamicImage, RgbImage},
+    };
+
+    use super::*;
+
+    fn gradient(shift: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            img_hash::image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x + y + shift) * 2) as u8])
+        }))
+    }
+
+    #[test]
+    fn image_hashes_are_converted_with_distances() {
+        let hasher = HasherConfig::new().to_hasher();
+        let (a, b) = (hasher.hash_image(&gradient(0)), hasher.hash_image(&gradient(40)));
+        let (bits_a, bits_b) = (
+            lookup64::Bits::try_from(&a).unwrap(),
+            lookup64::Bits::try_from(&b).unwrap(),
+        );
+        assert!(a.dist(&b) > 0, "images differ");
+        assert_eq!(bits_a.xor_dist(&bits_b), a.dist(&b));
+        assert_eq!(ImageHash::from(bits_a), a);
+        assert_eq!(ImageHash::<[u8; 8]>::from(bits_b).as_bytes(), b.as_bytes());
+        assert!(lookup256::Bits::try_from(&a).is_err(), "hash is too short");
+
+        let hasher = HasherConfig::new().hash_size(16, 16).to_hasher();
+        let hash = hasher.hash_image(&gradient(0));
+        let bits = lookup256::Bits::try_from(&hash).unwrap();
+        assert_eq!(ImageHash::<[u8; 32]>::from(bits).as_bytes(), hash.as_bytes());
+    }
+}
@@ -0,0 +1,322 @@
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use hloo_core::BitContainer;
+use rkyv::{
+    Archive, Serialize,
+    api::high::{HighSerializer, HighValidator},
+    bytecheck::CheckBytes,
+    rancor,
+    ser::allocator::ArenaHandle,
+    util::AlignedVec,
+};
+use thiserror::Error;
+
+use crate::{
+    DynBitPermuter,
+    mmvec::{MmVec, MmVecError, Pod},
+};
+
+use super::{Index, MemMapIndex, PersistentIndex, ScanBound, SearchResultItem};
+
+/// Alignment of records in the record file. Archived values are accessed in place, so every record starts at an offset
+/// aligned like the buffers values are serialized into.
+const RECORD_ALIGN: usize = 16;
+
+/// Serializer of values inserted into an [`ArchivedIndex`].
+pub type RecordSerializer<'a> = HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>;
+
+/// Validator of values read from an [`ArchivedIndex`].
+pub type RecordValidator<'a> = HighValidator<'a, rancor::Error>;
+
+#[derive(Debug, Error)]
+pub enum ArchivedIndexError {
+    #[error("storage error: {0}")]
+    Storage(#[from] MmVecError),
+    #[error("failed to archive a value: {0}")]
+    Serialize(rancor::Error),
+    #[error("record of {len} bytes at {offset} is out of bounds of the record file")]
+    RecordOutOfBounds { offset: u64, len: u64 },
+    #[error("record of {len} bytes at {offset} is invalid: {error}")]
+    InvalidRecord {
+        offset: u64,
+        len: u64,
+        error: rancor::Error,
+    },
+}
+
+/// Location of an archived value in the record file of an [`ArchivedIndex`]. Stored as the value of its items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct RecordRef {
+    offset: u64,
+    len: u64,
+}
+
+// SAFETY: two integers without padding
+unsafe impl Pod for RecordRef {}
+
+impl RecordRef {
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Memory-mapped index of variable-length values, archived with [rkyv](https://docs.rs/rkyv).
+///
+/// Values don't have to be `Copy`: each one is archived into a record appended to a companion file (stored next to the
+/// index file), and the index stores its location. Searches return references to the archived values in the
+/// memory-mapped record file, without copying or deserializing them. Records are validated on every access, so a
+/// corrupted record file results in an error rather than undefined behavior.
+///
+/// Records are never rewritten: records of removed items stay in the file until the index is rebuilt.
+pub struct ArchivedIndex<K, T, M>
+where
+    (K, RecordRef): Pod,
+{
+    inner: MemMapIndex<K, RecordRef, M>,
+    records: MmVec<u8>,
+    _marker: PhantomData<T>,
+}
+
+impl<K, T, M> ArchivedIndex<K, T, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<RecordValidator<'a>>,
+    M: Copy + Ord,
+{
+    /// Path of the record file belonging to the index at `index_path`.
+    pub fn records_path(index_path: &Path) -> PathBuf {
+        let mut path = index_path.as_os_str().to_owned();
+        path.push(".records");
+        path.into()
+    }
+
+    /// The index of record locations.
+    pub fn index(&self) -> &MemMapIndex<K, RecordRef, M> {
+        &self.inner
+    }
+
+    /// Number of items, including removed items not yet compacted.
+    pub fn len(&self) -> usize {
+        self.inner.data().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Size of the record file in bytes, including records of removed items.
+    pub fn records_size(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Insert items, archiving their values. Records are written to disk before the items referencing them.
+    pub fn insert(&mut self, items: &[(K, T)]) -> Result<(), ArchivedIndexError>
+    where
+        T: for<'a> Serialize<RecordSerializer<'a>>,
+    {
+        if self.records.is_read_only() {
+            return Err(MmVecError::ReadOnly {}.into());
+        }
+        let start = self.records.len();
+        let mut bytes = Vec::new();
+        let mut refs = Vec::with_capacity(items.len());
+        for (key, value) in items {
+            let archived = rkyv::to_bytes::<rancor::Error>(value).map_err(ArchivedIndexError::Serialize)?;
+            let record = RecordRef {
+                offset: (start + bytes.len()) as u64,
+                len: archived.len() as u64,
+            };
+            refs.push((*key, record));
+            bytes.extend_from_slice(&archived);
+            bytes.resize(bytes.len().next_multiple_of(RECORD_ALIGN), 0);
+        }
+        self.records.resize_zeroed(start + bytes.len())?;
+        self.records
+            .slice_mut(start..start + bytes.len())
+            .copy_from_slice(&bytes);
+        // a crash after this leaves unreferenced records, but no references to missing ones
+        self.records.flush()?;
+        self.inner.insert(&refs)?;
+        Ok(())
+    }
+
+    /// Remove items by keys. Their records stay in the record file.
+    pub fn remove(&mut self, keys: &[K]) -> Result<(), ArchivedIndexError> {
+        Ok(self.inner.remove(keys)?)
+    }
+
+    /// Physically remove deleted items from the index. See [`Index::compact`].
+    pub fn compact(&mut self) -> Result<(), ArchivedIndexError> {
+        Ok(self.inner.compact()?)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    /// Access the archived value at `record`.
+    pub fn get(&self, record: &RecordRef) -> Result<&T::Archived, ArchivedIndexError> {
+        let (offset, len) = (record.offset as usize, record.len as usize);
+        let bytes = self.records.as_slice().get(offset..offset.saturating_add(len)).ok_or(
+            ArchivedIndexError::RecordOutOfBounds {
+                offset: record.offset,
+                len: record.len,
+            },
+        )?;
+        rkyv::access::<T::Archived, rancor::Error>(bytes).map_err(|error| ArchivedIndexError::InvalidRecord {
+            offset: record.offset,
+            len: record.len,
+            error,
+        })
+    }
+
+    /// Search for items within `distance` of `key`, returning references to their archived values.
+    pub fn search(&self, key: &K, distance: u32) -> Result<Vec<SearchResultItem<&T::Archived>>, ArchivedIndexError> {
+        self.inner
+            .get_candidates(key)
+            .scan(distance)
+            .into_iter()
+            .map(|item| Ok(SearchResultItem::new(self.get(item.data())?, item.distance())))
+            .collect()
+    }
+}
+
+impl<K, T, M> PersistentIndex<K, M> for ArchivedIndex<K, T, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<RecordValidator<'a>>,
+    M: Copy + Ord,
+{
+    type Error = ArchivedIndexError;
+
+    fn create(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        let records = MmVec::new_empty(sig, Self::records_path(path))?;
+        Ok(Self {
+            inner: MemMapIndex::create(permuter, sig, path)?,
+            records,
+            _marker: PhantomData,
+        })
+    }
+
+    fn load(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        let records = MmVec::from_path(sig, Self::records_path(path))?;
+        Ok(Self {
+            inner: MemMapIndex::load(permuter, sig, path)?,
+            records,
+            _marker: PhantomData,
+        })
+    }
+
+    fn load_read_only(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        let records = MmVec::open_read_only(sig, Self::records_path(path))?;
+        Ok(Self {
+            inner: MemMapIndex::load_read_only(permuter, sig, path)?,
+            records,
+            _marker: PhantomData,
+        })
+    }
+
+    fn persist(&self) -> Result<(), Self::Error> {
+        self.records.flush()?;
+        Ok(self.inner.persist()?)
+    }
+
+    fn reload(&mut self) -> Result<bool, Self::Error> {
+        if !self.inner.reload()? {
+            return Ok(false);
+        }
+        // records are appended in place, so the mapping may not cover the new ones
+        self.records = MmVec::open_read_only(self.records.sig(), self.records.path().to_path_buf())?;
+        Ok(true)
+    }
+
+    fn is_stale(&self) -> bool {
+        self.inner.is_stale()
+    }
+
+    fn destroy(self) -> Result<(), Self::Error> {
+        self.records.destroy()?;
+        Ok(self.inner.destroy()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[derive(Archive, Serialize)]
+    struct Metadata {
+        name: String,
+        tags: Vec<u32>,
+    }
+
+    fn metadata(name: &str, n_tags: u32) -> Metadata {
+        Metadata {
+            name: name.to_string(),
+            tags: (0..n_tags).collect(),
+        }
+    }
+
+    #[test]
+    fn archived_index_returns_archived_values() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("index.dat");
+        let keys = [
+            Bits::new([0b11111000100010_001000100010001000u32]),
+            Bits::new([0b11111000100010_001000100011111000u32]),
+            Bits::new([0b11001000111110_001000100010001010u32]),
+        ];
+        let mut index = ArchivedIndex::<Bits, Metadata, Mask>::create(Permutations::get_variant(1), 0, &path).unwrap();
+        index
+            .insert(&[(keys[0], metadata("first", 3)), (keys[1], metadata("second", 0))])
+            .unwrap();
+        index.insert(&[(keys[2], metadata("third", 100))]).unwrap();
+        assert_eq!(index.records_size() % RECORD_ALIGN, 0);
+
+        let results = index.search(&keys[0], 3).unwrap();
+        let names: Vec<_> = results.iter().map(|item| item.data().name.as_str()).collect();
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(results[0].data().tags.as_slice(), [0, 1, 2]);
+
+        let mut reader =
+            ArchivedIndex::<Bits, Metadata, Mask>::load_read_only(Permutations::get_variant(1), 0, &path).unwrap();
+        assert_eq!(reader.search(&keys[2], 0).unwrap()[0].data().tags.len(), 100);
+        index.remove(&[keys[1]]).unwrap();
+        index.insert(&[(keys[1], metadata("second again", 1))]).unwrap();
+        assert!(reader.reload().unwrap(), "writer has changed the index");
+        let results = reader.search(&keys[1], 0).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data().name, "second again");
+
+        let invalid = RecordRef {
+            offset: index.records_size() as u64,
+            len: 16,
+        };
+        assert!(matches!(
+            index.get(&invalid),
+            Err(ArchivedIndexError::RecordOutOfBounds { .. })
+        ));
+        drop(reader);
+        index.destroy().unwrap();
+        assert!(!ArchivedIndex::<Bits, Metadata, Mask>::records_path(&path).exists());
+    }
+}
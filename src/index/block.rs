@@ -0,0 +1,153 @@
+use std::{cmp::Ordering, ops::Range};
+
+use super::BlockLocator;
+
+/// A view into a contiguous range of index entries, independent of the memory layout of the index.
+#[derive(Debug)]
+pub enum Block<'a, K, V> {
+    /// Keys and values are interleaved: `[(K, V)]`.
+    Interleaved(&'a [(K, V)]),
+    /// Keys and values are stored in separate arrays of the same length (structure-of-arrays).
+    Split(&'a [K], &'a [V]),
+}
+
+impl<K, V> Clone for Block<'_, K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for Block<'_, K, V> {}
+
+impl<'a, K, V> Block<'a, K, V> {
+    /// Number of entries in this block.
+    pub fn len(&self) -> usize {
+        match self {
+            Block::Interleaved(data) => data.len(),
+            Block::Split(keys, _) => keys.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Key of the `i`-th entry.
+    pub fn key(&self, i: usize) -> &'a K {
+        match self {
+            Block::Interleaved(data) => &data[i].0,
+            Block::Split(keys, _) => &keys[i],
+        }
+    }
+
+    /// Value of the `i`-th entry.
+    pub fn value(&self, i: usize) -> &'a V {
+        match self {
+            Block::Interleaved(data) => &data[i].1,
+            Block::Split(_, values) => &values[i],
+        }
+    }
+
+    /// Key and value of the `i`-th entry.
+    pub fn get(&self, i: usize) -> (&'a K, &'a V) {
+        (self.key(i), self.value(i))
+    }
+
+    /// Get a sub-block.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        match self {
+            Block::Interleaved(data) => Block::Interleaved(&data[range]),
+            Block::Split(keys, values) => Block::Split(&keys[range.clone()], &values[range]),
+        }
+    }
+
+    /// Iterate over keys and values in this block.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a K, &'a V)> + use<'a, K, V> {
+        let this = *self;
+        (0..this.len()).map(move |i| this.get(i))
+    }
+
+    /// Iterate over keys in this block.
+    pub fn keys(&self) -> impl Iterator<Item = &'a K> + use<'a, K, V> {
+        let this = *self;
+        (0..this.len()).map(move |i| this.key(i))
+    }
+
+    /// Position of the first entry whose key does not satisfy `pred`, assuming the block is partitioned by it.
+    pub fn partition_point(&self, pred: impl Fn(&K) -> bool) -> usize {
+        match self {
+            Block::Interleaved(data) => data.partition_point(|(key, _)| pred(key)),
+            Block::Split(keys, _) => keys.partition_point(pred),
+        }
+    }
+
+    /// Get the underlying slice, if the block is interleaved.
+    pub fn as_interleaved(&self) -> Option<&'a [(K, V)]> {
+        match self {
+            Block::Interleaved(data) => Some(data),
+            Block::Split(..) => None,
+        }
+    }
+
+    /// Copy entries into a vector.
+    pub fn to_vec(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    /// Locate a sub-block for which `f` returns `Ordering::Equal` using the given `BlockLocator`.
+    pub fn locate_by(&self, locator: BlockLocator, f: impl Fn(&K) -> Ordering) -> Self {
+        self.slice(self.locate_range_by(locator, f))
+    }
+
+    /// Same as [`Block::locate_by`], but returns the range of positions instead of a sub-block.
+    pub fn locate_range_by(&self, locator: BlockLocator, f: impl Fn(&K) -> Ordering) -> Range<usize> {
+        match self {
+            Block::Interleaved(data) => locator.locate_range_by(data, |(key, _)| f(key)),
+            Block::Split(keys, _) => locator.locate_range_by(keys, f),
+        }
+    }
+}
+
+impl<'a, K, V> From<&'a [(K, V)]> for Block<'a, K, V> {
+    fn from(value: &'a [(K, V)]) -> Self {
+        Block::Interleaved(value)
+    }
+}
+
+impl<'a, K, V> From<&'a Vec<(K, V)>> for Block<'a, K, V> {
+    fn from(value: &'a Vec<(K, V)>) -> Self {
+        Block::Interleaved(value)
+    }
+}
+
+impl<'a, K, V, const N: usize> From<&'a [(K, V); N]> for Block<'a, K, V> {
+    fn from(value: &'a [(K, V); N]) -> Self {
+        Block::Interleaved(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_interleaved_blocks_are_equivalent() {
+        let data = vec![(1u32, 0), (2u32, 1), (2u32, 2), (3u32, 3), (4u32, 4)];
+        let (keys, values): (Vec<_>, Vec<_>) = data.iter().copied().unzip();
+        let interleaved = Block::from(&data);
+        let split = Block::Split(&keys, &values);
+
+        assert_eq!(interleaved.len(), split.len(), "len");
+        assert_eq!(interleaved.to_vec(), split.to_vec(), "contents");
+        for (name, block) in [("interleaved", interleaved), ("split", split)] {
+            let located = block.locate_by(BlockLocator::BinarySearch, |k| k.cmp(&2));
+            assert_eq!(located.to_vec(), &data[1..3], "{name}: located block");
+            let located = block.locate_by(BlockLocator::BinarySearch, |k| k.cmp(&5));
+            assert!(located.is_empty(), "{name}: missing block");
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::Path;
+
+use hloo_core::{BitContainer, BitPermuter};
+
+use crate::{DynBitPermuter, filevec::FileVec, metrics, mmvec::MmVecError};
+
+use super::{Block, BlockLocator, Index, IndexStats, MemIndex, PersistentIndex, ScanBound, StorageStats};
+
+/// Index which is kept in memory and stored in a file without memory-mapping it.
+///
+/// This is an alternative to [`MemMapIndex`](super::MemMapIndex) for environments where memory maps behave badly (e.g.
+/// some network mounts). Searches are served from memory, like in [`MemIndex`]; every modification atomically rewrites
+/// the file through [`FileVec`]. The file format is the same as for `MemMapIndex`, without the tombstones.
+pub struct FileIndex<K, V, M>
+where
+    (K, V): Copy,
+{
+    inner: MemIndex<K, V, M>,
+    storage: FileVec<(K, V)>,
+    /// Data after the insert staged by [`Index::stage_insert`], which is written to the staged file.
+    staged: Option<Vec<(K, V)>>,
+}
+
+impl<K, V, M> FileIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord,
+{
+    fn with_storage(permuter: DynBitPermuter<K, M>, storage: FileVec<(K, V)>) -> Result<Self, MmVecError> {
+        // Safety: the signature of the file was checked by `FileVec`
+        let data = unsafe { storage.to_vec()? };
+        Ok(Self {
+            inner: MemIndex::with_data(permuter, data),
+            storage,
+            staged: None,
+        })
+    }
+
+    /// Write the in-memory data to the file.
+    fn store(&mut self) -> Result<(), MmVecError> {
+        let data = self.inner.data();
+        let items = data.as_interleaved().expect("memory index data is interleaved");
+        self.storage.replace(items)
+    }
+
+    pub fn destroy(self) -> Result<(), MmVecError> {
+        self.storage.destroy()
+    }
+}
+
+impl<K, V, M> Index<K, V, M> for FileIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord,
+{
+    type Error = MmVecError;
+
+    fn permuter(&self) -> &dyn BitPermuter<K, M> {
+        self.inner.permuter()
+    }
+
+    fn block_locator(&self) -> BlockLocator {
+        self.inner.block_locator()
+    }
+
+    fn data(&self) -> Block<'_, K, V> {
+        self.inner.data()
+    }
+
+    fn stats(&self) -> &IndexStats {
+        self.inner.stats()
+    }
+
+    fn storage_stats(&self) -> StorageStats {
+        // the file is rewritten by every modification, so it was last written when it was last modified
+        StorageStats {
+            bytes_on_disk: self.storage.storage_size().unwrap_or(0),
+            last_flush: self.storage.modified().ok(),
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh();
+    }
+
+    fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        if self.storage.is_read_only() {
+            return Err(MmVecError::ReadOnly {});
+        }
+        // MemIndex is infallible
+        let _ = self.inner.insert(items);
+        self.store()
+    }
+
+    fn stage_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.staged = None;
+        let data = self.inner.data_with(items);
+        self.storage.stage_replace(&data)?;
+        self.staged = Some(data);
+        Ok(())
+    }
+
+    fn commit_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        let Some(data) = self.staged.take() else {
+            return Ok(());
+        };
+        metrics::insert(items.len());
+        self.storage.commit_replace()?;
+        self.inner.set_data(data);
+        Ok(())
+    }
+
+    fn discard_insert(&mut self) {
+        self.staged = None;
+        self.storage.discard_replace();
+    }
+
+    fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
+        if self.storage.is_read_only() {
+            return Err(MmVecError::ReadOnly {});
+        }
+        let _ = self.inner.remove(keys);
+        self.store()
+    }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        if self.storage.is_read_only() {
+            return Err(MmVecError::ReadOnly {});
+        }
+        let _ = self.inner.retain(pred);
+        self.store()
+    }
+}
+
+impl<K, V, M> PersistentIndex<K, M> for FileIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord,
+{
+    type Error = MmVecError;
+
+    fn create(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        Self::with_storage(permuter, FileVec::new_empty(sig, path.to_path_buf())?)
+    }
+
+    fn load(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        Self::with_storage(permuter, FileVec::from_path(sig, path.to_path_buf())?)
+    }
+
+    fn load_read_only(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        Self::with_storage(permuter, FileVec::open_read_only(sig, path.to_path_buf())?)
+    }
+
+    fn persist(&self) -> Result<(), Self::Error> {
+        self.storage.flush()
+    }
+
+    fn reload(&mut self) -> Result<bool, Self::Error> {
+        if !self.storage.refresh()? {
+            return Ok(false);
+        }
+        // Safety: the signature of the file was checked by `FileVec`
+        let data = unsafe { self.storage.to_vec()? };
+        self.inner.set_data(data);
+        Ok(true)
+    }
+
+    fn destroy(self) -> Result<(), Self::Error> {
+        FileIndex::destroy(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn test_file_index_is_persisted() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("index.dat");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        let mut index = FileIndex::create(Permutations::get_variant(1), 0, &path).unwrap();
+        index.insert(&data).unwrap();
+        index.remove(&[data[1].0]).unwrap();
+        let mut reader = FileIndex::<Bits, i32, Mask>::load_read_only(Permutations::get_variant(1), 0, &path).unwrap();
+        assert_eq!(
+            reader.data().to_vec(),
+            index.data().to_vec(),
+            "reader should see the same data"
+        );
+        assert!(reader.insert(&data).is_err(), "reader should not be able to insert");
+
+        index.insert(&data[1..2]).unwrap();
+        assert!(reader.reload().unwrap(), "writer has inserted items");
+        assert!(reader.contains_key(&data[1].0), "reader should see new items");
+        drop(reader);
+        drop(index);
+
+        let index = FileIndex::<Bits, i32, Mask>::load(Permutations::get_variant(1), 0, &path).unwrap();
+        let mut expected: Vec<_> = data.iter().map(|(k, v)| (index.permuter().apply(k), *v)).collect();
+        expected.sort_unstable();
+        assert_eq!(index.data().to_vec(), expected, "data after reload");
+        index.destroy().unwrap();
+    }
+}
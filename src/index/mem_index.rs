@@ -1,16 +1,37 @@
-use std::{collections::BTreeSet, marker::PhantomData};
+use std::{
+    collections::BTreeSet,
+    marker::PhantomData,
+    sync::{Arc, OnceLock, PoisonError, RwLock},
+};
 
 use hloo_core::{BitContainer, BitPermuter};
 
-use crate::DynBitPermuter;
+use crate::{
+    metrics,
+    util::{merge_sorted_by_key, sort_unstable_by_key},
+    DynBitPermuter,
+};
 
-use super::{extract_key, BlockLocator, Index, IndexStats};
+use super::{candidates_in, extract_key, Block, BlockLocator, Candidates, Index, IndexStats, ScanBound};
 
+/// Data of a [`MemIndex`] published to its handles.
+type Published<K, V> = Arc<RwLock<Arc<Vec<(K, V)>>>>;
+
+/// In-memory index.
+///
+/// Data is stored behind an [`Arc`] and modified copy-on-write, so readers can take a [`MemIndexSnapshot`] and search
+/// it on any thread without locking, while the index itself keeps accepting inserts. Readers which should follow the
+/// inserts get a [`MemIndexHandle`], which every modification is published to.
+///
+/// While the data is shared with snapshots, modifications build the new data from the shared one, instead of copying
+/// it first and then modifying the copy.
 pub struct MemIndex<K, V, M> {
-    permuter: DynBitPermuter<K, M>,
+    permuter: Arc<dyn BitPermuter<K, M>>,
     block_locator: BlockLocator,
     current_stats: IndexStats,
-    data: Vec<(K, V)>,
+    data: Arc<Vec<(K, V)>>,
+    /// Data published to handles, if any were taken.
+    published: OnceLock<Published<K, V>>,
     _dummy: PhantomData<M>,
 }
 
@@ -21,25 +42,110 @@ where
 {
     pub fn new(permuter: DynBitPermuter<K, M>) -> Self {
         Self {
-            permuter,
+            permuter: Arc::from(permuter),
             block_locator: BlockLocator::BinarySearch,
             current_stats: IndexStats::default(),
-            data: Vec::new(),
+            data: Arc::new(Vec::new()),
+            published: OnceLock::new(),
             _dummy: PhantomData,
         }
     }
+
+    /// Create an index over `data`, which must already be permuted and sorted by key.
+    #[cfg(any(feature = "fs", feature = "serde"))]
+    pub(crate) fn with_data(permuter: DynBitPermuter<K, M>, data: Vec<(K, V)>) -> Self {
+        Self {
+            data: Arc::new(data),
+            ..Self::new(permuter)
+        }
+    }
+
+    /// Replace the data, which must already be permuted and sorted by key.
+    #[cfg(feature = "fs")]
+    pub(crate) fn set_data(&mut self, data: Vec<(K, V)>) {
+        self.replace_data(data);
+    }
+
+    /// Get the data after inserting `items`, without modifying the index.
+    #[cfg(feature = "fs")]
+    pub(crate) fn data_with(&self, items: &[(K, V)]) -> Vec<(K, V)>
+    where
+        K: Ord + ScanBound,
+        V: Copy + ScanBound,
+    {
+        let mut data = Vec::with_capacity(self.data.len() + items.len());
+        data.extend_from_slice(&self.data);
+        data.extend(items.iter().map(|(k, v)| (self.permuter.apply(k), *v)));
+        sort_unstable_by_key(&mut data, extract_key);
+        data
+    }
+
+    /// Get the permuted items, sorted by key.
+    #[cfg(feature = "serde")]
+    pub(crate) fn items(&self) -> &[(K, V)] {
+        &self.data
+    }
+
+    /// Take an immutable snapshot of the current data. This is cheap: the data is not copied until the next
+    /// modification of the index, which will then operate on a private copy.
+    pub fn snapshot(&self) -> MemIndexSnapshot<K, V, M> {
+        MemIndexSnapshot {
+            permuter: self.permuter.clone(),
+            block_locator: self.block_locator,
+            data: self.data.clone(),
+        }
+    }
+
+    /// Get a handle to the data, which follows all modifications of the index. See [`MemIndexHandle`].
+    pub fn handle(&self) -> MemIndexHandle<K, V, M> {
+        let published = self.published.get_or_init(|| Arc::new(RwLock::new(self.data.clone())));
+        MemIndexHandle {
+            permuter: self.permuter.clone(),
+            block_locator: self.block_locator,
+            data: published.clone(),
+        }
+    }
+
+    fn replace_data(&mut self, data: Vec<(K, V)>) {
+        self.data = Arc::new(data);
+        self.publish();
+    }
+
+    /// Keep the items for which `keep` returns `true`, in place unless the data is shared.
+    fn retain_data(&mut self, keep: impl Fn(&(K, V)) -> bool)
+    where
+        V: Copy,
+    {
+        match Arc::get_mut(&mut self.data) {
+            Some(data) => {
+                data.retain(keep);
+                self.publish();
+            }
+            None => {
+                let data = self.data.iter().filter(|item| keep(item)).copied().collect();
+                self.replace_data(data);
+            }
+        }
+    }
+
+    /// Make the current data visible to handles.
+    fn publish(&self) {
+        if let Some(published) = self.published.get() {
+            *published.write().unwrap_or_else(PoisonError::into_inner) = self.data.clone();
+        }
+    }
 }
 
 impl<K, V, M> Index<K, V, M> for MemIndex<K, V, M>
 where
-    K: Copy + BitContainer + Ord,
-    V: Copy,
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
     M: Copy + Ord,
 {
     type Error = ();
 
-    fn data(&self) -> &[(K, V)] {
-        &self.data
+    fn data(&self) -> Block<'_, K, V> {
+        Block::Interleaved(&self.data)
     }
 
     fn permuter(&self) -> &dyn BitPermuter<K, M> {
@@ -59,24 +165,113 @@ where
     }
 
     fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
-        let items_permuted = items.iter().map(|(k, v)| (self.permuter.apply(k), *v));
-        self.data.extend(items_permuted);
-        self.data.sort_unstable_by_key(extract_key);
+        metrics::insert(items.len());
+        let Some(data) = Arc::get_mut(&mut self.data) else {
+            let mut permuted: Vec<_> = items.iter().map(|(k, v)| (self.permuter.apply(k), *v)).collect();
+            sort_unstable_by_key(&mut permuted, extract_key);
+            let Some(&filler) = permuted.first() else {
+                return Ok(());
+            };
+            let mut merged = vec![filler; self.data.len() + permuted.len()];
+            merge_sorted_by_key(&self.data, &permuted, &mut merged, extract_key);
+            self.replace_data(merged);
+            return Ok(());
+        };
+        let start = data.len();
+        data.extend_from_slice(items);
+        for (k, _) in &mut data[start..] {
+            self.permuter.apply_inplace(k);
+        }
+        sort_unstable_by_key(data, extract_key);
+        self.publish();
         Ok(())
     }
 
     fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
-        let set: BTreeSet<_> = keys.iter().map(|k| self.permuter.apply(k)).collect();
-        self.data.retain(|(k, _)| !set.contains(k));
+        let set: BTreeSet<_> = self.permuter.apply_many(keys).into_iter().collect();
+        self.retain_data(|(k, _)| !set.contains(k));
         Ok(())
     }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        let permuter = self.permuter.clone();
+        self.retain_data(|(k, v)| pred(&permuter.revert(k), v));
+        Ok(())
+    }
+}
+
+/// Handle to the data of a [`MemIndex`], which readers on other threads can search without locking the index: every
+/// modification of the index is published to it as soon as it is complete. Cheap to clone.
+pub struct MemIndexHandle<K, V, M> {
+    permuter: Arc<dyn BitPermuter<K, M>>,
+    block_locator: BlockLocator,
+    data: Published<K, V>,
+}
+
+impl<K, V, M> Clone for MemIndexHandle<K, V, M> {
+    fn clone(&self) -> Self {
+        Self {
+            permuter: self.permuter.clone(),
+            block_locator: self.block_locator,
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<K, V, M> MemIndexHandle<K, V, M> {
+    /// Take a snapshot of the data most recently published by the index. The lock is only held to clone an [`Arc`],
+    /// so this never waits for a modification of the index, and the data is not copied.
+    pub fn snapshot(&self) -> MemIndexSnapshot<K, V, M> {
+        MemIndexSnapshot {
+            permuter: self.permuter.clone(),
+            block_locator: self.block_locator,
+            data: self.data.read().unwrap_or_else(PoisonError::into_inner).clone(),
+        }
+    }
+}
+
+/// Immutable, cheaply clonable view of a [`MemIndex`] at some point in time. Can be sent to and shared between
+/// threads; unaffected by subsequent modifications of the index.
+pub struct MemIndexSnapshot<K, V, M> {
+    permuter: Arc<dyn BitPermuter<K, M>>,
+    block_locator: BlockLocator,
+    data: Arc<Vec<(K, V)>>,
+}
+
+impl<K, V, M> Clone for MemIndexSnapshot<K, V, M> {
+    fn clone(&self) -> Self {
+        Self {
+            permuter: self.permuter.clone(),
+            block_locator: self.block_locator,
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<K, V, M> MemIndexSnapshot<K, V, M>
+where
+    K: BitContainer,
+    V: Clone,
+    M: Ord,
+{
+    /// Get data as a block.
+    pub fn data(&self) -> Block<'_, K, V> {
+        Block::Interleaved(&self.data)
+    }
+
+    /// Retrieve candidates for a given search.
+    pub fn get_candidates(&self, key: &K) -> Candidates<'_, K, V> {
+        candidates_in(self.permuter.as_ref(), self.block_locator, self.data(), key)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use hloo_core::{BitContainer, BitPermuter};
+    use hloo_core::{BitContainer, BitPermuter, Pod};
     use hloo_macros::make_permutations;
 
+    use crate::index::SearchResultItem;
+
     use super::*;
 
     make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
@@ -93,7 +288,165 @@ mod tests {
             (Bits::new([0b10011110100010_001000100010001100u32]), 4),
         ];
         index.insert(&data).unwrap();
-        let result = index.get_candidates(&data[2].0).block;
+        let result = index.get_candidates(&data[2].0).block().to_vec();
         assert_eq!(result, &data[2..3]);
     }
+
+    #[test]
+    fn test_mem_index_retain_works_correctly() {
+        let mut index = MemIndex::new(Permutations::get_variant(1));
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+            (Bits::new([0b10011110100010_001000100010001100u32]), 4),
+        ];
+        index.insert(&data).unwrap();
+        index.retain(|k, v| *v != 2 && *k != data[3].0).unwrap();
+        let mut expected: Vec<_> = [data[0], data[2]]
+            .iter()
+            .map(|(k, v)| (index.permuter().apply(k), *v))
+            .collect();
+        expected.sort_unstable_by_key(extract_key);
+        assert_eq!(index.data().to_vec(), expected);
+    }
+
+    #[test]
+    fn test_mem_index_contains_key_works_correctly() {
+        let mut index = MemIndex::new(Permutations::get_variant(2));
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        index.insert(&data[..2]).unwrap();
+        assert!(index.contains_key(&data[0].0), "inserted key 0");
+        assert!(index.contains_key(&data[1].0), "inserted key 1");
+        assert!(!index.contains_key(&data[2].0), "key which was not inserted");
+        assert!(!index.contains_key(&Bits::MAX), "missing key");
+    }
+
+    #[test]
+    fn test_mem_index_snapshot_is_not_affected_by_inserts() {
+        let mut index = MemIndex::new(Permutations::get_variant(0));
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        index.insert(&data[..1]).unwrap();
+        let snapshot = index.snapshot();
+        let reader = {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || snapshot.get_candidates(&data[0].0).scan(0))
+        };
+        index.insert(&data[1..]).unwrap();
+        index.remove(&[data[0].0]).unwrap();
+
+        assert_eq!(
+            reader.join().unwrap(),
+            vec![SearchResultItem::new(0, 0)],
+            "reader thread"
+        );
+        assert_eq!(snapshot.data().len(), 1, "snapshot data");
+        assert!(
+            snapshot.get_candidates(&data[1].0).scan(0).is_empty(),
+            "snapshot should not see inserts"
+        );
+        assert_eq!(index.data().len(), 1, "index data");
+        assert_eq!(
+            index.get_candidates(&data[1].0).scan(0),
+            vec![SearchResultItem::new(3, 0)]
+        );
+    }
+
+    #[test]
+    fn test_mem_index_handle_follows_modifications() {
+        let mut index = MemIndex::new(Permutations::get_variant(0));
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        index.insert(&data[..1]).unwrap();
+        let handle = index.handle();
+        let snapshot = handle.snapshot();
+
+        index.insert(&data[1..]).unwrap();
+        let reader = {
+            let handle = handle.clone();
+            std::thread::spawn(move || handle.snapshot().get_candidates(&data[1].0).scan(0))
+        };
+        assert_eq!(
+            reader.join().unwrap(),
+            vec![SearchResultItem::new(3, 0)],
+            "reader thread"
+        );
+        assert_eq!(
+            handle.snapshot().data().to_vec(),
+            index.data().to_vec(),
+            "published data"
+        );
+        assert_eq!(snapshot.data().len(), 1, "snapshot taken before the insert");
+
+        index.remove(&[data[0].0]).unwrap();
+        assert!(
+            handle.snapshot().get_candidates(&data[0].0).scan(0).is_empty(),
+            "removed item"
+        );
+        assert_eq!(handle.snapshot().data().len(), 1, "published data after removal");
+    }
+
+    #[test]
+    fn test_mem_index_iter_blocks_groups_by_mask() {
+        let mut index = MemIndex::new(Permutations::get_variant(0));
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+            (Bits::new([0b10011110100010_001000100010001100u32]), 4),
+        ];
+        index.insert(&data).unwrap();
+        assert_eq!(
+            index.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            index.data().to_vec(),
+            "iter"
+        );
+
+        let blocks: Vec<_> = index
+            .iter_blocks()
+            .map(|(mask, items)| (mask, items.collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(blocks.len(), 3, "number of blocks");
+        assert_eq!(
+            blocks.iter().map(|(_, b)| b.len()).sum::<usize>(),
+            data.len(),
+            "total items"
+        );
+        for (mask, block) in &blocks {
+            assert!(
+                block.iter().all(|(k, _)| index.permuter().mask(k) == *mask),
+                "block mask"
+            );
+        }
+        assert!(
+            blocks.windows(2).all(|w| w[0].0 < w[1].0),
+            "blocks should be sorted by mask"
+        );
+        assert_eq!(blocks[2].1.len(), 2, "block with two items");
+        assert_eq!(index.first(), Some(blocks[0].1[0]), "first");
+        assert_eq!(index.last(), Some(blocks[2].1[1]), "last");
+        assert_eq!(
+            index.range(blocks[1].0..blocks[2].0).collect::<Vec<_>>(),
+            blocks[1].1,
+            "range of one block"
+        );
+        assert_eq!(index.range(blocks[0].0..Mask::MAX).count(), 4, "range of all blocks");
+        assert_eq!(index.range(blocks[2].0..blocks[0].0).next(), None, "empty range");
+        let key = index.permuter().revert(blocks[2].1[0].0);
+        assert_eq!(index.predict_block_size(&key), 2, "predicted block size");
+        assert_eq!(
+            index.predict_block_size(&Bits::MAX),
+            0,
+            "predicted size of a missing block"
+        );
+    }
 }
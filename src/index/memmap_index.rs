@@ -1,60 +1,204 @@
 use std::{
     collections::BTreeSet,
     marker::PhantomData,
+    ops::Range,
     path::{Path, PathBuf},
 };
 
 use hloo_core::{BitContainer, BitPermuter};
 
 use crate::{
-    mmvec::{MmVec, MmVecError},
+    metrics,
+    mmvec::{AccessPattern, Corruption, FlushPolicy, MmVec, MmVecError, Pod},
+    util::FromLeBytes,
     DynBitPermuter,
 };
 
-use super::{extract_key, BlockLocator, Index, IndexStats, PersistentIndex};
+use super::{
+    extract_key, locate_candidates, mask_blocks, mask_range, tombstones::Tombstones, Block, BlockLocator, Candidates,
+    Index, IndexStats, IndexValidation, PersistentIndex, ScanBound, StorageStats,
+};
 
 pub type MemMapIndexError = MmVecError;
 
+/// Memory-mapped index.
+///
+/// Removal is deferred: removed items are only marked as deleted in a side bitmap (stored next to the index file), and
+/// are skipped during searches. They are physically removed by [`Index::compact`], or by the next insert.
+///
+/// A single process can open the index for writing, while any number of processes open it with
+/// [`PersistentIndex::load_read_only`]. Readers pick up changes made by the writer with [`PersistentIndex::reload`].
 pub struct MemMapIndex<K, V, M>
 where
-    (K, V): Copy,
+    (K, V): Pod,
 {
     permuter: DynBitPermuter<K, M>,
     block_locator: BlockLocator,
     current_stats: IndexStats,
+    sig: u64,
     data: MmVec<(K, V)>,
+    tombstones: Tombstones,
     _dummy: PhantomData<M>,
 }
 
 impl<K, V, M> MemMapIndex<K, V, M>
 where
-    (K, V): Copy,
+    (K, V): Pod,
 {
-    pub(crate) fn new_with_data(permuter: DynBitPermuter<K, M>, data: MmVec<(K, V)>) -> Self {
+    pub(crate) fn new_with_data(
+        permuter: DynBitPermuter<K, M>,
+        sig: u64,
+        data: MmVec<(K, V)>,
+        tombstones: Tombstones,
+    ) -> Self {
         Self {
             permuter,
             block_locator: BlockLocator::BinarySearch,
             current_stats: IndexStats::default(),
+            sig,
             data,
+            tombstones,
             _dummy: PhantomData,
         }
     }
 
     pub fn new(permuter: DynBitPermuter<K, M>, sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        let tombstones = Tombstones::create(sig, &path)?;
         let data = MmVec::new_empty(sig, path)?;
-        Ok(Self::new_with_data(permuter, data))
+        Ok(Self::new_with_data(permuter, sig, data, tombstones))
     }
 
     pub fn destroy(self) -> Result<(), MmVecError> {
         self.data.destroy()?;
+        self.tombstones.destroy()?;
         Ok(())
     }
+
+    /// Write a copy of the index, including its tombstones, to `path`. See [`MmVec::backup_to`].
+    pub fn backup_to(&self, path: &Path) -> Result<(), MmVecError> {
+        self.data.backup_to(path)?;
+        self.tombstones.backup_to(path)
+    }
+
+    /// Signature the index was created with.
+    pub fn sig(&self) -> u64 {
+        self.sig
+    }
+
+    /// Let the OS know how the index data is going to be accessed. See [`MmVec::advise`].
+    pub fn advise(&mut self, pattern: AccessPattern) {
+        self.data.advise(pattern);
+    }
+
+    /// Load the whole index into memory ahead of searches. See [`MmVec::populate`].
+    pub fn warmup(&self) {
+        self.data.populate();
+    }
+
+    /// Back the index data with transparent huge pages. See [`MmVec::set_huge_pages`].
+    pub fn set_huge_pages(&mut self, enabled: bool) -> Result<(), MmVecError> {
+        self.data.set_huge_pages(enabled)
+    }
+
+    /// Set when in-place modifications are written to disk, for both the index data and the tombstones. See
+    /// [`FlushPolicy`].
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) -> Result<(), MmVecError> {
+        self.data.set_flush_policy(policy)?;
+        self.tombstones.set_flush_policy(policy)
+    }
+
+    /// Maximum number of items, if limited. See [`MemMapIndex::set_max_len`].
+    pub fn max_len(&self) -> Option<usize> {
+        self.data.max_len()
+    }
+
+    /// Limit the number of items. Inserts which would grow the index beyond `max_len` fail with
+    /// [`MmVecError::TooLarge`] and leave it unchanged. See [`MmVec::set_max_len`].
+    pub fn set_max_len(&mut self, max_len: Option<usize>) {
+        self.data.set_max_len(max_len);
+    }
+
+    /// User metadata stored alongside the index. See [`MmVec::metadata`].
+    pub fn metadata(&self) -> Vec<u8> {
+        self.data.metadata()
+    }
+
+    /// Store user metadata alongside the index. See [`MmVec::set_metadata`].
+    pub fn set_metadata(&mut self, metadata: &[u8]) -> Result<(), MmVecError> {
+        self.data.set_metadata(metadata)
+    }
+
+    /// Number of removed items which are not yet physically removed from the index.
+    pub fn n_tombstones(&self) -> usize {
+        self.tombstones.count()
+    }
+}
+
+impl<K, V, M> MemMapIndex<K, V, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    V: Pod + ScanBound,
+    M: Copy + Ord,
+{
+    /// Build an index at `path` from items which do not fit in memory. At most `run_len` items are held in memory at a
+    /// time. See [`MmVec::from_iter_external_sorted`].
+    pub fn from_iter_external(
+        permuter: DynBitPermuter<K, M>,
+        sig: u64,
+        path: PathBuf,
+        items: impl IntoIterator<Item = (K, V)>,
+        run_len: usize,
+    ) -> Result<Self, MmVecError> {
+        let permuted = items.into_iter().map(|(k, v)| (permuter.apply(&k), v));
+        let data = MmVec::from_iter_external_sorted(sig, permuted, run_len, extract_key, path.clone())?;
+        let tombstones = Tombstones::create(sig, &path)?;
+        Ok(Self::new_with_data(permuter, sig, data, tombstones))
+    }
+
+    /// Insert all records of a raw dump at `path`, e.g. produced by an external system. Returns the number of
+    /// inserted records.
+    ///
+    /// The dump is a sequence of `(key, value)` records without any header, each of them the little-endian encoding
+    /// of the key followed by the one of the value (see [`FromLeBytes`]). Keys are in their original (non-permuted)
+    /// form. The records are merged into the index in a single pass, see [`MmVec::append_from_file`].
+    pub fn import_raw(&mut self, path: &Path) -> Result<usize, MmVecError>
+    where
+        K: FromLeBytes,
+        V: FromLeBytes,
+    {
+        let imported = self.stage_import_raw(path)?;
+        self.data.commit_staged_insert()?;
+        Ok(imported)
+    }
+
+    /// Prepare [`MemMapIndex::import_raw`] like [`Index::stage_insert`]: the import is applied by
+    /// [`MemMapIndex::commit_import_raw`], or abandoned by [`Index::discard_insert`]. Returns the number of records.
+    pub(crate) fn stage_import_raw(&mut self, path: &Path) -> Result<usize, MmVecError>
+    where
+        K: FromLeBytes,
+        V: FromLeBytes,
+    {
+        // positions are going to change, so tombstones have to be applied first
+        self.compact()?;
+        let permuter = &self.permuter;
+        let decode = |record: &[u8]| {
+            let (k, v) = <(K, V)>::from_le_slice(record);
+            (permuter.apply(&k), v)
+        };
+        self.data
+            .stage_append_from_file(path, <(K, V)>::SIZE, decode, extract_key)
+    }
+
+    /// Apply the import staged by [`MemMapIndex::stage_import_raw`].
+    pub(crate) fn commit_import_raw(&mut self) -> Result<(), MmVecError> {
+        self.data.commit_staged_insert()
+    }
 }
 
 impl<K, V, M> Index<K, V, M> for MemMapIndex<K, V, M>
 where
-    K: Copy + BitContainer + Ord,
-    V: Copy,
+    K: Pod + BitContainer + Ord + ScanBound,
+    V: Pod + ScanBound,
     M: Copy + Ord,
 {
     type Error = MmVecError;
@@ -67,64 +211,261 @@ where
         self.block_locator
     }
 
-    fn data(&self) -> &[(K, V)] {
-        unsafe { self.data.as_slice() }
+    fn data(&self) -> Block<'_, K, V> {
+        Block::Interleaved(self.data.as_slice())
     }
 
     fn stats(&self) -> &IndexStats {
         &self.current_stats
     }
 
+    fn storage_stats(&self) -> StorageStats {
+        let data_size = self
+            .data
+            .storage_size()
+            .unwrap_or_else(|_| self.data.expected_storage_size());
+        StorageStats {
+            bytes_on_disk: data_size + self.tombstones.storage_size(),
+            last_flush: self.data.info().last_flush,
+        }
+    }
+
     fn refresh(&mut self) {
+        // computing stats is a full scan
+        let pattern = self.data.access_pattern();
+        self.data.advise(AccessPattern::Sequential);
         self.current_stats = self.compute_stats();
+        self.data.advise(pattern);
     }
 
     fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
-        let mut permuted: Vec<_> = items.iter().map(|(k, v)| (self.permuter.apply(k), *v)).collect();
-        // pre-sort the permuted items to create a "two-sorted-sequences" pattern
-        permuted.sort_unstable_by_key(extract_key);
-        // SAFETY: ???
-        unsafe {
-            self.data.insert_sorted(&permuted, extract_key)?;
+        metrics::insert(items.len());
+        // positions are going to change, so tombstones have to be applied first
+        self.compact()?;
+        let mut permuted = items.to_vec();
+        for (k, _) in &mut permuted {
+            self.permuter.apply_inplace(k);
         }
-        Ok(())
+        // the batch is sorted by `insert_sorted`, while it is copied into the merge journal
+        self.data.insert_sorted(&permuted, extract_key)
+    }
+
+    fn stage_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.compact()?;
+        let mut permuted = items.to_vec();
+        for (k, _) in &mut permuted {
+            self.permuter.apply_inplace(k);
+        }
+        self.data.stage_insert_sorted(&permuted, extract_key)
+    }
+
+    fn commit_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        metrics::insert(items.len());
+        self.data.commit_staged_insert()
+    }
+
+    fn discard_insert(&mut self) {
+        self.data.discard_staged_insert();
     }
 
     fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
-        let set: BTreeSet<_> = keys.iter().map(|k| self.permuter.apply(k)).collect();
-        // SAFETY: ???
-        unsafe {
-            self.data.remove_matching(|(k, _)| set.contains(k), extract_key)?;
+        let set: BTreeSet<_> = self.permuter.apply_many(keys).into_iter().collect();
+        let data = self.data();
+        let positions: Vec<_> = set
+            .iter()
+            .flat_map(|permuted_key| data.locate_range_by(self.block_locator, |key| key.cmp(permuted_key)))
+            .collect();
+        let generation = self.data.generation();
+        for pos in positions {
+            self.tombstones.set(pos, generation)?;
         }
+        self.tombstones.flush()?;
         Ok(())
     }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        let permuter = &self.permuter;
+        let tombstones = self.tombstones.view(0);
+        self.data
+            .retain(|i, (k, v)| !tombstones.is_set(i) && pred(&permuter.revert(k), v))?;
+        self.tombstones.clear()?;
+        Ok(())
+    }
+
+    fn compact(&mut self) -> Result<(), Self::Error> {
+        if self.tombstones.count() == 0 {
+            return Ok(());
+        }
+        let tombstones = self.tombstones.view(0);
+        self.data.retain(|i, _| !tombstones.is_set(i))?;
+        self.tombstones.clear()?;
+        Ok(())
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let tombstones = self.tombstones.view(0);
+        self.data()
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| !tombstones.is_set(*i))
+            .map(|(_, item)| item)
+    }
+
+    fn last<'a>(&'a self) -> Option<(&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let data = self.data();
+        let tombstones = self.tombstones.view(0);
+        (0..data.len())
+            .rev()
+            .find(|i| !tombstones.is_set(*i))
+            .map(|i| data.get(i))
+    }
+
+    fn range<'a>(&'a self, masks: Range<M>) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let data = self.data();
+        let tombstones = self.tombstones.view(0);
+        mask_range(self.permuter(), data, masks)
+            .filter(move |i| !tombstones.is_set(*i))
+            .map(move |i| data.get(i))
+    }
+
+    fn iter_blocks<'a>(&'a self) -> impl Iterator<Item = (M, impl Iterator<Item = (&'a K, &'a V)>)>
+    where
+        K: 'a,
+        V: 'a,
+        M: 'a,
+    {
+        let data = self.data();
+        let tombstones = self.tombstones.view(0);
+        mask_blocks(self.permuter(), data)
+            .filter(move |(_, range)| range.clone().any(|i| !tombstones.is_set(i)))
+            .map(move |(mask, range)| {
+                let items = range.filter(move |i| !tombstones.is_set(*i)).map(move |i| data.get(i));
+                (mask, items)
+            })
+    }
+
+    fn get_candidates<'a>(&'a self, key: &K) -> Candidates<'a, K, V> {
+        let data = self.data();
+        let (permuted_key, range) = locate_candidates(self.permuter(), self.block_locator, data, key);
+        if self.tombstones.count() == 0 {
+            Candidates::new(permuted_key, data.slice(range))
+        } else {
+            let tombstones = self.tombstones.view(range.start);
+            Candidates::with_tombstones(permuted_key, data.slice(range), tombstones)
+        }
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        let permuted_key = self.permuter.apply(key);
+        let range = self
+            .data()
+            .locate_range_by(self.block_locator, |key| key.cmp(&permuted_key));
+        let tombstones = self.tombstones.view(0);
+        range.into_iter().any(|i| !tombstones.is_set(i))
+    }
+
+    fn validate(&self) -> Result<IndexValidation, Self::Error> {
+        let mut report = IndexValidation::from_data_by(self.data().keys(), |a, b| a.cmp(b));
+        let (expected_size, actual_size) = (self.data.expected_storage_size(), self.data.storage_size()?);
+        if expected_size != actual_size {
+            report.storage_size_mismatch = Some((expected_size, actual_size));
+        }
+        if self.data.sig() != self.sig {
+            report.signature_mismatch = Some((self.sig, self.data.sig()));
+        }
+        Ok(report)
+    }
+}
+
+/// Check that loaded data is sorted by key, so that a corrupted file is not searched. This is a full scan.
+fn check_sorted<K: Ord, V>(data: &mut MmVec<(K, V)>) -> Result<(), MmVecError>
+where
+    (K, V): Pod,
+{
+    let pattern = data.access_pattern();
+    data.advise(AccessPattern::Sequential);
+    let report = IndexValidation::from_data_by(data.as_slice().iter().map(|(k, _)| k), |a, b| a.cmp(b));
+    data.advise(pattern);
+    match report.first_unsorted {
+        Some(position) => Err(MmVecError::corrupted(data.path(), Corruption::Unsorted { position })),
+        None => Ok(()),
+    }
+}
+
+impl<K, V, M> MemMapIndex<K, V, M>
+where
+    K: Ord,
+    (K, V): Pod,
+{
+    /// Load the index like [`PersistentIndex::load`], and also check that its data is sorted (see
+    /// [`Corruption::Unsorted`]), so that a corrupted file is not searched. This is a full scan, so it is meant for
+    /// loading indexes which may have been damaged, e.g. after an incident; [`Index::validate`] reports the same.
+    pub fn load_checked(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, MmVecError> {
+        let mut index = Self::load(permuter, sig, path)?;
+        check_sorted(&mut index.data)?;
+        Ok(index)
+    }
 }
 
 impl<K, V, M> PersistentIndex<K, M> for MemMapIndex<K, V, M>
 where
-    (K, V): Copy,
+    (K, V): Pod,
 {
     type Error = MmVecError;
 
     fn create(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
-        let data = MmVec::new_empty(sig, path.to_path_buf())?;
-        Ok(Self::new_with_data(permuter, data))
+        Self::new(permuter, sig, path.to_path_buf())
     }
 
+    /// Load the index. Its data is not checked to be sorted, see [`MemMapIndex::load_checked`].
     fn load(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
         let data = MmVec::from_path(sig, path.to_path_buf())?;
-        Ok(Self::new_with_data(permuter, data))
+        let tombstones = Tombstones::load_or_create(sig, path, data.generation())?;
+        Ok(Self::new_with_data(permuter, sig, data, tombstones))
+    }
+
+    fn load_read_only(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        let data = MmVec::open_read_only(sig, path.to_path_buf())?;
+        let tombstones = Tombstones::open_read_only(sig, path, data.generation())?;
+        Ok(Self::new_with_data(permuter, sig, data, tombstones))
     }
 
     fn persist(&self) -> Result<(), Self::Error> {
         self.data.flush()?;
+        self.tombstones.flush()?;
         Ok(())
     }
+
+    fn reload(&mut self) -> Result<bool, Self::Error> {
+        let data_changed = self.data.refresh()?;
+        let tombstones_changed = self.tombstones.reload(self.data.generation())?;
+        Ok(data_changed || tombstones_changed)
+    }
+
+    fn is_stale(&self) -> bool {
+        self.data.is_stale()
+    }
+
+    fn destroy(self) -> Result<(), Self::Error> {
+        MemMapIndex::destroy(self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use hloo_core::{BitContainer, BitPermuter};
+    use hloo_core::{BitContainer, BitPermuter, Pod};
     use hloo_macros::make_permutations;
 
     use super::*;
@@ -146,10 +487,38 @@ mod tests {
             (Bits::new([0b10011110100010_001000100010001100u32]), 4),
         ];
         index.insert(&data).unwrap();
-        let result = index.get_candidates(&data[2].0).block;
+        let result = index.get_candidates(&data[2].0).block().to_vec();
         assert_eq!(result, &data[2..3]);
     }
 
+    #[test]
+    fn memmap_index_can_be_built_with_external_sort() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let perm = Permutations::get_variant(2);
+        let data: Vec<_> = (0..1000u32)
+            .map(|i| (Bits::new([i.wrapping_mul(2654435761)]), i))
+            .collect();
+        let mut expected: Vec<_> = data.iter().map(|(k, v)| (perm.apply(k), *v)).collect();
+        expected.sort_by_key(|(k, _)| *k);
+
+        let path = tempdir.path().join("storage.bin");
+        let index = MemMapIndex::from_iter_external(perm, 0, path, data.iter().copied(), 64).unwrap();
+        let keys: Vec<_> = index.data().to_vec().into_iter().map(|(k, _)| k).collect();
+        let expected_keys: Vec<_> = expected.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, expected_keys, "items should be sorted");
+        assert!(index.contains_key(&data[500].0), "items should be searchable");
+        let mut files: Vec<_> = std::fs::read_dir(tempdir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            ["storage.bin", "storage.bin.tombstones"],
+            "runs should be removed"
+        );
+    }
+
     #[test]
     fn memmap_index_insert_works_correctly() {
         let tempdir = tempfile::tempdir().expect("failed to create temp dir");
@@ -182,7 +551,7 @@ mod tests {
                 "[{i}] index length is wrong after first insert"
             );
             assert_eq!(
-                index.data(),
+                index.data().to_vec(),
                 expected_first,
                 "[{i}] index contents is wrong after first insert"
             );
@@ -193,7 +562,7 @@ mod tests {
                 "[{i}] index length is wrong after second insert"
             );
             assert_eq!(
-                index.data(),
+                index.data().to_vec(),
                 expected_second,
                 "[{i}] index contents is wrong after second insert"
             );
@@ -214,7 +583,7 @@ mod tests {
                 Bits::new([0b11111000100010_001000100010001000u32]),
                 Bits::new([0b11001000111110_001000100010001010u32]),
             ];
-            let mut expected: Vec<_> = vec![
+            let mut expected: Vec<_> = [
                 (Bits::new([0b11111000100010_001000100011111000u32]), 2),
                 (Bits::new([0b10011110100010_001000100010001100u32]), 4),
             ]
@@ -227,13 +596,226 @@ mod tests {
 
             index.insert(&data).unwrap();
             index.remove(&to_remove).unwrap();
+            assert_eq!(
+                index.n_tombstones(),
+                to_remove.len(),
+                "[{i}] removed items should be tombstoned"
+            );
+            for key in &to_remove {
+                assert!(!index.contains_key(key), "[{i}] removed key should not be found");
+                assert!(
+                    index.get_candidates(key).scan(0).is_empty(),
+                    "[{i}] removed key should not be returned by a scan"
+                );
+            }
+
+            assert_eq!(
+                index.iter().count(),
+                index.data().len() - to_remove.len(),
+                "[{i}] iter should skip removed items"
+            );
 
+            let alive: Vec<_> = index.iter().collect();
+            assert_eq!(
+                index.first(),
+                alive.first().copied(),
+                "[{i}] first should skip removed items"
+            );
+            assert_eq!(
+                index.last(),
+                alive.last().copied(),
+                "[{i}] last should skip removed items"
+            );
+            let blocks: Vec<_> = index
+                .iter_blocks()
+                .map(|(mask, items)| (mask, items.collect::<Vec<_>>()))
+                .collect();
+            assert!(
+                blocks.iter().all(|(_, items)| !items.is_empty()),
+                "[{i}] blocks should not be empty"
+            );
+            let block_items: Vec<_> = blocks.iter().flat_map(|(_, items)| items.iter().copied()).collect();
+            assert_eq!(block_items, alive, "[{i}] iter_blocks should skip removed items");
+            let last_mask = blocks.last().unwrap().0;
+            let before_last: Vec<_> = alive
+                .iter()
+                .copied()
+                .filter(|(k, _)| index.permuter().mask(k) != last_mask)
+                .collect();
+            assert_eq!(
+                index.range(blocks[0].0..last_mask).collect::<Vec<_>>(),
+                before_last,
+                "[{i}] range should skip removed items"
+            );
+
+            index.compact().unwrap();
+            assert_eq!(index.n_tombstones(), 0, "[{i}] compaction should clear tombstones");
             expected.sort_unstable_by_key(|(k, _)| *k);
             assert_eq!(
-                index.data(),
+                index.data().to_vec(),
                 expected,
                 "[{i}] index contents is wrong after second insert"
             );
         }
     }
+
+    #[test]
+    fn memmap_index_validate_works_correctly() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let index_path = tempdir.path().join("storage.bin");
+        let mut index = MemMapIndex::new(Permutations::get_variant(0), 42, index_path.clone()).unwrap();
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        index.insert(&data).unwrap();
+        let report = index.validate().unwrap();
+        assert!(report.is_ok(), "fresh index should be valid: {report:?}");
+        assert_eq!(report.n_items, data.len(), "n items");
+
+        index.data.as_slice_mut().reverse();
+        let report = index.validate().unwrap();
+        assert_eq!(report.first_unsorted, Some(1), "unsorted data should be detected");
+
+        index.sig = 43;
+        let report = index.validate().unwrap();
+        assert_eq!(
+            report.signature_mismatch,
+            Some((43, 42)),
+            "signature mismatch should be detected"
+        );
+    }
+
+    #[test]
+    fn memmap_index_does_not_load_corrupted_data() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let index_path = tempdir.path().join("storage.bin");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+            (Bits::new([0b10011110100010_001000100010001100u32]), 4),
+        ];
+        {
+            let mut index = MemMapIndex::new(Permutations::get_variant(0), 0, index_path.clone()).unwrap();
+            index.insert(&data).unwrap();
+            index.data.as_slice_mut().swap(1, 2);
+            index.persist().unwrap();
+        }
+        let load = || MemMapIndex::<Bits, i32, Mask>::load_checked(Permutations::get_variant(0), 0, &index_path);
+        let Err(MmVecError::Corrupted { path, corruption }) = load() else {
+            panic!("unsorted data should not be loaded");
+        };
+        let index = MemMapIndex::<Bits, i32, Mask>::load(Permutations::get_variant(0), 0, &index_path).unwrap();
+        assert_eq!(
+            index.validate().unwrap().first_unsorted,
+            Some(2),
+            "unsorted data should be reported"
+        );
+        drop(index);
+        assert_eq!(
+            (path, corruption),
+            (index_path.clone(), Corruption::Unsorted { position: 2 })
+        );
+
+        std::fs::File::options()
+            .write(true)
+            .open(&index_path)
+            .unwrap()
+            .set_len(10)
+            .unwrap();
+        assert!(matches!(
+            load(),
+            Err(MmVecError::Corrupted {
+                corruption: Corruption::TruncatedHeader { file_size: 10 },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn memmap_index_rejects_inserts_beyond_max_len() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let index_path = tempdir.path().join("storage.bin");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        let mut index = MemMapIndex::new(Permutations::get_variant(0), 0, index_path).unwrap();
+        index.set_max_len(Some(1));
+        assert!(matches!(
+            index.insert(&data),
+            Err(MmVecError::TooLarge { len: 2, max_len: 1 })
+        ));
+        assert_eq!(index.data().len(), 0, "rejected insert leaves the index unchanged");
+        index.insert(&data[..1]).unwrap();
+        assert_eq!(index.max_len(), Some(1));
+    }
+
+    #[test]
+    fn memmap_index_tombstones_survive_reload() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let index_path = tempdir.path().join("storage.bin");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        {
+            let mut index = MemMapIndex::new(Permutations::get_variant(0), 0, index_path.clone()).unwrap();
+            index.insert(&data).unwrap();
+            index.remove(&[data[0].0]).unwrap();
+            index.persist().unwrap();
+        }
+        let mut index = MemMapIndex::<Bits, i32, Mask>::load(Permutations::get_variant(0), 0, &index_path).unwrap();
+        assert_eq!(index.n_tombstones(), 1, "tombstones should be loaded");
+        assert!(!index.contains_key(&data[0].0), "removed key");
+        assert!(index.contains_key(&data[1].0), "present key");
+
+        index.insert(&[data[0]]).unwrap();
+        assert_eq!(index.n_tombstones(), 0, "insert should compact the index");
+        assert_eq!(index.data().len(), 2, "re-inserted key");
+        index.destroy().unwrap();
+        assert!(
+            !Tombstones::path_for(&index_path).exists(),
+            "tombstones should be destroyed"
+        );
+    }
+
+    #[test]
+    fn memmap_index_ignores_tombstones_of_compacted_data() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let index_path = tempdir.path().join("storage.bin");
+        let stale_path = tempdir.path().join("stale.tombstones");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        {
+            let mut index = MemMapIndex::new(Permutations::get_variant(0), 0, index_path.clone()).unwrap();
+            index.insert(&data).unwrap();
+            index.remove(&[data[0].0]).unwrap();
+            index.persist().unwrap();
+            std::fs::copy(Tombstones::path_for(&index_path), &stale_path).unwrap();
+            index.compact().unwrap();
+            index.persist().unwrap();
+        }
+        // as if clearing the tombstones never reached the disk
+        std::fs::copy(&stale_path, Tombstones::path_for(&index_path)).unwrap();
+
+        let reader =
+            MemMapIndex::<Bits, i32, Mask>::load_read_only(Permutations::get_variant(0), 0, &index_path).unwrap();
+        assert_eq!(
+            reader.n_tombstones(),
+            0,
+            "stale tombstones should be ignored by readers"
+        );
+        assert!(
+            reader.contains_key(&data[1].0),
+            "compacted data should be searched as is"
+        );
+        drop(reader);
+        let index = MemMapIndex::<Bits, i32, Mask>::load(Permutations::get_variant(0), 0, &index_path).unwrap();
+        assert_eq!(index.n_tombstones(), 0, "stale tombstones should be cleared");
+        assert_eq!(index.iter().count(), 1, "remaining items");
+        assert!(index.contains_key(&data[1].0), "present key");
+    }
 }
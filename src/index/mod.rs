@@ -1,21 +1,73 @@
+mod block;
+pub use block::Block;
+
 mod stats;
-pub use stats::IndexStats;
+pub use stats::{IndexStats, StorageStats};
+
+mod validation;
+pub use validation::{IndexValidation, LookupValidation};
+
+#[cfg(feature = "rkyv")]
+mod archived_index;
+#[cfg(feature = "rkyv")]
+pub use archived_index::{ArchivedIndex, ArchivedIndexError, RecordRef, RecordSerializer, RecordValidator};
 
 mod mem_index;
-pub use mem_index::MemIndex;
+pub use mem_index::{MemIndex, MemIndexHandle, MemIndexSnapshot};
+
+#[cfg(feature = "fs")]
+mod file_index;
+#[cfg(feature = "fs")]
+pub use file_index::FileIndex;
 
+#[cfg(feature = "fs")]
 mod memmap_index;
+#[cfg(feature = "fs")]
 pub use memmap_index::{MemMapIndex, MemMapIndexError};
 
-use std::{hash::Hash, path::Path};
+#[cfg(feature = "fs")]
+mod network_safe_index;
+#[cfg(feature = "fs")]
+pub use network_safe_index::NetworkSafeIndex;
+
+#[cfg(feature = "redb")]
+mod redb_index;
+#[cfg(feature = "redb")]
+pub use redb_index::{RedbIndex, RedbIndexError};
+
+#[cfg(feature = "rocksdb")]
+mod rocks_index;
+#[cfg(feature = "rocksdb")]
+pub use rocks_index::{RocksIndex, RocksIndexError};
+
+#[cfg(feature = "object_store")]
+mod object_store_index;
+#[cfg(feature = "object_store")]
+pub use object_store_index::{ObjectStoreIndex, ObjectStoreIndexError};
+
+mod split_mem_index;
+pub use split_mem_index::SplitMemIndex;
+
+mod storage_index;
+pub use storage_index::{DynVecStorage, StorageIndex, VecStorage};
+
+#[cfg(feature = "fs")]
+mod tiered_index;
+#[cfg(feature = "fs")]
+pub use tiered_index::TieredIndex;
+
+mod tombstones;
+use tombstones::TombstoneView;
+
+use std::{cell::RefCell, collections::BTreeMap, hash::Hash, path::Path};
 
 use hloo_core::{BitContainer, BitPermuter};
 
 use crate::DynBitPermuter;
 
-use std::cmp::Ordering;
+use std::{cmp::Ordering, ops::Range};
 
-use crate::util::extended_binary_search_by;
+use crate::util::{extended_binary_search_by, extended_binary_search_range_by};
 
 /// Locates continuous blocks in sorted slices.
 #[derive(Clone, Copy, Debug)]
@@ -30,12 +82,38 @@ impl BlockLocator {
             BlockLocator::BinarySearch => extended_binary_search_by(slice, f),
         }
     }
+
+    pub fn locate_range_by<T>(&self, slice: &[T], f: impl Fn(&T) -> Ordering) -> Range<usize> {
+        match self {
+            BlockLocator::BinarySearch => extended_binary_search_range_by(slice, f),
+        }
+    }
 }
 
+/// Blocks larger than this are scanned in parallel when the `rayon` feature is enabled.
+pub const PARALLEL_SCAN_THRESHOLD: usize = 100_000;
+
+/// Bounds required for keys and values to be scanned or sorted. Requires `Send + Sync` only when the `rayon` feature is
+/// enabled, as large blocks are then scanned and sorted on multiple threads.
+#[cfg(feature = "rayon")]
+pub trait ScanBound: Send + Sync {}
+
+#[cfg(feature = "rayon")]
+impl<T: Send + Sync> ScanBound for T {}
+
+/// Bounds required for keys and values to be scanned or sorted. Requires `Send + Sync` only when the `rayon` feature is
+/// enabled, as large blocks are then scanned and sorted on multiple threads.
+#[cfg(not(feature = "rayon"))]
+pub trait ScanBound {}
+
+#[cfg(not(feature = "rayon"))]
+impl<T> ScanBound for T {}
+
 /// Represents a single block of potential candidates for a distance search.
 pub struct Candidates<'a, K, V> {
     key: K,
-    block: &'a [(K, V)],
+    block: Block<'a, K, V>,
+    tombstones: Option<TombstoneView<'a>>,
 }
 
 impl<'a, K, V> Candidates<'a, K, V>
@@ -43,8 +121,27 @@ where
     K: BitContainer,
     V: Clone,
 {
-    pub fn new(key: K, block: &'a [(K, V)]) -> Self {
-        Self { key, block }
+    pub fn new(key: K, block: impl Into<Block<'a, K, V>>) -> Self {
+        Self {
+            key,
+            block: block.into(),
+            tombstones: None,
+        }
+    }
+
+    /// Create candidates which skip deleted positions.
+    #[cfg(feature = "fs")]
+    pub(crate) fn with_tombstones(key: K, block: Block<'a, K, V>, tombstones: TombstoneView<'a>) -> Self {
+        Self {
+            key,
+            block,
+            tombstones: Some(tombstones),
+        }
+    }
+
+    /// The block of candidates.
+    pub fn block(&self) -> Block<'a, K, V> {
+        self.block
     }
 
     /// How many candidates there are.
@@ -57,22 +154,108 @@ where
     }
 
     /// Performs a full scan of candidates and returns results.
-    pub fn scan(&self, distance: u32) -> Vec<SearchResultItem<V>> {
-        self.block
-            .iter()
-            .filter_map(move |(this_key, value)| {
-                let dist = this_key.xor_dist(&self.key);
-                if dist <= distance {
-                    Some(SearchResultItem::new(value.clone(), dist))
-                } else {
-                    None
-                }
-            })
-            .collect()
+    ///
+    /// With the `rayon` feature enabled, blocks larger than [`PARALLEL_SCAN_THRESHOLD`] are scanned in parallel.
+    pub fn scan(&self, distance: u32) -> Vec<SearchResultItem<V>>
+    where
+        K: ScanBound,
+        V: ScanBound,
+    {
+        self.scan_with(distance, K::xor_dist)
+    }
+
+    /// Performs a full scan of candidates like [`Candidates::scan`], computing distances with `metric` instead of
+    /// [`BitContainer::xor_dist`]. `metric` is called with the search key and a candidate key, both in the form they
+    /// are stored in, i.e. permuted when candidates come from an index.
+    pub fn scan_with(&self, distance: u32, metric: impl Fn(&K, &K) -> u32 + ScanBound) -> Vec<SearchResultItem<V>>
+    where
+        K: ScanBound,
+        V: ScanBound,
+    {
+        #[cfg(feature = "rayon")]
+        if self.block.len() > PARALLEL_SCAN_THRESHOLD {
+            return self.par_scan_with(distance, &metric);
+        }
+        self.seq_scan_with(distance, &metric)
+    }
+
+    /// Performs a full scan of candidates on the current thread.
+    pub fn seq_scan(&self, distance: u32) -> Vec<SearchResultItem<V>> {
+        self.seq_scan_with(distance, &K::xor_dist)
+    }
+
+    fn seq_scan_with(&self, distance: u32, metric: &impl Fn(&K, &K) -> u32) -> Vec<SearchResultItem<V>> {
+        match self.block {
+            Block::Interleaved(block) => block
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (this_key, value))| {
+                    self.matches(i, this_key, distance, metric)
+                        .map(|dist| SearchResultItem::new(value.clone(), dist))
+                })
+                .collect(),
+            // only keys are scanned, values are fetched by position
+            Block::Split(keys, values) => keys
+                .iter()
+                .enumerate()
+                .filter_map(|(i, this_key)| {
+                    self.matches(i, this_key, distance, metric)
+                        .map(|dist| SearchResultItem::new(values[i].clone(), dist))
+                })
+                .collect(),
+        }
+    }
+
+    /// Performs a full scan of candidates using rayon worker threads.
+    #[cfg(feature = "rayon")]
+    pub fn par_scan(&self, distance: u32) -> Vec<SearchResultItem<V>>
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+    {
+        self.par_scan_with(distance, &K::xor_dist)
+    }
+
+    #[cfg(feature = "rayon")]
+    fn par_scan_with(&self, distance: u32, metric: &(impl Fn(&K, &K) -> u32 + Sync)) -> Vec<SearchResultItem<V>>
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        match self.block {
+            Block::Interleaved(block) => block
+                .par_iter()
+                .enumerate()
+                .filter_map(|(i, (this_key, value))| {
+                    self.matches(i, this_key, distance, metric)
+                        .map(|dist| SearchResultItem::new(value.clone(), dist))
+                })
+                .collect(),
+            Block::Split(keys, values) => keys
+                .par_iter()
+                .enumerate()
+                .filter_map(|(i, this_key)| {
+                    self.matches(i, this_key, distance, metric)
+                        .map(|dist| SearchResultItem::new(values[i].clone(), dist))
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the distance to the candidate at position `i` if it is alive and within `distance`.
+    #[inline]
+    fn matches(&self, i: usize, this_key: &K, distance: u32, metric: &impl Fn(&K, &K) -> u32) -> Option<u32> {
+        if self.tombstones.is_some_and(|t| t.is_set(i)) {
+            return None;
+        }
+        let dist = metric(&self.key, this_key);
+        (dist <= distance).then_some(dist)
     }
 }
 
-///
+/// Single search result: the stored value and its distance to the search key.
 #[derive(Clone, Copy, Eq, Debug)]
 pub struct SearchResultItem<V> {
     data: V,
@@ -127,36 +310,216 @@ where
     /// Get currently used `BlockLocator`.
     fn block_locator(&self) -> BlockLocator;
 
-    /// Get data as a slice.
-    fn data(&self) -> &[(K, V)];
+    /// Get data as a block.
+    fn data(&self) -> Block<'_, K, V>;
 
     /// Get stats for this index.
     fn stats(&self) -> &IndexStats;
 
+    /// Get the storage used by this index. In-memory indexes use none.
+    fn storage_stats(&self) -> StorageStats {
+        StorageStats::default()
+    }
+
     /// Refresh index: recompute stats etc.
     fn refresh(&mut self);
 
     /// Insert items into this index.
     fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error>;
 
+    /// Prepare an insert of `items` for [`Index::commit_insert`], without changing the contents of the index: acquire
+    /// everything the insert needs (e.g. disk space), so that committing it can't fail for lack of resources. This lets
+    /// [`Lookup::insert`](crate::Lookup::insert) insert into several indexes all-or-nothing. The insert is abandoned by
+    /// [`Index::discard_insert`] or by staging another one, and the index must not be modified in between.
+    ///
+    /// By default nothing is staged and committing inserts the items, which suits indexes whose inserts can't fail.
+    fn stage_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        let _ = items;
+        Ok(())
+    }
+
+    /// Apply the insert of `items` staged by [`Index::stage_insert`].
+    fn commit_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.insert(items)
+    }
+
+    /// Abandon the insert staged by [`Index::stage_insert`], releasing what it acquired.
+    fn discard_insert(&mut self) {}
+
     /// Remove items from this index.
     fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error>;
 
+    /// Retain only the items for which `pred` returns `true`. Keys are passed to `pred` in their original
+    /// (non-permuted) form. Items are visited in index order.
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error>;
+
+    /// Iterate over stored items in index order. Keys are in permuted form.
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.data().iter()
+    }
+
+    /// First item in index order. Key is in permuted form.
+    fn first<'a>(&'a self) -> Option<(&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter().next()
+    }
+
+    /// Last item in index order. Key is in permuted form.
+    fn last<'a>(&'a self) -> Option<(&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let data = self.data();
+        data.len().checked_sub(1).map(|i| data.get(i))
+    }
+
+    /// Iterate over the items whose masks fall into `masks`, in index order. Keys are in permuted form.
+    ///
+    /// Like [`Index::iter`], skips items which were removed but not compacted yet.
+    fn range<'a>(&'a self, masks: Range<M>) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let data = self.data();
+        data.slice(mask_range(self.permuter(), data, masks)).iter()
+    }
+
+    /// Iterate over blocks of items sharing the same mask, in index order. Keys are in permuted form.
+    ///
+    /// Like [`Index::iter`], skips items which were removed but not compacted yet, and blocks with no other items.
+    fn iter_blocks<'a>(&'a self) -> impl Iterator<Item = (M, impl Iterator<Item = (&'a K, &'a V)>)>
+    where
+        K: 'a,
+        V: 'a,
+        M: 'a,
+    {
+        let data = self.data();
+        mask_blocks(self.permuter(), data).map(move |(mask, range)| (mask, data.slice(range).iter()))
+    }
+
+    /// Remove all items from this index and return them, with keys in their original (non-permuted) form.
+    fn drain(&mut self) -> Result<Vec<(K, V)>, Self::Error> {
+        self.compact()?;
+        let permuter = self.permuter();
+        let items = self
+            .data()
+            .iter()
+            .map(|(key, value)| (permuter.revert(key), value.clone()))
+            .collect();
+        self.retain(|_, _| false)?;
+        Ok(items)
+    }
+
+    /// Remove exact duplicate `(key, value)` pairs in a single pass over the index.
+    fn dedup(&mut self) -> Result<(), Self::Error>
+    where
+        K: Clone + PartialEq,
+        V: PartialEq,
+    {
+        // items are sorted by key, so duplicates can only occur within a run of equal keys
+        let run: RefCell<(Option<K>, Vec<V>)> = RefCell::new((None, Vec::new()));
+        self.retain(|key, value| {
+            let (run_key, run_values) = &mut *run.borrow_mut();
+            if run_key.as_ref() != Some(key) {
+                *run_key = Some(key.clone());
+                run_values.clear();
+            } else if run_values.contains(value) {
+                return false;
+            }
+            run_values.push(value.clone());
+            true
+        })
+    }
+
+    /// Select the `(key, value)` pairs of `items` which are neither stored in this index nor repeated earlier in
+    /// `items`, preserving their order.
+    ///
+    /// Blocks of indexes which defer removal may contain removed items, so such indexes should be compacted first.
+    fn absent_items(&self, items: &[(K, V)]) -> Vec<(K, V)>
+    where
+        K: Clone + Ord,
+        V: Clone + PartialEq,
+    {
+        let permuter = self.permuter();
+        let data = self.data();
+        let mut seen: BTreeMap<&K, Vec<&V>> = BTreeMap::new();
+        let mut absent = Vec::with_capacity(items.len());
+        for (key, value) in items {
+            let seen_values = seen.entry(key).or_default();
+            if seen_values.contains(&value) {
+                continue;
+            }
+            seen_values.push(value);
+            let permuted_key = permuter.apply(key);
+            let run = data.locate_by(self.block_locator(), |key| key.cmp(&permuted_key));
+            if !run.iter().any(|(_, stored)| stored == value) {
+                absent.push((key.clone(), value.clone()));
+            }
+        }
+        absent
+    }
+
+    /// Insert items, skipping `(key, value)` pairs which are already stored in this index or repeated in `items`.
+    /// Returns the number of skipped items.
+    fn insert_new(&mut self, items: &[(K, V)]) -> Result<usize, Self::Error>
+    where
+        K: Clone + Ord,
+        V: Clone + PartialEq,
+    {
+        self.compact()?;
+        let absent = self.absent_items(items);
+        self.insert(&absent)?;
+        Ok(items.len() - absent.len())
+    }
+
     /// Retrieve candidates for a given search.
     fn get_candidates<'a>(&'a self, key: &K) -> Candidates<'a, K, V> {
-        let permuter = self.permuter();
-        let permuted_key = permuter.apply(key);
-        let masked_key = permuter.mask(&permuted_key);
-        let block = self
-            .block_locator()
-            .locate_by(self.data(), |(key, _)| permuter.mask_and_cmp(key, &masked_key));
-        Candidates::new(permuted_key, block)
+        candidates_in(self.permuter(), self.block_locator(), self.data(), key)
+    }
+
+    /// Number of candidates a search for `key` would scan. Only locates the block boundaries, without scanning.
+    fn predict_block_size(&self, key: &K) -> usize {
+        self.get_candidates(key).len()
+    }
+
+    /// Physically remove deleted items, if this index defers removal.
+    fn compact(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Check whether the index contains the given key. Performs a binary search over the full permuted key.
+    fn contains_key(&self, key: &K) -> bool
+    where
+        K: Ord,
+    {
+        let permuted_key = self.permuter().apply(key);
+        !self
+            .data()
+            .locate_by(self.block_locator(), |key| key.cmp(&permuted_key))
+            .is_empty()
     }
 
     /// Compute stats for this index.
     fn compute_stats(&self) -> IndexStats {
         let permuter = self.permuter();
-        IndexStats::from_data(self.data(), |(key, _)| permuter.mask(key))
+        IndexStats::from_masks(self.data().keys().map(|key| permuter.mask(key)))
+    }
+
+    /// Check integrity of this index.
+    fn validate(&self) -> Result<IndexValidation, Self::Error>
+    where
+        K: Ord,
+    {
+        Ok(IndexValidation::from_data_by(self.data().keys(), |a, b| a.cmp(b)))
     }
 }
 
@@ -171,7 +534,87 @@ where
 
     fn load(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error>;
 
+    /// Load the index for reading only. Read-only indexes can be used while another process writes to the index, and
+    /// pick up its changes with [`PersistentIndex::reload`].
+    fn load_read_only(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error>;
+
     fn persist(&self) -> Result<(), Self::Error>;
+
+    /// Pick up changes made by a writer, if this index is read-only. Returns whether anything has changed.
+    fn reload(&mut self) -> Result<bool, Self::Error>;
+
+    /// Whether the writer has changed this read-only index in place since it was loaded or last reloaded, or is
+    /// changing it right now, so that searches made in the meantime may have seen it half-modified. Such searches
+    /// should be repeated after [`PersistentIndex::reload`]. Indexes which are never changed in place return `false`.
+    fn is_stale(&self) -> bool {
+        false
+    }
+
+    /// Close the index and remove all of its files.
+    fn destroy(self) -> Result<(), Self::Error>;
+}
+
+/// Locate the block of candidates for `key` in `data`, which is sorted by keys permuted with `permuter`. Returns the
+/// permuted key and the range of candidates.
+pub(crate) fn locate_candidates<K, V, M>(
+    permuter: &dyn BitPermuter<K, M>,
+    block_locator: BlockLocator,
+    data: Block<'_, K, V>,
+    key: &K,
+) -> (K, Range<usize>) {
+    let permuted_key = permuter.apply(key);
+    let masked_key = permuter.mask(&permuted_key);
+    let range = data.locate_range_by(block_locator, |key| permuter.mask_and_cmp(key, &masked_key));
+    (permuted_key, range)
+}
+
+/// Positions of the items in `data`, which is sorted by keys permuted with `permuter`, whose masks fall into `masks`.
+pub(crate) fn mask_range<K, V, M>(
+    permuter: &dyn BitPermuter<K, M>,
+    data: Block<'_, K, V>,
+    masks: Range<M>,
+) -> Range<usize> {
+    let start = data.partition_point(|key| permuter.mask_and_cmp(key, &masks.start).is_lt());
+    let end = data.partition_point(|key| permuter.mask_and_cmp(key, &masks.end).is_lt());
+    start..end.max(start)
+}
+
+/// Iterate over the masks of `data`, which is sorted by keys permuted with `permuter`, and the positions of the items
+/// having them.
+pub(crate) fn mask_blocks<'a, K, V, M>(
+    permuter: &'a dyn BitPermuter<K, M>,
+    data: Block<'a, K, V>,
+) -> impl Iterator<Item = (M, Range<usize>)> + 'a {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        if start >= data.len() {
+            return None;
+        }
+        let mask = permuter.mask(data.key(start));
+        let len = data
+            .slice(start..data.len())
+            .keys()
+            .take_while(|key| permuter.mask_and_cmp(key, &mask).is_eq())
+            .count();
+        let range = start..start + len;
+        start += len;
+        Some((mask, range))
+    })
+}
+
+/// Locate candidates for `key` in `data`, which is sorted by keys permuted with `permuter`.
+pub(crate) fn candidates_in<'a, K, V, M>(
+    permuter: &dyn BitPermuter<K, M>,
+    block_locator: BlockLocator,
+    data: Block<'a, K, V>,
+    key: &K,
+) -> Candidates<'a, K, V>
+where
+    K: BitContainer,
+    V: Clone,
+{
+    let (permuted_key, range) = locate_candidates(permuter, block_locator, data, key);
+    Candidates::new(permuted_key, data.slice(range))
 }
 
 /// Extract the first element from a tuple.
@@ -180,7 +623,11 @@ pub fn extract_key<K: Copy, V>(item: &(K, V)) -> K {
 }
 
 /// Perform a naive distance search for a key with a given distance.
-pub fn naive_search<K: BitContainer, V: Clone>(data: &[(K, V)], key: K, distance: u32) -> Vec<SearchResultItem<V>> {
+pub fn naive_search<K, V>(data: &[(K, V)], key: K, distance: u32) -> Vec<SearchResultItem<V>>
+where
+    K: BitContainer + ScanBound,
+    V: Clone + ScanBound,
+{
     Candidates::new(key, data).scan(distance)
 }
 
@@ -239,4 +686,23 @@ mod tests {
             "pos 0-2 - data"
         );
     }
+
+    #[test]
+    fn test_candidate_scan_with_custom_metric() {
+        let data = vec![(MyKey(1u32), 0), (MyKey(2u32), 1), (MyKey(4u32), 2), (MyKey(8u32), 3)];
+        let candidates = Candidates::new(MyKey(2), &data);
+        // only candidates greater than the key are at a finite distance
+        let res = candidates.scan_with(3, |key, candidate| candidate.0.checked_sub(key.0).unwrap_or(u32::MAX));
+        assert_eq!(res, vec![SearchResultItem::new(1, 0), SearchResultItem::new(2, 2)]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_scan_matches_sequential_scan() {
+        let data: Vec<_> = (0..(PARALLEL_SCAN_THRESHOLD as u32 * 2))
+            .map(|i| (MyKey(i % 1000), i))
+            .collect();
+        let candidates = Candidates::new(MyKey(500), &data);
+        assert_eq!(candidates.scan(10), candidates.seq_scan(10));
+    }
 }
@@ -0,0 +1,250 @@
+use std::{ops::Range, path::Path};
+
+use hloo_core::{BitContainer, BitPermuter};
+
+use crate::{
+    DynBitPermuter,
+    mmvec::{MmVecError, Pod, is_network_filesystem},
+};
+
+use super::{
+    Block, BlockLocator, Candidates, FileIndex, Index, IndexStats, IndexValidation, MemMapIndex, PersistentIndex,
+    ScanBound, StorageStats,
+};
+
+/// Index which is memory-mapped on local filesystems, and stored like [`FileIndex`] on network filesystems (see
+/// [`is_network_filesystem`]).
+///
+/// Memory maps are not coherent between hosts on network filesystems such as NFS or SMB: a process may read a mix of
+/// old and new pages of a file written by another host, or fault on pages of a file which another host has replaced.
+/// `FileIndex` reads its file with positional reads into memory instead, and read-only indexes pick up the changes of a
+/// writer on another host on [`PersistentIndex::reload`], which compares the generation stored in the header.
+///
+/// Writers lock their files on either storage. On network filesystems, locks rely on the server (e.g. the lock manager
+/// of NFSv3), and an index can't be created or loaded for writing if locking fails.
+///
+/// The storage is chosen when the index is created or loaded. Both use the same file format, so an index can be moved
+/// between filesystems, as long as the memory-mapped one is compacted first (see [`Index::compact`]).
+pub enum NetworkSafeIndex<K, V, M>
+where
+    (K, V): Pod,
+{
+    MemMap(MemMapIndex<K, V, M>),
+    File(FileIndex<K, V, M>),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $index:ident => $e:expr) => {
+        match $self {
+            NetworkSafeIndex::MemMap($index) => $e,
+            NetworkSafeIndex::File($index) => $e,
+        }
+    };
+}
+
+impl<K, V, M> NetworkSafeIndex<K, V, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    V: Pod + ScanBound,
+    M: Copy + Ord,
+{
+    /// Whether the index is memory-mapped, i.e. its file is not on a network filesystem.
+    pub fn is_memory_mapped(&self) -> bool {
+        matches!(self, Self::MemMap(_))
+    }
+}
+
+impl<K, V, M> Index<K, V, M> for NetworkSafeIndex<K, V, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    V: Pod + ScanBound,
+    M: Copy + Ord,
+{
+    type Error = MmVecError;
+
+    fn permuter(&self) -> &dyn BitPermuter<K, M> {
+        dispatch!(self, index => index.permuter())
+    }
+
+    fn block_locator(&self) -> BlockLocator {
+        dispatch!(self, index => index.block_locator())
+    }
+
+    fn data(&self) -> Block<'_, K, V> {
+        dispatch!(self, index => index.data())
+    }
+
+    fn stats(&self) -> &IndexStats {
+        dispatch!(self, index => index.stats())
+    }
+
+    fn storage_stats(&self) -> StorageStats {
+        dispatch!(self, index => index.storage_stats())
+    }
+
+    fn refresh(&mut self) {
+        dispatch!(self, index => index.refresh())
+    }
+
+    fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.insert(items))
+    }
+
+    fn stage_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.stage_insert(items))
+    }
+
+    fn commit_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.commit_insert(items))
+    }
+
+    fn discard_insert(&mut self) {
+        dispatch!(self, index => index.discard_insert())
+    }
+
+    fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.remove(keys))
+    }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.retain(pred))
+    }
+
+    fn compact(&mut self) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.compact())
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        // the memory-mapped index skips removed items
+        let iter: Box<dyn Iterator<Item = _>> = dispatch!(self, index => Box::new(index.iter()));
+        iter
+    }
+
+    fn last<'a>(&'a self) -> Option<(&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        dispatch!(self, index => index.last())
+    }
+
+    fn range<'a>(&'a self, masks: Range<M>) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        let range: Box<dyn Iterator<Item = _>> = dispatch!(self, index => Box::new(index.range(masks)));
+        range
+    }
+
+    fn iter_blocks<'a>(&'a self) -> impl Iterator<Item = (M, impl Iterator<Item = (&'a K, &'a V)>)>
+    where
+        K: 'a,
+        V: 'a,
+        M: 'a,
+    {
+        let blocks: Box<dyn Iterator<Item = (M, Box<dyn Iterator<Item = _>>)>> = dispatch!(self, index => {
+            let blocks = index.iter_blocks();
+            Box::new(blocks.map(|(mask, items)| (mask, Box::new(items) as Box<dyn Iterator<Item = _>>)))
+        });
+        blocks
+    }
+
+    fn get_candidates<'a>(&'a self, key: &K) -> Candidates<'a, K, V> {
+        dispatch!(self, index => index.get_candidates(key))
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        dispatch!(self, index => index.contains_key(key))
+    }
+
+    fn validate(&self) -> Result<IndexValidation, Self::Error> {
+        dispatch!(self, index => index.validate())
+    }
+}
+
+impl<K, V, M> PersistentIndex<K, M> for NetworkSafeIndex<K, V, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    V: Pod + ScanBound,
+    M: Copy + Ord,
+{
+    type Error = MmVecError;
+
+    fn create(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        if is_network_filesystem(path)? {
+            FileIndex::create(permuter, sig, path).map(Self::File)
+        } else {
+            MemMapIndex::create(permuter, sig, path).map(Self::MemMap)
+        }
+    }
+
+    fn load(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        if is_network_filesystem(path)? {
+            FileIndex::load(permuter, sig, path).map(Self::File)
+        } else {
+            MemMapIndex::load(permuter, sig, path).map(Self::MemMap)
+        }
+    }
+
+    fn load_read_only(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        if is_network_filesystem(path)? {
+            FileIndex::load_read_only(permuter, sig, path).map(Self::File)
+        } else {
+            MemMapIndex::load_read_only(permuter, sig, path).map(Self::MemMap)
+        }
+    }
+
+    fn persist(&self) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.persist())
+    }
+
+    fn reload(&mut self) -> Result<bool, Self::Error> {
+        dispatch!(self, index => index.reload())
+    }
+
+    fn is_stale(&self) -> bool {
+        dispatch!(self, index => index.is_stale())
+    }
+
+    fn destroy(self) -> Result<(), Self::Error> {
+        dispatch!(self, index => index.destroy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, Pod};
+    use hloo_macros::make_permutations;
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn network_safe_index_is_memory_mapped_on_local_filesystems() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("index.dat");
+        let is_network = is_network_filesystem(&path).unwrap();
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        let mut index = NetworkSafeIndex::create(Permutations::get_variant(1), 0, &path).unwrap();
+        assert_eq!(index.is_memory_mapped(), !is_network);
+        index.insert(&data).unwrap();
+        index.remove(&[data[0].0]).unwrap();
+        assert_eq!(index.iter().count(), 1, "removed item is skipped");
+        index.persist().unwrap();
+        drop(index);
+
+        let reader =
+            NetworkSafeIndex::<Bits, i32, Mask>::load_read_only(Permutations::get_variant(1), 0, &path).unwrap();
+        assert!(reader.contains_key(&data[1].0));
+        assert!(!reader.contains_key(&data[0].0));
+    }
+}
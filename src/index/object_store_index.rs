@@ -0,0 +1,361 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    ops::Range,
+    ptr,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{self, AtomicU64},
+    },
+};
+
+use bytes::Bytes;
+use hloo_core::{BitContainer, BitPermuter, Pod};
+use object_store::{ObjectStore, ObjectStoreExt, path::Path};
+use thiserror::Error;
+
+use crate::{
+    DynBitPermuter,
+    mmvec::{FORMAT_MAGIC, FORMAT_VERSION, HEADER_SIZE, MAGIC_OFFSET, VERSION_OFFSET},
+    util::describe_signature_mismatch,
+};
+
+use super::{Candidates, ScanBound, SearchResultItem};
+
+/// Size of the pages fetched from the store by default, in bytes.
+const DEFAULT_PAGE_SIZE: usize = 1 << 20;
+
+/// Number of pages cached by default.
+const DEFAULT_CACHE_PAGES: usize = 256;
+
+#[derive(Debug, Error)]
+pub enum ObjectStoreIndexError {
+    #[error("object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+    #[error("signature does not match: {}", describe_signature_mismatch(*expected, *actual))]
+    SignatureMismatch { expected: u64, actual: u64 },
+    #[error("object is truncated: {actual} bytes, but the header declares {expected}")]
+    Truncated { expected: u64, actual: u64 },
+    /// The object is in a file format this version does not read, see
+    /// [`MmVecError::UnsupportedFormat`](crate::mmvec::MmVecError::UnsupportedFormat). Objects without a format
+    /// version are reported as version 0.
+    #[error("object is in format version {version}, but only version {FORMAT_VERSION} is supported")]
+    UnsupportedFormat { version: u64 },
+}
+
+/// Read-only index over an index file stored in an object store (e.g. S3 or GCS, see [`object_store`]).
+///
+/// The object is a file written by [`MemMapIndex`](super::MemMapIndex) or [`FileIndex`](super::FileIndex), uploaded
+/// as is. Nothing is downloaded up front: searches locate their block with a binary search over the object, fetching
+/// fixed-size pages of it with range requests, and keep recently used pages in an LRU cache. Pages around the middle
+/// of the object are hit by every search, so they stay cached, and a cold search costs about `log2(pages)` requests.
+///
+/// Tombstones of memory-mapped indexes are not uploaded, so indexes should be compacted (see
+/// [`Index::compact`](super::Index::compact)) before uploading. The object must not change while the index is open.
+///
+/// Like [`TieredIndex`](super::TieredIndex), this index doesn't implement [`Index`](super::Index), since its data is
+/// not in memory; searches are `async` instead.
+pub struct ObjectStoreIndex<K, V, M> {
+    store: Arc<dyn ObjectStore>,
+    location: Path,
+    permuter: DynBitPermuter<K, M>,
+    len: usize,
+    page_size: usize,
+    cache: Mutex<PageCache>,
+    fetched_pages: AtomicU64,
+    _marker: PhantomData<V>,
+}
+
+impl<K, V, M> ObjectStoreIndex<K, V, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    V: Pod + ScanBound,
+    M: Copy + Ord,
+{
+    /// Open the index stored at `location`, checking its format version, signature and size.
+    pub async fn open(
+        store: Arc<dyn ObjectStore>,
+        location: Path,
+        permuter: DynBitPermuter<K, M>,
+        sig: u64,
+    ) -> Result<Self, ObjectStoreIndexError> {
+        let header = store.get_range(&location, 0..HEADER_SIZE).await?;
+        let field =
+            |i: usize| u64::from_ne_bytes(header[i * 8..i * 8 + 8].try_into().expect("slice has the right length"));
+        let version = if field(MAGIC_OFFSET / 8) == FORMAT_MAGIC {
+            field(VERSION_OFFSET / 8)
+        } else {
+            0
+        };
+        if version != FORMAT_VERSION {
+            return Err(ObjectStoreIndexError::UnsupportedFormat { version });
+        }
+        if field(0) != sig {
+            return Err(ObjectStoreIndexError::SignatureMismatch {
+                expected: sig,
+                actual: field(0),
+            });
+        }
+        let len = field(1);
+        let size = store.head(&location).await?.size;
+        let expected = HEADER_SIZE + len * size_of::<(K, V)>() as u64;
+        if size < expected {
+            return Err(ObjectStoreIndexError::Truncated { expected, actual: size });
+        }
+        Ok(Self {
+            store,
+            location,
+            permuter,
+            len: len as usize,
+            page_size: DEFAULT_PAGE_SIZE,
+            cache: Mutex::new(PageCache::new(DEFAULT_CACHE_PAGES)),
+            fetched_pages: AtomicU64::new(0),
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn permuter(&self) -> &dyn BitPermuter<K, M> {
+        self.permuter.as_ref()
+    }
+
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Change the size of fetched pages (1 MiB by default) and the number of cached pages (256 by default), dropping
+    /// cached pages. Setting `pages` to 0 disables the cache.
+    ///
+    /// ## Panics
+    /// Panics if `page_size` is 0.
+    pub fn set_cache(&mut self, page_size: usize, pages: usize) {
+        assert!(page_size > 0, "page size must be positive");
+        self.page_size = page_size;
+        *self.cache.get_mut().unwrap_or_else(PoisonError::into_inner) = PageCache::new(pages);
+    }
+
+    /// Number of pages currently cached.
+    pub fn cached_pages(&self) -> usize {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).pages.len()
+    }
+
+    /// Number of pages fetched from the store since the index was opened.
+    pub fn fetched_pages(&self) -> u64 {
+        self.fetched_pages.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Fetch the candidates for a given search: the permuted key and the items of its block.
+    pub async fn get_candidates(&self, key: &K) -> Result<(K, Vec<(K, V)>), ObjectStoreIndexError> {
+        let permuted_key = self.permuter.apply(key);
+        let masked_key = self.permuter.mask(&permuted_key);
+        let start = self
+            .partition_point(0..self.len, |key| {
+                self.permuter.mask_and_cmp(key, &masked_key) == Ordering::Less
+            })
+            .await?;
+        let end = self
+            .partition_point(start..self.len, |key| {
+                self.permuter.mask_and_cmp(key, &masked_key) != Ordering::Greater
+            })
+            .await?;
+        Ok((permuted_key, self.read_items(start..end).await?))
+    }
+
+    /// Search for items within `distance` of `key`.
+    pub async fn search(&self, key: &K, distance: u32) -> Result<Vec<SearchResultItem<V>>, ObjectStoreIndexError> {
+        let (permuted_key, items) = self.get_candidates(key).await?;
+        Ok(Candidates::new(permuted_key, items.as_slice()).scan(distance))
+    }
+
+    /// Find the first position in `range` whose key doesn't satisfy `pred`, assuming keys satisfying it come first.
+    async fn partition_point(
+        &self,
+        range: Range<usize>,
+        pred: impl Fn(&K) -> bool,
+    ) -> Result<usize, ObjectStoreIndexError> {
+        let (mut lo, mut hi) = (range.start, range.end);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let (key, _) = self.read_items(mid..mid + 1).await?[0];
+            if pred(&key) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    /// Read the items in `range` through the page cache, fetching all missing pages at once.
+    async fn read_items(&self, range: Range<usize>) -> Result<Vec<(K, V)>, ObjectStoreIndexError> {
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let item_size = size_of::<(K, V)>();
+        let data_size = (self.len * item_size) as u64;
+        let (start, end) = ((range.start * item_size) as u64, (range.end * item_size) as u64);
+        let page_size = self.page_size as u64;
+        let page_nos = start / page_size..end.div_ceil(page_size);
+
+        // the lock is not held while fetching
+        let mut pages: Vec<_> = {
+            let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            page_nos.clone().map(|no| (no, cache.get(no))).collect()
+        };
+        let missing: Vec<_> = pages
+            .iter()
+            .filter(|(_, page)| page.is_none())
+            .map(|(no, _)| *no)
+            .collect();
+        if !missing.is_empty() {
+            let ranges: Vec<_> = missing
+                .iter()
+                .map(|no| HEADER_SIZE + no * page_size..HEADER_SIZE + ((no + 1) * page_size).min(data_size))
+                .collect();
+            let fetched = self.store.get_ranges(&self.location, &ranges).await?;
+            self.fetched_pages
+                .fetch_add(fetched.len() as u64, atomic::Ordering::Relaxed);
+            let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut fetched = missing.into_iter().zip(fetched);
+            for (no, page) in pages.iter_mut().filter(|(_, page)| page.is_none()) {
+                let (fetched_no, bytes) = fetched.next().expect("every missing page is fetched");
+                debug_assert_eq!(*no, fetched_no);
+                cache.insert(fetched_no, bytes.clone());
+                *page = Some(bytes);
+            }
+        }
+
+        let mut bytes = Vec::with_capacity((end - start) as usize);
+        for (no, page) in pages {
+            let page = page.expect("every page is either cached or fetched");
+            let page_start = no * page_size;
+            let from = start.saturating_sub(page_start) as usize;
+            let to = (end - page_start).min(page.len() as u64) as usize;
+            bytes.extend_from_slice(&page[from..to]);
+        }
+        Ok(bytes
+            .chunks_exact(item_size)
+            // Safety: items are `Pod`, so any bytes are valid items, and the signature of the object was checked
+            .map(|item| unsafe { ptr::read_unaligned(item.as_ptr().cast::<(K, V)>()) })
+            .collect())
+    }
+}
+
+/// Cache of fixed-size pages of the data section, evicted in least-recently-used order.
+struct PageCache {
+    // page number -> bytes and the time of the last use
+    pages: HashMap<u64, (Bytes, u64)>,
+    // time of the last use -> page number
+    uses: BTreeMap<u64, u64>,
+    clock: u64,
+    capacity: usize,
+}
+
+impl PageCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            pages: HashMap::with_capacity(capacity),
+            uses: BTreeMap::new(),
+            clock: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, no: u64) -> Option<Bytes> {
+        self.clock += 1;
+        let (bytes, used) = self.pages.get_mut(&no)?;
+        self.uses.remove(used);
+        *used = self.clock;
+        self.uses.insert(self.clock, no);
+        Some(bytes.clone())
+    }
+
+    fn insert(&mut self, no: u64, bytes: Bytes) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((_, used)) = self.pages.remove(&no) {
+            self.uses.remove(&used);
+        }
+        while self.pages.len() >= self.capacity {
+            let (_, evicted) = self.uses.pop_first().expect("cache is not empty");
+            self.pages.remove(&evicted);
+        }
+        self.clock += 1;
+        self.pages.insert(no, (bytes, self.clock));
+        self.uses.insert(self.clock, no);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use hloo_core::BitContainer;
+    use hloo_macros::make_permutations;
+    use object_store::{PutPayload, memory::InMemory};
+
+    use super::*;
+    use crate::index::{Index, MemMapIndex, PersistentIndex};
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn object_store_index_searches_like_memmap_index() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("index.dat");
+        let data: Vec<_> = (0..500u32)
+            .map(|i| (Bits::new([i.wrapping_mul(0x9E3779B9) ^ (i << 20)]), i as i64))
+            .collect();
+        let mut index = MemMapIndex::create(Permutations::get_variant(1), 7, &path).unwrap();
+        index.insert(&data).unwrap();
+        index.remove(&[data[3].0]).unwrap();
+        index.compact().unwrap();
+        index.persist().unwrap();
+
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let location = Path::from("archive/index.dat");
+        let bytes = std::fs::read(&path).unwrap();
+        block_on(store.put(&location, PutPayload::from(bytes))).unwrap();
+
+        assert!(matches!(
+            block_on(ObjectStoreIndex::<Bits, i64, Mask>::open(
+                store.clone(),
+                location.clone(),
+                Permutations::get_variant(1),
+                8
+            )),
+            Err(ObjectStoreIndexError::SignatureMismatch { .. })
+        ));
+        let mut remote = block_on(ObjectStoreIndex::open(store, location, Permutations::get_variant(1), 7)).unwrap();
+        assert_eq!(remote.len(), data.len() - 1);
+        // pages smaller than items and blocks, so that reads span several of them
+        remote.set_cache(48, 64);
+
+        let sorted = |mut results: Vec<SearchResultItem<i64>>| {
+            results.sort_by_key(|item| (*item.data(), item.distance()));
+            results
+        };
+        for (key, _) in &data[..50] {
+            let expected = sorted(index.get_candidates(key).scan(4));
+            let actual = sorted(block_on(remote.search(key, 4)).unwrap());
+            assert_eq!(actual, expected);
+        }
+        assert!(
+            block_on(remote.search(&data[3].0, 0)).unwrap().is_empty(),
+            "removed item is not found"
+        );
+        assert!(remote.cached_pages() <= 64);
+
+        let fetched = remote.fetched_pages();
+        block_on(remote.search(&data[3].0, 0)).unwrap();
+        assert_eq!(remote.fetched_pages(), fetched, "pages of the last search are cached");
+    }
+}
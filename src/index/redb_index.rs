@@ -0,0 +1,370 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    ptr, slice,
+};
+
+use hloo_core::{BitContainer, BitPermuter};
+use redb::{
+    Database, ReadOnlyDatabase, ReadTransaction, ReadableDatabase, ReadableTable, TableDefinition, WriteTransaction,
+};
+use thiserror::Error;
+
+use crate::{
+    DynBitPermuter, metrics,
+    mmvec::Pod,
+    util::{describe_signature_mismatch, sort_unstable_by_key},
+};
+
+use super::{Block, BlockLocator, Index, IndexStats, MemIndex, PersistentIndex, ScanBound, StorageStats, extract_key};
+
+/// Items of every block, by the bytes of its mask.
+const BLOCKS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("blocks");
+/// Metadata of the index: its signature.
+const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+const SIG_KEY: &str = "sig";
+
+#[derive(Debug, Error)]
+pub enum RedbIndexError {
+    #[error("signature does not match: {}", describe_signature_mismatch(*expected, *actual))]
+    SignatureMismatch { expected: u64, actual: u64 },
+    #[error("database has no signature, it was not created by an index")]
+    MissingSignature,
+    #[error("index is opened read-only")]
+    ReadOnly,
+    #[error("database error: {0}")]
+    Database(#[from] redb::Error),
+    #[error("i/o error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+macro_rules! impl_from_redb_error {
+    ($($error:ident),*) => {
+        $(
+            impl From<redb::$error> for RedbIndexError {
+                fn from(e: redb::$error) -> Self {
+                    Self::Database(e.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_redb_error!(DatabaseError, TransactionError, TableError, StorageError, CommitError);
+
+enum Db {
+    Writable(Database),
+    ReadOnly(ReadOnlyDatabase),
+}
+
+impl Db {
+    fn begin_read(&self) -> Result<ReadTransaction, redb::TransactionError> {
+        match self {
+            Db::Writable(db) => db.begin_read(),
+            Db::ReadOnly(db) => db.begin_read(),
+        }
+    }
+}
+
+/// Index which is kept in memory and stored in a [redb](https://docs.rs/redb) database, one entry per block of items
+/// sharing a mask.
+///
+/// This is an alternative to [`MemMapIndex`](super::MemMapIndex) for environments which don't allow memory-mapping
+/// files directly, but allow embedded databases. Searches are served from memory, like in [`MemIndex`]; every
+/// modification rewrites the blocks it has touched in a single transaction, which is durable once the modification
+/// returns, so [`PersistentIndex::persist`] has nothing to do.
+///
+/// The database is locked while it is open: read-only indexes can't be loaded while a writer has it open, and vice
+/// versa, so [`PersistentIndex::reload`] never finds changes.
+pub struct RedbIndex<K, V, M> {
+    inner: MemIndex<K, V, M>,
+    /// Transaction of the insert staged by [`Index::stage_insert`], and the data after it. Declared before the
+    /// database, so that it is dropped first.
+    staged: Option<(WriteTransaction, Vec<(K, V)>)>,
+    db: Db,
+    path: PathBuf,
+}
+
+impl<K, V, M> RedbIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord + Pod,
+    (K, V): Pod,
+{
+    fn open(permuter: DynBitPermuter<K, M>, sig: u64, db: Db, path: &Path) -> Result<Self, RedbIndexError> {
+        let txn = db.begin_read()?;
+        let actual = txn
+            .open_table(META)?
+            .get(SIG_KEY)?
+            .ok_or(RedbIndexError::MissingSignature)?
+            .value();
+        if actual != sig {
+            return Err(RedbIndexError::SignatureMismatch { expected: sig, actual });
+        }
+        let mut data = Vec::new();
+        for entry in txn.open_table(BLOCKS)?.iter()? {
+            let (_, items) = entry?;
+            data.extend(items_from_bytes::<(K, V)>(items.value()));
+        }
+        // blocks are ordered by the bytes of their masks, which may differ from the order of masks
+        sort_unstable_by_key(&mut data, extract_key);
+        Ok(Self {
+            inner: MemIndex::with_data(permuter, data),
+            staged: None,
+            db,
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn writable_db(&self) -> Result<&Database, RedbIndexError> {
+        match &self.db {
+            Db::Writable(db) => Ok(db),
+            Db::ReadOnly(_) => Err(RedbIndexError::ReadOnly),
+        }
+    }
+
+    /// Write the blocks of the given masks from memory to the database, removing the ones which are now empty.
+    fn store_blocks(&self, masks: impl IntoIterator<Item = M>) -> Result<(), RedbIndexError> {
+        let data = self.inner.data();
+        let items = data.as_interleaved().expect("memory index data is interleaved");
+        self.write_blocks(items, masks)?.commit()?;
+        Ok(())
+    }
+
+    /// Write the blocks of the given masks from `items`, which are permuted and sorted by key, in a new transaction,
+    /// which is returned without committing it.
+    fn write_blocks(
+        &self,
+        items: &[(K, V)],
+        masks: impl IntoIterator<Item = M>,
+    ) -> Result<WriteTransaction, RedbIndexError> {
+        let txn = self.writable_db()?.begin_write()?;
+        {
+            let mut table = txn.open_table(BLOCKS)?;
+            let permuter = self.inner.permuter();
+            let data = Block::Interleaved(items);
+            for mask in masks {
+                let range = data.locate_range_by(self.inner.block_locator(), |key| permuter.mask_and_cmp(key, &mask));
+                let mask_bytes = items_as_bytes(slice::from_ref(&mask));
+                if range.is_empty() {
+                    table.remove(mask_bytes)?;
+                } else {
+                    table.insert(mask_bytes, items_as_bytes(&items[range]))?;
+                }
+            }
+        }
+        Ok(txn)
+    }
+
+    /// Masks of the blocks holding `keys`, which are in their original form.
+    fn masks_of<'a>(&self, keys: impl Iterator<Item = &'a K>) -> BTreeSet<M>
+    where
+        K: 'a,
+    {
+        let permuter = self.inner.permuter();
+        keys.map(|key| permuter.mask(&permuter.apply(key))).collect()
+    }
+
+    pub fn destroy(self) -> Result<(), RedbIndexError> {
+        drop(self.staged);
+        drop(self.db);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+impl<K, V, M> Index<K, V, M> for RedbIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord + Pod,
+    (K, V): Pod,
+{
+    type Error = RedbIndexError;
+
+    fn permuter(&self) -> &dyn BitPermuter<K, M> {
+        self.inner.permuter()
+    }
+
+    fn block_locator(&self) -> BlockLocator {
+        self.inner.block_locator()
+    }
+
+    fn data(&self) -> Block<'_, K, V> {
+        self.inner.data()
+    }
+
+    fn stats(&self) -> &IndexStats {
+        self.inner.stats()
+    }
+
+    fn storage_stats(&self) -> StorageStats {
+        // every modification is committed right away, so the database was last written when it was last modified
+        let metadata = fs::metadata(&self.path).ok();
+        StorageStats {
+            bytes_on_disk: metadata.as_ref().map_or(0, |metadata| metadata.len()),
+            last_flush: metadata.and_then(|metadata| metadata.modified().ok()),
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh();
+    }
+
+    fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.writable_db()?;
+        // MemIndex is infallible
+        let _ = self.inner.insert(items);
+        self.store_blocks(self.masks_of(items.iter().map(|(key, _)| key)))
+    }
+
+    fn stage_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        // only one write transaction can be open at a time
+        self.staged = None;
+        let data = self.inner.data_with(items);
+        let txn = self.write_blocks(&data, self.masks_of(items.iter().map(|(key, _)| key)))?;
+        self.staged = Some((txn, data));
+        Ok(())
+    }
+
+    fn commit_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        let Some((txn, data)) = self.staged.take() else {
+            return Ok(());
+        };
+        metrics::insert(items.len());
+        txn.commit()?;
+        self.inner.set_data(data);
+        Ok(())
+    }
+
+    fn discard_insert(&mut self) {
+        self.staged = None;
+    }
+
+    fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
+        self.writable_db()?;
+        let _ = self.inner.remove(keys);
+        self.store_blocks(self.masks_of(keys.iter()))
+    }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        self.writable_db()?;
+        let masks: Vec<_> = self.inner.iter_blocks().map(|(mask, _)| mask).collect();
+        let _ = self.inner.retain(pred);
+        self.store_blocks(masks)
+    }
+}
+
+impl<K, V, M> PersistentIndex<K, M> for RedbIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord + Pod,
+    (K, V): Pod,
+{
+    type Error = RedbIndexError;
+
+    fn create(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        let db = Database::create(path)?;
+        let txn = db.begin_write()?;
+        // the file may hold a database already, which is replaced
+        txn.delete_table(BLOCKS)?;
+        txn.open_table(BLOCKS)?;
+        txn.open_table(META)?.insert(SIG_KEY, sig)?;
+        txn.commit()?;
+        Self::open(permuter, sig, Db::Writable(db), path)
+    }
+
+    fn load(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        Self::open(permuter, sig, Db::Writable(Database::open(path)?), path)
+    }
+
+    fn load_read_only(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        Self::open(permuter, sig, Db::ReadOnly(ReadOnlyDatabase::open(path)?), path)
+    }
+
+    fn persist(&self) -> Result<(), Self::Error> {
+        // modifications are committed as they are made
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<bool, Self::Error> {
+        // writers can't open the database while this index has it open
+        Ok(false)
+    }
+
+    fn destroy(self) -> Result<(), Self::Error> {
+        RedbIndex::destroy(self)
+    }
+}
+
+fn items_as_bytes<T: Pod>(items: &[T]) -> &[u8] {
+    // Safety: items are stored as raw bytes, the same way as in memory-mapped vectors
+    unsafe { slice::from_raw_parts(items.as_ptr().cast::<u8>(), size_of_val(items)) }
+}
+
+/// Copy items stored by [`items_as_bytes`]. Trailing bytes which don't make up a whole item are ignored.
+fn items_from_bytes<T: Pod>(bytes: &[u8]) -> Vec<T> {
+    let n = bytes.len() / size_of::<T>();
+    let mut items = Vec::<T>::with_capacity(n);
+    // Safety: any bit pattern is a valid `T`, and the bytes of `n` items fit into the allocation
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), items.as_mut_ptr().cast::<u8>(), n * size_of::<T>());
+        items.set_len(n);
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn test_redb_index_is_persisted() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("index.redb");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        let mut index = RedbIndex::create(Permutations::get_variant(1), 0, &path).unwrap();
+        index.insert(&data).unwrap();
+        index.remove(&[data[1].0]).unwrap();
+        index.retain(|_, value| *value != 3).unwrap();
+        drop(index);
+
+        let mut index = RedbIndex::<Bits, i32, Mask>::load(Permutations::get_variant(1), 0, &path).unwrap();
+        assert_eq!(
+            index.data().to_vec(),
+            [(index.permuter().apply(&data[0].0), 0)],
+            "data after load"
+        );
+        index.insert(&data[1..]).unwrap();
+        assert!(index.storage_stats().bytes_on_disk > 0, "database is stored");
+        drop(index);
+
+        assert!(matches!(
+            RedbIndex::<Bits, i32, Mask>::load(Permutations::get_variant(1), 1, &path),
+            Err(RedbIndexError::SignatureMismatch { expected: 1, actual: 0 })
+        ));
+        let mut reader = RedbIndex::<Bits, i32, Mask>::load_read_only(Permutations::get_variant(1), 0, &path).unwrap();
+        let mut expected: Vec<_> = data.iter().map(|(k, v)| (reader.permuter().apply(k), *v)).collect();
+        expected.sort_unstable();
+        assert_eq!(reader.data().to_vec(), expected, "reader should see all data");
+        assert!(matches!(reader.insert(&data), Err(RedbIndexError::ReadOnly)));
+        drop(reader);
+
+        let index = RedbIndex::<Bits, i32, Mask>::create(Permutations::get_variant(1), 0, &path).unwrap();
+        assert!(index.data().is_empty(), "created index replaces the database");
+        index.destroy().unwrap();
+        assert!(!path.exists(), "database is removed");
+    }
+}
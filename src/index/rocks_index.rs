@@ -0,0 +1,443 @@
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    fs, io, iter,
+    path::{Path, PathBuf},
+    ptr, slice,
+};
+
+use hloo_core::{BitContainer, BitPermuter};
+use rocksdb::{DB, Direction, IteratorMode, Options, WriteBatch};
+use thiserror::Error;
+
+use crate::{
+    DynBitPermuter,
+    mmvec::Pod,
+    util::{describe_signature_mismatch, sort_unstable_by_key},
+};
+
+use super::{Block, BlockLocator, Index, IndexStats, MemIndex, PersistentIndex, ScanBound, StorageStats, extract_key};
+
+/// First byte of the keys of items. Metadata keys start with a zero byte, so they precede all items.
+const ITEM_PREFIX: u8 = 1;
+const SIG_KEY: &[u8] = b"\0sig";
+/// Number of write batches applied to the database, used by read-only indexes to detect changes.
+const GENERATION_KEY: &[u8] = b"\0generation";
+
+/// Key and value of an entry of the database.
+type Entry = (Box<[u8]>, Box<[u8]>);
+
+#[derive(Debug, Error)]
+pub enum RocksIndexError {
+    #[error("signature does not match: {}", describe_signature_mismatch(*expected, *actual))]
+    SignatureMismatch { expected: u64, actual: u64 },
+    #[error("database has no signature, it was not created by an index")]
+    MissingSignature,
+    #[error("database holds an entry of unexpected size")]
+    InvalidEntry,
+    #[error("index is opened read-only")]
+    ReadOnly,
+    #[error("database error: {0}")]
+    Database(#[from] rocksdb::Error),
+    #[error("i/o error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Index which is kept in memory and stored in a [RocksDB](https://docs.rs/rocksdb) database.
+///
+/// Every distinct item is an entry of the database, keyed by the mask of its permuted key, followed by the permuted key
+/// and the value, and holding the number of copies of the item. Modifications only write the entries of the items they
+/// touch, in a single write batch, so large indexes are updated incrementally. Blocks are contiguous ranges of
+/// entries, which can be read without loading the index with [`RocksIndex::read_block`].
+///
+/// Searches are served from memory, like in [`MemIndex`]. Writes are durable once [`PersistentIndex::persist`] syncs
+/// the write-ahead log. Values are stored as their in-memory representation, so they shouldn't have padding bytes.
+pub struct RocksIndex<K, V, M> {
+    inner: MemIndex<K, V, M>,
+    db: DB,
+    path: PathBuf,
+    generation: u64,
+    read_only: bool,
+    /// Write batch of the insert staged by [`Index::stage_insert`].
+    staged: Option<WriteBatch>,
+}
+
+impl<K, V, M> RocksIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound + Pod,
+    V: Copy + ScanBound + Pod,
+    M: Copy + Ord + Pod,
+{
+    fn open(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path, read_only: bool) -> Result<Self, RocksIndexError> {
+        let db = open_db(path, read_only)?;
+        let actual = read_u64(&db, SIG_KEY)?.ok_or(RocksIndexError::MissingSignature)?;
+        if actual != sig {
+            return Err(RocksIndexError::SignatureMismatch { expected: sig, actual });
+        }
+        let generation = read_u64(&db, GENERATION_KEY)?.unwrap_or(0);
+        let data = read_items::<K, V, M>(&db, &[ITEM_PREFIX])?;
+        Ok(Self {
+            inner: MemIndex::with_data(permuter, data),
+            db,
+            path: path.to_path_buf(),
+            generation,
+            read_only,
+            staged: None,
+        })
+    }
+
+    /// Read the block of items with the given mask from the database, without using the items in memory. Keys are in
+    /// permuted form.
+    pub fn read_block(&self, mask: &M) -> Result<Vec<(K, V)>, RocksIndexError> {
+        let mut prefix = vec![ITEM_PREFIX];
+        prefix.extend_from_slice(pod_bytes(mask));
+        read_items::<K, V, M>(&self.db, &prefix)
+    }
+
+    fn check_writable(&self) -> Result<(), RocksIndexError> {
+        if self.read_only {
+            return Err(RocksIndexError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Number of copies of the item with the given database key.
+    fn count(&self, item_key: &[u8]) -> Result<u64, RocksIndexError> {
+        Ok(read_u64(&self.db, item_key)?.unwrap_or(0))
+    }
+
+    /// Write batch which adds `items` to the database.
+    fn insert_batch(&self, items: &[(K, V)]) -> Result<WriteBatch, RocksIndexError> {
+        let permuter = self.inner.permuter();
+        let mut added = BTreeMap::<_, u64>::new();
+        for (key, value) in items {
+            *added
+                .entry(item_key(permuter, &permuter.apply(key), value))
+                .or_default() += 1;
+        }
+        let mut batch = WriteBatch::default();
+        for (item_key, n) in added {
+            batch.put(&item_key, (self.count(&item_key)? + n).to_le_bytes());
+        }
+        Ok(batch)
+    }
+
+    /// Apply `batch` to the database, along with a new generation.
+    fn commit(&mut self, mut batch: WriteBatch) -> Result<(), RocksIndexError> {
+        batch.put(GENERATION_KEY, (self.generation + 1).to_le_bytes());
+        self.db.write(batch)?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    pub fn destroy(self) -> Result<(), RocksIndexError> {
+        drop(self.db);
+        DB::destroy(&Options::default(), &self.path)?;
+        Ok(())
+    }
+}
+
+impl<K, V, M> Index<K, V, M> for RocksIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound + Pod,
+    V: Copy + ScanBound + Pod,
+    M: Copy + Ord + Pod,
+{
+    type Error = RocksIndexError;
+
+    fn permuter(&self) -> &dyn BitPermuter<K, M> {
+        self.inner.permuter()
+    }
+
+    fn block_locator(&self) -> BlockLocator {
+        self.inner.block_locator()
+    }
+
+    fn data(&self) -> Block<'_, K, V> {
+        self.inner.data()
+    }
+
+    fn stats(&self) -> &IndexStats {
+        self.inner.stats()
+    }
+
+    fn storage_stats(&self) -> StorageStats {
+        // files of the database are only written when they are flushed
+        let mut stats = StorageStats::default();
+        for entry in fs::read_dir(&self.path).into_iter().flatten().flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            stats.bytes_on_disk += metadata.len();
+            let modified = metadata.modified().ok();
+            stats.last_flush = stats.last_flush.max(modified);
+        }
+        stats
+    }
+
+    fn refresh(&mut self) {
+        self.inner.refresh();
+    }
+
+    fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.check_writable()?;
+        let batch = self.insert_batch(items)?;
+        self.commit(batch)?;
+        // MemIndex is infallible
+        let _ = self.inner.insert(items);
+        Ok(())
+    }
+
+    fn stage_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.staged = None;
+        self.check_writable()?;
+        self.staged = Some(self.insert_batch(items)?);
+        Ok(())
+    }
+
+    fn commit_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        let Some(batch) = self.staged.take() else {
+            return Ok(());
+        };
+        self.commit(batch)?;
+        let _ = self.inner.insert(items);
+        Ok(())
+    }
+
+    fn discard_insert(&mut self) {
+        self.staged = None;
+    }
+
+    fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
+        self.check_writable()?;
+        let permuter = self.inner.permuter();
+        let permuted: BTreeSet<_> = permuter.apply_many(keys).into_iter().collect();
+        let mut batch = WriteBatch::default();
+        for key in &permuted {
+            let mut prefix = vec![ITEM_PREFIX];
+            prefix.extend_from_slice(pod_bytes(&permuter.mask(key)));
+            prefix.extend_from_slice(pod_bytes(key));
+            for entry in scan_prefix(&self.db, &prefix) {
+                batch.delete(entry?.0);
+            }
+        }
+        self.commit(batch)?;
+        let _ = self.inner.remove(keys);
+        Ok(())
+    }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        self.check_writable()?;
+        // `pred` may be stateful, so it is called once per item, in index order
+        let permuter = self.inner.permuter();
+        let keep: Vec<_> = self
+            .inner
+            .iter()
+            .map(|(key, value)| pred(&permuter.revert(key), value))
+            .collect();
+        let mut removed = BTreeMap::<_, u64>::new();
+        for ((key, value), _) in self.inner.iter().zip(&keep).filter(|(_, keep)| !**keep) {
+            *removed.entry(item_key(permuter, key, value)).or_default() += 1;
+        }
+        let mut batch = WriteBatch::default();
+        for (item_key, n) in removed {
+            match self.count(&item_key)?.saturating_sub(n) {
+                0 => batch.delete(&item_key),
+                left => batch.put(&item_key, left.to_le_bytes()),
+            }
+        }
+        self.commit(batch)?;
+        let i = Cell::new(0);
+        let _ = self.inner.retain(|_, _| {
+            i.set(i.get() + 1);
+            keep[i.get() - 1]
+        });
+        Ok(())
+    }
+}
+
+impl<K, V, M> PersistentIndex<K, M> for RocksIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound + Pod,
+    V: Copy + ScanBound + Pod,
+    M: Copy + Ord + Pod,
+{
+    type Error = RocksIndexError;
+
+    fn create(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        // the directory may hold a database already, which is replaced
+        DB::destroy(&Options::default(), path)?;
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        let db = DB::open(&options, path)?;
+        let mut batch = WriteBatch::default();
+        batch.put(SIG_KEY, sig.to_le_bytes());
+        batch.put(GENERATION_KEY, 0u64.to_le_bytes());
+        db.write(batch)?;
+        db.flush_wal(true)?;
+        drop(db);
+        Self::open(permuter, sig, path, false)
+    }
+
+    fn load(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        Self::open(permuter, sig, path, false)
+    }
+
+    /// Read-only indexes can be loaded while a writer has the database open, and see its state at the time of
+    /// loading, or of the last [`PersistentIndex::reload`].
+    fn load_read_only(permuter: DynBitPermuter<K, M>, sig: u64, path: &Path) -> Result<Self, Self::Error> {
+        Self::open(permuter, sig, path, true)
+    }
+
+    fn persist(&self) -> Result<(), Self::Error> {
+        if !self.read_only {
+            self.db.flush_wal(true)?;
+        }
+        Ok(())
+    }
+
+    fn reload(&mut self) -> Result<bool, Self::Error> {
+        if !self.read_only {
+            return Ok(false);
+        }
+        let db = open_db(&self.path, true)?;
+        let generation = read_u64(&db, GENERATION_KEY)?.unwrap_or(0);
+        if generation == self.generation {
+            return Ok(false);
+        }
+        self.inner.set_data(read_items::<K, V, M>(&db, &[ITEM_PREFIX])?);
+        self.db = db;
+        self.generation = generation;
+        Ok(true)
+    }
+
+    fn destroy(self) -> Result<(), Self::Error> {
+        RocksIndex::destroy(self)
+    }
+}
+
+fn open_db(path: &Path, read_only: bool) -> Result<DB, rocksdb::Error> {
+    if read_only {
+        DB::open_for_read_only(&Options::default(), path, false)
+    } else {
+        DB::open(&Options::default(), path)
+    }
+}
+
+fn read_u64(db: &DB, key: &[u8]) -> Result<Option<u64>, RocksIndexError> {
+    let Some(bytes) = db.get(key)? else {
+        return Ok(None);
+    };
+    let bytes = bytes.try_into().map_err(|_| RocksIndexError::InvalidEntry)?;
+    Ok(Some(u64::from_le_bytes(bytes)))
+}
+
+/// Entries of `db` whose keys start with `prefix`.
+fn scan_prefix<'a>(db: &'a DB, prefix: &'a [u8]) -> impl Iterator<Item = Result<Entry, rocksdb::Error>> + 'a {
+    db.iterator(IteratorMode::From(prefix, Direction::Forward))
+        .take_while(move |entry| entry.as_ref().map_or(true, |(key, _)| key.starts_with(prefix)))
+}
+
+/// Read all copies of the items whose database keys start with `prefix`, sorted by key.
+fn read_items<K, V, M>(db: &DB, prefix: &[u8]) -> Result<Vec<(K, V)>, RocksIndexError>
+where
+    K: Copy + Ord + ScanBound + Pod,
+    V: Copy + ScanBound + Pod,
+    M: Pod,
+{
+    let mut items = Vec::new();
+    for entry in scan_prefix(db, prefix) {
+        let (item_key, count) = entry?;
+        if item_key.len() != 1 + size_of::<M>() + size_of::<K>() + size_of::<V>() {
+            return Err(RocksIndexError::InvalidEntry);
+        }
+        let count = u64::from_le_bytes(count.as_ref().try_into().map_err(|_| RocksIndexError::InvalidEntry)?);
+        let (key, value) = item_key[1 + size_of::<M>()..].split_at(size_of::<K>());
+        items.extend(iter::repeat_n(
+            (read_pod::<K>(key), read_pod::<V>(value)),
+            count as usize,
+        ));
+    }
+    // entries are ordered by the bytes of masks and keys, which may differ from the order of keys
+    sort_unstable_by_key(&mut items, extract_key);
+    Ok(items)
+}
+
+/// Database key of an item with the permuted key `key`.
+fn item_key<K: Pod, V: Pod, M: Pod>(permuter: &dyn BitPermuter<K, M>, key: &K, value: &V) -> Vec<u8> {
+    let mut item_key = vec![ITEM_PREFIX];
+    item_key.extend_from_slice(pod_bytes(&permuter.mask(key)));
+    item_key.extend_from_slice(pod_bytes(key));
+    item_key.extend_from_slice(pod_bytes(value));
+    item_key
+}
+
+fn pod_bytes<T: Pod>(value: &T) -> &[u8] {
+    // Safety: values are stored as raw bytes, the same way as in memory-mapped vectors
+    unsafe { slice::from_raw_parts(ptr::from_ref(value).cast::<u8>(), size_of::<T>()) }
+}
+
+/// ## Panics
+/// Panics if `bytes` is not of the size of `T`.
+fn read_pod<T: Pod>(bytes: &[u8]) -> T {
+    assert_eq!(bytes.len(), size_of::<T>(), "slice with incorrect length");
+    // Safety: any bit pattern is a valid `T`, and the bytes are read unaligned
+    unsafe { ptr::read_unaligned(bytes.as_ptr().cast::<T>()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn test_rocks_index_is_persisted() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("index");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        let mut index = RocksIndex::create(Permutations::get_variant(1), 0, &path).unwrap();
+        index.insert(&data).unwrap();
+        index.insert(&data[..1]).unwrap();
+        index.remove(&[data[1].0]).unwrap();
+        let mut reader = RocksIndex::<Bits, i32, Mask>::load_read_only(Permutations::get_variant(1), 0, &path).unwrap();
+        assert_eq!(
+            reader.data().to_vec(),
+            index.data().to_vec(),
+            "reader should see the same data"
+        );
+        assert!(matches!(reader.insert(&data), Err(RocksIndexError::ReadOnly)));
+
+        index.retain(|_, value| *value != 3).unwrap();
+        index.persist().unwrap();
+        assert!(reader.reload().unwrap(), "writer has removed items");
+        assert!(!reader.reload().unwrap(), "nothing has changed since");
+        let item = (index.permuter().apply(&data[0].0), 0);
+        assert_eq!(reader.data().to_vec(), [item, item], "duplicates are kept");
+        let mask = index.permuter().mask(&item.0);
+        assert_eq!(
+            index.read_block(&mask).unwrap(),
+            [item, item],
+            "block read from the database"
+        );
+        drop(reader);
+        drop(index);
+
+        assert!(matches!(
+            RocksIndex::<Bits, i32, Mask>::load(Permutations::get_variant(1), 1, &path),
+            Err(RocksIndexError::SignatureMismatch { expected: 1, actual: 0 })
+        ));
+        let index = RocksIndex::<Bits, i32, Mask>::load(Permutations::get_variant(1), 0, &path).unwrap();
+        assert_eq!(index.data().to_vec(), [item, item], "data after load");
+        assert!(index.storage_stats().bytes_on_disk > 0, "database is stored");
+        index.destroy().unwrap();
+        assert!(!path.join("CURRENT").exists(), "database is removed");
+    }
+}
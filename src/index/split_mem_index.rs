@@ -0,0 +1,162 @@
+use std::{collections::BTreeSet, marker::PhantomData};
+
+use hloo_core::{BitContainer, BitPermuter};
+
+use crate::{metrics, util::sort_unstable_by_key, DynBitPermuter};
+
+use super::{extract_key, Block, BlockLocator, Index, IndexStats, ScanBound};
+
+/// In-memory index with structure-of-arrays layout: permuted keys and values are stored in separate arrays.
+///
+/// Only keys are touched while scanning candidates, and values are fetched by position for matching keys only. This
+/// improves scan throughput when values are large compared to keys.
+pub struct SplitMemIndex<K, V, M> {
+    permuter: DynBitPermuter<K, M>,
+    block_locator: BlockLocator,
+    current_stats: IndexStats,
+    keys: Vec<K>,
+    values: Vec<V>,
+    _dummy: PhantomData<M>,
+}
+
+impl<K, V, M> SplitMemIndex<K, V, M>
+where
+    K: Copy,
+    M: Copy + Ord,
+{
+    pub fn new(permuter: DynBitPermuter<K, M>) -> Self {
+        Self {
+            permuter,
+            block_locator: BlockLocator::BinarySearch,
+            current_stats: IndexStats::default(),
+            keys: Vec::new(),
+            values: Vec::new(),
+            _dummy: PhantomData,
+        }
+    }
+}
+
+impl<K, V, M> SplitMemIndex<K, V, M>
+where
+    K: Copy + Ord + ScanBound,
+    V: Copy + ScanBound,
+{
+    fn rebuild(&mut self, mut data: Vec<(K, V)>) {
+        sort_unstable_by_key(&mut data, extract_key);
+        (self.keys, self.values) = data.into_iter().unzip();
+    }
+}
+
+impl<K, V, M> Index<K, V, M> for SplitMemIndex<K, V, M>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord,
+{
+    type Error = ();
+
+    fn data(&self) -> Block<'_, K, V> {
+        Block::Split(&self.keys, &self.values)
+    }
+
+    fn permuter(&self) -> &dyn BitPermuter<K, M> {
+        self.permuter.as_ref()
+    }
+
+    fn block_locator(&self) -> BlockLocator {
+        self.block_locator
+    }
+
+    fn stats(&self) -> &IndexStats {
+        &self.current_stats
+    }
+
+    fn refresh(&mut self) {
+        self.current_stats = self.compute_stats();
+    }
+
+    fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        metrics::insert(items.len());
+        let mut data = self.data().to_vec();
+        let start = data.len();
+        data.extend_from_slice(items);
+        for (k, _) in &mut data[start..] {
+            self.permuter.apply_inplace(k);
+        }
+        self.rebuild(data);
+        Ok(())
+    }
+
+    fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
+        let set: BTreeSet<_> = self.permuter.apply_many(keys).into_iter().collect();
+        let data = self
+            .data()
+            .iter()
+            .filter(|(k, _)| !set.contains(k))
+            .map(|(k, v)| (*k, *v))
+            .collect();
+        self.rebuild(data);
+        Ok(())
+    }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        let permuter = &self.permuter;
+        (self.keys, self.values) = self
+            .data()
+            .iter()
+            .filter(|(k, v)| pred(&permuter.revert(k), v))
+            .map(|(k, v)| (*k, *v))
+            .unzip();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use crate::index::MemIndex;
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn test_split_mem_index_matches_mem_index() {
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+            (Bits::new([0b10011110100010_001000100010001100u32]), 4),
+        ];
+        for (i, perm) in Permutations::get_all_variants().into_iter().enumerate() {
+            let mut reference = MemIndex::new(Permutations::get_variant(i));
+            let mut index = SplitMemIndex::new(perm);
+            reference.insert(&data).unwrap();
+            index.insert(&data).unwrap();
+            assert_eq!(
+                index.data().to_vec(),
+                reference.data().to_vec(),
+                "[{i}] data after insert"
+            );
+
+            let candidates = index.get_candidates(&data[1].0);
+            let expected = reference.get_candidates(&data[1].0);
+            assert_eq!(
+                candidates.block().to_vec(),
+                expected.block().to_vec(),
+                "[{i}] candidates"
+            );
+            assert_eq!(candidates.scan(3), expected.scan(3), "[{i}] scan");
+
+            reference.remove(&[data[0].0]).unwrap();
+            index.remove(&[data[0].0]).unwrap();
+            assert_eq!(
+                index.data().to_vec(),
+                reference.data().to_vec(),
+                "[{i}] data after remove"
+            );
+        }
+    }
+}
@@ -1,5 +1,7 @@
+use std::time::SystemTime;
+
 /// Statistics of the index.
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct IndexStats {
     pub n_items: usize,
     pub n_blocks: usize,
@@ -13,13 +15,23 @@ impl IndexStats {
     where
         M: Ord,
     {
-        let mut it = data.iter().map(mask_fn);
+        Self::from_masks(data.iter().map(mask_fn))
+    }
+
+    /// Compute stats from a sequence of (sorted) masks.
+    pub fn from_masks<M>(masks: impl IntoIterator<Item = M>) -> Self
+    where
+        M: Ord,
+    {
+        let mut it = masks.into_iter();
         if let Some(mut prev_key) = it.next() {
+            let mut n_items = 1usize;
             let mut curr_size = 1usize;
             let mut n_blocks = 1usize;
             let mut min = usize::MAX;
             let mut max = 0;
             for key in it {
+                n_items += 1;
                 if prev_key == key {
                     curr_size += 1;
                 } else {
@@ -32,9 +44,9 @@ impl IndexStats {
             }
             IndexStats {
                 n_blocks,
-                n_items: data.len(),
+                n_items,
                 min_block_size: min.min(curr_size),
-                avg_block_size: data.len() / n_blocks,
+                avg_block_size: n_items / n_blocks,
                 max_block_size: max.max(curr_size),
             }
         } else {
@@ -43,6 +55,15 @@ impl IndexStats {
     }
 }
 
+/// Storage used by an index, see [`Index::storage_stats`](super::Index::storage_stats).
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Size of the files of the index in bytes, 0 for in-memory indexes.
+    pub bytes_on_disk: u64,
+    /// When the index was last written to disk, if it is known and the index was ever written.
+    pub last_flush: Option<SystemTime>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
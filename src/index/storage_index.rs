@@ -0,0 +1,386 @@
+use std::{collections::BTreeSet, convert::Infallible};
+
+use hloo_core::{BitContainer, BitPermuter};
+
+use crate::{DynBitPermuter, metrics, util::sort_unstable_by_key};
+
+#[cfg(feature = "fs")]
+use crate::mmvec::{MmVec, MmVecError, Pod};
+
+use super::{Block, BlockLocator, Index, IndexStats, ScanBound, extract_key};
+
+/// Storage of the items of a [`StorageIndex`], sorted by (permuted) key.
+///
+/// The trait is object-safe, so custom backends can be plugged into indexes (and through them into
+/// [`SimpleLookup`](crate::SimpleLookup)) without writing an index from scratch. It is implemented for [`Vec`] and,
+/// with the `fs` feature, for [`MmVec`].
+pub trait VecStorage<K, V> {
+    type Error;
+
+    /// Get all items, sorted by key.
+    fn as_slice(&self) -> &[(K, V)];
+
+    /// Merge `items`, which are sorted by key, into the storage, keeping it sorted by key.
+    fn insert_sorted(&mut self, items: &[(K, V)]) -> Result<(), Self::Error>;
+
+    /// Prepare merging `items`, which are sorted by key, without changing the contents: acquire everything
+    /// [`VecStorage::commit_insert_sorted`] needs, so that it can't fail for lack of resources. See
+    /// [`Index::stage_insert`].
+    ///
+    /// By default nothing is staged and committing inserts the items.
+    fn stage_insert_sorted(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        let _ = items;
+        Ok(())
+    }
+
+    /// Merge `items` staged by [`VecStorage::stage_insert_sorted`] into the storage.
+    fn commit_insert_sorted(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.insert_sorted(items)
+    }
+
+    /// Abandon the merge staged by [`VecStorage::stage_insert_sorted`], releasing what it acquired.
+    fn discard_staged_insert(&mut self) {}
+
+    /// Remove all items for which `pred` returns `true`, keeping the order of the remaining ones.
+    fn remove_matching(&mut self, pred: &dyn Fn(&(K, V)) -> bool) -> Result<(), Self::Error>;
+
+    /// Make all modifications durable. Storages which are not persistent do nothing.
+    fn persist(&self) -> Result<(), Self::Error>;
+}
+
+impl<K, V> VecStorage<K, V> for Vec<(K, V)>
+where
+    K: Copy + Ord + ScanBound,
+    V: Copy + ScanBound,
+{
+    type Error = Infallible;
+
+    fn as_slice(&self) -> &[(K, V)] {
+        self
+    }
+
+    fn insert_sorted(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.extend_from_slice(items);
+        sort_unstable_by_key(self, extract_key);
+        Ok(())
+    }
+
+    fn remove_matching(&mut self, pred: &dyn Fn(&(K, V)) -> bool) -> Result<(), Self::Error> {
+        self.retain(|item| !pred(item));
+        Ok(())
+    }
+
+    fn persist(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<K, V> VecStorage<K, V> for MmVec<(K, V)>
+where
+    K: Pod + Ord + ScanBound,
+    V: Pod + ScanBound,
+{
+    type Error = MmVecError;
+
+    fn as_slice(&self) -> &[(K, V)] {
+        MmVec::as_slice(self)
+    }
+
+    fn insert_sorted(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        MmVec::insert_sorted(self, items, extract_key)
+    }
+
+    fn stage_insert_sorted(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        MmVec::stage_insert_sorted(self, items, extract_key)
+    }
+
+    fn commit_insert_sorted(&mut self, _items: &[(K, V)]) -> Result<(), Self::Error> {
+        self.commit_staged_insert()
+    }
+
+    fn discard_staged_insert(&mut self) {
+        MmVec::discard_staged_insert(self);
+    }
+
+    fn remove_matching(&mut self, pred: &dyn Fn(&(K, V)) -> bool) -> Result<(), Self::Error> {
+        self.retain(|_, item| !pred(item))
+    }
+
+    fn persist(&self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+}
+
+/// Boxed storage of a [`StorageIndex`].
+pub type DynVecStorage<K, V, E> = Box<dyn VecStorage<K, V, Error = E>>;
+
+/// Index over any [`VecStorage`], e.g. one implemented outside of this crate.
+///
+/// Removal is immediate: removed items are taken out of the storage right away.
+pub struct StorageIndex<K, V, M, E> {
+    permuter: DynBitPermuter<K, M>,
+    block_locator: BlockLocator,
+    current_stats: IndexStats,
+    storage: DynVecStorage<K, V, E>,
+    /// Permuted and sorted items of the insert staged by [`Index::stage_insert`].
+    staged: Vec<(K, V)>,
+}
+
+impl<K, V, M, E> StorageIndex<K, V, M, E> {
+    /// Create an index over `storage`, which must be empty or hold items permuted with `permuter` and sorted by key.
+    pub fn new(permuter: DynBitPermuter<K, M>, storage: DynVecStorage<K, V, E>) -> Self {
+        Self {
+            permuter,
+            block_locator: BlockLocator::BinarySearch,
+            current_stats: IndexStats::default(),
+            storage,
+            staged: Vec::new(),
+        }
+    }
+
+    pub fn storage(&self) -> &dyn VecStorage<K, V, Error = E> {
+        self.storage.as_ref()
+    }
+
+    /// Close the index and take its storage back.
+    pub fn into_storage(self) -> DynVecStorage<K, V, E> {
+        self.storage
+    }
+
+    /// Make all modifications durable. See [`VecStorage::persist`].
+    pub fn persist(&self) -> Result<(), E> {
+        self.storage.persist()
+    }
+}
+
+impl<K, V, M, E> Index<K, V, M> for StorageIndex<K, V, M, E>
+where
+    K: Copy + BitContainer + Ord + ScanBound,
+    V: Copy + ScanBound,
+    M: Copy + Ord,
+{
+    type Error = E;
+
+    fn permuter(&self) -> &dyn BitPermuter<K, M> {
+        self.permuter.as_ref()
+    }
+
+    fn block_locator(&self) -> BlockLocator {
+        self.block_locator
+    }
+
+    fn data(&self) -> Block<'_, K, V> {
+        Block::Interleaved(self.storage.as_slice())
+    }
+
+    fn stats(&self) -> &IndexStats {
+        &self.current_stats
+    }
+
+    fn refresh(&mut self) {
+        self.current_stats = self.compute_stats();
+    }
+
+    fn insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        metrics::insert(items.len());
+        let mut permuted = items.to_vec();
+        for (k, _) in &mut permuted {
+            self.permuter.apply_inplace(k);
+        }
+        sort_unstable_by_key(&mut permuted, extract_key);
+        self.storage.insert_sorted(&permuted)
+    }
+
+    fn stage_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        let mut permuted = items.to_vec();
+        for (k, _) in &mut permuted {
+            self.permuter.apply_inplace(k);
+        }
+        sort_unstable_by_key(&mut permuted, extract_key);
+        self.staged.clear();
+        self.storage.stage_insert_sorted(&permuted)?;
+        self.staged = permuted;
+        Ok(())
+    }
+
+    fn commit_insert(&mut self, items: &[(K, V)]) -> Result<(), Self::Error> {
+        metrics::insert(items.len());
+        let staged = std::mem::take(&mut self.staged);
+        self.storage.commit_insert_sorted(&staged)
+    }
+
+    fn discard_insert(&mut self) {
+        self.staged.clear();
+        self.storage.discard_staged_insert();
+    }
+
+    fn remove(&mut self, keys: &[K]) -> Result<(), Self::Error> {
+        let set: BTreeSet<_> = self.permuter.apply_many(keys).into_iter().collect();
+        self.storage.remove_matching(&|(k, _)| set.contains(k))
+    }
+
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), Self::Error> {
+        let permuter = &self.permuter;
+        self.storage.remove_matching(&|(k, v)| !pred(&permuter.revert(k), v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use crate::{Lookup, SimpleLookup};
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    /// Storage which counts writes, standing in for a third-party backend.
+    #[derive(Default)]
+    struct CountingStorage {
+        items: Vec<(Bits, i32)>,
+        writes: usize,
+    }
+
+    impl VecStorage<Bits, i32> for CountingStorage {
+        type Error = String;
+
+        fn as_slice(&self) -> &[(Bits, i32)] {
+            &self.items
+        }
+
+        fn insert_sorted(&mut self, items: &[(Bits, i32)]) -> Result<(), Self::Error> {
+            self.writes += 1;
+            self.items.extend_from_slice(items);
+            self.items.sort_by_key(|(k, _)| *k);
+            Ok(())
+        }
+
+        fn remove_matching(&mut self, pred: &dyn Fn(&(Bits, i32)) -> bool) -> Result<(), Self::Error> {
+            self.writes += 1;
+            self.items.retain(|item| !pred(item));
+            Ok(())
+        }
+
+        fn persist(&self) -> Result<(), Self::Error> {
+            Err(format!("{} writes are lost", self.writes))
+        }
+    }
+
+    /// Storage which fails to insert items beyond its capacity, like a full disk.
+    struct BoundedStorage {
+        items: Vec<(Bits, i32)>,
+        capacity: usize,
+    }
+
+    impl VecStorage<Bits, i32> for BoundedStorage {
+        type Error = String;
+
+        fn as_slice(&self) -> &[(Bits, i32)] {
+            &self.items
+        }
+
+        fn insert_sorted(&mut self, items: &[(Bits, i32)]) -> Result<(), Self::Error> {
+            self.stage_insert_sorted(items)?;
+            self.items.extend_from_slice(items);
+            self.items.sort_by_key(|(k, _)| *k);
+            Ok(())
+        }
+
+        fn stage_insert_sorted(&mut self, items: &[(Bits, i32)]) -> Result<(), Self::Error> {
+            if self.items.len() + items.len() > self.capacity {
+                return Err("storage is full".to_string());
+            }
+            Ok(())
+        }
+
+        fn remove_matching(&mut self, pred: &dyn Fn(&(Bits, i32)) -> bool) -> Result<(), Self::Error> {
+            self.items.retain(|item| !pred(item));
+            Ok(())
+        }
+
+        fn persist(&self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_inserts_leave_lookups_unchanged() {
+        let variants = Permutations::get_all_variants();
+        let n_indexes = variants.len();
+        let indexes = variants
+            .into_iter()
+            .enumerate()
+            .map(|(i, permuter)| {
+                // only the last index runs out of space
+                let capacity = if i + 1 == n_indexes { 2 } else { 10 };
+                let storage = BoundedStorage {
+                    items: Vec::new(),
+                    capacity,
+                };
+                StorageIndex::new(permuter, Box::new(storage))
+            })
+            .collect();
+        let mut lookup: SimpleLookup<Bits, i32, Mask, StorageIndex<_, _, _, String>> = SimpleLookup::new(indexes);
+        let existing = [(Bits::new([1]), 1), (Bits::new([2]), 2)];
+        lookup.insert(&existing).unwrap();
+        let contents: Vec<_> = lookup
+            .indexes()
+            .iter()
+            .map(|index| index.storage().as_slice().to_vec())
+            .collect();
+
+        // a copy of an existing pair is inserted too, and it must not end up in any index
+        let batch = [(Bits::new([1]), 1), (Bits::new([3]), 3)];
+        assert_eq!(lookup.insert(&batch), Err("storage is full".to_string()));
+        for (index, contents) in lookup.indexes().iter().zip(&contents) {
+            assert_eq!(index.storage().as_slice(), contents);
+        }
+        assert_eq!(lookup.search_simple(&Bits::new([1]), 0).len(), 1);
+    }
+
+    #[test]
+    fn custom_storages_can_be_plugged_into_lookups() {
+        let indexes = Permutations::get_all_variants()
+            .into_iter()
+            .map(|permuter| StorageIndex::new(permuter, Box::new(CountingStorage::default())))
+            .collect();
+        let mut lookup: SimpleLookup<Bits, i32, Mask, StorageIndex<_, _, _, String>> = SimpleLookup::new(indexes);
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+        ];
+        lookup.insert(&data).unwrap();
+        assert_eq!(lookup.search_simple(&data[0].0, 3).len(), 2);
+        lookup.remove(&[data[1].0]).unwrap();
+        let result = lookup.search_simple(&data[0].0, 3);
+        assert_eq!(result.into_iter().map(|item| *item.data()).collect::<Vec<_>>(), [0]);
+        for index in lookup.indexes() {
+            assert_eq!(index.storage().as_slice().len(), 2);
+            assert_eq!(index.persist(), Err("2 writes are lost".to_string()));
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn memmap_storage_index_retains_items() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("storage.bin");
+        let storage = MmVec::new_empty(0, path.clone()).unwrap();
+        let mut index = StorageIndex::new(Permutations::get_variant(1), Box::new(storage));
+        let data = [(Bits::new([1]), 1), (Bits::new([2]), 2), (Bits::new([3]), 3)];
+        index.insert(&data).unwrap();
+        index.retain(|key, _| *key != data[1].0).unwrap();
+        index.persist().unwrap();
+        drop(index);
+
+        let storage = MmVec::<(Bits, i32)>::from_path(0, path).unwrap();
+        let index = StorageIndex::new(Permutations::get_variant(1), Box::new(storage));
+        assert!(index.contains_key(&data[2].0));
+        assert!(!index.contains_key(&data[1].0));
+        assert_eq!(index.iter().count(), 2);
+    }
+}
@@ -0,0 +1,186 @@
+use std::path::Path;
+
+use hloo_core::{BitContainer, Pod};
+
+use crate::DynBitPermuter;
+
+use super::{Candidates, Index, MemIndex, MemMapIndex, MemMapIndexError, PersistentIndex, ScanBound, SearchResultItem};
+
+/// Two-tier index: a small in-memory delta (hot tier) layered over a large memory-mapped index (cold tier).
+///
+/// Inserts go to the hot tier only, so they are as cheap as for [`MemIndex`]. Searches look at both tiers. Items are
+/// moved into the cold tier (and become durable) on [`TieredIndex::merge`].
+///
+/// Both tiers must use the same permutation.
+pub struct TieredIndex<K, V, M>
+where
+    (K, V): Pod,
+{
+    hot: MemIndex<K, V, M>,
+    cold: MemMapIndex<K, V, M>,
+}
+
+impl<K, V, M> TieredIndex<K, V, M>
+where
+    K: Pod + BitContainer + Ord + ScanBound,
+    V: Pod + ScanBound,
+    M: Copy + Ord,
+{
+    pub fn new(hot: MemIndex<K, V, M>, cold: MemMapIndex<K, V, M>) -> Self {
+        Self { hot, cold }
+    }
+
+    /// Create an index with an empty hot tier over a new cold tier at `path`.
+    pub fn create(
+        hot_permuter: DynBitPermuter<K, M>,
+        cold_permuter: DynBitPermuter<K, M>,
+        sig: u64,
+        path: &Path,
+    ) -> Result<Self, MemMapIndexError> {
+        let cold = MemMapIndex::create(cold_permuter, sig, path)?;
+        Ok(Self::new(MemIndex::new(hot_permuter), cold))
+    }
+
+    /// Create an index with an empty hot tier over an existing cold tier at `path`.
+    pub fn load(
+        hot_permuter: DynBitPermuter<K, M>,
+        cold_permuter: DynBitPermuter<K, M>,
+        sig: u64,
+        path: &Path,
+    ) -> Result<Self, MemMapIndexError> {
+        let cold = MemMapIndex::load(cold_permuter, sig, path)?;
+        Ok(Self::new(MemIndex::new(hot_permuter), cold))
+    }
+
+    pub fn hot(&self) -> &MemIndex<K, V, M> {
+        &self.hot
+    }
+
+    pub fn cold(&self) -> &MemMapIndex<K, V, M> {
+        &self.cold
+    }
+
+    /// Total number of items in both tiers (including removed items not yet compacted in the cold tier).
+    pub fn len(&self) -> usize {
+        self.hot.data().len() + self.cold.data().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Insert items into the hot tier.
+    pub fn insert(&mut self, items: &[(K, V)]) {
+        // MemIndex is infallible
+        let _ = self.hot.insert(items);
+    }
+
+    /// Remove items from both tiers.
+    pub fn remove(&mut self, keys: &[K]) -> Result<(), MemMapIndexError> {
+        let _ = self.hot.remove(keys);
+        self.cold.remove(keys)
+    }
+
+    /// Retain only the items for which `pred` returns `true`, in both tiers.
+    pub fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> Result<(), MemMapIndexError> {
+        let _ = self.hot.retain(&pred);
+        self.cold.retain(pred)
+    }
+
+    /// Check whether either tier contains the given key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.hot.contains_key(key) || self.cold.contains_key(key)
+    }
+
+    /// Retrieve candidates for a given search from both tiers: hot tier first.
+    pub fn get_candidates<'a>(&'a self, key: &K) -> [Candidates<'a, K, V>; 2] {
+        [self.hot.get_candidates(key), self.cold.get_candidates(key)]
+    }
+
+    /// Scan candidates from both tiers.
+    pub fn search(&self, key: &K, distance: u32) -> Vec<SearchResultItem<V>> {
+        self.get_candidates(key)
+            .iter()
+            .flat_map(|candidates| candidates.scan(distance))
+            .collect()
+    }
+
+    /// Move all items from the hot tier into the cold tier, compacting it, and persist the cold tier.
+    pub fn merge(&mut self) -> Result<(), MemMapIndexError> {
+        if !self.hot.data().is_empty() {
+            let permuter = self.hot.permuter();
+            let items: Vec<_> = self.hot.data().iter().map(|(k, v)| (permuter.revert(k), *v)).collect();
+            self.cold.insert(&items)?;
+            let _ = self.hot.retain(|_, _| false);
+            self.hot.refresh();
+        }
+        self.cold.compact()?;
+        self.cold.refresh();
+        self.cold.persist()
+    }
+
+    /// Recompute stats of both tiers.
+    pub fn refresh(&mut self) {
+        self.hot.refresh();
+        self.cold.refresh();
+    }
+
+    /// Destroy the cold tier storage. Items in the hot tier are dropped.
+    pub fn destroy(self) -> Result<(), MemMapIndexError> {
+        self.cold.destroy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hloo_core::{BitContainer, BitPermuter, Pod};
+    use hloo_macros::make_permutations;
+
+    use super::*;
+
+    make_permutations!(struct_name = "Permutations", f = 32, r = 5, k = 1, w = 32);
+
+    #[test]
+    fn test_tiered_index_searches_both_tiers() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tempdir.path().join("storage.bin");
+        let data = [
+            (Bits::new([0b11111000100010_001000100010001000u32]), 0),
+            (Bits::new([0b11111000100010_001000100011111000u32]), 2),
+            (Bits::new([0b11001000111110_001000100010001010u32]), 3),
+            (Bits::new([0b10011110100010_001000100010001100u32]), 4),
+        ];
+        let mut index =
+            TieredIndex::create(Permutations::get_variant(0), Permutations::get_variant(0), 0, &path).unwrap();
+        index.insert(&data[..2]);
+        index.merge().unwrap();
+        index.insert(&data[2..]);
+        assert_eq!(index.hot().data().len(), 2, "hot tier");
+        assert_eq!(index.cold().data().len(), 2, "cold tier");
+        for (key, value) in &data {
+            let result = index.search(key, 0);
+            assert_eq!(result, vec![SearchResultItem::new(*value, 0)], "search for {value}");
+        }
+
+        index.remove(&[data[0].0, data[3].0]).unwrap();
+        assert!(!index.contains_key(&data[0].0), "removed from cold tier");
+        assert!(!index.contains_key(&data[3].0), "removed from hot tier");
+        assert!(index.search(&data[0].0, 0).is_empty(), "search for removed item");
+
+        index.merge().unwrap();
+        assert!(index.hot().data().is_empty(), "hot tier after merge");
+        let mut expected: Vec<_> = [data[1], data[2]]
+            .iter()
+            .map(|(k, v)| (index.cold().permuter().apply(k), *v))
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(index.cold().data().to_vec(), expected, "cold tier after merge");
+        drop(index);
+
+        let index =
+            TieredIndex::<Bits, i32, Mask>::load(Permutations::get_variant(0), Permutations::get_variant(0), 0, &path)
+                .unwrap();
+        assert_eq!(index.len(), 2, "merged items should be durable");
+        index.destroy().unwrap();
+    }
+}
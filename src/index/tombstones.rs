@@ -0,0 +1,189 @@
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "fs")]
+use crate::mmvec::{FlushPolicy, MmVec, MmVecError};
+
+/// Read-only view of a tombstone bitmap, shifted by `offset` positions.
+#[derive(Clone, Copy)]
+pub(crate) struct TombstoneView<'a> {
+    bits: &'a [u64],
+    offset: usize,
+}
+
+impl<'a> TombstoneView<'a> {
+    #[cfg(feature = "fs")]
+    pub fn new(bits: &'a [u64], offset: usize) -> Self {
+        Self { bits, offset }
+    }
+
+    /// Whether the item at position `i` (relative to `offset`) is deleted.
+    pub fn is_set(&self, i: usize) -> bool {
+        let pos = self.offset + i;
+        self.bits
+            .get(pos / 64)
+            .is_some_and(|word| word & (1 << (pos % 64)) != 0)
+    }
+}
+
+/// Memory-mapped bitmap of deleted positions.
+///
+/// Positions are only meaningful for the generation of the index data they were set for (see
+/// [`MmVec::generation`]), which is stored in the metadata of the bitmap. Bits set for another generation, e.g. when
+/// the data was compacted but clearing the bitmap never reached the disk, are ignored.
+#[cfg(feature = "fs")]
+pub(crate) struct Tombstones {
+    bits: MmVec<u64>,
+    count: usize,
+    /// Whether the bits were set for another generation of the data.
+    stale: bool,
+}
+
+#[cfg(feature = "fs")]
+impl Tombstones {
+    /// Path of the tombstone file belonging to the index at `index_path`.
+    pub fn path_for(index_path: &Path) -> PathBuf {
+        let mut path = index_path.as_os_str().to_owned();
+        path.push(".tombstones");
+        path.into()
+    }
+
+    pub fn create(sig: u64, index_path: &Path) -> Result<Self, MmVecError> {
+        let bits = MmVec::new_empty(sig, Self::path_for(index_path))?;
+        Ok(Self {
+            bits,
+            count: 0,
+            stale: false,
+        })
+    }
+
+    /// Load the tombstones of the index at `index_path`, whose data has the given `generation`, or create an empty
+    /// bitmap if there are none. Tombstones of another generation are cleared.
+    pub fn load_or_create(sig: u64, index_path: &Path, generation: u64) -> Result<Self, MmVecError> {
+        let path = Self::path_for(index_path);
+        if !path.exists() {
+            return Self::create(sig, index_path);
+        }
+        let bits = MmVec::<u64>::from_path(sig, path)?;
+        let mut tombstones = Self::with_bits(bits, generation);
+        if tombstones.stale {
+            tombstones.clear()?;
+            tombstones.flush()?;
+        }
+        Ok(tombstones)
+    }
+
+    /// Open the tombstones of the index at `index_path`, whose data has the given `generation`, for reading only.
+    pub fn open_read_only(sig: u64, index_path: &Path, generation: u64) -> Result<Self, MmVecError> {
+        let bits = MmVec::<u64>::open_read_only(sig, Self::path_for(index_path))?;
+        Ok(Self::with_bits(bits, generation))
+    }
+
+    fn with_bits(bits: MmVec<u64>, generation: u64) -> Self {
+        let mut tombstones = Self {
+            bits,
+            count: 0,
+            stale: false,
+        };
+        tombstones.recount(generation);
+        tombstones
+    }
+
+    /// Generation of the data the bits were set for. Bitmaps written by earlier versions don't have it, and are
+    /// assumed to belong to the current data.
+    fn generation(&self) -> Option<u64> {
+        let metadata = self.bits.metadata();
+        Some(u64::from_le_bytes(metadata.as_slice().try_into().ok()?))
+    }
+
+    fn recount(&mut self, generation: u64) {
+        self.stale = self.generation().is_some_and(|bound| bound != generation);
+        self.count = match self.stale {
+            true => 0,
+            false => self.bits.as_slice().iter().map(|w| w.count_ones() as usize).sum(),
+        };
+    }
+
+    /// Pick up changes made by a writer, if opened read-only. `generation` is the current generation of the data.
+    /// Returns whether anything has changed.
+    pub fn reload(&mut self, generation: u64) -> Result<bool, MmVecError> {
+        if !self.bits.is_read_only() {
+            return Ok(false);
+        }
+        let count = self.count;
+        let remapped = self.bits.refresh()?;
+        // bits may be set in place without remapping
+        self.recount(generation);
+        Ok(remapped || count != self.count)
+    }
+
+    /// Write a copy of the tombstones to the location belonging to the index at `index_path`. See
+    /// [`MmVec::backup_to`].
+    pub fn backup_to(&self, index_path: &Path) -> Result<(), MmVecError> {
+        self.bits.backup_to(&Self::path_for(index_path))
+    }
+
+    /// Number of deleted positions.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn view(&self, offset: usize) -> TombstoneView<'_> {
+        let bits = if self.stale { &[] } else { self.bits.as_slice() };
+        TombstoneView::new(bits, offset)
+    }
+
+    /// Mark position `i` of the data with the given `generation` as deleted.
+    pub fn set(&mut self, i: usize, generation: u64) -> Result<(), MmVecError> {
+        if self.bits.is_read_only() {
+            return Err(MmVecError::ReadOnly {});
+        }
+        match self.generation() {
+            Some(bound) if bound == generation => {}
+            bound => {
+                // bits set for another generation don't mark positions of this one
+                if bound.is_some() {
+                    self.clear()?;
+                }
+                self.bits.set_metadata(&generation.to_le_bytes())?;
+                self.stale = false;
+            }
+        }
+        let word = i / 64;
+        if word >= self.bits.len() {
+            self.bits.resize_zeroed(word + 1)?;
+        }
+        let slot = &mut self.bits.slice_mut(word..word + 1)[0];
+        if *slot & (1 << (i % 64)) == 0 {
+            *slot |= 1 << (i % 64);
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    /// Remove all tombstones.
+    pub fn clear(&mut self) -> Result<(), MmVecError> {
+        self.bits.resize_zeroed(0)?;
+        self.count = 0;
+        Ok(())
+    }
+
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) -> Result<(), MmVecError> {
+        self.bits.set_flush_policy(policy)
+    }
+
+    pub fn flush(&self) -> Result<(), MmVecError> {
+        self.bits.flush()
+    }
+
+    /// Size of the tombstone file in bytes.
+    pub fn storage_size(&self) -> u64 {
+        self.bits
+            .storage_size()
+            .unwrap_or_else(|_| self.bits.expected_storage_size())
+    }
+
+    pub fn destroy(self) -> Result<(), MmVecError> {
+        self.bits.destroy()
+    }
+}
@@ -0,0 +1,102 @@
+use std::cmp::Ordering;
+
+/// Result of an index integrity check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexValidation {
+    /// Number of items in the index.
+    pub n_items: usize,
+    /// Position of the first item which is not sorted by permuted key, if any.
+    pub first_unsorted: Option<usize>,
+    /// Expected and actual size of the backing storage in bytes, if they do not match.
+    pub storage_size_mismatch: Option<(u64, u64)>,
+    /// Expected and actual signature of the backing storage, if they do not match.
+    pub signature_mismatch: Option<(u64, u64)>,
+}
+
+impl IndexValidation {
+    /// Check that the data is sorted according to the given comparator.
+    pub fn from_data_by<T>(data: impl IntoIterator<Item = T>, cmp: impl Fn(&T, &T) -> Ordering) -> Self {
+        let mut it = data.into_iter();
+        let mut n_items = 0;
+        let mut first_unsorted = None;
+        if let Some(mut prev) = it.next() {
+            n_items += 1;
+            for el in it {
+                if first_unsorted.is_none() && cmp(&prev, &el).is_gt() {
+                    first_unsorted = Some(n_items);
+                }
+                n_items += 1;
+                prev = el;
+            }
+        }
+        Self {
+            n_items,
+            first_unsorted,
+            ..Default::default()
+        }
+    }
+
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.first_unsorted.is_none() && self.storage_size_mismatch.is_none() && self.signature_mismatch.is_none()
+    }
+}
+
+/// Result of a lookup integrity check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LookupValidation {
+    /// Validation results for each of the indexes.
+    pub indexes: Vec<IndexValidation>,
+    /// Whether all indexes contain the same number of items.
+    pub item_counts_agree: bool,
+}
+
+impl LookupValidation {
+    pub fn new(indexes: Vec<IndexValidation>) -> Self {
+        let item_counts_agree = indexes.windows(2).all(|w| w[0].n_items == w[1].n_items);
+        Self {
+            indexes,
+            item_counts_agree,
+        }
+    }
+
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.item_counts_agree && self.indexes.iter().all(IndexValidation::is_ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_detects_unsorted_data() {
+        let data = vec![(1u32, 0), (2u32, 1), (2u32, 2), (1u32, 3), (4u32, 4)];
+        let report = IndexValidation::from_data_by(&data, |(a, _), (b, _)| a.cmp(b));
+        assert_eq!(report.n_items, data.len(), "n items");
+        assert_eq!(report.first_unsorted, Some(3), "first unsorted");
+        assert!(!report.is_ok(), "should not be ok");
+
+        let report = IndexValidation::from_data_by(&data[..3], |(a, _), (b, _)| a.cmp(b));
+        assert_eq!(report.first_unsorted, None, "sorted");
+        assert!(report.is_ok(), "should be ok");
+    }
+
+    #[test]
+    fn test_lookup_validation_detects_count_mismatch() {
+        let ok = IndexValidation {
+            n_items: 2,
+            ..Default::default()
+        };
+        let report = LookupValidation::new(vec![ok.clone(), ok.clone()]);
+        assert!(report.is_ok(), "same counts");
+        let other = IndexValidation {
+            n_items: 3,
+            ..Default::default()
+        };
+        let report = LookupValidation::new(vec![ok, other]);
+        assert!(!report.item_counts_agree, "different counts");
+        assert!(!report.is_ok(), "should not be ok");
+    }
+}
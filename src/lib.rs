@@ -1,6 +1,8 @@
 //! Basic usage:
 //!
 //! ```
+//! use hloo::Lookup;
+//!
 //! // 1) Create a Lookup Util (sort of a factory for lookups)
 //! hloo::init_lookup!(LookupUtil, 32, 5, 1, 32);
 //! // 2) Create lookup with the types you need from permuter
@@ -18,19 +20,49 @@
 //! // in-memory
 //! let mem_lookup = lookup64::MemLookup::<i64>::default();
 //!
-//! // memory-mapped
+//! // memory-mapped, with the `fs` feature (enabled by default)
+//! # #[cfg(feature = "fs")] {
 //! let path: std::path::PathBuf = "/tmp/some-path".try_into().unwrap();
 //! let memmap_lookup = lookup64::MemMapLookup::<i64>::create(&path);
+//! # }
+//! ```
+//!
+//! If the parameters are only known at runtime, use [`DynLookup`](lookup::DynLookup) instead of `init_lookup!`:
+//!
+//! ```
+//! use hloo::{lookup::DynLookup, Lookup};
+//!
+//! let util = DynLookup::new(96, 5, 2, 32).expect("invalid parameters");
+//! let mut lookup = util.create_mem_lookup::<i64>();
+//! let key = util.key_from_be_bytes(&[0; 12]);
+//! lookup.insert(&[(key, 123456)]);
+//! lookup.search(&key, 4);
 //! ```
 
+pub mod export;
 pub mod index;
 pub mod lookup;
+pub mod metrics;
 pub mod util;
 
+#[cfg(feature = "fs")]
+pub mod filevec;
+#[cfg(feature = "fs")]
 pub mod mmvec;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "img_hash")]
+pub mod image_hash;
+#[cfg(feature = "compression")]
+pub mod compressed;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+
 pub use hloo_core;
-pub use hloo_macros::make_permutations;
+pub use hloo_macros::{BitContainer, make_permutations};
 
 pub use index::Index;
 pub use lookup::{Lookup, SimpleLookup};
@@ -38,14 +70,83 @@ pub use lookup::{Lookup, SimpleLookup};
 pub type DynBitPermuter<B, M> = Box<dyn hloo_core::BitPermuter<B, M>>;
 
 /// This macro serves as an initialization step to create lookups with specified configuration.
+///
+/// `init_lookup!(pub mod my_lookup, f, r, k, w)` creates module `my_lookup` holding the key type `Bits`, the mask type
+/// `Mask`, the permutations, aliases of lookup types (e.g. `MemLookup<T>`), and `LookupUtil`, which creates or loads
+/// lookups. Each invocation has its own module, so several of them can be placed side by side:
+///
+/// ```
+/// use hloo::Lookup;
+///
+/// hloo::init_lookup!(pub mod lookup64, 64, 5, 1, 64);
+/// hloo::init_lookup!(pub mod lookup128, 128, 5, 1, 64);
+///
+/// let mut lookup = lookup64::LookupUtil::create_mem_lookup::<i64>();
+/// lookup.insert(&[(lookup64::Bits::default(), 1)]).unwrap();
+/// let other = lookup128::LookupUtil::create_mem_lookup::<i64>();
+/// assert_eq!(other.indexes().len(), 5);
+/// ```
+///
+/// `init_lookup!(Name, f, r, k, w)` places the same items at the call site, with `Name` instead of `LookupUtil`.
+///
+/// `init_lookup!(Name { Key = (f, r, k, w), ... })` declares several configurations at once. Each of them gets its own
+/// key type `Key`, with mask type `KeyMask` and permutations `KeyPermutations`, and `Name` creates or loads lookups
+/// for any of them, taking the key type as the first type parameter. Lookup types are in
+/// [`lookup::factory`](crate::lookup::factory):
+///
+/// ```
+/// use hloo::{lookup::factory::MemLookup, Lookup};
+///
+/// hloo::init_lookup!(Hashes {
+///     Bits64 = (64, 5, 1, 64),
+///     Bits256 = (256, 8, 2, 64),
+/// });
+///
+/// let mut short: MemLookup<Bits64, i64> = Hashes::create_mem_lookup::<Bits64, i64>();
+/// short.insert(&[(Bits64::default(), 1)]).unwrap();
+/// let long = Hashes::create_mem_lookup::<Bits256, i64>();
+/// assert_eq!(long.indexes().len(), 28);
+/// ```
 #[macro_export]
 macro_rules! init_lookup {
-    ($name:ident,$f:literal,$r:literal,$k:literal,$w:literal) => {
-        use hloo::{
-            hloo_core::{BitContainer, BitPermuter},
-            Lookup,
-        };
-        hloo::make_permutations!(struct_name = "Permutations", f = $f, r = $r, k = $k, w = $w);
+    ($vis:vis mod $module:ident, $f:literal, $r:literal, $k:literal, $w:literal) => {
+        #[doc = concat!("Lookups with bit permutation parameters f = ", $f, ", r = ", $r, ", k = ", $k, ", w = ", $w)]
+        $vis mod $module {
+            $crate::init_lookup!(@items LookupUtil, $f, $r, $k, $w);
+        }
+    };
+    ($name:ident, $f:literal, $r:literal, $k:literal, $w:literal) => {
+        #[doc(hidden)]
+        mod __hloo_lookup {
+            $crate::init_lookup!(@items $name, $f, $r, $k, $w);
+        }
+        pub use __hloo_lookup::*;
+    };
+    ($name:ident { $($key:ident = ($f:literal, $r:literal, $k:literal, $w:literal)),+ $(,)? }) => {
+        #[doc(hidden)]
+        mod __hloo_lookups {
+            use $crate::hloo_core::{self, BitContainer, BitPermuter, Pod};
+
+            $(
+                $crate::make_permutations!(key_name = $key, f = $f, r = $r, k = $k, w = $w);
+                $crate::init_lookup!(@from_le_bytes $key);
+            )+
+
+            #[doc = concat!("This struct can create or load lookups with keys ", $(stringify!($key), " ",)+)]
+            #[doc = "or any other [`PermutedKey`](hloo_core::PermutedKey). See [`hloo::lookup::factory`]."]
+            pub struct $name;
+
+            impl $name {
+                $crate::init_lookup!(@generic_factory);
+            }
+        }
+        pub use __hloo_lookups::*;
+    };
+    (@items $name:ident, $f:literal, $r:literal, $k:literal, $w:literal) => {
+        use $crate::hloo_core::{self, BitContainer, BitPermuter, Pod};
+
+        $crate::make_permutations!(struct_name = "Permutations", f = $f, r = $r, k = $k, w = $w);
+        $crate::init_lookup!(@from_le_bytes Bits);
 
         #[doc = "This struct can create or load lookups with the following underlying "]
         #[doc = "bit permutation parameters: f = "]
@@ -58,31 +159,187 @@ macro_rules! init_lookup {
         #[doc = stringify!($w)]
         pub struct $name;
 
-        pub type MemIndex<T> = hloo::index::MemIndex<Bits, T, Mask>;
-        pub type MemLookup<T> = hloo::SimpleLookup<Bits, T, Mask, MemIndex<T>>;
-        pub type MemMapIndex<T> = hloo::index::MemMapIndex<Bits, T, Mask>;
-        pub type MemMapLookup<T> = hloo::SimpleLookup<Bits, T, Mask, MemMapIndex<T>>;
+        pub type MemIndex<T> = $crate::lookup::factory::MemIndex<Bits, T>;
+        pub type MemLookup<T> = $crate::lookup::factory::MemLookup<Bits, T>;
+        pub type SplitMemIndex<T> = $crate::lookup::factory::SplitMemIndex<Bits, T>;
+        pub type SplitMemLookup<T> = $crate::lookup::factory::SplitMemLookup<Bits, T>;
+        $crate::__with_fs! {
+            pub type MemMapIndex<T> = $crate::lookup::factory::MemMapIndex<Bits, T>;
+            pub type MemMapLookup<T> = $crate::lookup::factory::MemMapLookup<Bits, T>;
+            pub type FileIndex<T> = $crate::lookup::factory::FileIndex<Bits, T>;
+            pub type FileLookup<T> = $crate::lookup::factory::FileLookup<Bits, T>;
+        }
 
         impl $name {
             pub fn create_mem_lookup<T>() -> MemLookup<T> {
-                let permutations = Permutations::get_all_variants();
-                let indexes = permutations.into_iter().map(MemIndex::new).collect();
-                MemLookup::new(indexes)
+                $crate::lookup::factory::create_mem_lookup()
+            }
+
+            pub fn create_split_mem_lookup<T>() -> SplitMemLookup<T> {
+                $crate::lookup::factory::create_split_mem_lookup()
+            }
+
+            $crate::__with_fs! {
+                pub fn create_memmap_lookup<T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static>(
+                    path: &std::path::Path,
+                ) -> Result<MemMapLookup<T>, $crate::index::MemMapIndexError> {
+                    $crate::lookup::factory::create_memmap_lookup(path)
+                }
+
+                pub fn load_memmap_lookup<T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static>(
+                    path: &std::path::Path,
+                ) -> Result<MemMapLookup<T>, $crate::index::MemMapIndexError> {
+                    $crate::lookup::factory::load_memmap_lookup(path)
+                }
+
+                pub fn load_memmap_lookup_read_only<T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static>(
+                    path: &std::path::Path,
+                ) -> Result<MemMapLookup<T>, $crate::index::MemMapIndexError> {
+                    $crate::lookup::factory::load_memmap_lookup_read_only(path)
+                }
+
+                /// Create a lookup which is kept in memory and stored in files without memory-mapping them. See
+                /// [`hloo::index::FileIndex`].
+                pub fn create_file_lookup<T: Copy + $crate::index::ScanBound + 'static>(
+                    path: &std::path::Path,
+                ) -> Result<FileLookup<T>, $crate::mmvec::MmVecError> {
+                    $crate::lookup::factory::create_file_lookup(path)
+                }
+
+                pub fn load_file_lookup<T: Copy + $crate::index::ScanBound + 'static>(
+                    path: &std::path::Path,
+                ) -> Result<FileLookup<T>, $crate::mmvec::MmVecError> {
+                    $crate::lookup::factory::load_file_lookup(path)
+                }
+
+                pub fn load_file_lookup_read_only<T: Copy + $crate::index::ScanBound + 'static>(
+                    path: &std::path::Path,
+                ) -> Result<FileLookup<T>, $crate::mmvec::MmVecError> {
+                    $crate::lookup::factory::load_file_lookup_read_only(path)
+                }
+
+                /// Unpack a container created by `MemMapLookup::pack` into a new directory `path`, and load the lookup
+                /// from it.
+                pub fn unpack_memmap_lookup<T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static>(
+                    container: &std::path::Path,
+                    path: &std::path::Path,
+                ) -> Result<MemMapLookup<T>, $crate::lookup::container::ContainerError> {
+                    $crate::lookup::factory::unpack_memmap_lookup(container, path)
+                }
+            }
+        }
+    };
+    (@from_le_bytes $key:ident) => {
+        impl $crate::util::FromLeBytes for $key {
+            const SIZE: usize = $key::SIZE_BYTES;
+
+            fn from_le_slice(bytes: &[u8]) -> Self {
+                $key::from_le_bytes(bytes)
+            }
+        }
+
+        impl $crate::util::KeyBytes for $key {
+            const SIZE: usize = $key::SIZE_BYTES;
+
+            fn to_be_vec(&self) -> Vec<u8> {
+                self.to_be_bytes().to_vec()
+            }
+
+            fn from_be_slice(bytes: &[u8]) -> Self {
+                $key::from_be_bytes(bytes)
+            }
+        }
+    };
+    (@generic_factory) => {
+        pub fn create_mem_lookup<K: $crate::hloo_core::PermutedKey, T>() -> $crate::lookup::factory::MemLookup<K, T> {
+            $crate::lookup::factory::create_mem_lookup()
+        }
+
+        pub fn create_split_mem_lookup<K: $crate::hloo_core::PermutedKey, T>(
+        ) -> $crate::lookup::factory::SplitMemLookup<K, T> {
+            $crate::lookup::factory::create_split_mem_lookup()
+        }
+
+        $crate::__with_fs! {
+            pub fn create_memmap_lookup<
+                K: $crate::hloo_core::PermutedKey,
+                T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static,
+            >(
+                path: &std::path::Path,
+            ) -> Result<$crate::lookup::factory::MemMapLookup<K, T>, $crate::index::MemMapIndexError> {
+                $crate::lookup::factory::create_memmap_lookup(path)
+            }
+
+            pub fn load_memmap_lookup<
+                K: $crate::hloo_core::PermutedKey,
+                T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static,
+            >(
+                path: &std::path::Path,
+            ) -> Result<$crate::lookup::factory::MemMapLookup<K, T>, $crate::index::MemMapIndexError> {
+                $crate::lookup::factory::load_memmap_lookup(path)
             }
 
-            pub fn create_memmap_lookup<T: Copy + 'static>(
+            pub fn load_memmap_lookup_read_only<
+                K: $crate::hloo_core::PermutedKey,
+                T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static,
+            >(
                 path: &std::path::Path,
-            ) -> Result<MemMapLookup<T>, hloo::index::MemMapIndexError> {
-                let sig = hloo::util::sign_type::<T>($f, $r, $k, $w);
-                Ok(MemMapLookup::create(Permutations::get_all_variants(), sig, path)?)
+            ) -> Result<$crate::lookup::factory::MemMapLookup<K, T>, $crate::index::MemMapIndexError> {
+                $crate::lookup::factory::load_memmap_lookup_read_only(path)
             }
 
-            pub fn load_memmap_lookup<T: Copy + 'static>(
+            /// Create a lookup which is kept in memory and stored in files without memory-mapping them. See
+            /// [`hloo::index::FileIndex`].
+            pub fn create_file_lookup<K: $crate::hloo_core::PermutedKey, T: Copy + $crate::index::ScanBound + 'static>(
                 path: &std::path::Path,
-            ) -> Result<MemMapLookup<T>, hloo::index::MemMapIndexError> {
-                let sig = hloo::util::sign_type::<T>($f, $r, $k, $w);
-                Ok(MemMapLookup::load(Permutations::get_all_variants(), sig, path)?)
+            ) -> Result<$crate::lookup::factory::FileLookup<K, T>, $crate::mmvec::MmVecError> {
+                $crate::lookup::factory::create_file_lookup(path)
+            }
+
+            pub fn load_file_lookup<K: $crate::hloo_core::PermutedKey, T: Copy + $crate::index::ScanBound + 'static>(
+                path: &std::path::Path,
+            ) -> Result<$crate::lookup::factory::FileLookup<K, T>, $crate::mmvec::MmVecError> {
+                $crate::lookup::factory::load_file_lookup(path)
+            }
+
+            pub fn load_file_lookup_read_only<
+                K: $crate::hloo_core::PermutedKey,
+                T: Copy + $crate::index::ScanBound + 'static,
+            >(
+                path: &std::path::Path,
+            ) -> Result<$crate::lookup::factory::FileLookup<K, T>, $crate::mmvec::MmVecError> {
+                $crate::lookup::factory::load_file_lookup_read_only(path)
+            }
+
+            /// Unpack a container created by `MemMapLookup::pack` into a new directory `path`, and load the lookup from
+            /// it.
+            pub fn unpack_memmap_lookup<
+                K: $crate::hloo_core::PermutedKey,
+                T: $crate::mmvec::Pod + $crate::index::ScanBound + 'static,
+            >(
+                container: &std::path::Path,
+                path: &std::path::Path,
+            ) -> Result<$crate::lookup::factory::MemMapLookup<K, T>, $crate::lookup::container::ContainerError> {
+                $crate::lookup::factory::unpack_memmap_lookup(container, path)
             }
         }
     };
 }
+
+/// Expands to its input if the `fs` feature of this crate is enabled. Used by `init_lookup!`, whose expansion can't
+/// check features of this crate.
+#[cfg(feature = "fs")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_fs {
+    ($($tt:tt)*) => {
+        $($tt)*
+    };
+}
+
+#[cfg(not(feature = "fs"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __with_fs {
+    ($($tt:tt)*) => {};
+}
@@ -0,0 +1,172 @@
+//! Consistent backups of persistent lookups.
+//!
+//! A backup is a directory holding a copy of every index file of a lookup, which can be loaded like the original
+//! directory, and a manifest describing them. All copies are made while the lookup is borrowed, so they come from the
+//! same state of the lookup, even if it is modified right after.
+//!
+//! The manifest ([`MANIFEST_NAME`]) is a text file: a `hloo-backup <version>` line, followed by a line per file with
+//! its name, signature (hex), generation and size in bytes, separated by spaces.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::container::ContainerError;
+use crate::mmvec::{read_generation, read_signature, sync_parent_dir};
+
+/// Name of the manifest file in a backup directory.
+pub const MANIFEST_NAME: &str = "MANIFEST";
+
+const VERSION: u64 = 1;
+
+/// File listed in the manifest of a backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupEntry {
+    pub name: String,
+    pub sig: u64,
+    pub generation: u64,
+    pub size: u64,
+}
+
+impl BackupEntry {
+    fn from_file(dir: &Path, name: String) -> Result<Self, ContainerError> {
+        let path = dir.join(&name);
+        Ok(Self {
+            sig: read_signature(&path)?,
+            generation: read_generation(&path)?,
+            size: fs::metadata(&path)?.len(),
+            name,
+        })
+    }
+}
+
+/// Create a backup in a new directory `dir`, with files written into the directory passed to `write`.
+///
+/// Files are written into a temporary sibling directory, which is renamed to `dir` once they and the manifest are on
+/// disk, so `dir` either does not exist or holds the complete backup.
+pub(crate) fn create(
+    dir: &Path,
+    write: impl FnOnce(&Path) -> Result<(), ContainerError>,
+) -> Result<Vec<BackupEntry>, ContainerError> {
+    if dir.exists() {
+        return Err(ContainerError::DestinationExists(dir.to_path_buf()));
+    }
+    let mut tmp_dir = dir.as_os_str().to_owned();
+    tmp_dir.push(".tmp");
+    let tmp_dir = Path::new(&tmp_dir);
+    if tmp_dir.exists() {
+        // leftover of an interrupted backup
+        fs::remove_dir_all(tmp_dir)?;
+    }
+    fs::create_dir_all(tmp_dir)?;
+    write(tmp_dir)?;
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(tmp_dir)? {
+        let name = entry?.file_name().into_string().map_err(|name| {
+            ContainerError::InvalidFormat(format!("file name is not valid UTF-8: {}", name.to_string_lossy()))
+        })?;
+        names.push(name);
+    }
+    names.sort_unstable();
+    let entries = names
+        .into_iter()
+        .map(|name| BackupEntry::from_file(tmp_dir, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut writer = BufWriter::new(File::create(tmp_dir.join(MANIFEST_NAME))?);
+    writeln!(writer, "hloo-backup {VERSION}")?;
+    for entry in &entries {
+        writeln!(
+            writer,
+            "{} {:016x} {} {}",
+            entry.name, entry.sig, entry.generation, entry.size
+        )?;
+    }
+    writer
+        .into_inner()
+        .map_err(std::io::IntoInnerError::into_error)?
+        .sync_all()?;
+    #[cfg(unix)]
+    File::open(tmp_dir)?.sync_all()?;
+    fs::rename(tmp_dir, dir)?;
+    sync_parent_dir(dir)?;
+    Ok(entries)
+}
+
+/// Read the manifest of the backup in `dir`.
+pub fn manifest(dir: &Path) -> Result<Vec<BackupEntry>, ContainerError> {
+    let contents = fs::read_to_string(dir.join(MANIFEST_NAME))?;
+    let mut lines = contents.lines();
+    let version = lines
+        .next()
+        .and_then(|line| line.strip_prefix("hloo-backup "))
+        .and_then(|version| version.parse().ok())
+        .ok_or_else(|| ContainerError::InvalidFormat("not a backup manifest".to_owned()))?;
+    if version != VERSION {
+        return Err(ContainerError::UnsupportedVersion(version));
+    }
+    lines
+        .map(|line| {
+            let invalid = || ContainerError::InvalidFormat(format!("invalid manifest entry: {line}"));
+            let fields: Vec<_> = line.split(' ').collect();
+            let [name, sig, generation, size] = fields[..] else {
+                return Err(invalid());
+            };
+            Ok(BackupEntry {
+                name: name.to_owned(),
+                sig: u64::from_str_radix(sig, 16).map_err(|_| invalid())?,
+                generation: generation.parse().map_err(|_| invalid())?,
+                size: size.parse().map_err(|_| invalid())?,
+            })
+        })
+        .collect()
+}
+
+/// Check that the files of the backup in `dir` match its manifest, i.e. none of them is missing, truncated, or comes
+/// from a different state of the lookup.
+pub fn verify(dir: &Path) -> Result<Vec<BackupEntry>, ContainerError> {
+    let entries = manifest(dir)?;
+    for entry in &entries {
+        let actual = BackupEntry::from_file(dir, entry.name.clone())?;
+        if actual != *entry {
+            return Err(ContainerError::InvalidFormat(format!(
+                "{} does not match the manifest",
+                entry.name
+            )));
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mmvec::MmVec;
+
+    #[test]
+    fn backup_is_verified_against_manifest() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let vec = MmVec::from_slice(42, &[1u64, 2, 3], tempdir.path().join("vec.dat")).expect("failed to create vec");
+        let backup = tempdir.path().join("backup");
+        let entries = create(&backup, |dir| Ok(vec.backup_to(&dir.join("vec.dat"))?)).expect("failed to back up");
+        assert_eq!(entries.len(), 1, "backed up files");
+        assert_eq!(entries[0].sig, 42, "signature in the manifest");
+        assert_eq!(manifest(&backup).unwrap(), entries, "manifest");
+        assert_eq!(verify(&backup).unwrap(), entries, "verified entries");
+        assert!(matches!(
+            create(&backup, |_| Ok(())),
+            Err(ContainerError::DestinationExists(_))
+        ));
+
+        let copy = MmVec::<u64>::from_path(42, backup.join("vec.dat")).expect("failed to load the copy");
+        assert_eq!(copy.as_slice(), &[1, 2, 3], "copied contents");
+        drop(copy);
+
+        let file = File::options().write(true).open(backup.join("vec.dat")).unwrap();
+        file.set_len(entries[0].size - 8).unwrap();
+        assert!(matches!(verify(&backup), Err(ContainerError::InvalidFormat(_))));
+    }
+}
@@ -0,0 +1,226 @@
+//! Single-file container for lookup directories.
+//!
+//! Memory-mapped lookups keep every index in a separate file. A container packs all of them into one file with a
+//! table of contents, so a lookup can be shipped as a single file and unpacked atomically on the receiving side.
+//!
+//! Layout (all integers are little-endian `u64`): magic, version, number of entries; then, for each entry, the name
+//! length, the name (UTF-8), offset of the contents from the start of the container, and size of the contents;
+//! followed by the contents of all entries.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::mmvec::MmVecError;
+
+const MAGIC: u64 = u64::from_le_bytes(*b"HLOOPACK");
+const VERSION: u64 = 1;
+
+#[derive(Debug, Error)]
+pub enum ContainerError {
+    #[error("not a lookup container, or the container is corrupted: {0}")]
+    InvalidFormat(String),
+    #[error("unsupported container version: {0}")]
+    UnsupportedVersion(u64),
+    #[error("destination already exists: {0}")]
+    DestinationExists(PathBuf),
+    #[error("storage error: {0}")]
+    Storage(#[from] MmVecError),
+    #[error("i/o error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Entry in the table of contents of a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerEntry {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Pack all files in `dir` into a single container file at `container`.
+///
+/// Leftovers of interrupted writes (`*.tmp` files) and subdirectories are skipped. The container is written to a
+/// temporary file first, and then renamed into place.
+pub fn pack(dir: &Path, container: &Path) -> Result<Vec<ContainerEntry>, ContainerError> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            ContainerError::InvalidFormat(format!("file name is not valid UTF-8: {}", name.to_string_lossy()))
+        })?;
+        if entry.file_type()?.is_file() && !name.ends_with(".tmp") {
+            files.push((name, entry.metadata()?.len()));
+        }
+    }
+    files.sort_unstable();
+
+    let toc_size: u64 = 24 + files.iter().map(|(name, _)| 24 + name.len() as u64).sum::<u64>();
+    let mut offset = toc_size;
+    let entries: Vec<_> = files
+        .into_iter()
+        .map(|(name, size)| {
+            let entry = ContainerEntry { name, offset, size };
+            offset += size;
+            entry
+        })
+        .collect();
+
+    let tmp_path = with_suffix(container, ".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    for value in [MAGIC, VERSION, entries.len() as u64] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    for entry in &entries {
+        writer.write_all(&(entry.name.len() as u64).to_le_bytes())?;
+        writer.write_all(entry.name.as_bytes())?;
+        writer.write_all(&entry.offset.to_le_bytes())?;
+        writer.write_all(&entry.size.to_le_bytes())?;
+    }
+    for entry in &entries {
+        let copied = io::copy(&mut File::open(dir.join(&entry.name))?.take(entry.size), &mut writer)?;
+        if copied != entry.size {
+            let message = format!("{} was truncated while packing", entry.name);
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message).into());
+        }
+    }
+    writer
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()?;
+    fs::rename(&tmp_path, container)?;
+    Ok(entries)
+}
+
+/// Read the table of contents of a container.
+pub fn entries(container: &Path) -> Result<Vec<ContainerEntry>, ContainerError> {
+    let mut reader = BufReader::new(File::open(container)?);
+    let container_size = reader.get_ref().metadata()?.len();
+    if read_u64(&mut reader)? != MAGIC {
+        return Err(ContainerError::InvalidFormat("magic does not match".to_owned()));
+    }
+    let version = read_u64(&mut reader)?;
+    if version != VERSION {
+        return Err(ContainerError::UnsupportedVersion(version));
+    }
+    let n_entries = read_u64(&mut reader)?;
+    let mut entries = Vec::new();
+    for _ in 0..n_entries {
+        let name_len = read_u64(&mut reader)?;
+        if name_len > container_size {
+            return Err(ContainerError::InvalidFormat("entry name is too long".to_owned()));
+        }
+        let mut name = vec![0; name_len as usize];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8(name)
+            .map_err(|_| ContainerError::InvalidFormat("entry name is not valid UTF-8".to_owned()))?;
+        // entries are extracted into a single directory, so names must not point anywhere else
+        if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+            return Err(ContainerError::InvalidFormat(format!("invalid entry name: {name}")));
+        }
+        let offset = read_u64(&mut reader)?;
+        let size = read_u64(&mut reader)?;
+        if offset.checked_add(size).is_none_or(|end| end > container_size) {
+            return Err(ContainerError::InvalidFormat(format!("{name} is out of bounds")));
+        }
+        entries.push(ContainerEntry { name, offset, size });
+    }
+    Ok(entries)
+}
+
+/// Unpack a container into a new directory `dir`.
+///
+/// The contents are extracted into a temporary sibling directory, which is renamed to `dir` once all files are
+/// written to disk, so `dir` either does not exist or holds the complete lookup.
+pub fn unpack(container: &Path, dir: &Path) -> Result<Vec<ContainerEntry>, ContainerError> {
+    if dir.exists() {
+        return Err(ContainerError::DestinationExists(dir.to_path_buf()));
+    }
+    let entries = entries(container)?;
+    let tmp_dir = with_suffix(dir, ".tmp");
+    if tmp_dir.exists() {
+        // leftover of an interrupted unpack
+        fs::remove_dir_all(&tmp_dir)?;
+    }
+    fs::create_dir_all(&tmp_dir)?;
+
+    let mut source = File::open(container)?;
+    for entry in &entries {
+        source.seek(SeekFrom::Start(entry.offset))?;
+        let mut file = File::create(tmp_dir.join(&entry.name))?;
+        let copied = io::copy(&mut (&mut source).take(entry.size), &mut file)?;
+        if copied != entry.size {
+            return Err(ContainerError::InvalidFormat(format!("{} is truncated", entry.name)));
+        }
+        file.sync_all()?;
+    }
+    #[cfg(unix)]
+    File::open(&tmp_dir)?.sync_all()?;
+    fs::rename(&tmp_dir, dir)?;
+    Ok(entries)
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, ContainerError> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => ContainerError::InvalidFormat("container is truncated".to_owned()),
+        _ => err.into(),
+    })?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_roundtrip() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let source = tempdir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("b.dat"), b"second").unwrap();
+        fs::write(source.join("a.dat"), b"first").unwrap();
+        fs::write(source.join("a.dat.tmp"), b"leftover").unwrap();
+        let container = tempdir.path().join("lookup.hloo");
+
+        let packed = pack(&source, &container).unwrap();
+        let names: Vec<_> = packed.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a.dat", "b.dat"], "packed entries");
+        assert_eq!(entries(&container).unwrap(), packed, "table of contents");
+
+        let target = tempdir.path().join("target");
+        unpack(&container, &target).unwrap();
+        assert_eq!(fs::read(target.join("a.dat")).unwrap(), b"first");
+        assert_eq!(fs::read(target.join("b.dat")).unwrap(), b"second");
+        assert!(
+            !target.join("a.dat.tmp").exists(),
+            "temporary files should not be packed"
+        );
+        assert!(matches!(
+            unpack(&container, &target),
+            Err(ContainerError::DestinationExists(_))
+        ));
+
+        let len = fs::metadata(&container).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&container)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        assert!(
+            matches!(entries(&container), Err(ContainerError::InvalidFormat(_))),
+            "truncated container"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Lookups with bit permutation parameters known only at runtime.
+//!
+//! [`DynLookup`] is the runtime counterpart of the struct created by [`init_lookup!`](crate::init_lookup): it creates
+//! or loads lookups over [`DynBitVec`] keys, permuted by [`DynPermuter`]s. Permutations are interpreted rather than
+//! compiled, so prefer `init_lookup!` when the parameters are known at compile time.
+
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use hloo_core::{DynBitVec, DynPermuter, ParamsError};
+
+use crate::{DynBitPermuter, SimpleLookup, index, util::sign_type};
+#[cfg(feature = "fs")]
+use crate::{
+    index::{MemMapIndexError, ScanBound},
+    lookup::container::ContainerError,
+    mmvec::{MmVecError, Pod},
+};
+
+pub type DynMemIndex<T> = index::MemIndex<DynBitVec, T, DynBitVec>;
+pub type DynMemLookup<T> = SimpleLookup<DynBitVec, T, DynBitVec, DynMemIndex<T>>;
+pub type DynSplitMemIndex<T> = index::SplitMemIndex<DynBitVec, T, DynBitVec>;
+pub type DynSplitMemLookup<T> = SimpleLookup<DynBitVec, T, DynBitVec, DynSplitMemIndex<T>>;
+#[cfg(feature = "fs")]
+pub type DynMemMapIndex<T> = index::MemMapIndex<DynBitVec, T, DynBitVec>;
+#[cfg(feature = "fs")]
+pub type DynMemMapLookup<T> = SimpleLookup<DynBitVec, T, DynBitVec, DynMemMapIndex<T>>;
+#[cfg(feature = "fs")]
+pub type DynFileIndex<T> = index::FileIndex<DynBitVec, T, DynBitVec>;
+#[cfg(feature = "fs")]
+pub type DynFileLookup<T> = SimpleLookup<DynBitVec, T, DynBitVec, DynFileIndex<T>>;
+
+/// Creates or loads lookups with bit permutation parameters `f`, `r`, `k` and `w` known only at runtime. See
+/// [`init_lookup!`](crate::init_lookup) for their meaning.
+///
+/// Keys of the lookups are [`DynBitVec`]s of `f` bits, which can be created with [`DynLookup::key_from_be_bytes`] or
+/// [`DynLookup::key_from_le_bytes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DynLookup {
+    f: usize,
+    r: usize,
+    k: usize,
+    w: usize,
+}
+
+impl DynLookup {
+    /// Check the parameters and create a factory of lookups using them.
+    pub fn new(f: usize, r: usize, k: usize, w: usize) -> Result<Self, ParamsError> {
+        hloo_core::check_params(f, r, k, w)?;
+        Ok(Self { f, r, k, w })
+    }
+
+    /// Parameters of created lookups, as `(f, r, k, w)`.
+    pub fn params(&self) -> (usize, usize, usize, usize) {
+        (self.f, self.r, self.k, self.w)
+    }
+
+    /// Create permuters for all variants of the permutation, one per index.
+    pub fn permuters(&self) -> Vec<DynBitPermuter<DynBitVec, DynBitVec>> {
+        DynPermuter::create_all(self.f, self.r, self.k, self.w)
+            .expect("parameters are checked on creation")
+            .into_iter()
+            .map(|p| Box::new(p) as DynBitPermuter<DynBitVec, DynBitVec>)
+            .collect()
+    }
+
+    /// Signature of persistent lookups with values of type `T`.
+    ///
+    /// Keys are stored as [`DynBitVec`]s, so the signature differs from the one of lookups created by `init_lookup!`
+    /// with the same parameters, and their files can't be mixed up.
+    pub fn sig<T: 'static>(&self) -> u64 {
+        sign_type::<(DynBitVec, T)>(self.f as u64, self.r as u64, self.k as u64, self.w as u64)
+    }
+
+    /// Create a key from big-endian bytes. See [`DynBitVec::from_be_bytes`].
+    ///
+    /// ## Panics
+    /// Panics if `bytes` do not hold exactly `f` bits.
+    pub fn key_from_be_bytes(&self, bytes: &[u8]) -> DynBitVec {
+        self.check_key_len(bytes);
+        DynBitVec::from_be_bytes(bytes)
+    }
+
+    /// Create a key from little-endian words of `w` bits. See [`DynBitVec::from_le_bytes`].
+    ///
+    /// ## Panics
+    /// Panics if `bytes` do not hold exactly `f` bits.
+    pub fn key_from_le_bytes(&self, bytes: &[u8]) -> DynBitVec {
+        self.check_key_len(bytes);
+        DynBitVec::from_le_bytes(bytes, self.w)
+    }
+
+    fn check_key_len(&self, bytes: &[u8]) {
+        assert_eq!(bytes.len() * 8, self.f, "key should have length {}", self.f / 8);
+    }
+
+    pub fn create_mem_lookup<T>(&self) -> DynMemLookup<T> {
+        DynMemLookup::new(self.permuters().into_iter().map(DynMemIndex::new).collect())
+    }
+
+    pub fn create_split_mem_lookup<T>(&self) -> DynSplitMemLookup<T> {
+        DynSplitMemLookup::new(self.permuters().into_iter().map(DynSplitMemIndex::new).collect())
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn create_memmap_lookup<T: Pod + ScanBound + 'static>(
+        &self,
+        path: &Path,
+    ) -> Result<DynMemMapLookup<T>, MemMapIndexError> {
+        DynMemMapLookup::create(self.permuters(), self.sig::<T>(), path)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn load_memmap_lookup<T: Pod + ScanBound + 'static>(
+        &self,
+        path: &Path,
+    ) -> Result<DynMemMapLookup<T>, MemMapIndexError> {
+        DynMemMapLookup::load(self.permuters(), self.sig::<T>(), path)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn load_memmap_lookup_read_only<T: Pod + ScanBound + 'static>(
+        &self,
+        path: &Path,
+    ) -> Result<DynMemMapLookup<T>, MemMapIndexError> {
+        DynMemMapLookup::load_read_only(self.permuters(), self.sig::<T>(), path)
+    }
+
+    #[cfg(feature = "fs")]
+    /// Create a lookup which is kept in memory and stored in files without memory-mapping them. See
+    /// [`index::FileIndex`].
+    pub fn create_file_lookup<T: Copy + ScanBound + 'static>(
+        &self,
+        path: &Path,
+    ) -> Result<DynFileLookup<T>, MmVecError> {
+        DynFileLookup::create(self.permuters(), self.sig::<T>(), path)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn load_file_lookup<T: Copy + ScanBound + 'static>(&self, path: &Path) -> Result<DynFileLookup<T>, MmVecError> {
+        DynFileLookup::load(self.permuters(), self.sig::<T>(), path)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn load_file_lookup_read_only<T: Copy + ScanBound + 'static>(
+        &self,
+        path: &Path,
+    ) -> Result<DynFileLookup<T>, MmVecError> {
+        DynFileLookup::load_read_only(self.permuters(), self.sig::<T>(), path)
+    }
+
+    #[cfg(feature = "fs")]
+    /// Unpack a container created by `DynMemMapLookup::pack` into a new directory `path`, and load the lookup from
+    /// it.
+    pub fn unpack_memmap_lookup<T: Pod + ScanBound + 'static>(
+        &self,
+        container: &Path,
+        path: &Path,
+    ) -> Result<DynMemMapLookup<T>, ContainerError> {
+        DynMemMapLookup::unpack(self.permuters(), self.sig::<T>(), container, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lookup;
+
+    #[test]
+    fn dyn_lookup_finds_keys_within_distance() {
+        let util = DynLookup::new(96, 5, 2, 32).expect("valid params");
+        let key = util.key_from_be_bytes(&[0xAB; 12]);
+        let mut near = key;
+        near.set(3, !near.get(3));
+        near.set(90, !near.get(90));
+        let far = util.key_from_be_bytes(&[0x54; 12]);
+
+        let mut lookup = util.create_mem_lookup::<u32>();
+        assert_eq!(lookup.indexes().len(), 10);
+        // blocks are 20, 19, 19, 19, 19 bits long
+        assert_eq!(lookup.mask_bits(), 38);
+        lookup.insert(&[(near, 1), (far, 2)]).unwrap();
+        let result = lookup.search(&key, 2).unwrap();
+        let mut found: Vec<_> = result.flat_iter().map(|item| (*item.data(), item.distance())).collect();
+        // every index which has the key in the same block finds it
+        found.dedup();
+        assert_eq!(found, vec![(1, 2)]);
+        assert!(matches!(
+            DynLookup::new(512, 5, 2, 64),
+            Err(ParamsError::UnsupportedWidth { f: 512 })
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+//! Creation and loading of lookups over any key type generated by
+//! [`make_permutations!`](crate::make_permutations), which is what the structs created by
+//! [`init_lookup!`](crate::init_lookup) delegate to.
+//!
+//! Lookup types are named by the key type, e.g. `MemLookup<Bits64, i64>`; the mask type is inferred from it.
+
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use hloo_core::PermutedKey;
+
+use crate::{SimpleLookup, index, util::sign_type};
+#[cfg(feature = "redb")]
+use crate::index::RedbIndexError;
+#[cfg(feature = "rocksdb")]
+use crate::index::RocksIndexError;
+#[cfg(feature = "fs")]
+use crate::{
+    index::{MemMapIndexError, ScanBound},
+    lookup::container::ContainerError,
+    mmvec::{MmVecError, Pod},
+};
+
+pub type MemIndex<K, T> = index::MemIndex<K, T, <K as PermutedKey>::Mask>;
+pub type MemLookup<K, T> = SimpleLookup<K, T, <K as PermutedKey>::Mask, MemIndex<K, T>>;
+pub type SplitMemIndex<K, T> = index::SplitMemIndex<K, T, <K as PermutedKey>::Mask>;
+pub type SplitMemLookup<K, T> = SimpleLookup<K, T, <K as PermutedKey>::Mask, SplitMemIndex<K, T>>;
+#[cfg(feature = "fs")]
+pub type MemMapIndex<K, T> = index::MemMapIndex<K, T, <K as PermutedKey>::Mask>;
+#[cfg(feature = "fs")]
+pub type MemMapLookup<K, T> = SimpleLookup<K, T, <K as PermutedKey>::Mask, MemMapIndex<K, T>>;
+#[cfg(feature = "fs")]
+pub type FileIndex<K, T> = index::FileIndex<K, T, <K as PermutedKey>::Mask>;
+#[cfg(feature = "fs")]
+pub type FileLookup<K, T> = SimpleLookup<K, T, <K as PermutedKey>::Mask, FileIndex<K, T>>;
+#[cfg(feature = "fs")]
+pub type NetworkSafeIndex<K, T> = index::NetworkSafeIndex<K, T, <K as PermutedKey>::Mask>;
+#[cfg(feature = "fs")]
+pub type NetworkSafeLookup<K, T> = SimpleLookup<K, T, <K as PermutedKey>::Mask, NetworkSafeIndex<K, T>>;
+#[cfg(feature = "redb")]
+pub type RedbIndex<K, T> = index::RedbIndex<K, T, <K as PermutedKey>::Mask>;
+#[cfg(feature = "redb")]
+pub type RedbLookup<K, T> = SimpleLookup<K, T, <K as PermutedKey>::Mask, RedbIndex<K, T>>;
+#[cfg(feature = "rocksdb")]
+pub type RocksIndex<K, T> = index::RocksIndex<K, T, <K as PermutedKey>::Mask>;
+#[cfg(feature = "rocksdb")]
+pub type RocksLookup<K, T> = SimpleLookup<K, T, <K as PermutedKey>::Mask, RocksIndex<K, T>>;
+
+/// Signature of persistent lookups with keys `K` and values of type `T`.
+pub fn sig<K: PermutedKey, T: 'static>() -> u64 {
+    let [f, r, k, w] = K::PARAMS.map(|param| param as u64);
+    sign_type::<T>(f, r, k, w)
+}
+
+pub fn create_mem_lookup<K: PermutedKey, T>() -> MemLookup<K, T> {
+    MemLookup::new(K::permuters().into_iter().map(MemIndex::new).collect())
+}
+
+pub fn create_split_mem_lookup<K: PermutedKey, T>() -> SplitMemLookup<K, T> {
+    SplitMemLookup::new(K::permuters().into_iter().map(SplitMemIndex::new).collect())
+}
+
+#[cfg(feature = "fs")]
+pub fn create_memmap_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<MemMapLookup<K, T>, MemMapIndexError> {
+    MemMapLookup::create(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+pub fn load_memmap_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<MemMapLookup<K, T>, MemMapIndexError> {
+    MemMapLookup::load(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+pub fn load_memmap_lookup_read_only<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<MemMapLookup<K, T>, MemMapIndexError> {
+    MemMapLookup::load_read_only(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+/// Create a lookup which is kept in memory and stored in files without memory-mapping them. See
+/// [`index::FileIndex`].
+pub fn create_file_lookup<K: PermutedKey, T: Copy + ScanBound + 'static>(
+    path: &Path,
+) -> Result<FileLookup<K, T>, MmVecError> {
+    FileLookup::create(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+pub fn load_file_lookup<K: PermutedKey, T: Copy + ScanBound + 'static>(
+    path: &Path,
+) -> Result<FileLookup<K, T>, MmVecError> {
+    FileLookup::load(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+pub fn load_file_lookup_read_only<K: PermutedKey, T: Copy + ScanBound + 'static>(
+    path: &Path,
+) -> Result<FileLookup<K, T>, MmVecError> {
+    FileLookup::load_read_only(K::permuters(), sig::<K, T>(), path)
+}
+
+/// Create a lookup which is memory-mapped, unless `path` is on a network filesystem, where it is stored like a file
+/// lookup instead. See [`index::NetworkSafeIndex`].
+#[cfg(feature = "fs")]
+pub fn create_network_safe_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<NetworkSafeLookup<K, T>, MmVecError> {
+    NetworkSafeLookup::create(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+pub fn load_network_safe_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<NetworkSafeLookup<K, T>, MmVecError> {
+    NetworkSafeLookup::load(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+pub fn load_network_safe_lookup_read_only<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<NetworkSafeLookup<K, T>, MmVecError> {
+    NetworkSafeLookup::load_read_only(K::permuters(), sig::<K, T>(), path)
+}
+
+/// Create a lookup which is kept in memory and stored in redb databases. See [`index::RedbIndex`].
+#[cfg(feature = "redb")]
+pub fn create_redb_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<RedbLookup<K, T>, RedbIndexError>
+where
+    K::Mask: Pod,
+{
+    RedbLookup::create(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "redb")]
+pub fn load_redb_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<RedbLookup<K, T>, RedbIndexError>
+where
+    K::Mask: Pod,
+{
+    RedbLookup::load(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "redb")]
+pub fn load_redb_lookup_read_only<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<RedbLookup<K, T>, RedbIndexError>
+where
+    K::Mask: Pod,
+{
+    RedbLookup::load_read_only(K::permuters(), sig::<K, T>(), path)
+}
+
+/// Create a lookup which is kept in memory and stored in RocksDB databases. See [`index::RocksIndex`].
+#[cfg(feature = "rocksdb")]
+pub fn create_rocks_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<RocksLookup<K, T>, RocksIndexError>
+where
+    K::Mask: Pod,
+{
+    RocksLookup::create(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "rocksdb")]
+pub fn load_rocks_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<RocksLookup<K, T>, RocksIndexError>
+where
+    K::Mask: Pod,
+{
+    RocksLookup::load(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "rocksdb")]
+pub fn load_rocks_lookup_read_only<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    path: &Path,
+) -> Result<RocksLookup<K, T>, RocksIndexError>
+where
+    K::Mask: Pod,
+{
+    RocksLookup::load_read_only(K::permuters(), sig::<K, T>(), path)
+}
+
+#[cfg(feature = "fs")]
+/// Unpack a container created by `MemMapLookup::pack` into a new directory `path`, and load the lookup from it.
+pub fn unpack_memmap_lookup<K: PermutedKey, T: Pod + ScanBound + 'static>(
+    container: &Path,
+    path: &Path,
+) -> Result<MemMapLookup<K, T>, ContainerError> {
+    MemMapLookup::unpack(K::permuters(), sig::<K, T>(), container, path)
+}
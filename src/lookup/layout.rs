@@ -0,0 +1,116 @@
+//! Placement of index files of persistent lookups.
+
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "fs")]
+use crate::{
+    mmvec::{MmVecError, read_signature, sync_parent_dir, write_signature},
+    util::is_legacy_signature,
+};
+
+/// Where the file of each index of a persistent lookup is placed.
+///
+/// A directory (`Path` or `PathBuf`) places all files in it, named by [`index_file_name`]. Closures taking the number
+/// of the index and the signature of the lookup can be used for custom layouts.
+pub trait PathLayout {
+    /// Path to the file of the index with the given number, in a lookup with the given signature.
+    fn index_path(&self, i: usize, sig: u64) -> PathBuf;
+}
+
+/// Default name of the file of the index with the given number.
+pub fn index_file_name(i: usize, sig: u64) -> String {
+    format!("index_{i:04}_{sig:016x}.dat")
+}
+
+/// Number of the index and the signature in a file name created by [`index_file_name`].
+#[cfg(feature = "fs")]
+fn parse_index_file_name(name: &str) -> Option<(usize, u64)> {
+    let (i, sig) = name.strip_prefix("index_")?.strip_suffix(".dat")?.split_once('_')?;
+    Some((i.parse().ok()?, u64::from_str_radix(sig, 16).ok()?))
+}
+
+/// Migrate index files in the directory `dir` which were created by older versions of this crate, whose signatures
+/// changed between builds (see [`is_legacy_signature`]), to `sig`: the signature in their header is replaced, and they
+/// are renamed accordingly. Returns the number of migrated files.
+///
+/// Only the parameters and the value size stored in legacy signatures can be checked, so the caller has to make sure
+/// that the files hold values of the expected type. Files of memory-mapped and file indexes are supported; they must
+/// not be open. An interrupted migration can be resumed by running it again.
+#[cfg(feature = "fs")]
+pub fn migrate_legacy_signatures(dir: &Path, sig: u64) -> Result<usize, MmVecError> {
+    let mut migrated = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some((i, name_sig)) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(parse_index_file_name)
+        else {
+            continue;
+        };
+        if !is_legacy_signature(name_sig, sig) {
+            continue;
+        }
+        let header_sig = read_signature(&path)?;
+        if header_sig == name_sig {
+            write_signature(&path, sig)?;
+        } else if header_sig != sig {
+            // neither legacy nor migrated by an interrupted run
+            return Err(MmVecError::SignatureMismatch {
+                expected: name_sig,
+                actual: header_sig,
+            });
+        }
+        let new_path = dir.join(index_file_name(i, sig));
+        std::fs::rename(&path, &new_path)?;
+        sync_parent_dir(&new_path)?;
+        migrated += 1;
+    }
+    Ok(migrated)
+}
+
+impl PathLayout for Path {
+    fn index_path(&self, i: usize, sig: u64) -> PathBuf {
+        self.join(index_file_name(i, sig))
+    }
+}
+
+impl PathLayout for PathBuf {
+    fn index_path(&self, i: usize, sig: u64) -> PathBuf {
+        self.as_path().index_path(i, sig)
+    }
+}
+
+impl<F> PathLayout for F
+where
+    F: Fn(usize, u64) -> PathBuf,
+{
+    fn index_path(&self, i: usize, sig: u64) -> PathBuf {
+        self(i, sig)
+    }
+}
+
+/// Spreads index files over several directories (e.g. on different disks) in a round-robin fashion.
+#[derive(Debug, Clone)]
+pub struct StripedLayout {
+    dirs: Vec<PathBuf>,
+}
+
+impl StripedLayout {
+    /// ## Panics
+    /// Panics if `dirs` is empty.
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        assert!(!dirs.is_empty(), "at least one directory is required");
+        Self { dirs }
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+}
+
+impl PathLayout for StripedLayout {
+    fn index_path(&self, i: usize, sig: u64) -> PathBuf {
+        self.dirs[i % self.dirs.len()].index_path(i, sig)
+    }
+}
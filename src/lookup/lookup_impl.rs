@@ -1,12 +1,15 @@
 macro_rules! impl_lookup {
     ($name:ident,$index:ident) => {
-        pub struct $name<V: Copy>(
+        impl_lookup!($name, $index, Copy);
+    };
+    ($name:ident,$index:ident,$value:path) => {
+        pub struct $name<V: $value>(
             SimpleLookup<internal::Bits, V, internal::Mask, $index<internal::Bits, V, internal::Mask>>,
         );
 
         impl<V> Lookup<internal::Bits, V, internal::Mask> for $name<V>
         where
-            V: Copy,
+            V: $value + ScanBound,
         {
             type Index = $index<internal::Bits, V, internal::Mask>;
 
@@ -21,23 +24,129 @@ macro_rules! impl_lookup {
     };
 }
 
+#[cfg(feature = "fs")]
+macro_rules! impl_persistent_lookup {
+    ($name:ident,$index:ident,$value:path,$f:literal,$r:literal,$k:literal,$w:literal) => {
+        impl_lookup!($name, $index, $value);
+
+        impl<V> $name<V>
+        where
+            V: $value + ScanBound + 'static,
+        {
+            pub fn create(
+                path: &(impl PathLayout + ?Sized),
+            ) -> Result<Self, <$index<Bits, V, Mask> as PersistentIndex<Bits, Mask>>::Error> {
+                let sig = sign_type::<V>($f, $r, $k, $w);
+                Ok(Self(SimpleLookup::create(
+                    Permutations::get_all_variants(),
+                    sig,
+                    path,
+                )?))
+            }
+
+            pub fn load(
+                path: &(impl PathLayout + ?Sized),
+            ) -> Result<Self, <$index<Bits, V, Mask> as PersistentIndex<Bits, Mask>>::Error> {
+                let sig = sign_type::<V>($f, $r, $k, $w);
+                Ok(Self(SimpleLookup::load(
+                    Permutations::get_all_variants(),
+                    sig,
+                    path,
+                )?))
+            }
+
+            /// Migrate the index files in the directory `path` which were created by older versions of this crate. See
+            /// [`migrate_legacy_signatures`](crate::lookup::layout::migrate_legacy_signatures).
+            pub fn migrate_legacy_signatures(path: &std::path::Path) -> Result<usize, crate::mmvec::MmVecError> {
+                crate::lookup::layout::migrate_legacy_signatures(path, sign_type::<V>($f, $r, $k, $w))
+            }
+
+            /// Close this lookup and remove the files of all its indexes.
+            pub fn destroy(self) -> Result<(), <$index<Bits, V, Mask> as PersistentIndex<Bits, Mask>>::Error> {
+                self.0.destroy()
+            }
+
+            /// Persist and close this lookup, and pack its directory `path` into a single container file.
+            pub fn pack(
+                self,
+                path: &std::path::Path,
+                container: &std::path::Path,
+            ) -> Result<Vec<ContainerEntry>, ContainerError> {
+                self.0.pack(path, container)
+            }
+
+            /// Unpack a container created by [`Self::pack`] into a new directory `path`, and load the lookup from it.
+            pub fn unpack(container: &std::path::Path, path: &std::path::Path) -> Result<Self, ContainerError> {
+                let sig = sign_type::<V>($f, $r, $k, $w);
+                Ok(Self(SimpleLookup::unpack(
+                    Permutations::get_all_variants(),
+                    sig,
+                    container,
+                    path,
+                )?))
+            }
+
+            pub fn load_read_only(
+                path: &(impl PathLayout + ?Sized),
+            ) -> Result<Self, <$index<Bits, V, Mask> as PersistentIndex<Bits, Mask>>::Error> {
+                let sig = sign_type::<V>($f, $r, $k, $w);
+                Ok(Self(SimpleLookup::load_read_only(
+                    Permutations::get_all_variants(),
+                    sig,
+                    path,
+                )?))
+            }
+        }
+    };
+}
+
 macro_rules! impl_lookups {
     ($mod_name:ident,$f:literal,$r:literal,$k:literal,$w:literal) => {
         pub mod $mod_name {
             use crate::{
-                index::{MemIndex, MemMapIndex, PersistentIndex},
+                index::{MemIndex, ScanBound, SplitMemIndex},
                 lookup::Lookup,
-                util::sign_type,
                 SimpleLookup,
             };
+            #[cfg(feature = "fs")]
+            use crate::{
+                index::{FileIndex, MemMapIndex, PersistentIndex},
+                lookup::{
+                    backup::BackupEntry,
+                    container::{ContainerEntry, ContainerError},
+                    PathLayout,
+                },
+                mmvec::Pod,
+                util::sign_type,
+            };
 
             pub use internal::{Bits, Mask, Permutations};
 
             mod internal {
-                use hloo_core::{BitContainer, BitPermuter};
+                use hloo_core::{BitContainer, BitPermuter, Pod};
                 crate::make_permutations!(struct_name = "Permutations", f = $f, r = $r, k = $k, w = $w);
             }
 
+            impl crate::util::FromLeBytes for Bits {
+                const SIZE: usize = Bits::SIZE_BYTES;
+
+                fn from_le_slice(bytes: &[u8]) -> Self {
+                    Bits::from_le_bytes(bytes)
+                }
+            }
+
+            impl crate::util::KeyBytes for Bits {
+                const SIZE: usize = Bits::SIZE_BYTES;
+
+                fn to_be_vec(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+
+                fn from_be_slice(bytes: &[u8]) -> Self {
+                    Bits::from_be_bytes(bytes)
+                }
+            }
+
             impl_lookup!(MemLookup, MemIndex);
 
             impl<V> Default for MemLookup<V>
@@ -50,55 +159,315 @@ macro_rules! impl_lookups {
                 }
             }
 
-            impl_lookup!(MemMapLookup, MemMapIndex);
+            #[cfg(feature = "serde")]
+            impl<V> serde::Serialize for MemLookup<V>
+            where
+                V: Copy + ScanBound + serde::Serialize,
+            {
+                fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.0.serialize(serializer)
+                }
+            }
+
+            #[cfg(feature = "serde")]
+            impl<'de, V> serde::Deserialize<'de> for MemLookup<V>
+            where
+                V: Copy + ScanBound + serde::Deserialize<'de>,
+            {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    SimpleLookup::deserialize(deserializer).map(Self)
+                }
+            }
+
+            /// Lookup filled with arbitrary batches, see [`fill_lookup`](crate::fuzzing::fill_lookup).
+            #[cfg(feature = "arbitrary")]
+            impl<'a, V> hloo_core::arbitrary::Arbitrary<'a> for MemLookup<V>
+            where
+                V: Copy + ScanBound + hloo_core::arbitrary::Arbitrary<'a>,
+            {
+                fn arbitrary(u: &mut hloo_core::arbitrary::Unstructured<'a>) -> hloo_core::arbitrary::Result<Self> {
+                    let mut lookup = Self::default();
+                    let data = u.bytes(u.len())?;
+                    // inserts into memory indexes are infallible
+                    let _ = crate::fuzzing::fill_lookup(&mut lookup, data);
+                    Ok(lookup)
+                }
+            }
+
+            impl_lookup!(SplitMemLookup, SplitMemIndex);
+
+            impl<V> Default for SplitMemLookup<V>
+            where
+                V: Copy,
+            {
+                fn default() -> Self {
+                    let perms = Permutations::get_all_variants();
+                    Self(SimpleLookup::new(
+                        perms.into_iter().map(SplitMemIndex::new).collect(),
+                    ))
+                }
+            }
+
+            #[cfg(feature = "fs")]
+            impl_persistent_lookup!(MemMapLookup, MemMapIndex, Pod, $f, $r, $k, $w);
+
+            #[cfg(feature = "fs")]
             impl<V> MemMapLookup<V>
             where
-                V: Copy + 'static,
+                V: Pod,
             {
-                pub fn create(
-                    path: &std::path::Path,
-                ) -> Result<Self, <MemMapIndex<Bits, V, Mask> as PersistentIndex<Bits, Mask>>::Error> {
-                    let sig = sign_type::<V>($f, $r, $k, $w);
-                    Ok(Self(SimpleLookup::create(
-                        Permutations::get_all_variants(),
-                        sig,
-                        path,
-                    )?))
+                /// Load all indexes into memory ahead of searches. See [`SimpleLookup::warmup`].
+                pub fn warmup(&self) {
+                    self.0.warmup();
+                }
+
+                /// Insert all records of a raw dump at `path`. See [`SimpleLookup::import_raw`].
+                pub fn import_raw(&mut self, path: &std::path::Path) -> Result<usize, crate::mmvec::MmVecError>
+                where
+                    V: ScanBound + crate::util::FromLeBytes,
+                {
+                    self.0.import_raw(path)
                 }
 
-                pub fn load(
-                    path: &std::path::Path,
-                ) -> Result<Self, <MemMapIndex<Bits, V, Mask> as PersistentIndex<Bits, Mask>>::Error> {
-                    let sig = sign_type::<V>($f, $r, $k, $w);
-                    Ok(Self(SimpleLookup::load(
-                        Permutations::get_all_variants(),
-                        sig,
-                        path,
-                    )?))
+                /// Back up all indexes into a new directory `dir`, which can be loaded with [`Self::load`]. See
+                /// [`SimpleLookup::backup_to`].
+                pub fn backup_to(&self, dir: &std::path::Path) -> Result<Vec<BackupEntry>, ContainerError> {
+                    self.0.backup_to(dir)
                 }
             }
+
+            #[cfg(feature = "fs")]
+            impl_persistent_lookup!(FileLookup, FileIndex, Copy, $f, $r, $k, $w);
         }
     };
 }
 
 impl_lookups!(lookup64, 64, 4, 1, 64);
+impl_lookups!(lookup128, 128, 6, 1, 64);
+impl_lookups!(lookup192, 192, 7, 1, 64);
 impl_lookups!(lookup256, 256, 8, 1, 64);
 
+/// Key of any of the pre-defined widths, chosen by the length of its little-endian bytes.
 pub enum DynBits {
     Bits64(lookup64::Bits),
+    Bits128(lookup128::Bits),
+    Bits192(lookup192::Bits),
     Bits256(lookup256::Bits),
 }
 
-impl From<&[u8]> for DynBits {
-    fn from(value: &[u8]) -> Self {
-        match value.len() {
+impl DynBits {
+    /// Create a key from its little-endian bytes, or `None` if no pre-defined key is as long.
+    pub fn try_from_le_slice(value: &[u8]) -> Option<Self> {
+        Some(match value.len() {
             lookup64::Bits::SIZE_BYTES => Self::Bits64(lookup64::Bits::from_le_bytes(value)),
+            lookup128::Bits::SIZE_BYTES => Self::Bits128(lookup128::Bits::from_le_bytes(value)),
+            lookup192::Bits::SIZE_BYTES => Self::Bits192(lookup192::Bits::from_le_bytes(value)),
             lookup256::Bits::SIZE_BYTES => Self::Bits256(lookup256::Bits::from_le_bytes(value)),
-            len => panic!("invalid slice size: {len}"),
+            _ => return None,
+        })
+    }
+}
+
+impl From<&[u8]> for DynBits {
+    fn from(value: &[u8]) -> Self {
+        Self::try_from_le_slice(value).unwrap_or_else(|| panic!("invalid slice size: {}", value.len()))
+    }
+}
+
+/// Keys of the pre-defined widths, extracted from [`DynBits`] of the same width.
+trait FromDynBits: Sized {
+    fn from_dyn_bits(bits: DynBits) -> Option<Self>;
+}
+
+macro_rules! impl_from_dyn_bits {
+    ($($module:ident => $variant:ident),*) => {
+        $(
+            impl FromDynBits for $module::Bits {
+                fn from_dyn_bits(bits: DynBits) -> Option<Self> {
+                    match bits {
+                        DynBits::$variant(bits) => Some(bits),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_dyn_bits!(lookup64 => Bits64, lookup128 => Bits128, lookup192 => Bits192, lookup256 => Bits256);
+
+/// Convert little-endian bytes into a key of width `B`, failing if they are not `expected` bytes long.
+fn typed_key<B: FromDynBits, E>(key: &[u8], expected: usize) -> Result<B, DynWidthError<E>> {
+    DynBits::try_from_le_slice(key)
+        .and_then(B::from_dyn_bits)
+        .ok_or(DynWidthError::KeySizeMismatch {
+            expected,
+            actual: key.len(),
+        })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DynWidthError<E> {
+    #[error("keys of {0} bytes are not supported, only keys of 8, 16, 24 or 32 bytes are")]
+    UnsupportedKeySize(usize),
+    #[error("key is {actual} bytes long, but keys of this lookup are {expected} bytes long")]
+    KeySizeMismatch { expected: usize, actual: usize },
+    #[error(transparent)]
+    Search(#[from] crate::lookup::SearchError),
+    #[error("index error: {0:?}")]
+    Index(E),
+}
+
+/// Generates an enum over lookups `$lookup` of every pre-defined key width, which takes keys as little-endian bytes
+/// and dispatches to the lookup of their width.
+macro_rules! impl_dyn_width_lookup {
+    ($(#[$attr:meta])* $name:ident, $lookup:ident, $value:path, $error:ty) => {
+        $(#[$attr])*
+        pub enum $name<V: $value> {
+            Bits64(lookup64::$lookup<V>),
+            Bits128(lookup128::$lookup<V>),
+            Bits192(lookup192::$lookup<V>),
+            Bits256(lookup256::$lookup<V>),
+        }
+
+        impl<V> $name<V>
+        where
+            V: $value + crate::index::ScanBound,
+        {
+            /// Size of keys of this lookup in bytes.
+            pub fn key_size(&self) -> usize {
+                match self {
+                    Self::Bits64(_) => lookup64::Bits::SIZE_BYTES,
+                    Self::Bits128(_) => lookup128::Bits::SIZE_BYTES,
+                    Self::Bits192(_) => lookup192::Bits::SIZE_BYTES,
+                    Self::Bits256(_) => lookup256::Bits::SIZE_BYTES,
+                }
+            }
+
+            pub fn max_search_distance(&self) -> u32 {
+                dispatch_dyn_width!(self, lookup => crate::lookup::Lookup::max_search_distance(lookup))
+            }
+
+            /// Insert items, with keys as little-endian bytes of the size of keys of this lookup.
+            pub fn insert(&mut self, items: &[(&[u8], V)]) -> Result<(), DynWidthError<$error>> {
+                let key_size = self.key_size();
+                dispatch_dyn_width!(self, lookup => {
+                    let items = items
+                        .iter()
+                        .map(|(key, value)| typed_key(key, key_size).map(|key| (key, *value)))
+                        .collect::<Result<Vec<_>, DynWidthError<$error>>>()?;
+                    crate::lookup::Lookup::insert(lookup, &items).map_err(DynWidthError::Index)
+                })
+            }
+
+            /// Remove items by keys, given as little-endian bytes of the size of keys of this lookup.
+            pub fn remove(&mut self, keys: &[&[u8]]) -> Result<(), DynWidthError<$error>> {
+                let key_size = self.key_size();
+                dispatch_dyn_width!(self, lookup => {
+                    let keys = keys
+                        .iter()
+                        .map(|key| typed_key(key, key_size))
+                        .collect::<Result<Vec<_>, DynWidthError<$error>>>()?;
+                    crate::lookup::Lookup::remove(lookup, &keys).map_err(DynWidthError::Index)
+                })
+            }
+
+            /// Perform a distance search, with the key as little-endian bytes of the size of keys of this lookup.
+            pub fn search(
+                &self,
+                key: &[u8],
+                distance: u32,
+            ) -> Result<crate::lookup::SearchResult<V>, DynWidthError<$error>> {
+                let key_size = self.key_size();
+                dispatch_dyn_width!(self, lookup => {
+                    let key = typed_key::<_, $error>(key, key_size)?;
+                    Ok(crate::lookup::Lookup::search(lookup, &key, distance)?)
+                })
+            }
         }
+    };
+}
+
+macro_rules! dispatch_dyn_width {
+    ($self:expr, $lookup:ident => $e:expr) => {
+        match $self {
+            Self::Bits64($lookup) => $e,
+            Self::Bits128($lookup) => $e,
+            Self::Bits192($lookup) => $e,
+            Self::Bits256($lookup) => $e,
+        }
+    };
+}
+
+impl_dyn_width_lookup!(
+    /// In-memory lookup over keys of any of the pre-defined widths (64, 128, 192 or 256 bits), chosen at runtime.
+    ///
+    /// Keys are passed as little-endian bytes, like for [`DynBits`]. Every lookup holds keys of a single width; use one
+    /// lookup per width to serve keys of several widths.
+    DynWidthMemLookup,
+    MemLookup,
+    Copy,
+    ()
+);
+
+impl<V: Copy> DynWidthMemLookup<V> {
+    /// Create an empty lookup over keys of `key_size` bytes.
+    pub fn new(key_size: usize) -> Result<Self, DynWidthError<()>> {
+        Ok(match key_size {
+            lookup64::Bits::SIZE_BYTES => Self::Bits64(Default::default()),
+            lookup128::Bits::SIZE_BYTES => Self::Bits128(Default::default()),
+            lookup192::Bits::SIZE_BYTES => Self::Bits192(Default::default()),
+            lookup256::Bits::SIZE_BYTES => Self::Bits256(Default::default()),
+            _ => return Err(DynWidthError::UnsupportedKeySize(key_size)),
+        })
+    }
+}
+
+#[cfg(feature = "fs")]
+impl_dyn_width_lookup!(
+    /// Memory-mapped lookup over keys of any of the pre-defined widths (64, 128, 192 or 256 bits), chosen at runtime.
+    /// See [`DynWidthMemLookup`].
+    DynWidthMemMapLookup,
+    MemMapLookup,
+    crate::mmvec::Pod,
+    crate::mmvec::MmVecError
+);
+
+#[cfg(feature = "fs")]
+impl<V: crate::mmvec::Pod + crate::index::ScanBound + 'static> DynWidthMemMapLookup<V> {
+    /// Create a lookup over keys of `key_size` bytes in the directory `path`.
+    pub fn create(key_size: usize, path: &std::path::Path) -> Result<Self, DynWidthError<crate::mmvec::MmVecError>> {
+        let map_err = DynWidthError::Index;
+        Ok(match key_size {
+            lookup64::Bits::SIZE_BYTES => Self::Bits64(lookup64::MemMapLookup::create(path).map_err(map_err)?),
+            lookup128::Bits::SIZE_BYTES => Self::Bits128(lookup128::MemMapLookup::create(path).map_err(map_err)?),
+            lookup192::Bits::SIZE_BYTES => Self::Bits192(lookup192::MemMapLookup::create(path).map_err(map_err)?),
+            lookup256::Bits::SIZE_BYTES => Self::Bits256(lookup256::MemMapLookup::create(path).map_err(map_err)?),
+            _ => return Err(DynWidthError::UnsupportedKeySize(key_size)),
+        })
+    }
+
+    /// Load a lookup over keys of `key_size` bytes from the directory `path`.
+    pub fn load(key_size: usize, path: &std::path::Path) -> Result<Self, DynWidthError<crate::mmvec::MmVecError>> {
+        let map_err = DynWidthError::Index;
+        Ok(match key_size {
+            lookup64::Bits::SIZE_BYTES => Self::Bits64(lookup64::MemMapLookup::load(path).map_err(map_err)?),
+            lookup128::Bits::SIZE_BYTES => Self::Bits128(lookup128::MemMapLookup::load(path).map_err(map_err)?),
+            lookup192::Bits::SIZE_BYTES => Self::Bits192(lookup192::MemMapLookup::load(path).map_err(map_err)?),
+            lookup256::Bits::SIZE_BYTES => Self::Bits256(lookup256::MemMapLookup::load(path).map_err(map_err)?),
+            _ => return Err(DynWidthError::UnsupportedKeySize(key_size)),
+        })
+    }
+
+    /// Persist all indexes. See [`Lookup::persist`](crate::Lookup::persist).
+    pub fn persist(&self) -> Result<(), DynWidthError<crate::mmvec::MmVecError>> {
+        dispatch_dyn_width!(self, lookup => crate::lookup::Lookup::persist(lookup).map_err(DynWidthError::Index))
     }
 }
 
 pub enum DynBitsVec {
     Bits64(lookup64::Bits),
+    Bits128(lookup128::Bits),
+    Bits192(lookup192::Bits),
+    Bits256(lookup256::Bits),
 }
@@ -1,13 +1,47 @@
+#[cfg(feature = "fs")]
+pub mod backup;
+#[cfg(feature = "fs")]
+pub mod container;
+pub mod dyn_lookup;
+pub mod factory;
+pub mod layout;
 pub mod lookup_impl;
+#[cfg(feature = "serde")]
+mod serialization;
+mod stats;
+mod string_lookup;
 
-use std::{collections::HashSet, hash::Hash, marker::PhantomData, path::Path};
+use std::{collections::HashSet, hash::Hash, marker::PhantomData};
+#[cfg(feature = "fs")]
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
 
 use hloo_core::BitContainer;
 
 use crate::{
-    index::{Index, PersistentIndex, SearchResultItem},
-    DynBitPermuter,
+    index::{Candidates, Index, LookupValidation, PersistentIndex, ScanBound, SearchResultItem},
+    metrics, DynBitPermuter,
+};
+#[cfg(feature = "fs")]
+use crate::{
+    export::{ExportError, ExportReader, ExportWriter, ImportError},
+    index::MemMapIndex,
+    mmvec::{MmVecError, Pod},
+    util::{FromLeBytes, KeyBytes, ToLeBytes},
 };
+#[cfg(feature = "fs")]
+use backup::BackupEntry;
+#[cfg(feature = "fs")]
+use container::{ContainerEntry, ContainerError};
+pub use dyn_lookup::DynLookup;
+pub use layout::{PathLayout, StripedLayout, index_file_name};
+pub use stats::{IndexStatsSnapshot, StatsSnapshot};
+#[cfg(feature = "fs")]
+pub use string_lookup::STRINGS_FILE_NAME;
+pub use string_lookup::{StringLookup, StringLookupError, StringLookupResult};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -18,6 +52,9 @@ pub enum SearchError {
 
 pub struct SearchResult<V> {
     pub candidates_scanned: usize,
+    /// Whether every item within the search distance was found: `false` if the distance exceeded
+    /// [`Lookup::max_search_distance`] (see [`Lookup::search_unchecked_distance`]).
+    pub recall_guaranteed: bool,
     pub result: Vec<Vec<SearchResultItem<V>>>,
 }
 
@@ -31,7 +68,20 @@ impl<V> SearchResult<V> {
     }
 }
 
+/// Progress of [`Lookup::ingest`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IngestProgress {
+    /// Number of items inserted so far.
+    pub items: usize,
+    /// Number of batches inserted so far.
+    pub batches: usize,
+    /// Number of times the lookup was persisted so far.
+    pub flushes: usize,
+}
+
 pub type IndexResult<T, K, V, M, I> = Result<T, <I as Index<K, V, M>>::Error>;
+#[cfg(feature = "fs")]
+pub type ImportResult<T, K, V, M, I> = Result<T, ImportError<<I as Index<K, V, M>>::Error>>;
 
 pub trait Lookup<K, V, M>
 where
@@ -49,13 +99,64 @@ where
         self.indexes()[0].permuter().n_blocks() - 1
     }
 
+    /// Smallest number of key bits which indexes of this lookup compare exactly, see [`BitPermuter::mask_bits`].
+    /// Indexes differ when blocks are of unequal size.
+    ///
+    /// [`BitPermuter::mask_bits`]: hloo_core::BitPermuter::mask_bits
+    fn mask_bits(&self) -> u32 {
+        self.indexes()
+            .iter()
+            .map(|index| index.permuter().mask_bits())
+            .min()
+            .unwrap_or(0)
+    }
+
     /// Insert items into this lookup.
+    ///
+    /// Insertion is all-or-nothing: the insert is first staged in every index (see [`Index::stage_insert`]), which
+    /// acquires everything it needs, e.g. disk space, without changing the index. If an index fails to stage it, the
+    /// inserts staged so far are discarded and the error is returned, leaving the lookup unchanged. Otherwise, the
+    /// insert is committed to every index. Committing only writes to space acquired while staging; if it fails anyway,
+    /// the remaining indexes are still committed, so that they agree with the failed one once it is recovered (e.g.
+    /// memory-mapped indexes complete an interrupted insert when they are opened again), and the first error is
+    /// returned.
     fn insert(&mut self, items: &[(K, V)]) -> IndexResult<(), K, V, M, Self::Index> {
-        for index in self.indexes_mut() {
-            index.insert(items)?;
-            index.refresh();
+        let indexes = self.indexes_mut();
+        for i in 0..indexes.len() {
+            if let Err(e) = indexes[i].stage_insert(items) {
+                for index in &mut indexes[..i] {
+                    index.discard_insert();
+                }
+                return Err(e);
+            }
         }
-        Ok(())
+        let mut result = Ok(());
+        for index in indexes {
+            match index.commit_insert(items) {
+                Ok(()) => index.refresh(),
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Insert items, skipping `(key, value)` pairs which are already stored in this lookup or repeated in `items`.
+    /// Returns the number of skipped items.
+    ///
+    /// Every index holds the same items, so duplicates are detected with a binary search in the first one only.
+    fn insert_new(&mut self, items: &[(K, V)]) -> IndexResult<usize, K, V, M, Self::Index>
+    where
+        K: Clone,
+        V: PartialEq,
+    {
+        self.compact()?;
+        let absent = self.indexes()[0].absent_items(items);
+        self.insert(&absent)?;
+        Ok(items.len() - absent.len())
     }
 
     /// Remove items from the lookup by keys.
@@ -67,31 +168,103 @@ where
         Ok(())
     }
 
-    /// Perform a distance search.
-    fn search(&self, key: &K, distance: u32) -> Result<SearchResult<V>, SearchError> {
-        let max_distance = self.max_search_distance();
-        if distance > max_distance {
-            return Err(SearchError::DistanceExceedsMax {
-                distance,
-                max: max_distance,
-            });
-        }
-        let mut candidates_scanned = 0usize;
-        let mut result: Vec<Vec<SearchResultItem<V>>> = Vec::with_capacity(self.indexes().len());
-        for index in self.indexes() {
-            let candidates = index.get_candidates(key);
-            candidates_scanned += candidates.len();
-            result.push(candidates.scan(distance));
+    /// Physically remove deleted items from indexes which defer removal.
+    fn compact(&mut self) -> IndexResult<(), K, V, M, Self::Index> {
+        for index in self.indexes_mut() {
+            index.compact()?;
+            index.refresh();
         }
-        Ok(SearchResult {
-            candidates_scanned,
-            result,
+        Ok(())
+    }
+
+    /// Remove exact duplicate `(key, value)` pairs from all indexes.
+    fn dedup(&mut self) -> IndexResult<(), K, V, M, Self::Index>
+    where
+        K: Clone,
+        V: PartialEq,
+    {
+        for index in self.indexes_mut() {
+            index.dedup()?;
+            index.refresh();
+        }
+        Ok(())
+    }
+
+    /// Retain only the items for which `pred` returns `true`.
+    fn retain(&mut self, pred: impl Fn(&K, &V) -> bool) -> IndexResult<(), K, V, M, Self::Index> {
+        for index in self.indexes_mut() {
+            index.retain(&pred)?;
+            index.refresh();
+        }
+        Ok(())
+    }
+
+    /// Remove all items from this lookup and return them, with keys in their original (non-permuted) form.
+    fn drain(&mut self) -> IndexResult<Vec<(K, V)>, K, V, M, Self::Index> {
+        let mut items = None;
+        for index in self.indexes_mut() {
+            // every index holds the same items, so only the first one is collected
+            if items.is_none() {
+                items = Some(index.drain()?);
+            } else {
+                index.retain(|_, _| false)?;
+            }
+            index.refresh();
+        }
+        Ok(items.unwrap_or_default())
+    }
+
+    /// Perform a distance search.
+    fn search(&self, key: &K, distance: u32) -> Result<SearchResult<V>, SearchError>
+    where
+        K: ScanBound,
+        V: ScanBound,
+    {
+        search_indexes(self, key, distance, |_, candidates| candidates.scan(distance))
+    }
+
+    /// Perform a distance search like [`Lookup::search`], even if `distance` exceeds
+    /// [`Lookup::max_search_distance`]. Only items which share at least one block combination with the key are found
+    /// then, so such results are marked as not guaranteeing recall (see [`SearchResult::recall_guaranteed`]).
+    fn search_unchecked_distance(&self, key: &K, distance: u32) -> SearchResult<V>
+    where
+        K: ScanBound,
+        V: ScanBound,
+    {
+        scan_indexes(self, key, distance, |_, candidates| candidates.scan(distance))
+    }
+
+    /// Perform a distance search like [`Lookup::search`], filtering candidates with `metric` instead of
+    /// [`BitContainer::xor_dist`]. `metric` is called with the search key and a candidate key, both in their original
+    /// (non-permuted) form, so it may be asymmetric or ignore some bits.
+    ///
+    /// Candidates are still located by exact matches of blocks, so only items within `distance` bits of the key are
+    /// considered, whatever `metric` returns for the others.
+    fn search_with(
+        &self,
+        key: &K,
+        distance: u32,
+        metric: impl Fn(&K, &K) -> u32 + ScanBound,
+    ) -> Result<SearchResult<V>, SearchError>
+    where
+        K: ScanBound,
+        V: ScanBound,
+    {
+        search_indexes(self, key, distance, |index, candidates| {
+            let permuter = index.permuter();
+            candidates.scan_with(distance, |_, candidate| metric(key, &permuter.revert(candidate)))
         })
     }
 
+    /// Number of candidates a search for `key` would scan across all indexes, without performing the search.
+    fn predict_candidates_scanned(&self, key: &K) -> usize {
+        self.indexes().iter().map(|index| index.predict_block_size(key)).sum()
+    }
+
     fn search_simple(&self, key: &K, distance: u32) -> HashSet<SearchResultItem<V>>
     where
-        V: Hash + Eq,
+        K: ScanBound,
+        V: Hash + Eq + ScanBound,
     {
         self.search(key, distance)
             .expect("distance exceeds max")
@@ -99,6 +272,59 @@ where
             .collect()
     }
 
+    /// Take a snapshot of the statistics of all indexes, including the storage they use.
+    fn stats_snapshot(&self) -> StatsSnapshot {
+        let indexes = self
+            .indexes()
+            .iter()
+            .map(|index| IndexStatsSnapshot::new(index.stats(), &index.storage_stats()))
+            .collect();
+        StatsSnapshot::new(self.max_search_distance(), indexes)
+    }
+
+    /// Write all items of this lookup into the file `path` in the stable [export](crate::export) format, and return
+    /// their number.
+    #[cfg(feature = "fs")]
+    fn export(&self, path: &Path) -> Result<u64, ExportError>
+    where
+        K: KeyBytes,
+        V: ToLeBytes,
+    {
+        // every index holds the same items, so only the first one is exported
+        let index = &self.indexes()[0];
+        let permuter = index.permuter();
+        let n_items = index.iter().count() as u64;
+        let mut writer = ExportWriter::new(BufWriter::new(File::create(path)?), n_items)?;
+        for (key, value) in index.iter() {
+            writer.write_item(&permuter.revert(key), value)?;
+        }
+        writer.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(n_items)
+    }
+
+    /// Insert all items of an [export](crate::export) at `path` into this lookup, and return their number. The export
+    /// may come from a lookup with different parameters.
+    #[cfg(feature = "fs")]
+    fn import(&mut self, path: &Path) -> ImportResult<usize, K, V, M, Self::Index>
+    where
+        K: KeyBytes,
+        V: FromLeBytes,
+    {
+        let items = ExportReader::new(BufReader::new(File::open(path).map_err(ExportError::from)?))?
+            .collect::<Result<Vec<_>, _>>()?;
+        self.insert(&items).map_err(ImportError::Insert)?;
+        Ok(items.len())
+    }
+
+    /// Check integrity of all indexes, and whether they agree with each other.
+    fn validate(&self) -> IndexResult<LookupValidation, K, V, M, Self::Index> {
+        let mut reports = Vec::with_capacity(self.indexes().len());
+        for index in self.indexes() {
+            reports.push(index.validate()?);
+        }
+        Ok(LookupValidation::new(reports))
+    }
+
     fn persist(&self) -> IndexResult<(), K, V, M, Self::Index>
     where
         Self::Index: PersistentIndex<K, M, Error = <Self::Index as Index<K, V, M>>::Error>,
@@ -108,6 +334,132 @@ where
         }
         Ok(())
     }
+
+    /// Insert a stream of items in batches of `batch_size`, persisting the lookup after every `flush_every` batches
+    /// (or only at the end, if it is 0), and once more at the end if anything was inserted since the last flush.
+    /// `progress` is called after every inserted batch and every flush. Returns the final progress.
+    ///
+    /// Items are pulled from `items` only once the previous batch has been inserted, so a producer sending them
+    /// through a bounded channel is slowed down to the pace of the lookup. On error, items of the failed batch may be
+    /// partially inserted, and items inserted since the last flush may not be persisted.
+    fn ingest(
+        &mut self,
+        items: impl IntoIterator<Item = (K, V)>,
+        batch_size: usize,
+        flush_every: usize,
+        mut progress: impl FnMut(&IngestProgress),
+    ) -> IndexResult<IngestProgress, K, V, M, Self::Index>
+    where
+        Self::Index: PersistentIndex<K, M, Error = <Self::Index as Index<K, V, M>>::Error>,
+    {
+        assert!(batch_size > 0, "batch size should be positive");
+        let mut items = items.into_iter();
+        let mut state = IngestProgress::default();
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut unflushed = 0;
+        loop {
+            batch.clear();
+            batch.extend(items.by_ref().take(batch_size));
+            if batch.is_empty() {
+                break;
+            }
+            self.insert(&batch)?;
+            state.items += batch.len();
+            state.batches += 1;
+            unflushed += 1;
+            progress(&state);
+            if unflushed == flush_every {
+                self.persist()?;
+                state.flushes += 1;
+                unflushed = 0;
+                progress(&state);
+            }
+        }
+        if unflushed > 0 {
+            self.persist()?;
+            state.flushes += 1;
+            progress(&state);
+        }
+        Ok(state)
+    }
+
+    /// Pick up changes made by a writer in another process, if this lookup was loaded read-only. Returns whether
+    /// anything has changed.
+    fn reload(&mut self) -> IndexResult<bool, K, V, M, Self::Index>
+    where
+        Self::Index: PersistentIndex<K, M, Error = <Self::Index as Index<K, V, M>>::Error>,
+    {
+        let mut changed = false;
+        for index in self.indexes_mut() {
+            if index.reload()? {
+                index.refresh();
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Whether the writer has changed any index of this read-only lookup in place since it was loaded or last
+    /// reloaded, or is changing it right now. Searches made in the meantime may have seen half-modified indexes, and
+    /// should be repeated after [`Lookup::reload`]. See [`PersistentIndex::is_stale`].
+    fn is_stale(&self) -> bool
+    where
+        Self::Index: PersistentIndex<K, M>,
+    {
+        self.indexes().iter().any(PersistentIndex::is_stale)
+    }
+}
+
+/// Search every index of `lookup` for `key`, scanning candidates of each index with `scan`.
+fn search_indexes<K, V, M, L>(
+    lookup: &L,
+    key: &K,
+    distance: u32,
+    scan: impl Fn(&L::Index, &Candidates<'_, K, V>) -> Vec<SearchResultItem<V>>,
+) -> Result<SearchResult<V>, SearchError>
+where
+    K: BitContainer + Ord,
+    V: Clone,
+    M: Ord,
+    L: Lookup<K, V, M> + ?Sized,
+{
+    let max_distance = lookup.max_search_distance();
+    if distance > max_distance {
+        return Err(SearchError::DistanceExceedsMax {
+            distance,
+            max: max_distance,
+        });
+    }
+    Ok(scan_indexes(lookup, key, distance, scan))
+}
+
+/// Search every index of `lookup` for `key` like [`search_indexes`], without checking the distance.
+fn scan_indexes<K, V, M, L>(
+    lookup: &L,
+    key: &K,
+    distance: u32,
+    scan: impl Fn(&L::Index, &Candidates<'_, K, V>) -> Vec<SearchResultItem<V>>,
+) -> SearchResult<V>
+where
+    K: BitContainer + Ord,
+    V: Clone,
+    M: Ord,
+    L: Lookup<K, V, M> + ?Sized,
+{
+    let mut candidates_scanned = 0usize;
+    let mut result: Vec<Vec<SearchResultItem<V>>> = Vec::with_capacity(lookup.indexes().len());
+    for index in lookup.indexes() {
+        let candidates = index.get_candidates(key);
+        metrics::block_hit(candidates.len());
+        candidates_scanned += candidates.len();
+        result.push(scan(index, &candidates));
+    }
+    metrics::search(candidates_scanned);
+    SearchResult {
+        candidates_scanned,
+        recall_guaranteed: distance <= lookup.max_search_distance(),
+        result,
+    }
 }
 
 pub struct SimpleLookup<K, V, M, I> {
@@ -132,15 +484,15 @@ where
     M: Ord,
     I: Index<K, V, M> + PersistentIndex<K, M>,
 {
+    /// Create a lookup with index files placed according to `layout`; usually, a directory. See [`PathLayout`].
     pub fn create(
         permuters: Vec<DynBitPermuter<K, M>>,
         sig: u64,
-        path: &Path,
+        layout: &(impl PathLayout + ?Sized),
     ) -> Result<Self, <I as PersistentIndex<K, M>>::Error> {
         let mut indexes = Vec::new();
         for (i, p) in permuters.into_iter().enumerate() {
-            let index_path = path.join(format!("index_{i:04}_{sig:016x}.dat"));
-            indexes.push(I::create(p, sig, &index_path)?);
+            indexes.push(I::create(p, sig, &layout.index_path(i, sig))?);
         }
         Ok(Self::new(indexes))
     }
@@ -148,15 +500,132 @@ where
     pub fn load(
         permuters: Vec<DynBitPermuter<K, M>>,
         sig: u64,
-        path: &Path,
+        layout: &(impl PathLayout + ?Sized),
     ) -> Result<Self, <I as PersistentIndex<K, M>>::Error> {
         let mut indexes = Vec::new();
         for (i, p) in permuters.into_iter().enumerate() {
-            let index_path = path.join(format!("index_{i:04}_{sig:016x}.dat"));
-            indexes.push(I::load(p, sig, &index_path)?);
+            indexes.push(I::load(p, sig, &layout.index_path(i, sig))?);
         }
         Ok(Self::new(indexes))
     }
+
+    pub fn load_read_only(
+        permuters: Vec<DynBitPermuter<K, M>>,
+        sig: u64,
+        layout: &(impl PathLayout + ?Sized),
+    ) -> Result<Self, <I as PersistentIndex<K, M>>::Error> {
+        let mut indexes = Vec::new();
+        for (i, p) in permuters.into_iter().enumerate() {
+            indexes.push(I::load_read_only(p, sig, &layout.index_path(i, sig))?);
+        }
+        Ok(Self::new(indexes))
+    }
+
+    /// Close this lookup and remove the files of all its indexes.
+    pub fn destroy(self) -> Result<(), <I as PersistentIndex<K, M>>::Error> {
+        for index in self.indexes {
+            index.destroy()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<K, V, M, I> SimpleLookup<K, V, M, I>
+where
+    K: BitContainer,
+    V: Clone,
+    M: Ord,
+    I: Index<K, V, M> + PersistentIndex<K, M, Error = MmVecError>,
+{
+    /// Persist and close this lookup, and pack its directory `path` into a single container file. See
+    /// [`container::pack`].
+    ///
+    /// The lookup is consumed, because index files are locked while they are open, and locked files can't be read on
+    /// some platforms.
+    pub fn pack(self, path: &Path, container: &Path) -> Result<Vec<ContainerEntry>, ContainerError> {
+        for index in &self.indexes {
+            index.persist()?;
+        }
+        drop(self);
+        container::pack(path, container)
+    }
+
+    /// Unpack a container created by [`SimpleLookup::pack`] into a new directory `path`, and load the lookup from it.
+    pub fn unpack(
+        permuters: Vec<DynBitPermuter<K, M>>,
+        sig: u64,
+        container: &Path,
+        path: &Path,
+    ) -> Result<Self, ContainerError> {
+        container::unpack(container, path)?;
+        Ok(Self::load(permuters, sig, path)?)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<K, V, M> SimpleLookup<K, V, M, MemMapIndex<K, V, M>>
+where
+    (K, V): Pod,
+{
+    /// Load all indexes into memory ahead of searches, so that the first queries after loading the lookup do not stall
+    /// on page faults. See [`MemMapIndex::warmup`].
+    pub fn warmup(&self) {
+        for index in &self.indexes {
+            index.warmup();
+        }
+    }
+
+    /// Insert all records of a raw dump at `path` into every index, and return their number. See
+    /// [`MemMapIndex::import_raw`].
+    ///
+    /// The import is all-or-nothing, like [`Lookup::insert`]: it is staged in every index first, so if the dump can't
+    /// be imported into one of them (e.g. the disk is full), the lookup is left unchanged.
+    pub fn import_raw(&mut self, path: &Path) -> Result<usize, MmVecError>
+    where
+        K: Pod + BitContainer + Ord + ScanBound + FromLeBytes,
+        V: Pod + ScanBound + FromLeBytes,
+        M: Copy + Ord,
+    {
+        let mut imported = 0;
+        for i in 0..self.indexes.len() {
+            match self.indexes[i].stage_import_raw(path) {
+                Ok(n) => imported = n,
+                Err(e) => {
+                    for index in &mut self.indexes[..i] {
+                        index.discard_insert();
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        let mut result = Ok(imported);
+        for index in &mut self.indexes {
+            match index.commit_import_raw() {
+                Ok(()) => index.refresh(),
+                Err(e) => {
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Back up all indexes into a new directory `dir`, which can be loaded like the directory of this lookup. See
+    /// [`backup`].
+    ///
+    /// The lookup can't be modified while it is being backed up, so the copies are consistent with each other. They
+    /// are made from the mapped contents, so the lookup does not need to be persisted or closed first.
+    pub fn backup_to(&self, dir: &Path) -> Result<Vec<BackupEntry>, ContainerError> {
+        backup::create(dir, |tmp_dir| {
+            for (i, index) in self.indexes.iter().enumerate() {
+                index.backup_to(&tmp_dir.join(index_file_name(i, index.sig())))?;
+            }
+            Ok(())
+        })
+    }
 }
 
 impl<K, V, M, I> Lookup<K, V, M> for SimpleLookup<K, V, M, I>
@@ -0,0 +1,85 @@
+//! Serialization of in-memory lookups with `serde`, enabled by the `serde` feature.
+//!
+//! Lookups are stored as the parameters of their permutations, `[f, r, k, w]`, along with the permuted and sorted
+//! items of every index, so deserializing them does not permute or sort anything. Permuters are recreated from the
+//! key type, which has to match the parameters.
+
+use hloo_core::PermutedKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+use crate::{
+    SimpleLookup,
+    index::{MemIndex, ScanBound},
+};
+
+#[derive(Serialize)]
+struct LookupRef<'a, K, V> {
+    params: [usize; 4],
+    indexes: Vec<IndexRef<'a, K, V>>,
+}
+
+#[derive(Serialize)]
+struct IndexRef<'a, K, V> {
+    items: &'a [(K, V)],
+}
+
+#[derive(Deserialize)]
+struct LookupData<K, V> {
+    params: [usize; 4],
+    indexes: Vec<IndexData<K, V>>,
+}
+
+#[derive(Deserialize)]
+struct IndexData<K, V> {
+    items: Vec<(K, V)>,
+}
+
+impl<K, V> Serialize for SimpleLookup<K, V, K::Mask, MemIndex<K, V, K::Mask>>
+where
+    K: PermutedKey + Serialize,
+    V: Copy + ScanBound + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LookupRef {
+            params: K::PARAMS,
+            indexes: self
+                .indexes
+                .iter()
+                .map(|index| IndexRef { items: index.items() })
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for SimpleLookup<K, V, K::Mask, MemIndex<K, V, K::Mask>>
+where
+    K: PermutedKey + Deserialize<'de>,
+    V: Copy + ScanBound + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = LookupData::<K, V>::deserialize(deserializer)?;
+        if data.params != K::PARAMS {
+            return Err(D::Error::custom(format!(
+                "lookup was serialized with parameters {:?}, but keys are permuted with {:?}",
+                data.params,
+                K::PARAMS
+            )));
+        }
+        let permuters = K::permuters();
+        if data.indexes.len() != permuters.len() {
+            return Err(D::Error::invalid_length(
+                data.indexes.len(),
+                &format!("{} indexes", permuters.len()).as_str(),
+            ));
+        }
+        let mut indexes = Vec::with_capacity(permuters.len());
+        for (i, (permuter, data)) in permuters.into_iter().zip(data.indexes).enumerate() {
+            if !data.items.is_sorted_by_key(|(k, _)| *k) {
+                return Err(D::Error::custom(format!("items of index {i} are not sorted")));
+            }
+            indexes.push(MemIndex::with_data(permuter, data.items));
+        }
+        Ok(Self::new(indexes))
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::index::{IndexStats, StorageStats};
+
+/// Statistics of a lookup and its indexes, flattened to plain numbers to be exported, e.g. as Prometheus gauges or,
+/// with the `serde` feature, as JSON. See [`Lookup::stats_snapshot`](super::Lookup::stats_snapshot).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsSnapshot {
+    /// Number of items in the lookup. Every index holds all of them.
+    pub n_items: usize,
+    pub max_search_distance: u32,
+    /// Total size of the files of all indexes in bytes.
+    pub bytes_on_disk: u64,
+    /// Milliseconds since the Unix epoch of the oldest of the last flushes of the indexes, i.e. a time by which all
+    /// indexes were written to disk. `None` if any of the indexes was never flushed, or is kept in memory.
+    pub last_flush_unix_ms: Option<u64>,
+    pub indexes: Vec<IndexStatsSnapshot>,
+}
+
+/// Statistics of an index, see [`StatsSnapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexStatsSnapshot {
+    pub n_items: usize,
+    pub n_blocks: usize,
+    pub min_block_size: usize,
+    pub avg_block_size: usize,
+    pub max_block_size: usize,
+    pub bytes_on_disk: u64,
+    /// Milliseconds since the Unix epoch of the last flush, if any.
+    pub last_flush_unix_ms: Option<u64>,
+}
+
+impl IndexStatsSnapshot {
+    pub fn new(stats: &IndexStats, storage: &StorageStats) -> Self {
+        Self {
+            n_items: stats.n_items,
+            n_blocks: stats.n_blocks,
+            min_block_size: stats.min_block_size,
+            avg_block_size: stats.avg_block_size,
+            max_block_size: stats.max_block_size,
+            bytes_on_disk: storage.bytes_on_disk,
+            last_flush_unix_ms: storage.last_flush.map(unix_ms),
+        }
+    }
+}
+
+impl StatsSnapshot {
+    pub fn new(max_search_distance: u32, indexes: Vec<IndexStatsSnapshot>) -> Self {
+        let last_flush_unix_ms = indexes
+            .iter()
+            .map(|index| index.last_flush_unix_ms)
+            .collect::<Option<Vec<_>>>()
+            .and_then(|flushes| flushes.into_iter().min());
+        Self {
+            n_items: indexes.first().map_or(0, |index| index.n_items),
+            max_search_distance,
+            bytes_on_disk: indexes.iter().map(|index| index.bytes_on_disk).sum(),
+            last_flush_unix_ms,
+            indexes,
+        }
+    }
+}
+
+fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
@@ -0,0 +1,357 @@
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+use std::{collections::HashMap, marker::PhantomData, ops::Range};
+
+use hloo_core::BitContainer;
+use thiserror::Error;
+
+use crate::index::{Index, ScanBound, SearchResultItem};
+#[cfg(feature = "fs")]
+use crate::{
+    index::PersistentIndex,
+    mmvec::{MmVec, MmVecError},
+};
+
+use super::{Lookup, SearchError, SearchResult};
+
+/// Name of the file holding the strings of a [`StringLookup`], in the directory of its lookup.
+#[cfg(feature = "fs")]
+pub const STRINGS_FILE_NAME: &str = "strings.dat";
+
+/// Signature of string files.
+#[cfg(feature = "fs")]
+const STRINGS_SIG: u64 = u64::from_le_bytes(*b"hloostr1");
+
+/// Size of the length prefix of a string record.
+const LEN_SIZE: usize = size_of::<u32>();
+
+#[derive(Debug, Error)]
+pub enum StringLookupError<E> {
+    #[error("index error: {0:?}")]
+    Index(E),
+    #[cfg(feature = "fs")]
+    #[error("storage error: {0}")]
+    Storage(#[from] MmVecError),
+    #[error(transparent)]
+    Search(#[from] SearchError),
+    #[error("too many strings, ids are 32-bit")]
+    TooManyStrings,
+    #[error("string record at {0} is invalid")]
+    InvalidRecord(usize),
+    #[error("no string with id {0}")]
+    UnknownId(u32),
+}
+
+/// Result of operations on a [`StringLookup`] wrapping the lookup `L`.
+pub type StringLookupResult<T, K, M, L> =
+    Result<T, StringLookupError<<<L as Lookup<K, u32, M>>::Index as Index<K, u32, M>>::Error>>;
+
+/// Bytes of string records, each a little-endian `u32` length followed by UTF-8 bytes.
+enum Records {
+    Mem(Vec<u8>),
+    #[cfg(feature = "fs")]
+    File(MmVec<u8>),
+}
+
+impl Records {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Mem(bytes) => bytes,
+            #[cfg(feature = "fs")]
+            Self::File(bytes) => bytes.as_slice(),
+        }
+    }
+
+    fn append<E>(&mut self, record: &[u8]) -> Result<(), StringLookupError<E>> {
+        match self {
+            Self::Mem(bytes) => bytes.extend_from_slice(record),
+            #[cfg(feature = "fs")]
+            Self::File(bytes) => {
+                if bytes.is_read_only() {
+                    return Err(MmVecError::ReadOnly {}.into());
+                }
+                let start = bytes.len();
+                bytes.resize_zeroed(start + record.len())?;
+                bytes.slice_mut(start..start + record.len()).copy_from_slice(record);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Append-only table of distinct strings, identified by their position.
+struct StringTable {
+    records: Records,
+    spans: Vec<Range<usize>>,
+    ids: HashMap<Box<str>, u32>,
+}
+
+impl StringTable {
+    fn new(records: Records) -> Self {
+        Self {
+            records,
+            spans: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Index the records which were appended to the data since the last call, e.g. by another process.
+    #[cfg(feature = "fs")]
+    fn index_new_records<E>(&mut self) -> Result<bool, StringLookupError<E>> {
+        let data = self.records.as_slice();
+        let mut pos = self.spans.last().map_or(0, |span| span.end);
+        let n_strings = self.spans.len();
+        while pos < data.len() {
+            let invalid = || StringLookupError::InvalidRecord(pos);
+            let len = data.get(pos..pos + LEN_SIZE).ok_or_else(invalid)?;
+            let len = u32::from_le_bytes(len.try_into().expect("length prefix is 4 bytes")) as usize;
+            let span = pos + LEN_SIZE..pos + LEN_SIZE + len;
+            let string = data.get(span.clone()).ok_or_else(invalid)?;
+            let string = std::str::from_utf8(string).map_err(|_| invalid())?;
+            let id = u32::try_from(self.spans.len()).map_err(|_| StringLookupError::TooManyStrings)?;
+            self.ids.entry(string.into()).or_insert(id);
+            pos = span.end;
+            self.spans.push(span);
+        }
+        Ok(self.spans.len() > n_strings)
+    }
+
+    fn get(&self, id: u32) -> Option<&str> {
+        let span = self.spans.get(id as usize)?;
+        // records were validated when they were indexed, and are never modified
+        std::str::from_utf8(&self.records.as_slice()[span.clone()]).ok()
+    }
+
+    fn intern<E>(&mut self, string: &str) -> Result<u32, StringLookupError<E>> {
+        if let Some(id) = self.ids.get(string) {
+            return Ok(*id);
+        }
+        let id = u32::try_from(self.spans.len()).map_err(|_| StringLookupError::TooManyStrings)?;
+        let len = u32::try_from(string.len()).map_err(|_| StringLookupError::TooManyStrings)?;
+        let start = self.records.as_slice().len();
+        let mut record = Vec::with_capacity(LEN_SIZE + string.len());
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(string.as_bytes());
+        self.records.append(&record)?;
+        self.spans.push(start + LEN_SIZE..start + record.len());
+        self.ids.insert(string.into(), id);
+        Ok(id)
+    }
+}
+
+/// Lookup of string values. Strings are interned: the wrapped lookup stores their `u32` ids, and a table of distinct
+/// strings maps the ids back.
+///
+/// With the `fs` feature, the table can be stored in a file next to the indexes of a persistent lookup (see
+/// [`STRINGS_FILE_NAME`]). Strings are only ever appended to the table, so strings of removed items stay in it.
+pub struct StringLookup<K, M, L> {
+    lookup: L,
+    strings: StringTable,
+    _marker: PhantomData<(K, M)>,
+}
+
+impl<K, M, L> StringLookup<K, M, L>
+where
+    K: BitContainer + Ord + ScanBound,
+    M: Ord,
+    L: Lookup<K, u32, M>,
+{
+    /// Wrap an empty lookup, keeping the strings in memory.
+    pub fn new(lookup: L) -> Self {
+        Self {
+            lookup,
+            strings: StringTable::new(Records::Mem(Vec::new())),
+            _marker: PhantomData,
+        }
+    }
+
+    /// The wrapped lookup, holding ids of strings.
+    pub fn lookup(&self) -> &L {
+        &self.lookup
+    }
+
+    /// Number of distinct strings ever inserted.
+    pub fn n_strings(&self) -> usize {
+        self.strings.spans.len()
+    }
+
+    /// Get the string with the given id.
+    pub fn string(&self, id: u32) -> Option<&str> {
+        self.strings.get(id)
+    }
+
+    /// Get the id of the string, if it was ever inserted.
+    pub fn string_id(&self, string: &str) -> Option<u32> {
+        self.strings.ids.get(string).copied()
+    }
+
+    /// Insert items, interning their strings.
+    pub fn insert<S: AsRef<str>>(&mut self, items: &[(K, S)]) -> StringLookupResult<(), K, M, L>
+    where
+        K: Copy,
+    {
+        let items = items
+            .iter()
+            .map(|(key, string)| Ok((*key, self.strings.intern(string.as_ref())?)))
+            .collect::<Result<Vec<_>, StringLookupError<_>>>()?;
+        self.lookup.insert(&items).map_err(StringLookupError::Index)
+    }
+
+    /// Remove items by keys. Their strings stay in the table.
+    pub fn remove(&mut self, keys: &[K]) -> StringLookupResult<(), K, M, L> {
+        self.lookup.remove(keys).map_err(StringLookupError::Index)
+    }
+
+    /// Perform a distance search, resolving ids of found items to their strings.
+    pub fn search(&self, key: &K, distance: u32) -> StringLookupResult<SearchResult<&str>, K, M, L> {
+        let found = self.lookup.search(key, distance)?;
+        let result = found
+            .result
+            .into_iter()
+            .map(|items| {
+                items
+                    .into_iter()
+                    .map(|item| {
+                        let id = *item.data();
+                        let string = self.strings.get(id).ok_or(StringLookupError::UnknownId(id));
+                        string.map(|string| SearchResultItem::new(string, item.distance()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(SearchResult {
+            candidates_scanned: found.candidates_scanned,
+            recall_guaranteed: found.recall_guaranteed,
+            result,
+        })
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<K, M, L> StringLookup<K, M, L>
+where
+    K: BitContainer + Ord + ScanBound,
+    M: Ord,
+    L: Lookup<K, u32, M>,
+    L::Index: PersistentIndex<K, M, Error = <L::Index as Index<K, u32, M>>::Error>,
+{
+    fn with_records(lookup: L, records: MmVec<u8>) -> StringLookupResult<Self, K, M, L> {
+        let mut strings = StringTable::new(Records::File(records));
+        strings.index_new_records()?;
+        Ok(Self {
+            lookup,
+            strings,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Path of the string file of the lookup in the directory `dir`.
+    pub fn strings_path(dir: &Path) -> PathBuf {
+        dir.join(STRINGS_FILE_NAME)
+    }
+
+    /// Wrap an empty lookup, creating its string file in the directory `dir`.
+    pub fn create(lookup: L, dir: &Path) -> StringLookupResult<Self, K, M, L> {
+        Self::with_records(lookup, MmVec::new_empty(STRINGS_SIG, Self::strings_path(dir))?)
+    }
+
+    /// Wrap a lookup loaded from the directory `dir`, loading its string file.
+    pub fn load(lookup: L, dir: &Path) -> StringLookupResult<Self, K, M, L> {
+        Self::with_records(lookup, MmVec::from_path(STRINGS_SIG, Self::strings_path(dir))?)
+    }
+
+    /// Wrap a lookup loaded read-only from the directory `dir`, loading its string file read-only.
+    pub fn load_read_only(lookup: L, dir: &Path) -> StringLookupResult<Self, K, M, L> {
+        Self::with_records(lookup, MmVec::open_read_only(STRINGS_SIG, Self::strings_path(dir))?)
+    }
+
+    /// Persist the strings, and then the lookup, so that all ids in the persisted lookup can be resolved.
+    pub fn persist(&self) -> StringLookupResult<(), K, M, L> {
+        if let Records::File(records) = &self.strings.records {
+            records.flush()?;
+        }
+        self.lookup.persist().map_err(StringLookupError::Index)
+    }
+
+    /// Pick up changes made by a writer in another process, if this lookup was loaded read-only. Returns whether
+    /// anything has changed.
+    pub fn reload(&mut self) -> StringLookupResult<bool, K, M, L> {
+        let lookup_changed = self.lookup.reload().map_err(StringLookupError::Index)?;
+        // strings are reloaded after the lookup, so that they include the strings of all reloaded items
+        if let Records::File(records) = &mut self.strings.records {
+            records.refresh()?;
+        }
+        let strings_changed = self.strings.index_new_records()?;
+        Ok(lookup_changed || strings_changed)
+    }
+
+    /// Whether searches made since the lookup was loaded or last reloaded should be repeated after
+    /// [`StringLookup::reload`]. See [`Lookup::is_stale`]. Strings are only appended, so they are never stale.
+    pub fn is_stale(&self) -> bool {
+        self.lookup.is_stale()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    #[cfg(feature = "fs")]
+    use crate::lookup::lookup_impl::lookup64::MemMapLookup;
+    use crate::lookup::lookup_impl::lookup64::{Bits, MemLookup};
+
+    use super::*;
+
+    #[test]
+    fn string_lookup_interns_values() {
+        let mut lookup = StringLookup::new(MemLookup::default());
+        let (a, b) = (Bits::from(0u64), Bits::from(1u64));
+        lookup
+            .insert(&[(a, "https://example.com/a"), (b, "https://example.com/b")])
+            .unwrap();
+        lookup
+            .insert(&[(Bits::from(u64::MAX), "https://example.com/a")])
+            .unwrap();
+        assert_eq!(lookup.n_strings(), 2, "strings are interned");
+        let found: HashSet<_> = lookup
+            .search(&a, 1)
+            .unwrap()
+            .flat_iter()
+            .map(|item| *item.data())
+            .collect();
+        assert_eq!(found, HashSet::from(["https://example.com/a", "https://example.com/b"]));
+        assert_eq!(
+            lookup.string(lookup.string_id("https://example.com/b").unwrap()),
+            Some("https://example.com/b")
+        );
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn string_lookup_is_persisted_with_strings() {
+        let tempdir = tempfile::tempdir().expect("failed to create temp dir");
+        let dir = tempdir.path();
+        let key = Bits::from(42u64);
+        let mut writer = StringLookup::create(MemMapLookup::create(dir).unwrap(), dir).unwrap();
+        writer.insert(&[(key, "first".to_string())]).unwrap();
+        writer.persist().unwrap();
+
+        let mut reader = StringLookup::load_read_only(MemMapLookup::load_read_only(dir).unwrap(), dir).unwrap();
+        let strings = |lookup: &StringLookup<_, _, MemMapLookup<u32>>| -> HashSet<String> {
+            let found = lookup.search(&key, 0).unwrap();
+            found.flat_iter().map(|item| item.data().to_string()).collect()
+        };
+        assert_eq!(strings(&reader), HashSet::from(["first".to_string()]));
+        writer.insert(&[(key, "second".to_string())]).unwrap();
+        writer.persist().unwrap();
+        assert!(reader.reload().unwrap(), "writer has changed the lookup");
+        assert_eq!(
+            strings(&reader),
+            HashSet::from(["first".to_string(), "second".to_string()])
+        );
+        assert!(matches!(
+            reader.insert(&[(key, "third")]),
+            Err(StringLookupError::Storage(MmVecError::ReadOnly {}))
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+//! Metrics recorded with the [`metrics`](https://docs.rs/metrics) crate when the `metrics` feature is enabled, and
+//! exported by whichever recorder the application installs. Without the feature, nothing is recorded.
+
+/// Counter of searches, including searches with a custom metric.
+pub const SEARCHES: &str = "hloo_searches";
+/// Histogram of the number of candidates scanned by a search across all indexes.
+pub const CANDIDATES_SCANNED: &str = "hloo_search_candidates_scanned";
+/// Histogram of the sizes of blocks hit by searches, one sample per index.
+pub const BLOCK_SIZE: &str = "hloo_search_block_size";
+/// Histogram of the number of items inserted into an index at once.
+pub const INSERT_BATCH_SIZE: &str = "hloo_insert_batch_size";
+/// Histogram of the durations of flushes of memory-mapped vectors, in seconds.
+pub const FLUSH_DURATION: &str = "hloo_flush_duration_seconds";
+
+/// Describe all metrics to the installed recorder.
+#[cfg(feature = "metrics")]
+pub fn describe() {
+    use ::metrics::{Unit, describe_counter, describe_histogram};
+
+    describe_counter!(SEARCHES, Unit::Count, "number of searches");
+    describe_histogram!(CANDIDATES_SCANNED, Unit::Count, "candidates scanned by a search");
+    describe_histogram!(BLOCK_SIZE, Unit::Count, "sizes of blocks hit by searches");
+    describe_histogram!(INSERT_BATCH_SIZE, Unit::Count, "items inserted into an index at once");
+    describe_histogram!(
+        FLUSH_DURATION,
+        Unit::Seconds,
+        "durations of flushes of memory-mapped vectors"
+    );
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) use record::*;
+
+#[cfg(feature = "metrics")]
+mod record {
+    #[cfg(feature = "fs")]
+    use std::time::Duration;
+
+    use ::metrics::{counter, histogram};
+
+    use super::*;
+
+    pub(crate) fn search(candidates_scanned: usize) {
+        counter!(SEARCHES).increment(1);
+        histogram!(CANDIDATES_SCANNED).record(candidates_scanned as f64);
+    }
+
+    pub(crate) fn block_hit(size: usize) {
+        histogram!(BLOCK_SIZE).record(size as f64);
+    }
+
+    pub(crate) fn insert(batch_size: usize) {
+        histogram!(INSERT_BATCH_SIZE).record(batch_size as f64);
+    }
+
+    #[cfg(feature = "fs")]
+    pub(crate) fn flush(duration: Duration) {
+        histogram!(FLUSH_DURATION).record(duration);
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) use noop::*;
+
+#[cfg(not(feature = "metrics"))]
+mod noop {
+    #[cfg(feature = "fs")]
+    use std::time::Duration;
+
+    pub(crate) fn search(_: usize) {}
+
+    pub(crate) fn block_hit(_: usize) {}
+
+    pub(crate) fn insert(_: usize) {}
+
+    #[cfg(feature = "fs")]
+    pub(crate) fn flush(_: Duration) {}
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    use super::*;
+    use crate::{
+        Lookup,
+        lookup::lookup_impl::lookup64::{Bits, MemLookup},
+    };
+
+    /// Run `f` with a local recorder, and collect the recorded values by name.
+    fn record(f: impl FnOnce()) -> Vec<(String, DebugValue)> {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        ::metrics::with_local_recorder(&recorder, f);
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key.key().name().to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn searches_and_inserts_are_recorded() {
+        let values = record(|| {
+            let mut lookup = MemLookup::default();
+            lookup.insert(&[(Bits::new([1]), 1), (Bits::new([2]), 2)]).unwrap();
+            lookup.search(&Bits::new([1]), 1).unwrap();
+        });
+        let value = |name: &str| values.iter().find(|(n, _)| n == name).map(|(_, value)| value);
+        assert_eq!(value(SEARCHES), Some(&DebugValue::Counter(1)));
+        let Some(DebugValue::Histogram(block_sizes)) = value(BLOCK_SIZE) else {
+            panic!("block sizes are not recorded");
+        };
+        // every index is hit once
+        assert_eq!(block_sizes.len(), 4);
+        assert!(block_sizes.iter().all(|size| size.0 >= 1.0));
+        let Some(DebugValue::Histogram(batch_sizes)) = value(INSERT_BATCH_SIZE) else {
+            panic!("insert batch sizes are not recorded");
+        };
+        assert_eq!(batch_sizes.iter().map(|size| size.0).collect::<Vec<_>>(), [2.0; 4]);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn flushes_are_recorded() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let values = record(|| {
+            let mut lookup = crate::lookup::lookup_impl::lookup64::MemMapLookup::create(tmp_dir.path()).unwrap();
+            lookup.insert(&[(Bits::new([1]), 1)]).unwrap();
+            lookup.persist().unwrap();
+        });
+        assert!(
+            values
+                .iter()
+                .any(|(name, value)| name == FLUSH_DURATION && matches!(value, DebugValue::Histogram(_))),
+            "flushes are not recorded"
+        );
+    }
+}
@@ -2,78 +2,750 @@
 
 use core::slice;
 use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
     fs::{File, OpenOptions, copy, remove_file, rename},
-    io,
+    io::{self, Read, Write},
     marker::PhantomData,
-    mem::size_of,
+    mem::{ManuallyDrop, size_of},
+    ops::Range,
     path::{Path, PathBuf},
+    ptr,
+    sync::{
+        Arc, Condvar, Mutex, PoisonError,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use fs4::fs_std::FileExt;
-use memmap2::{MmapMut, MmapOptions};
+pub use hloo_core::Pod;
+use memmap2::{MmapOptions, MmapRaw};
 use thiserror::Error;
 
-use crate::util::partition;
+use crate::{
+    index::ScanBound,
+    metrics,
+    util::{describe_signature_mismatch, fnv1a, fnv1a_bytes, merge_ranks_by_key, partition, sort_unstable_by_key},
+};
 
 #[derive(Debug, Error)]
 pub enum MmVecError {
-    #[error("signature does not match: expected: {expected}, got: {actual} ")]
+    #[error("signature does not match: {}", describe_signature_mismatch(*expected, *actual))]
     SignatureMismatch { expected: u64, actual: u64 },
-    #[error("loading vectors which are not fully initialized or have trailing data in the file is not supported!")]
-    UninitializedVectorLoad {},
+    #[error("{} is corrupted: {corruption}", path.display())]
+    Corrupted { path: PathBuf, corruption: Corruption },
+    /// The file is in use by another process, e.g. one which is still shutting down. See [`LockMode`] for when files
+    /// conflict; retrying once the other process is done succeeds.
+    #[error("{} is in use by another process", path.display())]
+    Locked { path: PathBuf },
+    #[error("vector is opened read-only")]
+    ReadOnly {},
+    #[error("metadata is too long: {len} bytes, at most {MAX_METADATA_LEN} are supported")]
+    MetadataTooLong { len: usize },
+    #[error("size of the record dump ({size} bytes) is not a multiple of the record size ({record_size} bytes)")]
+    InvalidRecordDump { size: u64, record_size: usize },
+    #[error("vector would hold {len} items, but at most {max_len} are allowed")]
+    TooLarge { len: u64, max_len: u64 },
+    /// The file is in a format which this version does not read. Version 0 is the baseline format, whose header only
+    /// holds the signature and the length; such files can be converted with [`MmVec::migrate_baseline_format`].
+    #[error("{} is in format version {version}, but only version {FORMAT_VERSION} is supported", path.display())]
+    UnsupportedFormat { path: PathBuf, version: u64 },
     #[error("i/o error: {0}")]
     IoError(#[from] std::io::Error),
 }
 
+/// Problem found in a file which does not hold a valid vector, see [`MmVecError::Corrupted`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Corruption {
+    /// The file is too short to hold the header.
+    #[error("file is {file_size} bytes long, but the header takes {HEADER_SIZE} bytes")]
+    TruncatedHeader { file_size: u64 },
+    /// The header does not have the magic of any known format.
+    #[error("header has an unknown format magic {magic:#018x}")]
+    UnknownFormat { magic: u64 },
+    /// The header claims more items than its capacity.
+    #[error("header claims {len} items, but a capacity of {capacity}")]
+    LengthExceedsCapacity { len: u64, capacity: u64 },
+    /// The size of the file does not match the capacity in the header, e.g. because the file was truncated, or has
+    /// trailing data.
+    #[error("header claims {capacity} items of capacity ({expected_size} bytes), but the file is {actual_size} bytes")]
+    SizeMismatch {
+        capacity: u64,
+        expected_size: u64,
+        actual_size: u64,
+    },
+    /// Items are not sorted, starting from the one at `position`. Only checked by users of vectors which keep them
+    /// sorted, e.g. [`MemMapIndex::load_checked`](crate::index::MemMapIndex::load_checked).
+    #[error("item {position} is out of order")]
+    Unsorted { position: usize },
+    /// The journal of an interrupted insert does not match the vector, e.g. because the file was replaced meanwhile.
+    #[error("header claims {len} items, but the interrupted insert started with {journal_len}")]
+    JournalMismatch { len: u64, journal_len: u64 },
+}
+
+impl MmVecError {
+    pub(crate) fn corrupted(path: &Path, corruption: Corruption) -> Self {
+        Self::Corrupted {
+            path: path.to_path_buf(),
+            corruption,
+        }
+    }
+
+    /// Report an error which occurred while accessing the file at `path` as [`MmVecError::Locked`] if it was caused by
+    /// another process using the file, and as [`MmVecError::IoError`] otherwise.
+    pub(crate) fn accessing(path: &Path, err: io::Error) -> Self {
+        if is_in_use(&err) {
+            Self::Locked {
+                path: path.to_path_buf(),
+            }
+        } else {
+            Self::IoError(err)
+        }
+    }
+}
+
+/// Expected access pattern of a memory-mapped region, used as a hint for the OS.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPattern {
+    /// Items are accessed in random order, readahead is disabled. Best suited for searches.
+    #[default]
+    Random,
+    /// Items are accessed sequentially, aggressive readahead is enabled. Best suited for full scans.
+    Sequential,
+    /// Items are going to be accessed soon, and should be paged in ahead of time.
+    WillNeed,
+}
+
+/// How a vector opened for writing locks its backing file. Vectors opened with [`MmVec::open_read_only`] never lock.
+///
+/// Opening a file which is locked in a conflicting mode fails with [`MmVecError::Locked`], rather than waiting for the
+/// lock. Locks are released when the vector is dropped, or when the process exits.
+///
+/// On Windows, the same rules apply, with some differences:
+/// - files are opened with all share modes, so it is only the lock which keeps other processes out;
+/// - locks are mandatory: while a vector is locked, other processes can't read the file with regular reads, as
+///   [`FileVec::open_read_only`](crate::filevec::FileVec::open_read_only) does, and fail with [`MmVecError::Locked`].
+///   Memory-mapped readers are not affected;
+/// - the system releases the locks of a process some time after it exits, so a process which replaces another one
+///   (e.g. during a rolling restart) may have to retry;
+/// - a file can't be replaced while it is mapped by another process, e.g. a reader. Operations which rewrite the
+///   vector then fail with [`MmVecError::Locked`] and leave it unchanged.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Only one process can open the vector for writing.
+    #[default]
+    Exclusive,
+    /// Any number of processes can hold a shared lock at the same time, but not while another process holds an
+    /// exclusive one.
+    Shared,
+    /// The file is not locked. Intended for filesystems on which locking fails spuriously (e.g. some network
+    /// filesystems); the caller is responsible for making sure that no other process writes to the file concurrently.
+    None,
+}
+
+/// Bookkeeping information stored in the header, see [`MmVec::info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmVecInfo {
+    /// When the vector was created.
+    pub created: SystemTime,
+    /// When the vector was last written to disk, if ever.
+    pub last_flush: Option<SystemTime>,
+    /// Number of modifications made since the vector was created. Each call of a modifying method counts once.
+    pub updates: u64,
+    /// See [`MmVec::generation`].
+    pub generation: u64,
+}
+
+/// Options for creating or opening a vector, see [`MmVec::from_path_with`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MmVecOptions {
+    pub lock: LockMode,
+    /// Maximum number of items. Growing the vector beyond it fails with [`MmVecError::TooLarge`], like growing it
+    /// beyond what can be mapped on this platform does anyway. See [`MmVec::set_max_len`].
+    pub max_len: Option<usize>,
+}
+
+/// When modified data is written back to the backing file.
+///
+/// Inserts ([`MmVec::insert_sorted`]) and operations which replace the backing file (e.g. [`MmVec::retain`]) always
+/// write their changes to disk before returning, regardless of the policy. The policy only concerns other in-place
+/// modifications, i.e. writes through [`MmVec::as_slice_mut`] and growing the vector.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Flush only when the vector is dropped, or when [`MmVec::flush`] is called.
+    #[default]
+    OnDrop,
+    /// Flush if `period` has passed since the last flush. With `background` set, a separate thread does it;
+    /// otherwise it is done on the next in-place modification.
+    Interval { period: Duration, background: bool },
+    /// Flush on the next in-place modification once at least this many bytes were modified since the last flush.
+    DirtyBytes(usize),
+}
+
 pub struct MmVec<T>
 where
-    T: Copy,
+    T: Pod,
 {
     data: Option<Data<T>>,
     path: PathBuf,
+    access_pattern: AccessPattern,
+    huge_pages: bool,
+    flush_policy: FlushPolicy,
+    dirty_bytes: AtomicUsize,
+    last_flush: Mutex<Instant>,
+    flusher: Option<BackgroundFlusher>,
+    /// Shared with all snapshots of the current backing file.
+    snapshots: Arc<()>,
+    lock: LockMode,
+    max_len: Option<usize>,
+    /// Merge prepared by [`MmVec::stage_merge`], not applied yet.
+    staged: Option<Box<Journal<T>>>,
 }
 
 impl<T> MmVec<T>
 where
-    T: Copy,
+    T: Pod,
 {
     fn new(data: Data<T>, path: PathBuf) -> Self {
-        Self { data: Some(data), path }
+        Self {
+            data: Some(data),
+            path,
+            access_pattern: AccessPattern::default(),
+            huge_pages: false,
+            flush_policy: FlushPolicy::default(),
+            dirty_bytes: AtomicUsize::new(0),
+            last_flush: Mutex::new(Instant::now()),
+            flusher: None,
+            snapshots: Arc::new(()),
+            lock: LockMode::default(),
+            max_len: None,
+            staged: None,
+        }
     }
 
-    /// Creates an uninitialized vector with given length.
-    fn with_length_uninit(sig: u64, len: usize, path: PathBuf) -> Result<Self, MmVecError> {
-        let data = Data::new_uninit(&path, sig, len)?;
-        Ok(Self::new(data, path))
+    /// Unmap the data, making sure the background flusher does not use it anymore.
+    fn take_data(&mut self) -> Option<Data<T>> {
+        if let Some(flusher) = self.flusher.as_ref() {
+            flusher.set_mappings(None);
+        }
+        self.data.take()
+    }
+
+    /// Let the background flusher know about the current mappings.
+    fn track_mappings(&self) {
+        if let Some(flusher) = self.flusher.as_ref() {
+            flusher.set_mappings(self.data.as_ref().and_then(Data::writable_mappings));
+        }
+    }
+
+    /// Replace the mapped data, keeping the access pattern and huge page setting of this vector.
+    fn replace_data(&mut self, data: Data<T>) {
+        data.advise(self.access_pattern);
+        if self.huge_pages {
+            data.set_huge_pages(true).ok();
+        }
+        self.data = Some(data);
+        self.track_mappings();
+    }
+
+    fn with_options(data: Data<T>, path: PathBuf, options: MmVecOptions) -> Self {
+        let mut vec = Self::new(data, path);
+        vec.lock = options.lock;
+        vec.max_len = options.max_len;
+        vec
     }
 
     /// Creates a new, empty vector.
     pub fn new_empty(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
-        Self::with_length_uninit(sig, 0, path)
+        Self::new_empty_with(sig, path, MmVecOptions::default())
+    }
+
+    /// Same as [`MmVec::new_empty`], with the given options.
+    pub fn new_empty_with(sig: u64, path: PathBuf, options: MmVecOptions) -> Result<Self, MmVecError> {
+        let data =
+            Data::new_uninit_locked(&path, sig, 0, options.lock).map_err(|err| MmVecError::accessing(&path, err))?;
+        Ok(Self::with_options(data, path, options))
     }
 
     /// Dumps a slice into path, then mmaps it.
     pub fn from_slice(sig: u64, slice: &[T], path: PathBuf) -> Result<Self, MmVecError> {
-        let data = Data::new_with_data(&path, sig, slice)?;
-        Ok(Self::new(data, path))
+        Self::from_slice_with(sig, slice, path, MmVecOptions::default())
+    }
+
+    /// Same as [`MmVec::from_slice`], with the given options.
+    pub fn from_slice_with(sig: u64, slice: &[T], path: PathBuf, options: MmVecOptions) -> Result<Self, MmVecError> {
+        checked_len::<T>(slice.len() as u64, options.max_len)?;
+        let data =
+            Data::new_with_data(&path, sig, slice, options.lock).map_err(|err| MmVecError::accessing(&path, err))?;
+        Ok(Self::with_options(data, path, options))
+    }
+
+    /// Build a sorted vector from items which do not fit in memory, with an external merge sort.
+    ///
+    /// Items are read in runs of `run_len`; each run is sorted in memory and written to a temporary file next to
+    /// `path`, and then all runs are merged in a single pass. At most `run_len` items are held in memory at a time. The
+    /// result is written to a temporary file which is renamed to `path` once complete.
+    pub fn from_iter_external_sorted<I, O, F>(
+        sig: u64,
+        items: I,
+        run_len: usize,
+        sort_key: F,
+        path: PathBuf,
+    ) -> Result<Self, MmVecError>
+    where
+        I: IntoIterator<Item = T>,
+        T: ScanBound,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+    {
+        Self::from_iter_external_sorted_with(sig, items, run_len, sort_key, path, MmVecOptions::default())
+    }
+
+    /// Same as [`MmVec::from_iter_external_sorted`], with the given options.
+    pub fn from_iter_external_sorted_with<I, O, F>(
+        sig: u64,
+        items: I,
+        run_len: usize,
+        sort_key: F,
+        path: PathBuf,
+        options: MmVecOptions,
+    ) -> Result<Self, MmVecError>
+    where
+        I: IntoIterator<Item = T>,
+        T: ScanBound,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+    {
+        assert!(run_len > 0, "run length should be positive");
+        let mut items = items.into_iter();
+        let mut runs = Vec::new();
+        let mut buf = Vec::with_capacity(run_len);
+        let result = loop {
+            buf.clear();
+            buf.extend(items.by_ref().take(run_len));
+            if buf.is_empty() {
+                break Self::merge_runs(sig, &runs, &sort_key, path, options);
+            }
+            sort_unstable_by_key(&mut buf, &sort_key);
+            let mut run_path = path.as_os_str().to_owned();
+            run_path.push(format!(".run{}.tmp", runs.len()));
+            match Self::from_slice(sig, &buf, run_path.into()) {
+                Ok(run) => runs.push(run),
+                Err(err) => break Err(err),
+            }
+        };
+        for run in runs {
+            // runs are temporary files, so failing to remove them does not affect the result
+            run.destroy().ok();
+        }
+        result
+    }
+
+    /// K-way merge of sorted runs into a new vector at `path`.
+    fn merge_runs<O, F>(
+        sig: u64,
+        runs: &[Self],
+        sort_key: &F,
+        path: PathBuf,
+        options: MmVecOptions,
+    ) -> Result<Self, MmVecError>
+    where
+        F: Fn(&T) -> O,
+        O: Ord,
+    {
+        let len = checked_len::<T>(runs.iter().map(|run| run.len() as u64).sum(), options.max_len)?;
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        {
+            let mut new = Data::<T>::new_uninit(&tmp_path, sig, len)?;
+            new.advise(AccessPattern::Sequential);
+            let mut iters: Vec<_> = runs.iter().map(MmVec::iter).collect();
+            let mut heads = Vec::with_capacity(runs.len());
+            let mut heap = BinaryHeap::with_capacity(runs.len());
+            for (i, iter) in iters.iter_mut().enumerate() {
+                // runs are never empty
+                let head = iter.next().expect("run is not empty");
+                heap.push(Reverse((sort_key(&head), i)));
+                heads.push(head);
+            }
+            // all `len` items of the buffer are initialized below
+            for slot in new.as_slice_mut() {
+                let Reverse((_, i)) = heap.pop().expect("runs hold exactly `len` items");
+                *slot = heads[i];
+                if let Some(next) = iters[i].next() {
+                    heap.push(Reverse((sort_key(&next), i)));
+                    heads[i] = next;
+                }
+            }
+            new.flush()?;
+            drop(new);
+            open_file(&tmp_path)?.sync_all()?;
+        }
+        rename(&tmp_path, &path)?;
+        sync_parent_dir(&path)?;
+        // Safety: this is safe because we have just written valid data into the file.
+        let data = unsafe { Data::from_file_locked(&path, options.lock)? };
+        Ok(Self::with_options(data, path, options))
     }
 
-    /// Try to create a vector from the given path. Returns an error if the signature does not match, or if
-    /// the vector is not completely initialized.
+    /// Try to create a vector from the given path. Returns an error if the signature does not match, or
+    /// [`MmVecError::Corrupted`] describing the problem if the vector is not completely initialized.
+    ///
+    /// An insert which was interrupted (e.g. by a crash) is completed first, see [`MmVec::insert_sorted`].
     pub fn from_path(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        Self::from_path_with(sig, path, MmVecOptions::default())
+    }
+
+    /// Same as [`MmVec::from_path`], with the given options.
+    pub fn from_path_with(sig: u64, path: PathBuf, options: MmVecOptions) -> Result<Self, MmVecError> {
+        check_file_format::<T>(&path)?;
         // Safety: this is safe, because we are going to check the data.
-        let data = unsafe { Data::<T>::from_file_unchecked(&path)? };
+        let data = unsafe { Data::<T>::from_file_locked(&path, options.lock) }
+            .map_err(|err| MmVecError::accessing(&path, err))?;
+        Self::check_sig(&data, sig)?;
+        // only whole-file vectors with initialized headers are supported
+        Self::check_layout(&data, &path, false)?;
+        let mut data = data;
+        Self::complete_interrupted_merge(&mut data, &path)?;
+        Ok(Self::with_options(data, path, options))
+    }
+
+    /// Open the vector at the given path for reading only, without locking it.
+    ///
+    /// This allows a single writer process (which holds an exclusive lock) and any number of reader processes to share
+    /// the same file. Writers bump the generation counter in the header whenever the mapping has to change, and
+    /// whenever an insert moves items in place; readers pick up the changes with [`MmVec::refresh`]. An insert is only
+    /// published once it is complete, but readers share the pages it modifies, so reads which overlap it may see a
+    /// half-merged vector: readers should check [`MmVec::is_stale`] after reading, and refresh and repeat the read if
+    /// it returns `true`.
+    pub fn open_read_only(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        let data = Self::open_read_only_data(sig, &path)?;
+        Ok(Self::new(data, path))
+    }
+
+    fn open_read_only_data(sig: u64, path: &Path) -> Result<Data<T>, MmVecError> {
+        check_file_format::<T>(path)?;
+        // Safety: this is safe, because we are going to check the data.
+        let data = unsafe { Data::<T>::from_file_read_only(path) }.map_err(|err| MmVecError::accessing(path, err))?;
+        Self::check_sig(&data, sig)?;
+        // writers extend the file before updating the header, so the file may be larger than the header says
+        Self::check_layout(&data, path, true)?;
+        Ok(data)
+    }
+
+    /// Check that the header matches the size of the file. Files may be larger than the header says if `allow_larger`.
+    fn check_layout(data: &Data<T>, path: &Path, allow_larger: bool) -> Result<(), MmVecError> {
+        let (len, capacity) = (data.len(), data.header_capacity());
+        if len > capacity {
+            return Err(MmVecError::corrupted(
+                path,
+                Corruption::LengthExceedsCapacity { len, capacity },
+            ));
+        }
+        let actual_capacity = data.capacity() as u64;
+        if capacity > actual_capacity || (!allow_larger && capacity != actual_capacity) {
+            let corruption = Corruption::SizeMismatch {
+                capacity,
+                expected_size: Data::<T>::HEADER_SIZE + capacity * size_of::<T>() as u64,
+                actual_size: data.file.metadata()?.len(),
+            };
+            return Err(MmVecError::corrupted(path, corruption));
+        }
+        Ok(())
+    }
+
+    /// Make a vector which fails to load with [`MmVecError::Corrupted`] (e.g. after a power failure) usable again, and
+    /// load it.
+    ///
+    /// Items which the header claims but the file does not contain are dropped, and trailing bytes which do not form
+    /// a whole item are truncated. A file which loads fine is not modified. The contents of the remaining items are not
+    /// checked. An interrupted insert is completed, unless its journal does not match the recovered contents, in which
+    /// case it is discarded.
+    pub fn recover(sig: u64, path: PathBuf) -> Result<(Self, RecoveryReport), MmVecError> {
+        // not even the signature may have survived
+        let file_size = check_file_format::<T>(&path)?;
+        // Safety: this is safe, because we are going to check the data.
+        let mut data =
+            unsafe { Data::<T>::from_file_unchecked(&path) }.map_err(|err| MmVecError::accessing(&path, err))?;
+        Self::check_sig(&data, sig)?;
+        let header_len = data.len();
+        // whole items present in the file
+        let capacity = data.capacity();
+        let recovered_len = header_len.min(capacity as u64);
+        let report = RecoveryReport {
+            header_len,
+            recovered_len,
+            truncated_bytes: file_size - data.expected_file_size(capacity),
+        };
+        if report.truncated_bytes != 0 || data.header_capacity() != capacity as u64 || header_len != recovered_len {
+            // Safety: the file holds `capacity` items, and only the first `recovered_len` of them are kept
+            unsafe {
+                data.resize_capacity(capacity)?;
+                data.set_len(recovered_len);
+            }
+            data.set_generation(data.generation() + 1);
+            data.flush()?;
+            data.file.sync_all()?;
+        }
+        match Self::complete_interrupted_merge(&mut data, &path) {
+            // the journal does not belong to the recovered contents
+            Err(MmVecError::Corrupted { .. }) => remove_file(journal_path(&path))?,
+            result => result?,
+        }
+        Ok((Self::new(data, path), report))
+    }
+
+    /// Convert the file at `path` from the baseline format (format version 0, see [`MmVecError::UnsupportedFormat`]) to
+    /// the current one, and load it.
+    ///
+    /// The converted file is written to a temporary sibling file, which then replaces the original, so an interrupted
+    /// conversion leaves the original intact. The file must not be open.
+    pub fn migrate_baseline_format(sig: u64, path: PathBuf) -> Result<Self, MmVecError> {
+        let mut file = File::open(&path).map_err(|err| MmVecError::accessing(&path, err))?;
+        let file_size = file.metadata()?.len();
+        if file_size < BASELINE_HEADER_SIZE {
+            return Err(MmVecError::corrupted(&path, Corruption::TruncatedHeader { file_size }));
+        }
+        let mut header = [0u8; BASELINE_HEADER_SIZE as usize];
+        file.read_exact(&mut header)?;
+        let actual = u64::from_ne_bytes(header[..8].try_into().expect("slice has the right length"));
+        if actual != sig {
+            return Err(MmVecError::SignatureMismatch { expected: sig, actual });
+        }
+        let len = u64::from_ne_bytes(header[8..].try_into().expect("slice has the right length"));
+        let expected_size = baseline_file_size::<T>(len);
+        if expected_size != Some(file_size) {
+            let corruption = Corruption::SizeMismatch {
+                capacity: len,
+                expected_size: expected_size.unwrap_or(u64::MAX),
+                actual_size: file_size,
+            };
+            return Err(MmVecError::corrupted(&path, corruption));
+        }
+        let len = checked_len::<T>(len, None)?;
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        {
+            let mut new = Data::<T>::new_uninit(&tmp_path, sig, len)?;
+            let items = new.as_slice_mut();
+            // Safety: the slice is valid for its whole length, and any bytes are valid items since `T` is `Pod`
+            let bytes = unsafe { slice::from_raw_parts_mut(items.as_mut_ptr().cast::<u8>(), size_of_val(items)) };
+            file.read_exact(bytes)?;
+            new.flush()?;
+            drop(new);
+            open_file(&tmp_path)?.sync_all()?;
+        }
+        drop(file);
+        rename(&tmp_path, &path)?;
+        sync_parent_dir(&path)?;
+        Self::from_path(sig, path)
+    }
+
+    fn check_sig(data: &Data<T>, sig: u64) -> Result<(), MmVecError> {
         if data.sig() != sig {
             return Err(MmVecError::SignatureMismatch {
                 expected: sig,
                 actual: data.sig(),
             });
         }
-        // only whole-file, fully initialized vectors are supported
-        if data.len() != data.capacity() as u64 {
-            return Err(MmVecError::UninitializedVectorLoad {});
+        Ok(())
+    }
+
+    /// Whether this vector was opened with [`MmVec::open_read_only`].
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.data.as_ref().is_some_and(|d| d.read_only)
+    }
+
+    /// Generation counter, bumped by the writer whenever the backing file is replaced or remapped.
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.data.as_ref().map_or(0, Data::generation)
+    }
+
+    /// Creation time, last flush time and update counter of the vector. For read-only vectors, reflects changes made
+    /// by the writer.
+    #[must_use]
+    pub fn info(&self) -> MmVecInfo {
+        let time = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+        let data = self.data.as_ref();
+        MmVecInfo {
+            created: time(data.map_or(0, Data::created)),
+            last_flush: data.map(Data::last_flush).filter(|millis| *millis != 0).map(time),
+            updates: data.map_or(0, Data::updates),
+            generation: self.generation(),
+        }
+    }
+
+    /// Count a modification in the header.
+    fn record_update(&mut self) {
+        if let Some(data) = self.data.as_mut() {
+            data.set_updates(data.updates() + 1);
         }
-        Ok(Self::new(data, path))
+    }
+
+    /// Whether the writer has moved items in place since this read-only vector was opened or last refreshed, or is
+    /// moving them right now. Reads made in the meantime may have seen a half-merged vector, and should be repeated
+    /// after [`MmVec::refresh`]. Always `false` for vectors which are not read-only.
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        self.is_read_only()
+            && self
+                .data
+                .as_ref()
+                .is_some_and(|data| data.merging() || data.generation() != data.mapped_generation)
+    }
+
+    /// Remap the vector if the writer has changed it since it was opened or last refreshed. Returns whether the vector
+    /// was remapped. Does nothing for vectors which are not read-only.
+    pub fn refresh(&mut self) -> Result<bool, MmVecError> {
+        let mapped_generation = self.data.as_ref().map_or(0, |d| d.mapped_generation);
+        if !self.is_read_only() || read_generation(&self.path)? == mapped_generation {
+            return Ok(false);
+        }
+        let data = Self::open_read_only_data(self.sig(), &self.path)?;
+        drop(self.take_data());
+        self.replace_data(data);
+        Ok(true)
+    }
+
+    /// Expected access pattern of this vector.
+    #[must_use]
+    pub fn access_pattern(&self) -> AccessPattern {
+        self.access_pattern
+    }
+
+    /// Let the OS know how the vector is going to be accessed. The pattern is kept when the vector is remapped.
+    ///
+    /// Vectors start with [`AccessPattern::Random`], which suits searches. Switching to [`AccessPattern::Sequential`]
+    /// before a full scan enables readahead, which makes the scan considerably faster.
+    pub fn advise(&mut self, pattern: AccessPattern) {
+        self.access_pattern = pattern;
+        if let Some(data) = self.data.as_ref() {
+            data.advise(pattern);
+        }
+    }
+
+    /// Fault the whole vector into memory (and the page cache), so that the first accesses do not stall on page
+    /// faults. The access pattern is not changed.
+    pub fn populate(&self) {
+        if let Some(data) = self.data.as_ref() {
+            data.populate();
+        }
+    }
+
+    /// Whether huge pages were requested with [`MmVec::set_huge_pages`].
+    #[must_use]
+    pub fn huge_pages(&self) -> bool {
+        self.huge_pages
+    }
+
+    /// Ask the OS to back the data with transparent huge pages, which reduces TLB misses during random lookups in
+    /// large vectors. The setting is kept when the vector is remapped.
+    ///
+    /// Only supported on Linux, and only takes effect if the kernel supports transparent huge pages for file-backed
+    /// mappings (`CONFIG_READ_ONLY_THP_FOR_FS`, or files on tmpfs). On other platforms this does nothing.
+    /// Note that `MmapOptions::huge` can't be used instead, because it only applies to anonymous mappings.
+    ///
+    /// Returns an error if the kernel rejects the request, e.g. because transparent huge pages are disabled.
+    pub fn set_huge_pages(&mut self, enabled: bool) -> Result<(), MmVecError> {
+        if let Some(data) = self.data.as_ref() {
+            data.set_huge_pages(enabled)?;
+        }
+        self.huge_pages = enabled;
+        Ok(())
+    }
+
+    /// Current flush policy.
+    #[must_use]
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.flush_policy
+    }
+
+    /// Change the flush policy, starting or stopping the background flush thread as needed.
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) -> Result<(), MmVecError> {
+        // stop the current thread first, so that it does not outlive its policy
+        self.flusher = None;
+        if let FlushPolicy::Interval {
+            period,
+            background: true,
+        } = policy
+        {
+            self.flusher = Some(BackgroundFlusher::spawn(period)?);
+            self.track_mappings();
+        }
+        self.flush_policy = policy;
+        Ok(())
+    }
+
+    /// Account for `bytes` modified in place.
+    fn mark_dirty(&self, bytes: usize) {
+        self.dirty_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Flush if the flush policy says so.
+    fn maybe_flush(&self) -> Result<(), MmVecError> {
+        let due = match self.flush_policy {
+            FlushPolicy::OnDrop => false,
+            FlushPolicy::Interval { background: true, .. } => false,
+            FlushPolicy::Interval { period, .. } => {
+                self.last_flush.lock().unwrap_or_else(PoisonError::into_inner).elapsed() >= period
+                    && self.dirty_bytes.load(Ordering::Relaxed) > 0
+            }
+            FlushPolicy::DirtyBytes(limit) => self.dirty_bytes.load(Ordering::Relaxed) >= limit,
+        };
+        if due {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Reset dirty state after the data was written to disk.
+    fn mark_clean(&self) {
+        self.dirty_bytes.store(0, Ordering::Relaxed);
+        *self.last_flush.lock().unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    fn options(&self) -> MmVecOptions {
+        MmVecOptions {
+            lock: self.lock,
+            max_len: self.max_len,
+        }
+    }
+
+    fn check_writable(&self) -> Result<(), MmVecError> {
+        if self.is_read_only() {
+            return Err(MmVecError::ReadOnly {});
+        }
+        Ok(())
+    }
+
+    /// How the backing file is locked.
+    #[must_use]
+    pub fn lock_mode(&self) -> LockMode {
+        self.lock
+    }
+
+    /// Maximum number of items, if limited. See [`MmVec::set_max_len`].
+    #[must_use]
+    pub fn max_len(&self) -> Option<usize> {
+        self.max_len
+    }
+
+    /// Limit the number of items. Operations which would grow the vector beyond `max_len` fail with
+    /// [`MmVecError::TooLarge`] and leave it unchanged, so that oversized inserts are rejected up front instead of
+    /// failing while resizing the backing file. A vector which already holds more items can still shrink.
+    pub fn set_max_len(&mut self, max_len: Option<usize>) {
+        self.max_len = max_len;
+    }
+
+    /// Check that the vector may grow to `len` items, and convert the length.
+    fn check_len(&self, len: u64) -> Result<usize, MmVecError> {
+        checked_len::<T>(len, self.max_len)
     }
 
     /// Path to the backing file.
@@ -82,6 +754,29 @@ where
         &self.path
     }
 
+    /// User metadata stored in the header. Empty unless set with [`MmVec::set_metadata`].
+    #[must_use]
+    pub fn metadata(&self) -> Vec<u8> {
+        self.data.as_ref().map_or_else(Vec::new, |d| d.metadata().to_vec())
+    }
+
+    /// Store user metadata (at most [`MAX_METADATA_LEN`] bytes) in the header, replacing the previous one.
+    ///
+    /// Metadata is kept when the backing file is replaced. Changing it is an in-place modification, which is written
+    /// to disk according to the [`FlushPolicy`].
+    pub fn set_metadata(&mut self, metadata: &[u8]) -> Result<(), MmVecError> {
+        self.check_writable()?;
+        if metadata.len() > MAX_METADATA_LEN {
+            return Err(MmVecError::MetadataTooLong { len: metadata.len() });
+        }
+        if let Some(data) = self.data.as_mut() {
+            data.set_metadata(metadata);
+            self.mark_dirty(metadata.len());
+        }
+        self.record_update();
+        self.maybe_flush()
+    }
+
     /// Underlying file handle.
     #[must_use]
     pub fn file(&self) -> Option<&File> {
@@ -100,41 +795,145 @@ where
         self.data.as_ref().map_or(u64::MAX, Data::sig)
     }
 
+    /// Number of items this vector can hold without resizing the backing file.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.data.as_ref().map_or(0, Data::capacity)
+    }
+
     /// Whether this vector is empty.
     #[must_use]
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// Size of the backing file in bytes, as it is expected to be according to the header.
+    #[must_use]
+    pub fn expected_storage_size(&self) -> u64 {
+        let capacity = self.data.as_ref().map_or(0, Data::header_capacity);
+        Data::<T>::HEADER_SIZE + capacity * size_of::<T>() as u64
+    }
+
+    /// Actual size of the backing file in bytes.
+    pub fn storage_size(&self) -> Result<u64, MmVecError> {
+        match self.file() {
+            Some(file) => Ok(file.metadata()?.len()),
+            None => Ok(0),
+        }
+    }
+
     /// Get contents as a slice.
+    #[must_use]
+    pub fn as_slice(&self) -> &[T] {
+        self.data.as_ref().map_or(&[], Data::as_slice)
+    }
+
+    /// Iterate over copies of the items, reading the file sequentially. See [`MmVec::chunks`].
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.chunks(ITER_CHUNK_LEN).flat_map(|chunk| chunk.iter().copied())
+    }
+
+    /// Iterate over the contents in chunks of `chunk_len` items (the last chunk may be shorter).
     ///
-    /// ## Safety
-    /// Unsafe since we can't guarantee that the mmapped file truly contains T.
+    /// Readahead is enabled while the iterator is alive, and the access pattern of the vector is restored when the
+    /// iterator is dropped.
+    ///
+    /// Contents are checked to be `T` only by the signature of the vector, the same way as for searches over
+    /// memory-mapped indexes.
+    ///
+    /// ## Panics
+    /// Panics if `chunk_len` is 0.
+    pub fn chunks(&self, chunk_len: usize) -> Chunks<'_, T> {
+        if let Some(data) = self.data.as_ref() {
+            data.advise(AccessPattern::Sequential);
+        }
+        let inner = self.as_slice().chunks(chunk_len);
+        Chunks { vec: self, inner }
+    }
+
+    /// Take a read-only snapshot of the current contents.
+    ///
+    /// The snapshot shares the current mapping, so taking it is cheap. Operations which replace the backing file (e.g.
+    /// [`MmVec::insert_sorted`]) leave the snapshot intact; before the first in-place modification through this vector
+    /// (see [`FlushPolicy`]), the contents are copied to a new backing file, so the snapshot is not affected either.
+    ///
+    /// For a read-only vector, in-place modifications made by a writer in another process are visible in the snapshot.
     #[must_use]
-    pub unsafe fn as_slice(&self) -> &[T] {
-        self.data.as_ref().map_or(&[], |d| unsafe { d.as_slice() })
+    pub fn snapshot(&self) -> MmVecSnapshot<T> {
+        MmVecSnapshot {
+            mapped: self.data.as_ref().map(|d| Arc::clone(&d.mapped_data)),
+            len: self.len(),
+            generation: self.generation(),
+            _token: Arc::clone(&self.snapshots),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Copy the contents to a new backing file if any snapshot still uses the current one.
+    fn detach_snapshots(&mut self) -> Result<(), MmVecError> {
+        if Arc::strong_count(&self.snapshots) > 1 {
+            let len = self.len();
+            self.rewrite(len, |current, new| {
+                new.copy_from_slice(current);
+                len
+            })?;
+        }
+        Ok(())
     }
 
-    /// Get contents as a mutable slice.
+    /// Get contents as a mutable slice. The whole slice is considered modified by the [`FlushPolicy`], and is written
+    /// to disk on the next flush. Use [`MmVec::slice_mut`] to modify only a part of a large vector.
     ///
-    /// ## Safety
-    /// Unsafe since we can't guarantee that the mmapped file truly contains T.
+    /// ## Panics
+    /// Panics if the vector is read-only, or if a snapshot of the vector exists and the contents could not be copied
+    /// (see [`MmVec::snapshot`]).
+    #[must_use]
+    pub fn as_slice_mut(&mut self) -> &mut [T] {
+        self.slice_mut(0..self.len())
+    }
+
+    /// Get items in `range` as a mutable slice. Only these items are considered modified, so only the pages holding
+    /// them are written to disk on the next flush.
+    ///
+    /// ## Panics
+    /// Panics if `range` is out of bounds, if the vector is read-only, or if a snapshot of the vector exists and the
+    /// contents could not be copied (see [`MmVec::snapshot`]).
     #[must_use]
-    pub unsafe fn as_slice_mut(&mut self) -> &mut [T] {
-        self.data.as_mut().map_or(&mut [], |d| unsafe { d.as_slice_mut() })
+    pub fn slice_mut(&mut self, range: Range<usize>) -> &mut [T] {
+        assert!(!self.is_read_only(), "vector is opened read-only");
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range {range:?} is out of bounds"
+        );
+        self.detach_snapshots()
+            .expect("failed to copy contents used by a snapshot");
+        // modifications made through the previous slice are flushed now, if due; if flushing fails, the data stays
+        // dirty and is flushed later
+        self.maybe_flush().ok();
+        self.mark_dirty(range.len() * size_of::<T>());
+        self.record_update();
+        self.data.as_mut().map_or(&mut [], |d| d.slice_mut(range))
     }
 
     /// Flushes memory-mapped data into file.
     pub fn flush(&self) -> Result<(), MmVecError> {
-        Ok(self.data.as_ref().map_or(Ok(()), Data::flush)?)
+        let start = Instant::now();
+        self.data.as_ref().map_or(Ok(()), Data::flush)?;
+        self.mark_clean();
+        metrics::flush(start.elapsed());
+        Ok(())
     }
 
     /// Destroys self, removing the underlying file.
     pub fn destroy(mut self) -> Result<(), MmVecError> {
         let path = self.path.clone();
-        drop(self.data.take());
+        self.flusher = None;
+        drop(self.take_data());
 
-        remove_file(path)?;
+        remove_file(&path)?;
+        // leftovers of an interrupted rewrite or merge, if any
+        let _ = remove_file(self.tmp_path());
+        let _ = remove_file(journal_path(&path));
 
         Ok(())
     }
@@ -145,153 +944,755 @@ where
         copy(&self.path, &path)?;
 
         // Safety: this is safe because we know that the file contains valid data.
-        let copied = unsafe { Data::from_file_unchecked(&path)? };
-        Ok(Self::new(copied, path))
+        let copied = unsafe { Data::from_file_locked(&path, self.lock)? };
+        Ok(Self::with_options(copied, path, self.options()))
+    }
+
+    /// Writes a copy of the backing file to `path`, without opening the copy.
+    ///
+    /// The copy is made from the mapped contents rather than from the file, so it works while the file is locked, and
+    /// includes modifications which were not flushed yet. It is written to a temporary sibling file first, and then
+    /// renamed into place.
+    pub fn backup_to(&self, path: &Path) -> Result<(), MmVecError> {
+        let Some(data) = self.data.as_ref() else {
+            return Ok(());
+        };
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut file = create_new_file(Path::new(&tmp_path))?;
+        // Safety: both mappings are valid for their whole length, and are not modified while `self` is borrowed
+        unsafe {
+            file.write_all(slice::from_raw_parts(
+                data.mapped_header.as_ptr(),
+                data.mapped_header.len(),
+            ))?;
+            file.write_all(slice::from_raw_parts(data.mapped_data.as_ptr(), data.mapped_data.len()))?;
+        }
+        file.sync_all()?;
+        drop(file);
+        rename(&tmp_path, path)?;
+        sync_parent_dir(path)?;
+        Ok(())
     }
 
     /// Moves self into path, and returns a new vector at this path.
     pub fn move_to(mut self, path: PathBuf) -> Result<Self, MmVecError> {
         self.flush()?;
+        self.flusher = None;
+        drop(self.take_data());
+        let options = self.options();
         let current_path = self.path;
-        drop(self.data.take());
 
         rename(current_path, &path)?;
 
         // Safety: this is safe because we know that the file contains valid data.
-        let moved = unsafe { Data::from_file_unchecked(&path)? };
-        Ok(Self::new(moved, path))
+        let moved = unsafe { Data::from_file_locked(&path, self.lock)? };
+        let mut moved = Self::with_options(moved, path, options);
+        moved.advise(self.access_pattern);
+        if self.huge_pages {
+            moved.set_huge_pages(true).ok();
+        }
+        moved.set_flush_policy(self.flush_policy)?;
+        Ok(moved)
     }
 
-    /// Insert items into vector, preserving sorted order.
-    /// If the vector was not previously sorted, it will be.
+    /// Insert items into vector, preserving sorted order. The vector has to be sorted already.
     ///
-    /// Input sequence can be sorted to ensure better performance, but it is not required.
+    /// Input sequence does not have to be sorted: it is sorted after being copied into the journal, so there is no
+    /// need to sort it beforehand.
     ///
-    /// ## Safety
-    /// Unsafe since we can't guarantee that the mmapped file truly contains T.
-    pub unsafe fn insert_sorted<O, F>(&mut self, items: &[T], sort_key: F) -> Result<(), MmVecError>
+    /// The items are merged in place, from the end of the vector backwards, so only the items after the first inserted
+    /// one are moved. The capacity grows geometrically, so most inserts fit into the backing file without resizing it.
+    /// Each step of the merge is recorded in a journal next to the backing file before it is made, so if the merge is
+    /// interrupted (e.g. by a crash), it is completed the next time the vector is opened.
+    pub fn insert_sorted<O, F>(&mut self, items: &[T], sort_key: F) -> Result<(), MmVecError>
     where
-        F: Fn(&T) -> O,
+        T: ScanBound,
+        F: Fn(&T) -> O + ScanBound,
         O: Ord,
     {
-        self.flush()?;
-        let current_len = self.len();
-        unsafe {
-            self.resize(current_len + items.len())?;
-            self.as_slice_mut()[current_len..].copy_from_slice(items);
-            self.as_slice_mut().sort_unstable_by_key(sort_key);
+        self.check_writable()?;
+        self.merge_in_place(
+            items.len(),
+            |new| new.copy_from_slice(items),
+            sort_key,
+            merge_step_len::<T>(),
+        )
+    }
+
+    /// First half of [`MmVec::insert_sorted`]: acquire everything the insert needs (capacity and the journal), without
+    /// changing the contents. The insert is applied by [`MmVec::commit_staged_insert`], and abandoned by
+    /// [`MmVec::discard_staged_insert`] or by staging another one. The vector must not be modified in between.
+    pub(crate) fn stage_insert_sorted<O, F>(&mut self, items: &[T], sort_key: F) -> Result<(), MmVecError>
+    where
+        T: ScanBound,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+    {
+        self.check_writable()?;
+        self.stage_merge(
+            items.len(),
+            |new| new.copy_from_slice(items),
+            sort_key,
+            merge_step_len::<T>(),
+        )
+    }
+
+    /// Apply the insert staged by [`MmVec::stage_insert_sorted`], if any. Only writes to space acquired by staging.
+    pub(crate) fn commit_staged_insert(&mut self) -> Result<(), MmVecError> {
+        self.commit_staged_merge()
+    }
+
+    /// Abandon the insert staged by [`MmVec::stage_insert_sorted`], if any, removing its journal.
+    pub(crate) fn discard_staged_insert(&mut self) {
+        self.staged = None;
+    }
+
+    /// Insert records from the file at `path`, decoded by `decode`, keeping the vector sorted like
+    /// [`MmVec::insert_sorted`]. Returns the number of inserted records.
+    ///
+    /// The file must consist of records of exactly `record_size` bytes each. It is memory-mapped rather than read into
+    /// memory, and the records are decoded straight into the merge journal, so dumps larger than the available memory
+    /// can be imported. The file must not be modified while it is imported. If `decode` panics, the journal is removed
+    /// and the vector is left unchanged.
+    pub fn append_from_file<O, F, D>(
+        &mut self,
+        path: &Path,
+        record_size: usize,
+        decode: D,
+        sort_key: F,
+    ) -> Result<usize, MmVecError>
+    where
+        T: ScanBound,
+        D: Fn(&[u8]) -> T,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+    {
+        let n_records = self.stage_append_from_file(path, record_size, decode, sort_key)?;
+        self.commit_staged_insert()?;
+        Ok(n_records)
+    }
+
+    /// First half of [`MmVec::append_from_file`], like [`MmVec::stage_insert_sorted`]: the records are decoded into
+    /// the journal, and inserted by [`MmVec::commit_staged_insert`]. Returns the number of records.
+    pub(crate) fn stage_append_from_file<O, F, D>(
+        &mut self,
+        path: &Path,
+        record_size: usize,
+        decode: D,
+        sort_key: F,
+    ) -> Result<usize, MmVecError>
+    where
+        T: ScanBound,
+        D: Fn(&[u8]) -> T,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+    {
+        self.staged = None;
+        self.check_writable()?;
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        if record_size == 0 || size % record_size as u64 != 0 {
+            return Err(MmVecError::InvalidRecordDump { size, record_size });
         }
-        Ok(())
+        let n_records = size / record_size as u64;
+        if n_records == 0 {
+            return Ok(0);
+        }
+        self.check_len((self.len() as u64).saturating_add(n_records))?;
+        // fits, as the new length does
+        let n_records = n_records as usize;
+        // Safety: the dump is only read, and the caller guarantees that it is not modified meanwhile
+        let dump = unsafe { MmapOptions::new().map(&file)? };
+        #[cfg(unix)]
+        dump.advise(memmap2::Advice::Sequential).ok();
+        self.stage_merge(
+            n_records,
+            |new| {
+                for (slot, record) in new.iter_mut().zip(dump.chunks_exact(record_size)) {
+                    *slot = decode(record);
+                }
+            },
+            sort_key,
+            merge_step_len::<T>(),
+        )?;
+        Ok(n_records)
     }
 
     /// Remove all items matching the predicate, while preserving the sorted order.
     /// If the vector was not previously sorted, it will be.
     ///
-    /// ## Safety
-    /// Unsafe since we can't guarantee that the mmapped file truly contains T.
-    pub unsafe fn remove_matching<O, F, S>(&mut self, predicate: F, sort_key: S) -> Result<(), MmVecError>
+    /// The result is written to a temporary file which then replaces the backing file, so a crash in the middle of
+    /// this operation leaves the vector intact.
+    pub fn remove_matching<O, F, S>(&mut self, predicate: F, sort_key: S) -> Result<(), MmVecError>
     where
         F: Fn(&T) -> bool,
         S: Fn(&T) -> O,
         O: Ord,
     {
-        unsafe {
-            let split = partition(self.as_slice_mut(), |el| !predicate(el));
-            self.resize(split)?;
-            self.as_slice_mut().sort_unstable_by_key(sort_key);
-        }
-        Ok(())
+        self.check_writable()?;
+        self.rewrite(self.len(), |current, new| {
+            new.copy_from_slice(current);
+            let split = partition(new, |el| !predicate(el));
+            new[..split].sort_unstable_by_key(sort_key);
+            split
+        })
     }
 
-    unsafe fn resize(&mut self, new_len: usize) -> Result<(), MmVecError> {
-        self.flush()?;
-
-        // On Windows it is required that file is not mapped before resizing.
-        // The safest option is to just drop and recreate the Data.
-        #[cfg(windows)]
-        {
-            drop(self.data.take());
-            self.data = Some(Data::from_file_unchecked_resized(self.path(), new_len)?);
-        }
-        #[cfg(not(windows))]
+    /// Retain only the items for which the predicate returns `true`, preserving their relative order.
+    /// The predicate receives position of the item along with the item itself.
+    ///
+    /// Crash-safe in the same way as [`MmVec::remove_matching`].
+    pub fn retain<F>(&mut self, mut predicate: F) -> Result<(), MmVecError>
+    where
+        F: FnMut(usize, &T) -> bool,
+    {
+        self.check_writable()?;
+        self.rewrite(self.len(), |current, new| {
+            let mut retained = 0;
+            for (i, item) in current.iter().enumerate() {
+                if predicate(i, item) {
+                    new[retained] = *item;
+                    retained += 1;
+                }
+            }
+            retained
+        })
+    }
+
+    /// Path of the temporary file used to atomically replace the backing file.
+    fn tmp_path(&self) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(".tmp");
+        path.into()
+    }
+
+    /// Atomically replace contents of the vector.
+    ///
+    /// `fill` receives the current contents and a buffer of `max_len` items, and returns how many items of the buffer
+    /// make up the new contents. The buffer is backed by a temporary sibling file, which is synced to disk and then
+    /// renamed over the backing file. Until the rename, the backing file is not modified.
+    fn rewrite<F>(&mut self, max_len: usize, fill: F) -> Result<(), MmVecError>
+    where
+        F: FnOnce(&[T], &mut [T]) -> usize,
+    {
+        // the current contents are read once from start to end, and then unmapped
+        if let Some(data) = self.data.as_ref() {
+            data.advise(AccessPattern::Sequential);
+        }
+        let tmp_path = self.tmp_path();
         {
-            self.data.as_mut().map_or(Ok(()), |d| d.resize(new_len))?;
+            let mut new = Data::<T>::new_uninit_locked(&tmp_path, self.sig(), max_len, self.lock)?;
+            new.advise(AccessPattern::Sequential);
+            new.set_generation(self.generation() + 1);
+            if let Some(data) = self.data.as_ref() {
+                new.set_metadata(data.metadata());
+                new.set_created(data.created());
+                new.set_updates(data.updates() + 1);
+            }
+            // `fill` initializes the first `len` items of the buffer, and only those are kept
+            let len = fill(self.as_slice(), new.as_slice_mut());
+            unsafe { new.set_len(len as u64) };
+            new.set_header_capacity(len as u64);
+            new.flush()?;
+            // trailing space is truncated once the file is unmapped, which is required on some platforms
+            drop(new);
+            let file = open_file(&tmp_path)?;
+            if len != max_len {
+                resize_file_to_fit::<T>(&file, Data::<T>::HEADER_SIZE, len)?;
+            }
+            file.sync_all()?;
+        }
+        // the backing file has to be unmapped before it can be replaced on some platforms
+        drop(self.take_data());
+        if let Err(err) = rename(&tmp_path, &self.path) {
+            // the backing file is intact, so the vector is reopened unchanged
+            remove_file(&tmp_path).ok();
+            // Safety: the file has not been modified, and held valid data.
+            let data = unsafe { Data::from_file_locked(&self.path, self.lock) }
+                .map_err(|err| MmVecError::accessing(&self.path, err))?;
+            self.replace_data(data);
+            // on Windows, a file which is mapped by another process can't be replaced
+            if cfg!(windows) && err.kind() == io::ErrorKind::PermissionDenied {
+                return Err(MmVecError::Locked {
+                    path: self.path.clone(),
+                });
+            }
+            return Err(MmVecError::accessing(&self.path, err));
+        }
+        sync_parent_dir(&self.path)?;
+        // Safety: this is safe because we have just written valid data into the file.
+        let data = unsafe { Data::from_file_locked(&self.path, self.lock) }
+            .map_err(|err| MmVecError::accessing(&self.path, err))?;
+        self.replace_data(data);
+        // existing snapshots keep the old file
+        self.snapshots = Arc::new(());
+        // the new file is already on disk
+        self.mark_clean();
+        Ok(())
+    }
+
+    /// Merge `n` new items into the sorted contents in place, under a [`Journal`]. `fill` initializes the new items,
+    /// which are then sorted, and the merge moves at most `step` items at a time.
+    ///
+    /// If the merge fails after it started, the vector is closed, and the merge is completed when it is opened again.
+    fn merge_in_place<O, F, I>(&mut self, n: usize, fill: I, sort_key: F, step: usize) -> Result<(), MmVecError>
+    where
+        T: ScanBound,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+        I: FnOnce(&mut [T]),
+    {
+        self.stage_merge(n, fill, sort_key, step)?;
+        self.commit_staged_merge()
+    }
+
+    /// Prepare a merge for [`MmVec::commit_staged_merge`], without changing the contents: the capacity is grown, and
+    /// the new items are written to an uncommitted [`Journal`] along with their positions, so applying the merge does
+    /// not need any more disk space. A merge which was staged before is discarded.
+    fn stage_merge<O, F, I>(&mut self, n: usize, fill: I, sort_key: F, step: usize) -> Result<(), MmVecError>
+    where
+        T: ScanBound,
+        F: Fn(&T) -> O + ScanBound,
+        O: Ord,
+        I: FnOnce(&mut [T]),
+    {
+        self.staged = None;
+        let new_len = self.check_len((self.len() as u64).saturating_add(n as u64))?;
+        if n == 0 {
+            return Ok(());
+        }
+        self.detach_snapshots()?;
+        if new_len > self.capacity() {
+            // Safety: the length is kept, and the new capacity is only used by the merge
+            unsafe { self.resize_capacity(self.grown_capacity(new_len), self.len())? };
+        }
+        let Some(data) = self.data.as_mut() else {
+            return Ok(());
+        };
+        let mut journal = Journal::create(journal_path(&self.path), data.sig(), data.len() as usize, n, step)?;
+        let (items, ranks) = journal.items_and_ranks_mut();
+        fill(items);
+        sort_unstable_by_key(items, &sort_key);
+        merge_ranks_by_key(data.as_slice(), items, ranks, &sort_key);
+        // the grown capacity has to be on disk before the journal refers to it
+        data.flush()?;
+        self.staged = Some(Box::new(journal));
+        Ok(())
+    }
+
+    /// Apply the merge prepared by [`MmVec::stage_merge`], if any. The vector must not have been modified since.
+    fn commit_staged_merge(&mut self) -> Result<(), MmVecError> {
+        let Some(mut journal) = self.staged.take() else {
+            return Ok(());
+        };
+        self.record_update();
+        let Some(data) = self.data.as_mut() else {
+            return Ok(());
+        };
+        let (old_len, n) = (journal.old_len, journal.len);
+        assert_eq!(
+            data.len(),
+            old_len as u64,
+            "vector was modified after a merge was staged"
+        );
+        journal.commit()?;
+        if let Err(err) = data.run_merge(
+            &mut journal,
+            MergeState {
+                current: old_len,
+                items: n,
+            },
+            0,
+        ) {
+            // the vector is in the middle of the merge, so it is not usable until the journal completes it
+            drop(journal);
+            drop(self.take_data());
+            return Err(err.into());
+        }
+        journal.remove()?;
+        self.mark_clean();
+        Ok(())
+    }
+
+    /// Complete a merge of [`MmVec::merge_in_place`] which was interrupted, if any.
+    fn complete_interrupted_merge(data: &mut Data<T>, path: &Path) -> Result<(), MmVecError> {
+        let Some(mut journal) = Journal::open(journal_path(path), data.sig())? else {
+            return Ok(());
+        };
+        let (old_len, new_len) = (journal.old_len, journal.old_len + journal.len);
+        let len = data.len();
+        if len == new_len as u64 {
+            // the merge was completed, but the journal was not removed yet
+            if data.merging() {
+                data.end_update();
+                data.flush()?;
+            }
+            journal.remove()?;
+            return Ok(());
+        }
+        if len != old_len as u64 || data.capacity() < new_len {
+            let journal_len = old_len as u64;
+            return Err(MmVecError::corrupted(
+                path,
+                Corruption::JournalMismatch { len, journal_len },
+            ));
+        }
+        let (state, seq) = match journal.last_checkpoint() {
+            Some(checkpoint) => {
+                // the step of the checkpoint may have been interrupted, so it is redone from the saved items
+                data.restore_item_bytes(checkpoint.scratch_start, journal.scratch(&checkpoint));
+                (checkpoint.state, checkpoint.seq)
+            }
+            None => (
+                MergeState {
+                    current: old_len,
+                    items: journal.len,
+                },
+                0,
+            ),
+        };
+        data.run_merge(&mut journal, state, seq)?;
+        journal.remove()?;
+        Ok(())
+    }
+
+    /// Resize the vector. New items, if any, are zero-initialized.
+    pub fn resize_zeroed(&mut self, new_len: usize) -> Result<(), MmVecError> {
+        self.check_writable()?;
+        if new_len > self.len() {
+            self.check_len(new_len as u64)?;
+        }
+        self.resize(new_len)
+    }
+
+    /// Shrink the backing file to fit exactly the current contents.
+    ///
+    /// Crash-safe in the same way as [`MmVec::remove_matching`]. The backing file is never truncated in place, so
+    /// readers can keep using their mappings.
+    pub fn shrink_to_fit(&mut self) -> Result<(), MmVecError> {
+        self.check_writable()?;
+        let len = self.len();
+        if len != self.capacity() {
+            self.rewrite(len, |current, new| {
+                new.copy_from_slice(current);
+                len
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Resize the vector, growing the capacity geometrically if needed. Shrinking keeps the capacity.
+    /// New items, if any, are zero-initialized.
+    fn resize(&mut self, new_len: usize) -> Result<(), MmVecError> {
+        self.detach_snapshots()?;
+        self.record_update();
+        let Some(data) = self.data.as_mut() else {
+            return Ok(());
+        };
+        let (len, capacity) = (data.len() as usize, data.capacity());
+        if new_len <= capacity {
+            // Safety: items within capacity are backed by the file
+            unsafe {
+                data.set_len(new_len as u64);
+                // items past the current length may contain stale data
+                if new_len > len {
+                    data.slice_mut(len..new_len).fill_with(|| std::mem::zeroed());
+                    self.mark_dirty((new_len - len) * size_of::<T>());
+                }
+            }
+            return self.maybe_flush();
+        }
+        // the extended part of the file is zeroed by the OS, but the rest of the old capacity may contain stale data
+        unsafe {
+            data.set_len(capacity as u64);
+            data.slice_mut(len..capacity).fill_with(|| std::mem::zeroed());
+            self.mark_dirty((capacity - len) * size_of::<T>());
+            self.resize_capacity(self.grown_capacity(new_len), new_len)?;
+        }
+        self.maybe_flush()
+    }
+
+    /// Capacity to grow to so that `new_len` items fit: at least double the current capacity, within the limits.
+    fn grown_capacity(&self, new_len: usize) -> usize {
+        let max_len = self.max_len.unwrap_or(usize::MAX).min(platform_max_len::<T>());
+        new_len.max(self.capacity().saturating_mul(2).min(max_len))
+    }
+
+    /// Resize the backing file to fit `capacity` items and set the length to `new_len`. Both resizing and inserts grow
+    /// the vector through here, so the backing file is resized in place rather than replaced.
+    unsafe fn resize_capacity(&mut self, capacity: usize, new_len: usize) -> Result<(), MmVecError> {
+        // the background flusher must not hold on to mappings which are about to be released
+        if let Some(flusher) = self.flusher.as_ref() {
+            flusher.set_mappings(None);
+        }
+        self.data.as_mut().map_or(Ok(()), |d| unsafe {
+            d.resize_capacity(capacity)?;
+            d.set_len(new_len as u64);
+            d.advise(self.access_pattern);
+            if self.huge_pages {
+                d.set_huge_pages(true).ok();
+            }
+            Ok::<_, io::Error>(())
+        })?;
+        self.track_mappings();
+        if let Some(data) = self.data.as_mut() {
+            data.set_generation(data.generation() + 1);
         }
 
         Ok(())
     }
 }
 
+/// Changes made by [`MmVec::recover`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// Length recorded in the header.
+    pub header_len: u64,
+    /// Length of the recovered vector.
+    pub recovered_len: u64,
+    /// Number of trailing bytes removed from the file.
+    pub truncated_bytes: u64,
+}
+
+impl RecoveryReport {
+    /// Number of items which were lost.
+    #[must_use]
+    pub fn dropped_items(&self) -> u64 {
+        self.header_len - self.recovered_len
+    }
+
+    /// Whether the file was loadable as is.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.dropped_items() == 0 && self.truncated_bytes == 0
+    }
+}
+
+/// Maximum size of the user metadata stored in the header, see [`MmVec::set_metadata`].
+pub const MAX_METADATA_LEN: usize = 256;
+
+/// Size of the file header. Header layout: signature, length, capacity, generation, metadata length (all `u64`, native
+/// endianness), followed by the metadata, creation time, last flush time, update counter, format magic, format version
+/// and the merging flag. The rest is reserved, and zeroed. The size keeps the items aligned.
+pub(crate) const HEADER_SIZE: u64 = 384;
+
+/// Offset of the metadata in the header.
+pub(crate) const METADATA_OFFSET: usize = 40;
+
+/// Offsets of the creation time, last flush time (both in milliseconds since the Unix epoch) and the update counter in
+/// the header.
+pub(crate) const CREATED_OFFSET: usize = 296;
+pub(crate) const LAST_FLUSH_OFFSET: usize = 304;
+pub(crate) const UPDATES_OFFSET: usize = 312;
+
+/// Offsets of the format magic and the format version in the header.
+pub(crate) const MAGIC_OFFSET: usize = 320;
+pub(crate) const VERSION_OFFSET: usize = 328;
+
+/// Offset of the flag which is set while a merge moves items in place, see [`MmVec::is_stale`]. Files written before
+/// it was introduced have it zeroed.
+pub(crate) const MERGING_OFFSET: usize = 336;
+
+/// Marks files which have a format version. In files of the baseline format, items are stored at its place.
+pub(crate) const FORMAT_MAGIC: u64 = u64::from_le_bytes(*b"HLOOMVEC");
+
+/// Version of the file format written by this version of the crate, see [`MmVecError::UnsupportedFormat`].
+pub const FORMAT_VERSION: u64 = 1;
+
+/// Size of the header of the baseline format: signature and length.
+const BASELINE_HEADER_SIZE: u64 = 16;
+
+/// Distance between bytes touched by [`MmVec::populate`]; the smallest common page size.
+const POPULATE_STRIDE: usize = 4096;
+
+/// Granularity of dirty range tracking; modifications within the same page are flushed together.
+const DIRTY_PAGE_SIZE: usize = 4096;
+
+/// Number of separate dirty ranges tracked, beyond which they are merged into a single range spanning all of them.
+const MAX_DIRTY_RANGES: usize = 64;
+
+/// Number of items read at once by [`MmVec::iter`].
+const ITER_CHUNK_LEN: usize = 4096;
+
+/// Amount of data moved in one step of an in-place merge; this much is written to the journal before each step.
+const MERGE_STEP_BYTES: usize = 16 << 20;
+
+/// Iterator over chunks of a [`MmVec`], created by [`MmVec::chunks`].
+pub struct Chunks<'a, T>
+where
+    T: Pod,
+{
+    vec: &'a MmVec<T>,
+    inner: slice::Chunks<'a, T>,
+}
+
+impl<'a, T> Iterator for Chunks<'a, T>
+where
+    T: Pod,
+{
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for Chunks<'_, T> where T: Pod {}
+
+/// Read-only view of the contents of a [`MmVec`], created by [`MmVec::snapshot`].
+///
+/// The snapshot does not hold any locks and stays valid after the vector is modified or dropped.
+pub struct MmVecSnapshot<T>
+where
+    T: Pod,
+{
+    mapped: Option<Arc<MmapRaw>>,
+    len: usize,
+    generation: u64,
+    _token: Arc<()>,
+    dummy: PhantomData<T>,
+}
+
+impl<T> MmVecSnapshot<T>
+where
+    T: Pod,
+{
+    /// Length of the vector at the time of the snapshot.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Generation of the vector at the time of the snapshot.
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Get contents as a slice.
+    #[must_use]
+    pub fn as_slice(&self) -> &[T] {
+        self.mapped.as_ref().map_or(&[], |mapped| {
+            let len = self.len.min(mapped.len() / size_of::<T>());
+            unsafe { slice::from_raw_parts(mapped.as_ptr().cast::<T>(), len) }
+        })
+    }
+}
+
+impl<T> Drop for Chunks<'_, T>
+where
+    T: Pod,
+{
+    fn drop(&mut self) {
+        if let Some(data) = self.vec.data.as_ref() {
+            data.advise(self.vec.access_pattern);
+        }
+    }
+}
+
 /// Low-level memory-mapped data
 struct Data<T>
 where
-    T: Copy,
+    T: Pod,
 {
     #[allow(unused)]
     file: File,
-    mapped_header: MmapMut,
-    mapped_data: MmapMut,
+    // shared with the background flusher, if any
+    mapped_header: Arc<MmapRaw>,
+    mapped_data: Arc<MmapRaw>,
+    /// Parts of `mapped_data` modified through [`Data::slice_mut`] since the last flush.
+    dirty: Arc<Mutex<DirtyRanges>>,
+    read_only: bool,
+    /// Generation at the time the file was mapped.
+    mapped_generation: u64,
     dummy: PhantomData<T>,
 }
 
 impl<T> Data<T>
 where
-    T: Copy,
+    T: Pod,
 {
-    const HEADER_SIZE: u64 = 16;
+    const HEADER_SIZE: u64 = HEADER_SIZE;
 
     /// The caller must ensure that the file is not tampered with, and contains a valid `Data`
-    unsafe fn from_file_unchecked_impl(file: File) -> io::Result<Self> {
+    unsafe fn from_file_unchecked_impl(file: File, read_only: bool) -> io::Result<Self> {
+        // the data section starts right after the header, at a page offset which is a multiple of the header size
+        const {
+            assert!(
+                (Self::HEADER_SIZE as usize).is_multiple_of(align_of::<T>()),
+                "type is aligned to more than 64 bytes"
+            )
+        };
         let len_bytes = file.metadata()?.len();
 
-        // TODO proper error
+        // loads check the size of the file first, and report a corruption
         assert!(len_bytes >= Self::HEADER_SIZE, "file is too small");
+        let data_len = usize::try_from(len_bytes - Self::HEADER_SIZE).map_err(|_| file_too_large())?;
 
-        let header_mmap = unsafe { mmap(&file, 0, Self::HEADER_SIZE as usize) }?;
-        let data_mmap = unsafe { mmap(&file, Self::HEADER_SIZE, (len_bytes - Self::HEADER_SIZE) as usize) }?;
+        let header_mmap = unsafe { mmap(&file, 0, Self::HEADER_SIZE as usize, read_only) }?;
+        let data_mmap = unsafe { mmap(&file, Self::HEADER_SIZE, data_len, read_only) }?;
 
-        Ok(Self {
+        let mut data = Self {
             file,
-            mapped_header: header_mmap,
-            mapped_data: data_mmap,
+            mapped_header: Arc::new(header_mmap),
+            mapped_data: Arc::new(data_mmap),
+            dirty: Arc::default(),
+            read_only,
+            mapped_generation: 0,
             dummy: PhantomData,
-        })
+        };
+        data.mapped_generation = data.generation();
+        Ok(data)
+    }
+
+    /// Memory-maps the file for reading only, without locking it. The caller must ensure that the file contains a
+    /// valid `Data`
+    unsafe fn from_file_read_only(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        unsafe { Self::from_file_unchecked_impl(file, true) }
     }
 
     /// Memory-maps the file. The caller must ensure that the file contains a valid `Data`
     unsafe fn from_file_unchecked(path: &Path) -> io::Result<Self> {
-        let file = open_file(path)?;
-        file.try_lock_exclusive()?;
-        unsafe { Self::from_file_unchecked_impl(file) }
+        unsafe { Self::from_file_locked(path, LockMode::Exclusive) }
     }
 
-    /// Memory maps the file, resizing it to fit `len` Ts.
-    #[allow(unused)]
-    fn from_file_unchecked_resized(path: &Path, len: usize) -> io::Result<Self> {
+    /// Memory-maps the file, locking it as requested. The caller must ensure that the file contains a valid `Data`
+    unsafe fn from_file_locked(path: &Path, lock: LockMode) -> io::Result<Self> {
         let file = open_file(path)?;
-        file.try_lock_exclusive()?;
-        resize_file_to_fit::<T>(&file, Self::HEADER_SIZE, len)?;
-        // Safety:
-        // It is safe to memory-map this file, because:
-        // 1) We own the file handle and hold an exclusive file lock.
-        // 2) We do not read any data from the memory maps.
-        let mut data = unsafe { Self::from_file_unchecked_impl(file)? };
-        // Safety: we know that the file is sized to contain exactly len Ts
-        unsafe { data.set_len(len as u64) };
-        Ok(data)
+        lock_file(&file, lock)?;
+        unsafe { Self::from_file_unchecked_impl(file, false) }
     }
 
     /// Memory maps the file, resizing it to fit `len` Ts and initializing the header section.
     pub fn new_uninit(path: &Path, sig: u64, len: usize) -> io::Result<Self> {
+        Self::new_uninit_locked(path, sig, len, LockMode::Exclusive)
+    }
+
+    /// Same as `new_uninit`, locking the file as requested.
+    pub fn new_uninit_locked(path: &Path, sig: u64, len: usize, lock: LockMode) -> io::Result<Self> {
         let file = create_new_file(path)?;
-        file.try_lock_exclusive()?;
+        lock_file(&file, lock)?;
         resize_file_to_fit::<T>(&file, Self::HEADER_SIZE, len)?;
         // Safety:
         // It is safe to memory-map this file, because:
         // 1) We own the file handle and hold an exclusive file lock.
         // 2) We do not read any data from the memory maps.
-        let mut data = unsafe { Self::from_file_unchecked_impl(file)? };
+        let mut data = unsafe { Self::from_file_unchecked_impl(file, false)? };
         data.set_sig(sig);
+        data.set_format();
+        data.set_created(unix_millis_now());
+        data.set_header_capacity(len as u64);
         // Safety: we know that the file is sized to contain exactly len Ts
         unsafe { data.set_len(len as u64) };
         data.mapped_header.flush()?;
@@ -300,30 +1701,26 @@ where
 
     /// Memory maps the file, resizing it to fit `len` Ts, initializing header section and copying the
     /// data from `slice` into it.
-    pub fn new_with_data(path: &Path, sig: u64, slice: &[T]) -> io::Result<Self> {
-        let mut data = Self::new_uninit(path, sig, slice.len())?;
-        // Safety:
-        // It is safe to cast underlying data to &mut [T] and then write to it because:
-        // 1) we own the file handle and hold an exclusive file lock;
-        // 2) we won't read any uninitialized data;
-        // 3) `Self::new_uninit` created a file which is sized to hold exactly `slice.len()` Ts - so we know
-        // that we can fill it with `slice.len()` valid Ts.
-        unsafe { data.as_slice_mut() }.copy_from_slice(slice);
+    pub fn new_with_data(path: &Path, sig: u64, slice: &[T], lock: LockMode) -> io::Result<Self> {
+        let mut data = Self::new_uninit_locked(path, sig, slice.len(), lock)?;
+        // `Self::new_uninit` created a file which is sized to hold exactly `slice.len()` Ts
+        data.as_slice_mut().copy_from_slice(slice);
         Ok(data)
     }
 
     fn header_offset(&self, offset: usize) -> *const u8 {
         let start = self.mapped_header.as_ptr();
         assert!(offset < Self::HEADER_SIZE as usize, "offset is out of bounds");
-        assert!(offset % 8 == 0, "offset is not placed on u64 boundary");
+        assert!(offset.is_multiple_of(8), "offset is not placed on u64 boundary");
         // Safety: we checked prerequisites for `add`
         unsafe { start.add(offset) }
     }
 
     fn header_offset_mut(&mut self, offset: usize) -> *mut u8 {
+        assert!(!self.read_only, "header of a read-only mapping can't be modified");
         let start = self.mapped_header.as_mut_ptr();
         assert!(offset < Self::HEADER_SIZE as usize, "offset is out of bounds");
-        assert!(offset % 8 == 0, "offset is not placed on u64 boundary");
+        assert!(offset.is_multiple_of(8), "offset is not placed on u64 boundary");
         // Safety: we checked prerequisites for `add`
         unsafe { start.add(offset) }
     }
@@ -348,6 +1745,16 @@ where
         }
     }
 
+    /// Mark the header as being in the current format.
+    fn set_format(&mut self) {
+        // Safety:
+        // See safety comment in `.set_sig()`, same applies here.
+        unsafe {
+            *self.header_offset_mut(MAGIC_OFFSET).cast::<u64>() = FORMAT_MAGIC;
+            *self.header_offset_mut(VERSION_OFFSET).cast::<u64>() = FORMAT_VERSION;
+        }
+    }
+
     pub fn len(&self) -> u64 {
         // Safety:
         // See safety comment in `.sig()`, same applies here.
@@ -358,72 +1765,1152 @@ where
         unsafe { *self.header_offset_mut(8).cast::<u64>() = len };
     }
 
-    pub fn capacity(&self) -> usize {
-        self.mapped_data.len() / std::mem::size_of::<T>()
-    }
+    pub fn generation(&self) -> u64 {
+        // Safety: see `set_generation`
+        unsafe { AtomicU64::from_ptr(self.header_offset(24).cast::<u64>().cast_mut()) }.load(Ordering::SeqCst)
+    }
+
+    fn set_generation(&mut self, generation: u64) {
+        // Safety:
+        // See safety comment in `.set_sig()`, same applies here. Readers in other processes load the generation while
+        // it is stored, so it is accessed atomically.
+        unsafe { AtomicU64::from_ptr(self.header_offset_mut(24).cast::<u64>()) }.store(generation, Ordering::SeqCst);
+    }
+
+    /// Whether a merge is moving items in place.
+    pub fn merging(&self) -> bool {
+        // Safety: see `set_merging`
+        unsafe { AtomicU64::from_ptr(self.header_offset(MERGING_OFFSET).cast::<u64>().cast_mut()) }
+            .load(Ordering::SeqCst)
+            != 0
+    }
+
+    fn set_merging(&mut self, merging: bool) {
+        // Safety:
+        // See safety comment in `.set_generation()`, same applies here.
+        unsafe { AtomicU64::from_ptr(self.header_offset_mut(MERGING_OFFSET).cast::<u64>()) }
+            .store(merging.into(), Ordering::SeqCst);
+    }
+
+    /// Let readers know that items are about to be moved in place. They see the vector as stale (see
+    /// [`MmVec::is_stale`]) until [`Data::end_update`] publishes the result.
+    fn begin_update(&mut self) {
+        self.set_merging(true);
+        self.set_generation(self.generation() + 1);
+    }
+
+    /// Publish the changes made since [`Data::begin_update`], along with a new generation.
+    fn end_update(&mut self) {
+        self.set_generation(self.generation() + 1);
+        self.set_merging(false);
+    }
+
+    pub fn metadata(&self) -> &[u8] {
+        // Safety:
+        // See safety comment in `.sig()`, same applies here.
+        let len = unsafe { *self.header_offset(32).cast::<u64>() };
+        // the header may be corrupted, or updated by a writer in another process
+        let len = (len as usize).min(MAX_METADATA_LEN);
+        unsafe { slice::from_raw_parts(self.header_offset(METADATA_OFFSET), len) }
+    }
+
+    fn set_metadata(&mut self, metadata: &[u8]) {
+        assert!(metadata.len() <= MAX_METADATA_LEN, "metadata is too long");
+        // Safety:
+        // See safety comment in `.set_sig()`, same applies here; the metadata fits into the header.
+        unsafe {
+            let start = self.header_offset_mut(METADATA_OFFSET);
+            start.copy_from_nonoverlapping(metadata.as_ptr(), metadata.len());
+            *self.header_offset_mut(32).cast::<u64>() = metadata.len() as u64;
+        }
+    }
+
+    /// Creation time, in milliseconds since the Unix epoch.
+    pub fn created(&self) -> u64 {
+        // Safety:
+        // See safety comment in `.sig()`, same applies here.
+        unsafe { *self.header_offset(CREATED_OFFSET).cast::<u64>() }
+    }
+
+    fn set_created(&mut self, millis: u64) {
+        // Safety:
+        // See safety comment in `.set_sig()`, same applies here.
+        unsafe {
+            *self.header_offset_mut(CREATED_OFFSET).cast::<u64>() = millis;
+        }
+    }
+
+    /// Last flush time, in milliseconds since the Unix epoch, or 0 if never flushed.
+    pub fn last_flush(&self) -> u64 {
+        // Safety: see `record_flush_time`
+        unsafe { AtomicU64::from_ptr(self.header_offset(LAST_FLUSH_OFFSET).cast::<u64>().cast_mut()) }
+            .load(Ordering::Relaxed)
+    }
+
+    pub fn updates(&self) -> u64 {
+        // Safety:
+        // See safety comment in `.sig()`, same applies here.
+        unsafe { *self.header_offset(UPDATES_OFFSET).cast::<u64>() }
+    }
+
+    fn set_updates(&mut self, updates: u64) {
+        // Safety:
+        // See safety comment in `.set_sig()`, same applies here.
+        unsafe {
+            *self.header_offset_mut(UPDATES_OFFSET).cast::<u64>() = updates;
+        }
+    }
+
+    /// Capacity as recorded in the header.
+    pub fn header_capacity(&self) -> u64 {
+        // Safety:
+        // See safety comment in `.sig()`, same applies here.
+        unsafe { *self.header_offset(16).cast::<u64>() }
+    }
+
+    fn set_header_capacity(&mut self, capacity: u64) {
+        // Safety:
+        // See safety comment in `.set_sig()`, same applies here.
+        unsafe {
+            *self.header_offset_mut(16).cast::<u64>() = capacity;
+        }
+    }
+
+    /// Capacity of the memory-mapped region.
+    pub fn capacity(&self) -> usize {
+        self.mapped_data.len() / std::mem::size_of::<T>()
+    }
+
+    /// Size of a file holding `capacity` items.
+    fn expected_file_size(&self, capacity: usize) -> u64 {
+        Self::HEADER_SIZE + (capacity * size_of::<T>()) as u64
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        // the header may be updated by a writer in another process before this mapping is refreshed
+        let len = (self.len() as usize).min(self.capacity());
+        // Safety: the mapping holds at least `len` items, which are valid for any contents since `T` is `Pod`, and
+        // the mapping is aligned for `T` (see `from_file_unchecked_impl`)
+        unsafe { slice::from_raw_parts(self.mapped_data.as_ptr().cast::<T>(), len) }
+    }
+
+    pub fn as_slice_mut(&mut self) -> &mut [T] {
+        self.slice_mut(0..self.len() as usize)
+    }
+
+    /// Items in `range` as a mutable slice; only this range is written to disk on the next flush.
+    ///
+    /// ## Panics
+    /// Panics if `range` is out of bounds, or does not fit in the mapping (i.e. the length in the header is corrupted).
+    pub fn slice_mut(&mut self, range: Range<usize>) -> &mut [T] {
+        assert!(
+            range.start <= range.end && range.end <= self.len() as usize && range.end <= self.capacity(),
+            "range {range:?} is out of bounds"
+        );
+        self.mark_modified(range.clone());
+        // Safety: same as for `as_slice`; the range was checked to be within the mapping
+        unsafe {
+            let start = self.mapped_data.as_mut_ptr().cast::<T>().add(range.start);
+            slice::from_raw_parts_mut(start, range.len())
+        }
+    }
+
+    /// All items within the capacity as a mutable slice, regardless of the length. Modifications are not tracked, see
+    /// [`Data::mark_modified`].
+    fn capacity_slice_mut(&mut self) -> &mut [T] {
+        assert!(!self.read_only, "read-only mapping can't be modified");
+        // Safety: same as for `as_slice`; the mapping holds exactly `capacity` items
+        unsafe { slice::from_raw_parts_mut(self.mapped_data.as_mut_ptr().cast::<T>(), self.capacity()) }
+    }
+
+    /// Make sure that items in `range` are written to disk on the next flush.
+    fn mark_modified(&self, range: Range<usize>) {
+        let size = size_of::<T>();
+        self.dirty
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(range.start * size..range.end * size);
+    }
+
+    /// Raw bytes of the items in `range`, which may be past the length.
+    fn item_bytes(&self, range: Range<usize>) -> &[u8] {
+        assert!(
+            range.start <= range.end && range.end <= self.capacity(),
+            "range {range:?} is out of bounds"
+        );
+        let size = size_of::<T>();
+        // Safety: the range was checked to be within the mapping, and mapped bytes are always initialized
+        unsafe { slice::from_raw_parts(self.mapped_data.as_ptr().add(range.start * size), range.len() * size) }
+    }
+
+    /// Overwrite items starting at `start` with raw `bytes` previously taken from [`Data::item_bytes`].
+    fn restore_item_bytes(&mut self, start: usize, bytes: &[u8]) {
+        let size = size_of::<T>();
+        let range = start..start + bytes.len() / size;
+        assert!(bytes.len().is_multiple_of(size), "bytes do not form whole items");
+        assert!(
+            range.end <= self.capacity() && !self.read_only,
+            "range {range:?} can't be restored"
+        );
+        self.mark_modified(range);
+        // Safety: the range was checked to be within the mapping; any bytes are valid items since `T` is `Pod`
+        unsafe {
+            ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                self.mapped_data.as_mut_ptr().add(start * size),
+                bytes.len(),
+            )
+        };
+    }
+
+    /// Run the merge described by `journal` from `state` on, recording a checkpoint before each step starting with
+    /// `seq`, and then set the new length. Every step is on disk before the next one is recorded.
+    fn run_merge(&mut self, journal: &mut Journal<T>, mut state: MergeState, mut seq: u64) -> io::Result<()> {
+        let new_len = journal.old_len + journal.len;
+        // the new length is only published once all items are in place
+        self.begin_update();
+        while state.items > 0 {
+            let next = merge_step(None, journal.items(), journal.ranks(), state, journal.step);
+            // current items which are overwritten by this step are needed to redo it
+            let scratch_start = (next.current + next.items).min(state.current);
+            journal.checkpoint(seq, state, scratch_start, self.item_bytes(scratch_start..state.current))?;
+            let data = &mut self.capacity_slice_mut()[..new_len];
+            merge_step(Some(data), journal.items(), journal.ranks(), state, journal.step);
+            self.mark_modified(next.current + next.items..state.current + state.items);
+            self.flush()?;
+            state = next;
+            seq += 1;
+        }
+        // Safety: all `new_len` items were written above, and fit in the capacity
+        unsafe { self.set_len(new_len as u64) };
+        self.end_update();
+        self.flush()
+    }
+
+    #[allow(unused)]
+    pub unsafe fn resize(&mut self, len: usize) -> io::Result<()> {
+        unsafe {
+            self.resize_capacity(len)?;
+            self.set_len(len as u64);
+        }
+        Ok(())
+    }
+
+    /// Resize the file and the memory-mapped region to fit `capacity` Ts. Length is truncated to fit.
+    ///
+    /// The file stays open and locked throughout.
+    pub unsafe fn resize_capacity(&mut self, capacity: usize) -> io::Result<()> {
+        // On Windows a file can't be resized while any of its views are mapped, so they are released first.
+        #[cfg(windows)]
+        {
+            self.flush()?;
+            self.mapped_header = Arc::new(MmapOptions::new().len(1).map_anon()?.into());
+            self.mapped_data = Arc::new(MmapOptions::new().len(1).map_anon()?.into());
+        }
+        let new_len_bytes = resize_file_to_fit::<T>(&self.file, Self::HEADER_SIZE, capacity)?;
+        // Safety: we own the file handle, have exclusive lock in place and know that
+        unsafe {
+            #[cfg(windows)]
+            {
+                self.mapped_header = Arc::new(mmap(&self.file, 0, Self::HEADER_SIZE as usize, false)?);
+            }
+            let new_len_bytes = usize::try_from(new_len_bytes).map_err(|_| file_too_large())?;
+            if !self.remap_data(new_len_bytes) {
+                self.mapped_data = Arc::new(mmap(&self.file, Self::HEADER_SIZE, new_len_bytes, false)?);
+            }
+            if self.len() > capacity as u64 {
+                self.set_len(capacity as u64);
+            }
+        }
+        self.set_header_capacity(capacity as u64);
+        Ok(())
+    }
+
+    /// Resize the data mapping with `mremap`, which keeps the address if possible, and does not have to set up the
+    /// page tables from scratch. Returns whether the mapping was resized; it can't be if it is shared (e.g. with a
+    /// snapshot), or if it is empty.
+    ///
+    /// ## Safety
+    /// The file must be at least `len` bytes longer than the header.
+    #[cfg(target_os = "linux")]
+    unsafe fn remap_data(&mut self, len: usize) -> bool {
+        match Arc::get_mut(&mut self.mapped_data) {
+            Some(mapped) if mapped.len() > 0 && len > 0 => {
+                let options = memmap2::RemapOptions::new().may_move(true);
+                unsafe { mapped.remap(len, options) }.is_ok()
+            }
+            _ => false,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    unsafe fn remap_data(&mut self, _len: usize) -> bool {
+        false
+    }
+
+    /// Read ahead the data section, and touch every page of it.
+    fn populate(&self) {
+        // the hint makes the OS read the file in large chunks instead of page by page
+        #[cfg(unix)]
+        self.mapped_data.advise(memmap2::Advice::WillNeed).ok();
+        let ptr = self.mapped_data.as_ptr();
+        for offset in (0..self.mapped_data.len()).step_by(POPULATE_STRIDE) {
+            // Safety: the offset is within the mapping; the value is irrelevant, so concurrent writes do not matter
+            unsafe { ptr.add(offset).read_volatile() };
+        }
+    }
+
+    /// Pass the access pattern hint for the data section to the OS. Hints are best-effort, so errors are ignored.
+    fn advise(&self, pattern: AccessPattern) {
+        #[cfg(unix)]
+        {
+            let advice = match pattern {
+                AccessPattern::Random => memmap2::Advice::Random,
+                AccessPattern::Sequential => memmap2::Advice::Sequential,
+                AccessPattern::WillNeed => memmap2::Advice::WillNeed,
+            };
+            self.mapped_data.advise(advice).ok();
+        }
+        #[cfg(not(unix))]
+        let _ = pattern;
+    }
+
+    /// Enable or disable transparent huge pages for the data section. Does nothing on platforms other than Linux.
+    fn set_huge_pages(&self, enabled: bool) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            let advice = if enabled {
+                memmap2::Advice::HugePage
+            } else {
+                memmap2::Advice::NoHugePage
+            };
+            self.mapped_data.advise(advice)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = enabled;
+        Ok(())
+    }
+
+    /// Mappings to be flushed by the background flusher, unless read-only.
+    fn writable_mappings(&self) -> Option<Mappings> {
+        (!self.read_only).then(|| Mappings {
+            header: Arc::clone(&self.mapped_header),
+            data: Arc::clone(&self.mapped_data),
+            dirty: Arc::clone(&self.dirty),
+        })
+    }
+
+    /// Write the modified parts of the data section, and the header, to disk.
+    pub fn flush(&self) -> io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        flush_mappings(&self.mapped_header, &self.mapped_data, &self.dirty, true)
+    }
+}
+
+impl<T> Drop for Data<T>
+where
+    T: Pod,
+{
+    fn drop(&mut self) {
+        if !self.read_only {
+            let _ = self.flush();
+            let _ = self.file.unlock().ok();
+        }
+    }
+}
+
+/// Thread which periodically flushes the mappings of a vector.
+struct BackgroundFlusher {
+    shared: Arc<(Mutex<FlusherState>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Mappings of a vector, shared with the background flusher.
+struct Mappings {
+    header: Arc<MmapRaw>,
+    data: Arc<MmapRaw>,
+    dirty: Arc<Mutex<DirtyRanges>>,
+}
+
+struct FlusherState {
+    mappings: Option<Mappings>,
+    stop: bool,
+}
+
+impl BackgroundFlusher {
+    fn spawn(period: Duration) -> io::Result<Self> {
+        let state = FlusherState {
+            mappings: None,
+            stop: false,
+        };
+        let shared = Arc::new((Mutex::new(state), Condvar::new()));
+        let thread_shared = Arc::clone(&shared);
+        let thread = std::thread::Builder::new()
+            .name("mmvec-flush".to_owned())
+            .spawn(move || {
+                let (state, stopped) = &*thread_shared;
+                let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                loop {
+                    state = stopped
+                        .wait_timeout(state, period)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                    if state.stop {
+                        break;
+                    }
+                    // the lock is held while flushing, so that the mappings can't be unmapped by the owner meanwhile
+                    if let Some(Mappings { header, data, dirty }) = state.mappings.as_ref() {
+                        flush_mappings(header, data, dirty, false).ok();
+                    }
+                }
+            })?;
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Replace the mappings to flush. Blocks until a flush in progress, if any, is done.
+    fn set_mappings(&self, mappings: Option<Mappings>) {
+        self.shared.0.lock().unwrap_or_else(PoisonError::into_inner).mappings = mappings;
+    }
+}
+
+impl Drop for BackgroundFlusher {
+    fn drop(&mut self) {
+        let (state, stopped) = &*self.shared;
+        state.lock().unwrap_or_else(PoisonError::into_inner).stop = true;
+        stopped.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+pub(crate) fn unix_millis_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Progress of a backward merge: the first `current` items of the vector and the first `items` new items are not
+/// merged yet. Everything past `current + items` is in its final place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MergeState {
+    current: usize,
+    items: usize,
+}
+
+/// Advance a backward merge of sorted `items` into the sorted items at the start of `data` by at most `step` items, and
+/// return the new state. `ranks[j]` is the number of current items which go before `items[j]`. If `data` is `None`,
+/// only the new state is computed.
+///
+/// A step only writes items in `next.current + next.items..state.current + state.items`, and only reads current items
+/// before `state.current`, so it can be redone after being interrupted, as long as the current items it overwrote are
+/// restored.
+fn merge_step<T: Copy>(
+    mut data: Option<&mut [T]>,
+    items: &[T],
+    ranks: &[u64],
+    state: MergeState,
+    step: usize,
+) -> MergeState {
+    let MergeState {
+        current: mut i,
+        items: mut j,
+    } = state;
+    let mut budget = step;
+    while j > 0 && budget > 0 {
+        // ranks never exceed the number of current items left
+        let rank = ranks[j - 1] as usize;
+        if i > rank {
+            // current items which go after the last new item
+            let n = budget.min(i - rank);
+            if let Some(data) = data.as_deref_mut() {
+                data.copy_within(i - n..i, i - n + j);
+            }
+            i -= n;
+            budget -= n;
+        } else {
+            // new items which go after all remaining current items
+            let first = ranks[..j]
+                .partition_point(|rank| (*rank as usize) < i)
+                .max(j.saturating_sub(budget));
+            if let Some(data) = data.as_deref_mut() {
+                data[i + first..i + j].copy_from_slice(&items[first..j]);
+            }
+            budget -= j - first;
+            j = first;
+        }
+    }
+    MergeState { current: i, items: j }
+}
+
+/// Number of items merged in one step of [`MmVec::insert_sorted`].
+fn merge_step_len<T>() -> usize {
+    (MERGE_STEP_BYTES / size_of::<T>().max(1)).max(1)
+}
+
+/// Path of the journal of an in-place merge into the vector at `path`.
+fn journal_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".journal");
+    path.into()
+}
+
+/// Checkpoint of a merge step recorded in a [`Journal`]: the state before the step, and where the current items
+/// overwritten by the step were taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Checkpoint {
+    seq: u64,
+    state: MergeState,
+    scratch_start: usize,
+}
+
+/// Write-ahead journal of an in-place merge, stored next to the backing file while [`MmVec::insert_sorted`] runs.
+///
+/// Layout: a header (magic, signature, item size, length before the merge, number of new items, step length, and a
+/// checksum of these, all `u64`), two checkpoint records (sequence number, state, start of the scratch items, checksum
+/// of the scratch items, and a checksum of these), the ranks of the new items (`u64`), the new items, and two scratch
+/// slots. Checkpoint `seq` goes into record and slot `seq % 2`, so a torn checkpoint leaves the previous one intact.
+///
+/// The header is written once everything else is on disk, so a journal with a valid header describes a merge which may
+/// have started.
+struct Journal<T>
+where
+    T: Pod,
+{
+    #[allow(unused)]
+    file: File,
+    path: PathBuf,
+    mapped: ManuallyDrop<MmapRaw>,
+    sig: u64,
+    /// Length of the vector before the merge.
+    old_len: usize,
+    /// Number of new items.
+    len: usize,
+    /// Maximum number of items written in one step.
+    step: usize,
+    /// Whether the merge may have started; the file is removed on drop otherwise.
+    committed: bool,
+    dummy: PhantomData<T>,
+}
+
+impl<T> Journal<T>
+where
+    T: Pod,
+{
+    const MAGIC: u64 = u64::from_le_bytes(*b"HLOOJRNL");
+    const HEADER_WORDS: usize = 7;
+    const RECORD_WORDS: usize = 6;
+    const RECORDS_OFFSET: usize = 64;
+    const RANKS_OFFSET: usize = 192;
+
+    /// Create a journal for merging `len` items into `old_len` items. The new items and their ranks have to be filled
+    /// in before [`Journal::commit`].
+    fn create(path: PathBuf, sig: u64, old_len: usize, len: usize, step: usize) -> io::Result<Self> {
+        let size = Self::file_size(old_len, len, step).ok_or_else(file_too_large)?;
+        // a leftover journal of a completed merge is replaced
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)?;
+        file.set_len(size as u64)?;
+        // Safety: the file was just created by us, and is not modified by anyone else
+        let mapped = unsafe { mmap(&file, 0, size, false)? };
+        Ok(Self {
+            file,
+            path,
+            mapped: ManuallyDrop::new(mapped),
+            sig,
+            old_len,
+            len,
+            step,
+            committed: false,
+            dummy: PhantomData,
+        })
+    }
+
+    /// Open the journal at `path`, if there is a valid one. Invalid journals are left behind by merges which did not
+    /// start, so they are removed.
+    fn open(path: PathBuf, sig: u64) -> io::Result<Option<Self>> {
+        let file = match open_file(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let size = usize::try_from(file.metadata()?.len()).map_err(|_| file_too_large())?;
+        if size < Self::RANKS_OFFSET {
+            drop(file);
+            remove_file(&path)?;
+            return Ok(None);
+        }
+        // Safety: the journal is only used by the owner of the vector, which holds its lock, and its contents are
+        // validated before use
+        let mapped = unsafe { mmap(&file, 0, size, false)? };
+        let mut journal = Self {
+            file,
+            path,
+            mapped: ManuallyDrop::new(mapped),
+            sig,
+            old_len: 0,
+            len: 0,
+            step: 0,
+            committed: true,
+            dummy: PhantomData,
+        };
+        let header = journal.words(0, Self::HEADER_WORDS);
+        let fields = (
+            usize::try_from(header[3]),
+            usize::try_from(header[4]),
+            usize::try_from(header[5]),
+        );
+        let valid =
+            header[..3] == [Self::MAGIC, sig, size_of::<T>() as u64] && header[6] == fnv1a(header[..6].iter().copied());
+        if let (true, (Ok(old_len), Ok(len), Ok(step))) = (valid, fields)
+            && step > 0
+            && Self::file_size(old_len, len, step) == Some(size)
+        {
+            (journal.old_len, journal.len, journal.step) = (old_len, len, step);
+            return Ok(Some(journal));
+        }
+        journal.committed = false;
+        drop(journal);
+        Ok(None)
+    }
+
+    /// Size of a journal for merging `len` items into `old_len` items, or `None` if it does not fit in memory.
+    fn file_size(old_len: usize, len: usize, step: usize) -> Option<usize> {
+        let ranks_size = len.checked_mul(size_of::<u64>())?;
+        let items_size = len.checked_mul(size_of::<T>())?.checked_next_multiple_of(64)?;
+        let slot_size = step
+            .min(old_len)
+            .checked_mul(size_of::<T>())?
+            .checked_next_multiple_of(64)?;
+        ranks_size
+            .checked_add(Self::RANKS_OFFSET)?
+            .checked_next_multiple_of(64)?
+            .checked_add(items_size)?
+            .checked_add(slot_size.checked_mul(2)?)
+    }
+
+    /// Offset of the new items.
+    fn items_offset(&self) -> usize {
+        (Self::RANKS_OFFSET + self.len * size_of::<u64>()).next_multiple_of(64)
+    }
+
+    /// Maximum number of items in a scratch slot; only current items are saved, and at most a step of them.
+    fn slot_len(&self) -> usize {
+        self.step.min(self.old_len)
+    }
+
+    /// Offset of scratch slot `slot`.
+    fn slot_offset(&self, slot: usize) -> usize {
+        let slot_size = (self.slot_len() * size_of::<T>()).next_multiple_of(64);
+        self.items_offset() + (self.len * size_of::<T>()).next_multiple_of(64) + slot * slot_size
+    }
+
+    fn words(&self, offset: usize, len: usize) -> &[u64] {
+        assert!(
+            offset + len * size_of::<u64>() <= self.mapped.len(),
+            "offset is out of bounds"
+        );
+        // Safety: the range is within the mapping, which is page-aligned, and offsets are multiples of 8
+        unsafe { slice::from_raw_parts(self.mapped.as_ptr().add(offset).cast::<u64>(), len) }
+    }
+
+    fn words_mut(&mut self, offset: usize, len: usize) -> &mut [u64] {
+        assert!(
+            offset + len * size_of::<u64>() <= self.mapped.len(),
+            "offset is out of bounds"
+        );
+        // Safety: same as for `words`
+        unsafe { slice::from_raw_parts_mut(self.mapped.as_mut_ptr().add(offset).cast::<u64>(), len) }
+    }
+
+    fn items(&self) -> &[T] {
+        // Safety: the items are within the mapping and aligned for `T` (see `Data::from_file_unchecked_impl`), and
+        // any contents are valid since `T` is `Pod`
+        unsafe { slice::from_raw_parts(self.mapped.as_ptr().add(self.items_offset()).cast::<T>(), self.len) }
+    }
+
+    fn ranks(&self) -> &[u64] {
+        self.words(Self::RANKS_OFFSET, self.len)
+    }
+
+    /// The new items and their ranks, to be filled in before [`Journal::commit`].
+    fn items_and_ranks_mut(&mut self) -> (&mut [T], &mut [u64]) {
+        // Safety: same as for `items`; the items and the ranks do not overlap
+        unsafe {
+            let items = self.mapped.as_mut_ptr().add(self.items_offset()).cast::<T>();
+            let ranks = self.mapped.as_mut_ptr().add(Self::RANKS_OFFSET).cast::<u64>();
+            (
+                slice::from_raw_parts_mut(items, self.len),
+                slice::from_raw_parts_mut(ranks, self.len),
+            )
+        }
+    }
+
+    /// Write the journal to disk. Once this returns, the merge may start, and is completed when the vector is opened
+    /// if it is interrupted.
+    fn commit(&mut self) -> io::Result<()> {
+        self.mapped.flush()?;
+        let header = [
+            Self::MAGIC,
+            self.sig,
+            size_of::<T>() as u64,
+            self.old_len as u64,
+            self.len as u64,
+            self.step as u64,
+        ];
+        let words = self.words_mut(0, Self::HEADER_WORDS);
+        words[..6].copy_from_slice(&header);
+        words[6] = fnv1a(header);
+        self.mapped.flush_range(0, Self::HEADER_WORDS * size_of::<u64>())?;
+        sync_parent_dir(&self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    /// Record that the step `seq` starts from `state`, and overwrites current items starting at `scratch_start`, whose
+    /// contents are `scratch`.
+    fn checkpoint(&mut self, seq: u64, state: MergeState, scratch_start: usize, scratch: &[u8]) -> io::Result<()> {
+        let slot = (seq % 2) as usize;
+        let offset = self.slot_offset(slot);
+        assert!(
+            scratch.len() <= self.slot_len() * size_of::<T>(),
+            "scratch does not fit in a slot"
+        );
+        // Safety: the slot is within the mapping, and was checked to be large enough
+        unsafe { ptr::copy_nonoverlapping(scratch.as_ptr(), self.mapped.as_mut_ptr().add(offset), scratch.len()) };
+        let record = [
+            seq,
+            state.current as u64,
+            state.items as u64,
+            scratch_start as u64,
+            fnv1a_bytes(scratch.iter().copied()),
+        ];
+        let words = self.words_mut(Self::RECORDS_OFFSET + slot * 64, Self::RECORD_WORDS);
+        words[..5].copy_from_slice(&record);
+        words[5] = fnv1a(record);
+        if !scratch.is_empty() {
+            self.mapped.flush_range(offset, scratch.len())?;
+        }
+        self.mapped
+            .flush_range(Self::RECORDS_OFFSET + slot * 64, Self::RECORD_WORDS * size_of::<u64>())
+    }
+
+    /// Latest checkpoint which is completely on disk, if any.
+    fn last_checkpoint(&self) -> Option<Checkpoint> {
+        (0..2)
+            .filter_map(|slot| {
+                let words = self.words(Self::RECORDS_OFFSET + slot * 64, Self::RECORD_WORDS);
+                if words[5] != fnv1a(words[..5].iter().copied()) || words[0] % 2 != slot as u64 {
+                    return None;
+                }
+                let state = MergeState {
+                    current: usize::try_from(words[1])
+                        .ok()
+                        .filter(|current| *current <= self.old_len)?,
+                    items: usize::try_from(words[2]).ok().filter(|items| *items <= self.len)?,
+                };
+                let scratch_start = usize::try_from(words[3]).ok().filter(|start| *start <= state.current)?;
+                let checkpoint = Checkpoint {
+                    seq: words[0],
+                    state,
+                    scratch_start,
+                };
+                let valid = state.current - scratch_start <= self.slot_len()
+                    && fnv1a_bytes(self.scratch(&checkpoint).iter().copied()) == words[4];
+                valid.then_some(checkpoint)
+            })
+            .max_by_key(|checkpoint| checkpoint.seq)
+    }
+
+    /// Current items overwritten by the step of `checkpoint`, as they were before the step.
+    fn scratch(&self, checkpoint: &Checkpoint) -> &[u8] {
+        let offset = self.slot_offset((checkpoint.seq % 2) as usize);
+        let len = (checkpoint.state.current - checkpoint.scratch_start) * size_of::<T>();
+        // Safety: the slot is within the mapping, and holds at most `slot_len` items
+        unsafe { slice::from_raw_parts(self.mapped.as_ptr().add(offset), len) }
+    }
+
+    /// Remove the journal once the merge is complete.
+    fn remove(self) -> io::Result<()> {
+        let path = self.path.clone();
+        // unmapped first, since a mapped file can't be removed on some platforms
+        drop(self);
+        remove_file(path)
+    }
+}
+
+impl<T> Drop for Journal<T>
+where
+    T: Pod,
+{
+    fn drop(&mut self) {
+        // Safety: the mapping is not used anymore
+        unsafe { ManuallyDrop::drop(&mut self.mapped) };
+        if !self.committed {
+            remove_file(&self.path).ok();
+        }
+    }
+}
+
+/// Byte ranges of a data mapping modified since the last flush, page-aligned, sorted, and not touching each other.
+#[derive(Debug, Default)]
+struct DirtyRanges {
+    ranges: Vec<Range<usize>>,
+}
+
+impl DirtyRanges {
+    fn insert(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let range = range.start / DIRTY_PAGE_SIZE * DIRTY_PAGE_SIZE..range.end.next_multiple_of(DIRTY_PAGE_SIZE);
+        // ranges overlapping or adjacent to the new one are merged with it
+        let first = self.ranges.partition_point(|r| r.end < range.start);
+        let last = self.ranges.partition_point(|r| r.start <= range.end);
+        let merged = if first < last {
+            self.ranges[first].start.min(range.start)..self.ranges[last - 1].end.max(range.end)
+        } else {
+            range
+        };
+        self.ranges.splice(first..last, [merged]);
+        if self.ranges.len() > MAX_DIRTY_RANGES {
+            let bounds = self.ranges[0].start..self.ranges[self.ranges.len() - 1].end;
+            self.ranges = vec![bounds];
+        }
+    }
+}
+
+/// Flush the modified parts of `data`, then record the flush time in `header` and flush it.
+///
+/// The dirty ranges are forgotten only if `clear` is set. The background flusher does not clear them, since it may
+/// run between a mutable slice being handed out (which marks the range) and the actual writes.
+fn flush_mappings(header: &MmapRaw, data: &MmapRaw, dirty: &Mutex<DirtyRanges>, clear: bool) -> io::Result<()> {
+    let mut dirty = dirty.lock().unwrap_or_else(PoisonError::into_inner);
+    for range in &dirty.ranges {
+        // the mapping may have shrunk since the range was modified
+        let end = range.end.min(data.len());
+        if range.start < end {
+            data.flush_range(range.start, end - range.start)?;
+        }
+    }
+    if clear {
+        dirty.ranges.clear();
+    }
+    drop(dirty);
+    record_flush_time(header);
+    header.flush()
+}
+
+/// Store the current time as the last flush time in a mapped header.
+fn record_flush_time(header: &MmapRaw) {
+    // Safety: the header is at least `HEADER_SIZE` bytes long and page-aligned, so the field is in bounds and aligned.
+    // It is updated both by the owner of the mapping and by the background flusher, so it is only accessed atomically.
+    let field = unsafe { AtomicU64::from_ptr(header.as_mut_ptr().add(LAST_FLUSH_OFFSET).cast::<u64>()) };
+    field.store(unix_millis_now(), Ordering::Relaxed);
+}
+
+/// Lock the file, failing if it is already locked by someone else.
+pub(crate) fn lock_file(file: &File, lock: LockMode) -> io::Result<()> {
+    let locked = match lock {
+        LockMode::Exclusive => FileExt::try_lock_exclusive(file)?,
+        LockMode::Shared => FileExt::try_lock_shared(file)?,
+        LockMode::None => true,
+    };
+    if !locked {
+        return Err(io::Error::new(io::ErrorKind::WouldBlock, "file is locked"));
+    }
+    Ok(())
+}
+
+/// Whether the error was caused by another process using the file: it is locked (see [`lock_file`]), or, on Windows,
+/// it is opened, locked or mapped in a way which conflicts with the access.
+fn is_in_use(err: &io::Error) -> bool {
+    #[cfg(windows)]
+    {
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+        const ERROR_LOCK_VIOLATION: i32 = 33;
+        const ERROR_USER_MAPPED_FILE: i32 = 1224;
+        if matches!(
+            err.raw_os_error(),
+            Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION | ERROR_USER_MAPPED_FILE)
+        ) {
+            return true;
+        }
+    }
+    err.kind() == io::ErrorKind::WouldBlock
+}
+
+fn create_new_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+}
+
+fn open_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(false)
+        .read(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+}
+
+fn resize_file_to_fit<T>(file: &File, header_size: u64, len: usize) -> io::Result<u64> {
+    let needed_bytes = (size_of::<T>() as u64)
+        .checked_mul(len as u64)
+        .ok_or_else(file_too_large)?;
+    let size = header_size.checked_add(needed_bytes).ok_or_else(file_too_large)?;
+    if size > file.metadata()?.len() {
+        // disk space is reserved up front, so that running out of it is reported here, and not with a SIGBUS once the
+        // new pages are touched through the mapping
+        match FileExt::allocate(file, size) {
+            Ok(()) => return Ok(needed_bytes),
+            Err(err) if matches!(err.kind(), io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded) => {
+                return Err(err);
+            }
+            // not supported by the filesystem
+            Err(_) => {}
+        }
+    }
+    file.set_len(size)?;
+    Ok(needed_bytes)
+}
+
+/// Make a rename of `path` durable by syncing its parent directory.
+pub(crate) fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Largest number of items of type `T` which can be mapped on this platform, as mappings are limited to `isize::MAX`
+/// bytes.
+fn platform_max_len<T>() -> usize {
+    (isize::MAX as usize - HEADER_SIZE as usize) / size_of::<T>().max(1)
+}
+
+/// Check that a vector may hold `len` items, given its limit and the one of the platform, and convert the length.
+fn checked_len<T>(len: u64, max_len: Option<usize>) -> Result<usize, MmVecError> {
+    let max_len = max_len.unwrap_or(usize::MAX).min(platform_max_len::<T>());
+    usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= max_len)
+        .ok_or(MmVecError::TooLarge {
+            len,
+            max_len: max_len as u64,
+        })
+}
+
+fn file_too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::FileTooLarge, "file does not fit into the address space")
+}
+
+/// Check that the file at `path` holds a header of the current format, and return its size.
+fn check_file_format<T>(path: &Path) -> Result<u64, MmVecError> {
+    let file = File::open(path).map_err(|err| MmVecError::accessing(path, err))?;
+    check_format::<T>(&file, path)
+}
+
+/// Check that `file` (whose path is `path`) holds a header of the current format, and return its size. Files of the
+/// baseline format are recognized by their size, which matches the length in their header exactly.
+pub(crate) fn check_format<T>(mut file: &File, path: &Path) -> Result<u64, MmVecError> {
+    let file_size = file.metadata()?.len();
+    let mut header = [0u8; HEADER_SIZE as usize];
+    let read_len = file_size.min(HEADER_SIZE) as usize;
+    file.read_exact(&mut header[..read_len])
+        .map_err(|err| MmVecError::accessing(path, err))?;
+    let field = |offset: usize| {
+        u64::from_ne_bytes(
+            header[offset..offset + 8]
+                .try_into()
+                .expect("slice has the right length"),
+        )
+    };
+    if file_size >= HEADER_SIZE && field(MAGIC_OFFSET) == FORMAT_MAGIC {
+        let version = field(VERSION_OFFSET);
+        if version != FORMAT_VERSION {
+            return Err(MmVecError::UnsupportedFormat {
+                path: path.to_path_buf(),
+                version,
+            });
+        }
+        return Ok(file_size);
+    }
+    if file_size >= BASELINE_HEADER_SIZE && baseline_file_size::<T>(field(8)) == Some(file_size) {
+        return Err(MmVecError::UnsupportedFormat {
+            path: path.to_path_buf(),
+            version: 0,
+        });
+    }
+    if file_size < HEADER_SIZE {
+        return Err(MmVecError::corrupted(path, Corruption::TruncatedHeader { file_size }));
+    }
+    let magic = field(MAGIC_OFFSET);
+    Err(MmVecError::corrupted(path, Corruption::UnknownFormat { magic }))
+}
+
+/// Size of a file of the baseline format holding `len` items, unless it overflows.
+fn baseline_file_size<T>(len: u64) -> Option<u64> {
+    len.checked_mul(size_of::<T>() as u64)?
+        .checked_add(BASELINE_HEADER_SIZE)
+}
+
+/// Read the generation counter from the header of the file at `path`, without mapping it.
+pub(crate) fn read_generation(path: &Path) -> io::Result<u64> {
+    read_header_field(path, 24)
+}
+
+/// Read the signature from the header of the file at `path`, without mapping it.
+pub(crate) fn read_signature(path: &Path) -> io::Result<u64> {
+    read_header_field(path, 0)
+}
+
+/// Overwrite the signature in the header of the file at `path`, without mapping it, and sync it to disk. Fails if the
+/// file is locked, i.e. open for writing.
+pub(crate) fn write_signature(path: &Path, sig: u64) -> io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut file = open_file(path)?;
+    lock_file(&file, LockMode::Exclusive)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&sig.to_ne_bytes())?;
+    file.sync_all()
+}
 
-    pub unsafe fn as_slice(&self) -> &[T] {
-        unsafe { slice::from_raw_parts(self.mapped_data.as_ptr().cast::<T>(), self.len() as usize) }
-    }
+fn read_header_field(path: &Path, offset: usize) -> io::Result<u64> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    File::open(path)?.read_exact(&mut header)?;
+    Ok(u64::from_ne_bytes(
+        header[offset..offset + 8]
+            .try_into()
+            .expect("slice has the right length"),
+    ))
+}
 
-    pub unsafe fn as_slice_mut(&mut self) -> &mut [T] {
-        unsafe { slice::from_raw_parts_mut(self.mapped_data.as_mut_ptr().cast::<T>(), self.len() as usize) }
-    }
+/// Check whether `path` is on a network filesystem (e.g. NFS or SMB), on which memory maps are not coherent between
+/// hosts. If `path` doesn't exist yet, its closest existing ancestor is checked.
+///
+/// Detection relies on the filesystem type reported by `statfs` on Linux, macOS and FreeBSD. On Windows, only UNC paths
+/// (`\\server\share`) are detected, not mapped drives; on other platforms, no filesystem is considered a network one.
+/// FUSE mounts are not considered network filesystems, as local and remote ones (e.g. sshfs) can't be told apart.
+pub fn is_network_filesystem(path: &Path) -> io::Result<bool> {
+    let path = path
+        .ancestors()
+        .find(|ancestor| ancestor.as_os_str().is_empty() || ancestor.exists())
+        .expect("the last ancestor is empty or the root");
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    netfs::is_network_filesystem(path)
+}
 
-    #[cfg(not(windows))]
-    pub unsafe fn resize(&mut self, len: usize) -> io::Result<()> {
-        self.flush()?;
-        let new_len_bytes = resize_file_to_fit::<T>(&self.file, Self::HEADER_SIZE, len)?;
-        // Safety: we own the file handle, have exclusive lock in place and know that
-        self.mapped_data = mmap(&self.file, Self::HEADER_SIZE, new_len_bytes as usize)?;
-        self.set_len(len as u64);
-        Ok(())
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+mod netfs {
+    use std::{ffi::CString, io, mem::MaybeUninit, os::unix::ffi::OsStrExt, path::Path};
+
+    pub(super) fn is_network_filesystem(path: &Path) -> io::Result<bool> {
+        let path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+        let mut stat = MaybeUninit::<libc::statfs>::uninit();
+        // Safety: the path is a valid C string, and `statfs` initializes `stat` if it succeeds
+        let stat = unsafe {
+            if libc::statfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            stat.assume_init()
+        };
+        Ok(is_network_type(&stat))
     }
 
-    pub fn flush(&self) -> io::Result<()> {
-        self.mapped_header.flush()?;
-        self.mapped_data.flush()?;
-        Ok(())
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn is_network_type(stat: &libc::statfs) -> bool {
+        const NFS: u32 = 0x6969;
+        const SMB: u32 = 0x517B;
+        const CIFS: u32 = 0xFF53_4D42;
+        const SMB2: u32 = 0xFE53_4D42;
+        const AFS: u32 = 0x5346_414F;
+        const CEPH: u32 = 0x00C3_6400;
+        const CODA: u32 = 0x7375_7245;
+        const V9FS: u32 = 0x0102_1997;
+        const LUSTRE: u32 = 0x0BD0_0BD0;
+        const GFS2: u32 = 0x0116_1970;
+        const OCFS2: u32 = 0x7461_636F;
+        // the type of the field differs between architectures; magic numbers are 32 bits wide
+        let fs_type = stat.f_type as u32;
+        [NFS, SMB, CIFS, SMB2, AFS, CEPH, CODA, V9FS, LUSTRE, GFS2, OCFS2].contains(&fs_type)
     }
-}
 
-impl<T> Drop for Data<T>
-where
-    T: Copy,
-{
-    fn drop(&mut self) {
-        let _ = self.flush();
-        let _ = self.file.unlock().ok();
+    #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+    fn is_network_type(stat: &libc::statfs) -> bool {
+        const NETWORK_TYPES: [&[u8]; 5] = [b"nfs", b"smbfs", b"afpfs", b"webdav", b"cifs"];
+        let name: Vec<u8> = stat
+            .f_fstypename
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as u8)
+            .collect();
+        NETWORK_TYPES.contains(&name.as_slice())
     }
 }
 
-fn create_new_file(path: &Path) -> io::Result<File> {
-    OpenOptions::new()
-        .create(true)
-        .read(true)
-        .write(true)
-        .truncate(true)
-        .open(path)
-}
-
-fn open_file(path: &Path) -> io::Result<File> {
-    OpenOptions::new()
-        .create(false)
-        .read(true)
-        .write(true)
-        .truncate(false)
-        .open(path)
-}
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+)))]
+mod netfs {
+    use std::{io, path::Path};
 
-fn resize_file_to_fit<T>(file: &File, header_size: u64, len: usize) -> io::Result<u64> {
-    let needed_bytes = size_of::<T>() as u64 * len as u64;
-    file.set_len(header_size + needed_bytes)?;
-    Ok(needed_bytes)
+    pub(super) fn is_network_filesystem(path: &Path) -> io::Result<bool> {
+        #[cfg(windows)]
+        let is_unc = {
+            use std::path::{Component, Prefix};
+
+            matches!(
+                path.components().next(),
+                Some(Component::Prefix(prefix)) if matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..))
+            )
+        };
+        #[cfg(not(windows))]
+        let is_unc = {
+            let _ = path;
+            false
+        };
+        Ok(is_unc)
+    }
 }
 
-unsafe fn mmap(file: &File, offset: u64, len: usize) -> io::Result<MmapMut> {
+unsafe fn mmap(file: &File, offset: u64, len: usize, read_only: bool) -> io::Result<MmapRaw> {
     let mut opts = MmapOptions::new();
-    let mmap = unsafe { opts.offset(offset).len(len).map_mut(file)? };
+    opts.offset(offset).len(len);
+    let mmap = if read_only {
+        opts.map_raw_read_only(file)?
+    } else {
+        opts.map_raw(file)?
+    };
     #[cfg(unix)]
     mmap.advise(memmap2::Advice::Random).ok();
     Ok(mmap)
@@ -431,6 +2918,8 @@ unsafe fn mmap(file: &File, offset: u64, len: usize) -> io::Result<MmapMut> {
 
 #[cfg(test)]
 mod tests {
+    use std::io::Seek;
+
     use super::*;
 
     fn with_file_path(f: impl FnOnce(&Path)) {
@@ -480,7 +2969,6 @@ mod tests {
         });
     }
 
-    #[cfg(not(windows))]
     #[test]
     fn data_can_be_correctly_resized_grow() {
         with_file_path(|path| {
@@ -507,7 +2995,32 @@ mod tests {
         });
     }
 
-    #[cfg(not(windows))]
+    #[test]
+    fn data_contents_are_kept_when_remapping() {
+        with_file_path(|path| {
+            let items: Vec<u64> = (0..100).collect();
+            let mut data = Data::new_with_data(path, 42, &items, LockMode::Exclusive).expect("failed to create data");
+            unsafe {
+                // resized in place, or moved by `mremap` where supported
+                data.resize_capacity(10_000).expect("failed to resize data");
+                assert_eq!(
+                    data.as_slice(),
+                    &items[..],
+                    "contents after resizing an exclusive mapping"
+                );
+                // a shared mapping has to be replaced instead
+                let shared = Arc::clone(&data.mapped_data);
+                data.resize_capacity(20_000).expect("failed to resize data");
+                assert_eq!(data.as_slice(), &items[..], "contents after resizing a shared mapping");
+                assert_eq!(
+                    shared.len(),
+                    10_000 * size_of::<u64>(),
+                    "shared mapping should be intact"
+                );
+            }
+        });
+    }
+
     #[test]
     fn data_can_be_correctly_resized_shrink() {
         with_file_path(|path| {
@@ -536,7 +3049,7 @@ mod tests {
 
     #[test]
     fn mmvec_can_be_dumped_to_file_then_read() {
-        with_file_path(|path| unsafe {
+        with_file_path(|path| {
             let data = vec![199, 200, 200, 532, 449, 400];
             let vec = MmVec::from_slice(0, &data, path.to_path_buf()).expect("failed to create memvec");
             drop(vec);
@@ -544,4 +3057,686 @@ mod tests {
             assert_eq!(result.as_slice(), data.as_slice());
         });
     }
+
+    #[test]
+    fn mmvec_retain_preserves_order() {
+        with_file_path(|path| {
+            let data = vec![1, 2, 3, 4, 5, 6, 7];
+            let mut vec = MmVec::from_slice(0, &data, path.to_path_buf()).expect("failed to create memvec");
+            vec.retain(|_, x| x % 2 == 1).expect("failed to retain");
+            assert_eq!(vec.as_slice(), &[1, 3, 5, 7]);
+        });
+    }
+
+    #[test]
+    fn mmvec_rewrite_leaves_original_intact_until_replaced() {
+        with_file_path(|path| {
+            let mut vec = MmVec::from_slice(0, &[1, 3, 5], path.to_path_buf()).expect("failed to create memvec");
+            vec.insert_sorted(&[4, 2], |x| *x).expect("failed to insert");
+            assert_eq!(vec.as_slice(), &[1, 2, 3, 4, 5], "insert");
+            assert!(!journal_path(path).exists(), "journal should be removed");
+
+            vec.remove_matching(|x| *x > 3, |x| *x).expect("failed to remove");
+            assert_eq!(vec.as_slice(), &[1, 2, 3], "remove");
+            assert!(!vec.tmp_path().exists(), "temporary file should be renamed");
+
+            // simulate a crash after the temporary file was written, but before it replaced the original
+            std::fs::write(vec.tmp_path(), b"garbage").expect("failed to write garbage");
+            drop(vec);
+            let vec = MmVec::<i32>::from_path(0, path.to_path_buf()).expect("original should be loadable");
+            assert_eq!(vec.as_slice(), &[1, 2, 3], "original contents");
+            assert_eq!(
+                vec.storage_size().unwrap(),
+                vec.expected_storage_size(),
+                "storage size after remove"
+            );
+            vec.destroy().expect("failed to destroy");
+            assert!(!path.exists(), "file should be removed");
+        });
+    }
+
+    #[test]
+    fn mmvec_grows_geometrically_and_zeroes_reused_capacity() {
+        with_file_path(|path| {
+            let mut vec = MmVec::<u64>::new_empty(0, path.to_path_buf()).expect("failed to create memvec");
+            vec.resize_zeroed(1).expect("failed to resize");
+            vec.as_slice_mut()[0] = 7;
+            vec.resize_zeroed(2).expect("failed to resize");
+            assert_eq!(vec.capacity(), 2, "capacity should double");
+            vec.resize_zeroed(3).expect("failed to resize");
+            assert_eq!(vec.capacity(), 4, "capacity should double");
+            vec.as_slice_mut().fill(9);
+            vec.resize_zeroed(1).expect("failed to resize");
+            assert_eq!(vec.capacity(), 4, "shrinking should keep capacity");
+            vec.resize_zeroed(4).expect("failed to resize");
+            assert_eq!(vec.as_slice(), &[9, 0, 0, 0], "reused capacity should be zeroed");
+            assert_eq!(vec.storage_size().unwrap(), vec.expected_storage_size(), "storage size");
+            vec.resize_zeroed(2).expect("failed to resize");
+            drop(vec);
+
+            let mut vec = MmVec::<u64>::from_path(0, path.to_path_buf()).expect("failed to load memvec");
+            assert_eq!(
+                (vec.len(), vec.capacity()),
+                (2, 4),
+                "len and capacity should be persisted"
+            );
+            vec.shrink_to_fit().expect("failed to shrink");
+            assert_eq!(vec.capacity(), 2, "capacity after shrink");
+            assert_eq!(
+                vec.storage_size().unwrap(),
+                vec.expected_storage_size(),
+                "storage size after shrink"
+            );
+        });
+    }
+
+    #[test]
+    fn mmvec_inserts_reuse_capacity() {
+        with_file_path(|path| {
+            let mut vec =
+                MmVec::from_slice(0, &[10u64, 20, 30, 40], path.to_path_buf()).expect("failed to create memvec");
+            vec.insert_sorted(&[25], |x| *x).expect("failed to insert");
+            assert_eq!(vec.capacity(), 8, "capacity should double");
+            vec.insert_sorted(&[5, 45, 15], |x| *x).expect("failed to insert");
+            assert_eq!(vec.capacity(), 8, "items should fit into the reserved capacity");
+            vec.insert_sorted(&[35], |x| *x).expect("failed to insert");
+            assert_eq!(vec.capacity(), 16, "capacity should double");
+            assert_eq!(vec.storage_size().unwrap(), vec.expected_storage_size(), "storage size");
+            drop(vec);
+
+            let vec = MmVec::<u64>::from_path(0, path.to_path_buf()).expect("failed to load memvec");
+            assert_eq!(
+                vec.as_slice(),
+                &[5, 10, 15, 20, 25, 30, 35, 40, 45],
+                "contents after reload"
+            );
+            assert_eq!(vec.capacity(), 16, "capacity should be persisted");
+        });
+    }
+
+    #[test]
+    fn mmvec_staged_inserts_change_nothing_until_committed() {
+        with_file_path(|path| {
+            let mut vec = MmVec::from_slice(0, &[10u64, 20], path.to_path_buf()).expect("failed to create memvec");
+            vec.stage_insert_sorted(&[15], |x| *x).expect("failed to stage");
+            assert!(journal_path(path).exists(), "staging should create the journal");
+            assert_eq!(vec.as_slice(), &[10, 20], "contents after staging");
+            vec.discard_staged_insert();
+            assert!(!journal_path(path).exists(), "discarding should remove the journal");
+            vec.commit_staged_insert().expect("failed to commit");
+            assert_eq!(
+                vec.as_slice(),
+                &[10, 20],
+                "nothing should be committed after discarding"
+            );
+
+            vec.stage_insert_sorted(&[25, 5], |x| *x).expect("failed to stage");
+            vec.commit_staged_insert().expect("failed to commit");
+            assert_eq!(vec.as_slice(), &[5, 10, 20, 25], "contents after committing");
+            assert!(!journal_path(path).exists(), "committing should remove the journal");
+        });
+    }
+
+    #[test]
+    fn mmvec_inserts_within_capacity_keep_mapping() {
+        with_file_path(|path| {
+            let mut writer = MmVec::from_slice(0, &[1u64, 3], path.to_path_buf()).expect("failed to create memvec");
+            writer.insert_sorted(&[2], |x| *x).expect("failed to insert");
+            let mut reader = MmVec::<u64>::open_read_only(0, path.to_path_buf()).expect("failed to open reader");
+            let (generation, ptr) = (writer.generation(), writer.as_slice().as_ptr());
+
+            writer.insert_sorted(&[4], |x| *x).expect("failed to insert");
+            assert_eq!(
+                writer.generation(),
+                generation + 2,
+                "insert within capacity should be published with a new generation"
+            );
+            assert_eq!(
+                writer.as_slice().as_ptr(),
+                ptr,
+                "insert within capacity should keep the mapping"
+            );
+            assert!(reader.is_stale(), "reader should see that items were moved");
+            assert!(reader.refresh().unwrap(), "reader should pick up the insert");
+            assert!(!reader.is_stale(), "reader should be current after refreshing");
+            assert_eq!(reader.as_slice(), &[1, 2, 3, 4], "reader should see new contents");
+
+            let generation = writer.generation();
+            writer.insert_sorted(&[0], |x| *x).expect("failed to insert");
+            assert_eq!(
+                writer.generation(),
+                generation + 3,
+                "insert beyond capacity should grow the file"
+            );
+            assert!(reader.refresh().unwrap(), "reader should remap");
+            assert_eq!(reader.as_slice(), &[0, 1, 2, 3, 4], "reader should see new contents");
+        });
+    }
+
+    #[test]
+    fn mmvec_readers_see_merges_in_progress_as_stale() {
+        with_file_path(|path| {
+            let mut writer = MmVec::from_slice(0, &[1u64, 3], path.to_path_buf()).expect("failed to create memvec");
+            let mut reader = MmVec::<u64>::open_read_only(0, path.to_path_buf()).expect("failed to open reader");
+            assert!(!reader.is_stale(), "nothing changed yet");
+
+            let data = writer.data.as_mut().expect("vector should be mapped");
+            data.begin_update();
+            assert!(reader.is_stale(), "reader mapped before the merge");
+            assert!(reader.refresh().unwrap(), "generation was bumped");
+            assert!(reader.is_stale(), "reader mapped during the merge");
+
+            let data = writer.data.as_mut().expect("vector should be mapped");
+            data.end_update();
+            assert!(reader.is_stale(), "reader mapped before the merge was published");
+            assert!(reader.refresh().unwrap(), "generation was bumped");
+            assert!(!reader.is_stale(), "reader mapped after the merge");
+        });
+    }
+
+    #[test]
+    fn mmvec_read_only_reader_follows_writer() {
+        with_file_path(|path| {
+            let mut writer = MmVec::from_slice(0, &[1u64, 3], path.to_path_buf()).expect("failed to create memvec");
+            let mut reader = MmVec::<u64>::open_read_only(0, path.to_path_buf()).expect("failed to open reader");
+            assert!(reader.is_read_only(), "reader should be read-only");
+            assert_eq!(reader.as_slice(), &[1, 3], "initial contents");
+            assert!(!reader.refresh().unwrap(), "nothing changed yet");
+
+            writer.insert_sorted(&[2], |x| *x).expect("failed to insert");
+            assert!(reader.refresh().unwrap(), "file was grown");
+            assert_eq!(reader.as_slice(), &[1, 2, 3], "reader should see new contents");
+
+            writer.retain(|_, x| *x != 2).expect("failed to retain");
+            assert_eq!(
+                reader.as_slice(),
+                &[1, 2, 3],
+                "reader should see its snapshot until refreshed"
+            );
+            assert!(reader.refresh().unwrap(), "file was replaced");
+            assert_eq!(reader.as_slice(), &[1, 3], "reader should see new contents");
+
+            writer.resize_zeroed(10).expect("failed to resize");
+            assert!(reader.refresh().unwrap(), "file was remapped");
+            assert_eq!(reader.len(), 10, "reader should see new length");
+
+            assert!(
+                matches!(reader.resize_zeroed(1), Err(MmVecError::ReadOnly {})),
+                "reader can't resize"
+            );
+            assert!(
+                matches!(reader.retain(|_, _| true), Err(MmVecError::ReadOnly {})),
+                "reader can't modify"
+            );
+        });
+    }
+
+    #[test]
+    fn mmvec_access_pattern_is_kept_across_remaps() {
+        with_file_path(|path| {
+            let mut vec = MmVec::from_slice(0, &[1u64, 3], path.to_path_buf()).expect("failed to create memvec");
+            assert_eq!(vec.access_pattern(), AccessPattern::Random, "default pattern");
+            vec.advise(AccessPattern::Sequential);
+            vec.insert_sorted(&[2], |x| *x).expect("failed to insert");
+            vec.resize_zeroed(10).expect("failed to resize");
+            assert_eq!(
+                vec.access_pattern(),
+                AccessPattern::Sequential,
+                "pattern after remapping"
+            );
+            assert_eq!(&vec.as_slice()[..3], &[1, 2, 3], "contents after remapping");
+        });
+    }
+
+    #[test]
+    fn mmvec_huge_pages_setting_is_kept_across_remaps() {
+        with_file_path(|path| {
+            let mut vec = MmVec::from_slice(0, &[1u64, 3], path.to_path_buf()).expect("failed to create memvec");
+            // the kernel may not support huge pages for this file, which is fine as long as the setting is tracked
+            if vec.set_huge_pages(true).is_err() {
+                return;
+            }
+            vec.insert_sorted(&[2], |x| *x).expect("failed to insert");
+            vec.resize_zeroed(10).expect("failed to resize");
+            assert!(vec.huge_pages(), "setting after remapping");
+            assert_eq!(&vec.as_slice()[..3], &[1, 2, 3], "contents after remapping");
+        });
+    }
+
+    #[test]
+    fn mmvec_tracks_modified_ranges() {
+        with_file_path(|path| {
+            let mut vec = MmVec::from_slice(0, &[0u64; 4096], path.to_path_buf()).expect("failed to create memvec");
+            let dirty = |vec: &MmVec<u64>| {
+                let ranges = vec.data.as_ref().unwrap().dirty.lock().unwrap().ranges.clone();
+                ranges.into_iter().map(|r| (r.start, r.end)).collect::<Vec<_>>()
+            };
+            assert_eq!(
+                dirty(&vec),
+                [(0, 4096 * 8)],
+                "contents of a new vector are flushed later"
+            );
+            vec.flush().expect("failed to flush");
+            vec.slice_mut(10..20).fill(1);
+            vec.slice_mut(1030..1031)[0] = 2;
+            assert_eq!(
+                dirty(&vec),
+                [(0, 4096), (8192, 12288)],
+                "only pages of modified items are tracked"
+            );
+            vec.slice_mut(600..520 * 2).fill(3);
+            assert_eq!(dirty(&vec), [(0, 12288)], "adjacent ranges are merged");
+            vec.flush().expect("failed to flush");
+            assert_eq!(dirty(&vec), [], "ranges are cleared by flushing");
+
+            for i in (0..4096).step_by(32) {
+                vec.slice_mut(i..i + 1)[0] = 4;
+            }
+            assert_eq!(dirty(&vec), [(0, 4096 * 8)], "too many ranges are merged into one");
+            drop(vec);
+
+            let vec = MmVec::<u64>::from_path(0, path.to_path_buf()).expect("failed to load memvec");
+            assert_eq!(&vec.as_slice()[..3], &[4, 0, 0], "flushed contents");
+            assert_eq!(&vec.as_slice()[1030..1040], &[3; 10], "flushed contents");
+        });
+    }
+
+    #[test]
+    fn mmvec_flushes_according_to_policy() {
+        with_file_path(|path| {
+            let mut vec = MmVec::from_slice(0, &[0u64; 4], path.to_path_buf()).expect("failed to create memvec");
+            let dirty = |vec: &MmVec<u64>| vec.dirty_bytes.load(Ordering::Relaxed);
+            vec.set_flush_policy(FlushPolicy::DirtyBytes(48)).unwrap();
+            vec.as_slice_mut()[0] = 1;
+            vec.as_slice_mut()[1] = 2;
+            assert_eq!(dirty(&vec), 64, "no flush while below the limit");
+            vec.as_slice_mut()[2] = 3;
+            assert_eq!(dirty(&vec), 32, "flushed once the limit was reached");
+            vec.resize_zeroed(2).expect("failed to resize");
+            vec.resize_zeroed(4).expect("failed to resize");
+            assert_eq!(dirty(&vec), 0, "flushed once zeroing reached the limit");
+
+            vec.set_flush_policy(FlushPolicy::Interval {
+                period: Duration::from_millis(1),
+                background: true,
+            })
+            .unwrap();
+            vec.as_slice_mut()[2..].copy_from_slice(&[3, 4]);
+            vec.insert_sorted(&[5], |x| *x).expect("failed to insert");
+            std::thread::sleep(Duration::from_millis(10));
+            vec.set_flush_policy(FlushPolicy::OnDrop).unwrap();
+            assert!(vec.flusher.is_none(), "background thread should be stopped");
+            drop(vec);
+
+            let vec = MmVec::<u64>::from_path(0, path.to_path_buf()).expect("failed to load memvec");
+            assert_eq!(vec.as_slice(), &[1, 2, 3, 4, 5], "contents after reload");
+        });
+    }
+
+    #[test]
+    fn mmvec_can_be_iterated_in_chunks() {
+        with_file_path(|path| {
+            let items: Vec<u64> = (0..10_000).collect();
+            let vec = MmVec::from_slice(0, &items, path.to_path_buf()).expect("failed to create memvec");
+            assert_eq!(vec.iter().collect::<Vec<_>>(), items, "items");
+            let chunks = vec.chunks(3000);
+            assert_eq!(chunks.len(), 4, "number of chunks");
+            let lens: Vec<_> = chunks.map(<[u64]>::len).collect();
+            assert_eq!(lens, [3000, 3000, 3000, 1000], "chunk lengths");
+            vec.populate();
+            assert_eq!(
+                vec.access_pattern(),
+                AccessPattern::Random,
+                "access pattern should not change"
+            );
+        });
+    }
+
+    #[test]
+    fn mmvec_snapshot_is_not_affected_by_writes() {
+        with_file_path(|path| {
+            let mut vec = MmVec::from_slice(0, &[1u64, 3, 5], path.to_path_buf()).expect("failed to create memvec");
+            let first = vec.snapshot();
+            vec.insert_sorted(&[2, 4], |x| *x).unwrap();
+            let second = vec.snapshot();
+            vec.as_slice_mut()[0] = 10;
+            vec.resize_zeroed(7).unwrap();
+            let generation = vec.generation();
+            drop(vec);
+
+            assert_eq!(first.as_slice(), &[1, 3, 5], "snapshot taken before insert");
+            assert_eq!(
+                second.as_slice(),
+                &[1, 2, 3, 4, 5],
+                "snapshot taken before in-place writes"
+            );
+            assert!(
+                second.generation() < generation,
+                "in-place writes should happen on a copy"
+            );
+        });
+    }
+
+    #[test]
+    fn mmvec_can_be_recovered() {
+        with_file_path(|path| {
+            let items: Vec<u64> = (0..10).collect();
+            drop(MmVec::from_slice(0, &items, path.to_path_buf()).expect("failed to create memvec"));
+            let file = File::options().write(true).open(path).unwrap();
+            // the last three items are lost, and the last one only partially
+            file.set_len(HEADER_SIZE + 7 * 8 + 3).unwrap();
+            drop(file);
+            let Err(MmVecError::Corrupted { corruption, .. }) = MmVec::<u64>::from_path(0, path.to_path_buf()) else {
+                panic!("truncated file should not load");
+            };
+            assert_eq!(
+                corruption,
+                Corruption::SizeMismatch {
+                    capacity: 10,
+                    expected_size: HEADER_SIZE + 80,
+                    actual_size: HEADER_SIZE + 59,
+                }
+            );
+
+            let (vec, report) = MmVec::<u64>::recover(0, path.to_path_buf()).unwrap();
+            assert_eq!(report.dropped_items(), 3, "dropped items");
+            assert_eq!(report.truncated_bytes, 3, "truncated bytes");
+            assert_eq!(vec.as_slice(), &items[..7], "recovered items");
+            drop(vec);
+
+            let vec = MmVec::<u64>::from_path(0, path.to_path_buf()).expect("recovered vector should load");
+            drop(vec);
+            let (_, report) = MmVec::<u64>::recover(0, path.to_path_buf()).unwrap();
+            assert!(report.is_clean(), "nothing to recover");
+        });
+    }
+
+    #[test]
+    fn mmvec_baseline_format_is_migrated() {
+        with_file_path(|path| {
+            // the baseline header holds only the signature and the length
+            let items: Vec<u64> = (0..100).collect();
+            let mut bytes = [7u64, 100].map(u64::to_ne_bytes).concat();
+            bytes.extend(items.iter().flat_map(|item| item.to_ne_bytes()));
+            std::fs::write(path, &bytes).unwrap();
+            let results = [
+                MmVec::<u64>::from_path(7, path.to_path_buf()).map(drop),
+                MmVec::<u64>::open_read_only(7, path.to_path_buf()).map(drop),
+                MmVec::<u64>::recover(7, path.to_path_buf()).map(drop),
+            ];
+            for result in results {
+                assert!(
+                    matches!(result, Err(MmVecError::UnsupportedFormat { version: 0, .. })),
+                    "baseline format should be reported"
+                );
+            }
+
+            let vec = MmVec::<u64>::migrate_baseline_format(7, path.to_path_buf()).expect("failed to migrate");
+            assert_eq!(vec.as_slice(), &items, "migrated contents");
+            drop(vec);
+            let vec = MmVec::<u64>::from_path(7, path.to_path_buf()).expect("migrated file should load");
+            assert_eq!(vec.as_slice(), &items, "contents after reload");
+            drop(vec);
+
+            let mut file = File::options().write(true).open(path).unwrap();
+            file.seek(io::SeekFrom::Start(VERSION_OFFSET as u64)).unwrap();
+            file.write_all(&(FORMAT_VERSION + 1).to_ne_bytes()).unwrap();
+            drop(file);
+            let Err(MmVecError::UnsupportedFormat { version, .. }) = MmVec::<u64>::from_path(7, path.to_path_buf())
+            else {
+                panic!("newer format should not load");
+            };
+            assert_eq!(version, FORMAT_VERSION + 1, "reported version");
+        });
+    }
+
+    #[test]
+    fn mmvec_metadata_is_kept() {
+        with_file_path(|path| {
+            let mut vec = MmVec::<u64>::new_empty(0, path.to_path_buf()).expect("failed to create memvec");
+            assert!(vec.metadata().is_empty(), "no metadata initially");
+            vec.set_metadata(b"schema=2;watermark=1234").unwrap();
+            assert!(matches!(
+                vec.set_metadata(&[0; MAX_METADATA_LEN + 1]),
+                Err(MmVecError::MetadataTooLong { len: 257 })
+            ));
+            vec.insert_sorted(&[3, 1, 2], |x| *x).unwrap();
+            vec.resize_zeroed(100).unwrap();
+            drop(vec);
+
+            let vec = MmVec::<u64>::from_path(0, path.to_path_buf()).expect("failed to load memvec");
+            assert_eq!(vec.metadata(), b"schema=2;watermark=1234", "metadata after reload");
+            let reader = MmVec::<u64>::open_read_only(0, path.to_path_buf()).expect("failed to open memvec");
+            assert_eq!(reader.metadata(), vec.metadata(), "metadata seen by a reader");
+        });
+    }
+
+    #[test]
+    fn mmvec_lock_mode_is_configurable() {
+        with_file_path(|path| {
+            let vec = MmVec::from_slice(0, &[1u64, 2], path.to_path_buf()).expect("failed to create memvec");
+            assert!(
+                matches!(
+                    MmVec::<u64>::from_path(0, path.to_path_buf()),
+                    Err(MmVecError::Locked { path: locked }) if locked == path
+                ),
+                "file is locked exclusively"
+            );
+            drop(vec);
+
+            let shared = MmVecOptions {
+                lock: LockMode::Shared,
+                ..Default::default()
+            };
+            let _first = MmVec::<u64>::from_path_with(0, path.to_path_buf(), shared).expect("failed to load memvec");
+            let _second = MmVec::<u64>::from_path_with(0, path.to_path_buf(), shared).expect("lock is shared");
+            assert!(
+                matches!(
+                    MmVec::<u64>::from_path(0, path.to_path_buf()),
+                    Err(MmVecError::Locked { .. })
+                ),
+                "file is locked"
+            );
+
+            let unlocked = MmVecOptions {
+                lock: LockMode::None,
+                ..Default::default()
+            };
+            let mut vec = MmVec::<u64>::from_path_with(0, path.to_path_buf(), unlocked).expect("locks are ignored");
+            vec.insert_sorted(&[0], |x| *x).unwrap();
+            assert_eq!(vec.lock_mode(), LockMode::None, "lock mode is kept across rewrites");
+        });
+    }
+
+    #[test]
+    fn mmvec_interrupted_insert_is_completed_on_open() {
+        let current: Vec<u64> = (0..100).map(|x| x * 3).collect();
+        let items = [0, 1, 2, 50, 51, 52, 151, 299, 400, 401];
+        let mut expected = [current.as_slice(), &items].concat();
+        expected.sort_unstable();
+        for (steps, torn) in [(1, false), (2, true), (4, false), (4, true), (15, false)] {
+            with_file_path(|path| {
+                let mut vec = MmVec::from_slice(0, &current, path.to_path_buf()).unwrap();
+                let data = vec.data.as_mut().unwrap();
+                unsafe { data.resize_capacity(expected.len()).unwrap() };
+                let mut journal = Journal::create(journal_path(path), 0, current.len(), items.len(), 7).unwrap();
+                let (new, ranks) = journal.items_and_ranks_mut();
+                new.copy_from_slice(&items);
+                for (rank, item) in ranks.iter_mut().zip(new.iter()) {
+                    *rank = current.partition_point(|el| el <= item) as u64;
+                }
+                data.flush().unwrap();
+                journal.commit().unwrap();
+
+                // redo the first steps of `run_merge`, and crash in the middle of the last one
+                let mut state = MergeState {
+                    current: current.len(),
+                    items: items.len(),
+                };
+                for seq in 0..steps {
+                    let next = merge_step(None, journal.items(), journal.ranks(), state, journal.step);
+                    let scratch_start = (next.current + next.items).min(state.current);
+                    let scratch = data.item_bytes(scratch_start..state.current);
+                    journal.checkpoint(seq, state, scratch_start, scratch).unwrap();
+                    let written = next.current + next.items..state.current + state.items;
+                    if seq + 1 < steps {
+                        merge_step(
+                            Some(data.capacity_slice_mut()),
+                            journal.items(),
+                            journal.ranks(),
+                            state,
+                            7,
+                        );
+                    } else if torn {
+                        // the checkpoint did not make it to disk, so the step was not started
+                        let offset = Journal::<u64>::RECORDS_OFFSET + (seq % 2) as usize * 64;
+                        journal.words_mut(offset, Journal::<u64>::RECORD_WORDS)[4] ^= 1;
+                    } else {
+                        data.capacity_slice_mut()[written.clone()].fill(u64::MAX);
+                    }
+                    data.mark_modified(written);
+                    data.flush().unwrap();
+                    state = next;
+                }
+                drop(journal);
+                drop(vec);
+
+                let vec = MmVec::<u64>::from_path(0, path.to_path_buf()).expect("failed to load memvec");
+                assert_eq!(vec.as_slice(), &expected, "contents after {steps} steps (torn: {torn})");
+                assert!(!journal_path(path).exists(), "journal should be removed");
+            });
+        }
+    }
+
+    #[test]
+    fn mmvec_does_not_grow_beyond_max_len() {
+        with_file_path(|path| {
+            let options = MmVecOptions {
+                max_len: Some(3),
+                ..Default::default()
+            };
+            let mut vec = MmVec::from_slice_with(0, &[1u64, 3], path.to_path_buf(), options).unwrap();
+            assert_eq!(vec.max_len(), Some(3));
+            assert!(matches!(
+                vec.insert_sorted(&[0, 2], |x| *x),
+                Err(MmVecError::TooLarge { len: 4, max_len: 3 })
+            ));
+            assert_eq!(vec.as_slice(), &[1, 3], "rejected insert leaves the vector unchanged");
+            assert!(matches!(vec.resize_zeroed(4), Err(MmVecError::TooLarge { .. })));
+            vec.insert_sorted(&[2], |x| *x).unwrap();
+            assert_eq!(vec.as_slice(), &[1, 2, 3]);
+            assert_eq!(vec.max_len(), Some(3), "limit is kept across rewrites");
+
+            vec.set_max_len(Some(1));
+            vec.resize_zeroed(2).expect("vector can shrink while over the limit");
+            vec.set_max_len(None);
+            vec.resize_zeroed(4).unwrap();
+            assert!(matches!(
+                checked_len::<u64>(u64::MAX, None),
+                Err(MmVecError::TooLarge { len: u64::MAX, .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn mmvec_import_leaves_no_files_when_decoding_panics() {
+        with_file_path(|path| {
+            let dump_path = path.with_extension("dump");
+            let records: Vec<u8> = [4u64, 2, 8].iter().flat_map(|x| x.to_le_bytes()).collect();
+            std::fs::write(&dump_path, records).unwrap();
+            let decode = |record: &[u8]| u64::from_le_bytes(record.try_into().unwrap());
+            let mut vec = MmVec::from_slice(0, &[1u64, 5], path.to_path_buf()).unwrap();
+
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                vec.append_from_file(&dump_path, 8, |_| panic!("corrupt record"), |x| *x)
+            }));
+            assert!(result.is_err(), "decoding panicked");
+            assert_eq!(vec.as_slice(), &[1, 5], "vector is unchanged");
+            let mut files: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            files.sort();
+            assert_eq!(files, ["test.bin", "test.dump"], "no temporary files are left behind");
+
+            assert_eq!(vec.append_from_file(&dump_path, 8, decode, |x| *x).unwrap(), 3);
+            assert_eq!(vec.as_slice(), &[1, 2, 4, 5, 8]);
+        });
+    }
+
+    #[test]
+    fn mmvec_external_sort_keeps_options() {
+        with_file_path(|path| {
+            let items = [5u64, 3, 9, 1, 7];
+            let limited = MmVecOptions {
+                max_len: Some(4),
+                ..Default::default()
+            };
+            assert!(matches!(
+                MmVec::from_iter_external_sorted_with(0, items, 2, |x| *x, path.to_path_buf(), limited),
+                Err(MmVecError::TooLarge { len: 5, max_len: 4 })
+            ));
+
+            let options = MmVecOptions {
+                lock: LockMode::Shared,
+                max_len: Some(5),
+            };
+            let vec = MmVec::from_iter_external_sorted_with(0, items, 2, |x| *x, path.to_path_buf(), options).unwrap();
+            assert_eq!(vec.as_slice(), &[1, 3, 5, 7, 9]);
+            assert_eq!(vec.lock_mode(), LockMode::Shared, "lock mode is kept");
+            assert_eq!(vec.max_len(), Some(5), "limit is kept");
+            let _other = MmVec::<u64>::from_path_with(0, path.to_path_buf(), options).expect("lock is shared");
+        });
+    }
+
+    #[test]
+    fn network_filesystems_are_detected_for_missing_paths() {
+        with_file_path(|path| {
+            let missing = path.join("missing").join("index.dat");
+            let parent = path.parent().expect("file has a parent");
+            assert_eq!(
+                is_network_filesystem(&missing).unwrap(),
+                is_network_filesystem(parent).unwrap(),
+                "closest existing ancestor is checked"
+            );
+            assert!(is_network_filesystem(Path::new("relative/missing")).is_ok());
+        });
+    }
+
+    #[test]
+    fn mmvec_info_is_updated() {
+        with_file_path(|path| {
+            let before = SystemTime::now() - Duration::from_secs(1);
+            let mut vec = MmVec::<u64>::new_empty(0, path.to_path_buf()).expect("failed to create memvec");
+            let info = vec.info();
+            assert!(info.created >= before, "creation time");
+            assert_eq!(info.updates, 0, "no updates yet");
+            vec.insert_sorted(&[3, 1, 2], |x| *x).unwrap();
+            vec.as_slice_mut()[0] = 0;
+            vec.flush().unwrap();
+            let updated = vec.info();
+            assert_eq!(updated.created, info.created, "creation time is kept across rewrites");
+            assert_eq!(updated.updates, 2, "updates");
+            assert!(updated.last_flush.is_some_and(|t| t >= info.created), "flush time");
+
+            let reader = MmVec::<u64>::open_read_only(0, path.to_path_buf()).expect("failed to open memvec");
+            assert_eq!(reader.info(), updated, "info seen by a reader");
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mmvec_storage_is_preallocated() {
+        with_file_path(|path| {
+            let mut vec = MmVec::<u64>::new_empty(0, path.to_path_buf()).expect("failed to create memvec");
+            vec.resize_zeroed(100_000).unwrap();
+            let file = File::open(path).unwrap();
+            let size = file.metadata().unwrap().len();
+            assert_eq!(size, vec.expected_storage_size(), "file size");
+            assert!(
+                FileExt::allocated_size(&file).unwrap() >= size,
+                "disk space should be allocated"
+            );
+        });
+    }
 }
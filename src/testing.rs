@@ -0,0 +1,84 @@
+//! Generators of data for testing, benchmarking and fuzzing integrations, available with the `testing` feature.
+//!
+//! Keys are generated as arrays of 64-bit words, and can be converted into keys of lookups with `map_to` functions or
+//! with conversions of the key types.
+
+use hloo_core::rand::{self, Rng, seq::index::sample};
+
+/// Generate `n` uniformly distributed keys, sorted, with their positions in generation order as values.
+pub fn generate_uniform_data<const S: usize>(n: usize) -> Vec<([u64; S], usize)> {
+    let mut data: Vec<_> = (0..n).map(|i| (rand::random(), i)).collect();
+    data.sort_unstable_by_key(|(k, _)| *k);
+    data
+}
+
+/// Generate `n` keys which are uniformly distributed except for their first word, which is restricted to produce blocks
+/// of roughly `block_size` items in its top bits. With `really_bad_distribution`, all words of a key are made equal to
+/// the first one, so that all blocks of all indexes are skewed.
+///
+/// Keys are converted with `map_to` after sorting them, and get their positions in generation order as values.
+pub fn generate_uniform_data_with_block_size<T, const S: usize>(
+    n: usize,
+    block_size: usize,
+    really_bad_distribution: bool,
+    map_to: impl Fn([u64; S]) -> T,
+) -> Vec<(T, usize)> {
+    assert!(S > 0, "keys should have at least one word");
+    let n_blocks = (n / block_size).max(1) as u64;
+    let mut rng = rand::rng();
+    let mut data = generate_uniform_data::<S>(n);
+    for (key, _) in &mut data {
+        key[0] = rng.random_range(0..n_blocks) << 32;
+        if really_bad_distribution {
+            let first = key[0];
+            key.fill(first);
+        }
+    }
+    data.sort_unstable_by_key(|(k, _)| *k);
+    data.into_iter().map(|(k, v)| (map_to(k), v)).collect()
+}
+
+/// Flip `n` distinct random bits anywhere in `bits`, so that the result is at distance `n` from it.
+///
+/// # Panics
+///
+/// Panics if `n` is larger than the number of bits.
+pub fn flip_bits<const S: usize>(mut bits: [u64; S], n: usize) -> [u64; S] {
+    for pos in sample(&mut rand::rng(), S * u64::BITS as usize, n) {
+        bits[pos / 64] ^= 1 << (pos % 64);
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance<const S: usize>(a: &[u64; S], b: &[u64; S]) -> u32 {
+        a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+    }
+
+    #[test]
+    fn flip_bits_covers_the_whole_key() {
+        let key = [0u64; 4];
+        let mut flipped_words = [false; 4];
+        for _ in 0..100 {
+            let flipped = flip_bits(key, 3);
+            assert_eq!(distance(&key, &flipped), 3);
+            for (word, flipped) in flipped_words.iter_mut().zip(flipped) {
+                *word |= flipped != 0;
+            }
+        }
+        assert_eq!(flipped_words, [true; 4], "bits should be flipped in every word");
+        assert_eq!(flip_bits(key, 256), [u64::MAX; 4]);
+
+        let data = generate_uniform_data_with_block_size::<_, 4>(10_000, 100, false, |k| k[0]);
+        let mut masks: Vec<_> = data.iter().map(|(k, _)| *k).collect();
+        masks.dedup();
+        assert!(
+            masks.len() <= 100,
+            "should be no more than 100 distinct masks, got {}",
+            masks.len()
+        );
+    }
+}
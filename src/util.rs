@@ -1,9 +1,72 @@
-use std::{
-    any::TypeId,
-    cmp::Ordering,
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
-};
+use std::{cmp::Ordering, ops::Range};
+
+use crate::index::ScanBound;
+
+/// Slices longer than this are sorted in parallel when the `rayon` feature is enabled.
+pub const PARALLEL_SORT_THRESHOLD: usize = 1_000_000;
+
+/// Sort the slice by key without preserving the order of equal elements.
+///
+/// With the `rayon` feature enabled, slices longer than [`PARALLEL_SORT_THRESHOLD`] are sorted in parallel.
+pub fn sort_unstable_by_key<T, O, F>(data: &mut [T], f: F)
+where
+    T: ScanBound,
+    O: Ord,
+    F: Fn(&T) -> O + ScanBound,
+{
+    #[cfg(feature = "rayon")]
+    if data.len() > PARALLEL_SORT_THRESHOLD {
+        use rayon::slice::ParallelSliceMut;
+        data.par_sort_unstable_by_key(f);
+        return;
+    }
+    data.sort_unstable_by_key(f);
+}
+
+/// Merge two slices sorted by key into `out`, which must be exactly as long as both slices together. Equal items from
+/// `a` are placed first.
+///
+/// Runs of `a` between consecutive items of `b` are located with an exponential search and copied in bulk, so merging
+/// a small `b` into a large `a` takes little more than copying `a`.
+pub fn merge_sorted_by_key<T, O, F>(a: &[T], b: &[T], out: &mut [T], f: F)
+where
+    T: Copy,
+    O: Ord,
+    F: Fn(&T) -> O,
+{
+    assert_eq!(a.len() + b.len(), out.len(), "output should fit both slices exactly");
+    let (mut a, mut written) = (a, 0);
+    for item in b {
+        let key = f(item);
+        // the comparator never returns `Equal`, so this is the position of the first item greater than `key`
+        let run = exponential_search_by(a, |el| f(el).cmp(&key).then(Ordering::Less)).unwrap_err();
+        out[written..written + run].copy_from_slice(&a[..run]);
+        out[written + run] = *item;
+        written += run + 1;
+        a = &a[run..];
+    }
+    out[written..].copy_from_slice(a);
+}
+
+/// For every item of `b`, the number of items of `a` placed before it by [`merge_sorted_by_key`]. Both slices must be
+/// sorted by key.
+///
+/// Each rank is located with an exponential search starting from the previous one, so this takes about as many
+/// comparisons as the merge itself.
+#[cfg(feature = "fs")]
+pub(crate) fn merge_ranks_by_key<T, O, F>(a: &[T], b: &[T], ranks: &mut [u64], f: F)
+where
+    O: Ord,
+    F: Fn(&T) -> O,
+{
+    assert_eq!(b.len(), ranks.len(), "there should be a rank for every item");
+    let mut rank = 0;
+    for (item, out) in b.iter().zip(ranks) {
+        let key = f(item);
+        rank += exponential_search_by(&a[rank..], |el| f(el).cmp(&key).then(Ordering::Less)).unwrap_err();
+        *out = rank as u64;
+    }
+}
 
 /// Partition the slice according to the given predicate.
 ///
@@ -20,20 +83,29 @@ where
 /// which the comparator returns `Ordering::Equal`, and ending at the last such index (inclusive). If the comparator
 /// never returns `Ordering::Equal`, return an empty slice.
 pub fn extended_binary_search_by<T>(slice: &[T], f: impl Fn(&T) -> Ordering) -> &[T] {
+    &slice[extended_binary_search_range_by(slice, f)]
+}
+
+/// Same as [`extended_binary_search_by`], but returns the range of indexes instead of a slice.
+pub fn extended_binary_search_range_by<T>(slice: &[T], f: impl Fn(&T) -> Ordering) -> Range<usize> {
+    if slice.is_empty() {
+        return 0..0;
+    }
     // perform the first two steps of the binary search manually to get rid of OOB values right away
     // this may be helpful with some of the skew cases, and makes this search more robust against user-provided data
+    let full = slice;
     let mid = slice.len() / 2;
-    let slice = if f(&slice[mid]).then(Ordering::Greater) == Ordering::Greater {
+    let (offset, slice) = if f(&slice[mid]).then(Ordering::Greater) == Ordering::Greater {
         if f(&slice[0]) == Ordering::Greater {
             // not in bounds
-            return &slice[0..0];
+            return 0..0;
         }
-        &slice[..=mid]
+        (0, &slice[..=mid])
     } else if f(&slice[slice.len() - 1]) == Ordering::Less {
         // not in bounds
-        return &slice[0..0];
+        return 0..0;
     } else {
-        &slice[mid..]
+        (mid, &slice[mid..])
     };
 
     let maybe_block_start = slice.binary_search_by(|el| {
@@ -45,18 +117,20 @@ pub fn extended_binary_search_by<T>(slice: &[T], f: impl Fn(&T) -> Ordering) ->
     match maybe_block_start {
         Ok(_) => unreachable!("not possible to find an element with a comparator fn that never returns Equals"),
         Err(pos) if pos < slice.len() && f(&slice[pos]).is_eq() => {
+            // the block may extend past the narrowed slice, so its end is searched for in the full slice
+            let start = offset + pos;
             // exp_search performs best when blocks are small, otherwise binary_search is better
-            let block_end = exponential_search_by(&slice[pos..], |el| {
+            let block_end = exponential_search_by(&full[start..], |el| {
                 // 0 0 2 2 2 3 4 5 13
                 //     ^st ^end
                 f(el).then(Ordering::Less)
             });
             match block_end {
                 Ok(_) => unreachable!("not possible to find an element with a comparator fn that never returns Equals"),
-                Err(block_end) => &slice[pos..(pos + block_end).min(slice.len())],
+                Err(block_end) => start..(start + block_end).min(full.len()),
             }
         }
-        Err(_) => &slice[0..0],
+        Err(_) => 0..0,
     }
 }
 
@@ -76,22 +150,420 @@ where
         .map_err(|i| i + start)
 }
 
-/// Create a u64 signature for a given type and permutation parameters.
-pub fn sign_type<T: 'static>(f: u64, r: u64, k: u64, w: u64) -> u64 {
-    let t = TypeId::of::<T>();
-    let mut hasher = DefaultHasher::new();
-    t.hash(&mut hasher);
-    hasher.write_u64(f);
-    hasher.write_u64(r);
-    hasher.write_u64(k);
-    hasher.write_u64(w);
-    hasher.finish()
+/// Tag in the top bits of signatures created by [`sign_type`] which contain the parameters they were created with.
+const SIGNATURE_TAG: u64 = 0xC;
+
+/// Tag of signatures created by older versions of [`sign_type`], which hashed the `TypeId` of the value type. Such
+/// signatures change between builds; see [`is_legacy_signature`].
+const LEGACY_SIGNATURE_TAG: u64 = 0xB;
+
+/// Create a u64 signature for a given type and permutation parameters. Same as [`sign_type_with_schema`] with schema
+/// id 0.
+pub fn sign_type<T>(f: u64, r: u64, k: u64, w: u64) -> u64 {
+    sign_type_with_schema::<T>(0, f, r, k, w)
+}
+
+/// Create a u64 signature for a given type, schema id and permutation parameters.
+///
+/// Signatures are stable across builds and compiler versions: the type is only described by its size and alignment.
+/// `schema` tells apart value types which share them (e.g. `i64` and `u64`, or versions of a record type); it is up to
+/// the caller to pick distinct ids for those.
+///
+/// The parameters and the size of the type are stored in the signature as is, so that they can be recovered from a
+/// mismatching signature with [`SignatureParams::decode`]. The remaining 16 bits are a hash of the parameters, the
+/// alignment of the type and the schema id. Parameters which do not fit are only hashed.
+///
+/// Layout, from the most significant bits: tag (4 bits), f (12), r (8), k (4), w (8), size of the type (12), hash (16).
+pub fn sign_type_with_schema<T>(schema: u64, f: u64, r: u64, k: u64, w: u64) -> u64 {
+    let value_size = size_of::<T>() as u64;
+    let hash = fnv1a([f, r, k, w, value_size, align_of::<T>() as u64, schema]);
+    let params = SignatureParams { f, r, k, w, value_size };
+    params.encode(SIGNATURE_TAG, hash & 0xFFFF).unwrap_or(hash >> 4)
+}
+
+/// 64-bit FNV-1a hash of the little-endian bytes of `values`. Unlike `DefaultHasher`, it is stable across builds.
+pub(crate) fn fnv1a(values: impl IntoIterator<Item = u64>) -> u64 {
+    fnv1a_bytes(values.into_iter().flat_map(u64::to_le_bytes))
+}
+
+/// 64-bit FNV-1a hash of `bytes`.
+pub(crate) fn fnv1a_bytes(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Check whether `sig` was created by an older version of [`sign_type`] with the same parameters and value size as
+/// `expected`. Files with such signatures can be migrated to `expected` (see
+/// [`migrate_legacy_signatures`](crate::lookup::layout::migrate_legacy_signatures)).
+///
+/// Legacy signatures contain a hash of the `TypeId` of the value type, which cannot be reproduced, so only the
+/// parameters stored in them are compared; signatures whose parameters did not fit are never recognized.
+#[must_use]
+pub fn is_legacy_signature(sig: u64, expected: u64) -> bool {
+    sig >> 60 == LEGACY_SIGNATURE_TAG
+        && expected >> 60 == SIGNATURE_TAG
+        && SignatureParams::decode(sig).is_some_and(|params| Some(params) == SignatureParams::decode(expected))
+}
+
+/// Parameters stored in a signature created by [`sign_type`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureParams {
+    pub f: u64,
+    pub r: u64,
+    pub k: u64,
+    pub w: u64,
+    /// Size of the value type, in bytes.
+    pub value_size: u64,
+}
+
+impl SignatureParams {
+    const FIELDS: [(u32, u32); 5] = [(48, 12), (40, 8), (36, 4), (28, 8), (16, 12)];
+
+    fn values(&self) -> [u64; 5] {
+        [self.f, self.r, self.k, self.w, self.value_size]
+    }
+
+    fn encode(&self, tag: u64, hash: u64) -> Option<u64> {
+        let mut sig = tag << 60 | hash;
+        for ((shift, bits), value) in Self::FIELDS.into_iter().zip(self.values()) {
+            if value >> bits != 0 {
+                return None;
+            }
+            sig |= value << shift;
+        }
+        Some(sig)
+    }
+
+    /// Recover parameters from a signature. Returns `None` if the signature was not created by [`sign_type`] (of this
+    /// or an older version), or the parameters did not fit into it.
+    #[must_use]
+    pub fn decode(sig: u64) -> Option<Self> {
+        if !matches!(sig >> 60, SIGNATURE_TAG | LEGACY_SIGNATURE_TAG) {
+            return None;
+        }
+        let [f, r, k, w, value_size] = Self::FIELDS.map(|(shift, bits)| (sig >> shift) & ((1 << bits) - 1));
+        Some(Self { f, r, k, w, value_size })
+    }
+}
+
+impl std::fmt::Display for SignatureParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "f={},r={},k={},w={} with {}-byte values",
+            self.f, self.r, self.k, self.w, self.value_size
+        )
+    }
+}
+
+/// Human-readable description of a signature mismatch.
+#[cfg(feature = "fs")]
+pub(crate) fn describe_signature_mismatch(expected: u64, actual: u64) -> String {
+    if is_legacy_signature(actual, expected) {
+        return "file was signed by an older version of hloo, and can be migrated with `migrate_legacy_signatures`"
+            .to_string();
+    }
+    match (SignatureParams::decode(expected), SignatureParams::decode(actual)) {
+        (Some(expected), Some(actual)) if expected == actual => {
+            format!("file was built with {actual}, but for a different value type")
+        }
+        (Some(expected), Some(actual)) => format!("file was built with {actual}, but {expected} was requested"),
+        _ => format!("expected: {expected}, got: {actual}"),
+    }
+}
+
+/// Types which can be decoded from a fixed number of little-endian bytes, as stored in raw record dumps (see
+/// [`MemMapIndex::import_raw`](crate::index::MemMapIndex::import_raw)).
+///
+/// A pair is encoded as its first element followed by its second one. Bit containers created by the lookup macros
+/// are encoded as their words in order, each of them little-endian.
+pub trait FromLeBytes: Sized {
+    /// Number of bytes taken by an encoded value.
+    const SIZE: usize;
+
+    /// Decode a value from exactly [`Self::SIZE`] bytes.
+    ///
+    /// ## Panics
+    /// Panics if `bytes` has a different length.
+    fn from_le_slice(bytes: &[u8]) -> Self;
+}
+
+/// Values which can be encoded into the little-endian bytes decoded by [`FromLeBytes`], e.g. to be written into an
+/// [export](crate::export).
+pub trait ToLeBytes: FromLeBytes {
+    /// Encode the value into exactly [`Self::SIZE`](FromLeBytes::SIZE) bytes.
+    ///
+    /// ## Panics
+    /// Panics if `bytes` has a different length.
+    fn write_le_slice(&self, bytes: &mut [u8]);
+}
+
+macro_rules! impl_from_le_bytes {
+    ($($t:ty),*) => {
+        $(
+            impl FromLeBytes for $t {
+                const SIZE: usize = size_of::<$t>();
+
+                fn from_le_slice(bytes: &[u8]) -> Self {
+                    Self::from_le_bytes(bytes.try_into().expect("slice with incorrect length"))
+                }
+            }
+
+            impl ToLeBytes for $t {
+                fn write_le_slice(&self, bytes: &mut [u8]) {
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_from_le_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl<A, B> FromLeBytes for (A, B)
+where
+    A: FromLeBytes,
+    B: FromLeBytes,
+{
+    const SIZE: usize = A::SIZE + B::SIZE;
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        assert_eq!(bytes.len(), Self::SIZE, "slice with incorrect length");
+        let (a, b) = bytes.split_at(A::SIZE);
+        (A::from_le_slice(a), B::from_le_slice(b))
+    }
+}
+
+impl<A, B> ToLeBytes for (A, B)
+where
+    A: ToLeBytes,
+    B: ToLeBytes,
+{
+    fn write_le_slice(&self, bytes: &mut [u8]) {
+        assert_eq!(bytes.len(), Self::SIZE, "slice with incorrect length");
+        let (a, b) = bytes.split_at_mut(A::SIZE);
+        self.0.write_le_slice(a);
+        self.1.write_le_slice(b);
+    }
+}
+
+/// Keys which can be converted to and from a fixed number of big-endian bytes, i.e. the first bit of the key is the
+/// most significant bit of the first byte. Implemented for bit containers created by the lookup macros.
+pub trait KeyBytes: Sized {
+    /// Number of bytes taken by an encoded key.
+    const SIZE: usize;
+
+    /// Encode the key into [`Self::SIZE`] bytes.
+    fn to_be_vec(&self) -> Vec<u8>;
+
+    /// Decode a key from exactly [`Self::SIZE`] bytes.
+    ///
+    /// ## Panics
+    /// Panics if `bytes` has a different length.
+    fn from_be_slice(bytes: &[u8]) -> Self;
+}
+
+/// Blocks beyond `max_distance + 1` considered by [`recommend_params`].
+const MAX_EXTRA_BLOCKS: usize = 8;
+
+/// Lookup parameters evaluated by [`recommend_params`].
+///
+/// Estimates assume uniformly distributed keys, and blocks of equal size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamsRecommendation {
+    pub r: usize,
+    pub k: usize,
+    /// Number of indexes, i.e. permutations: `r` choose `k`.
+    pub n_indexes: usize,
+    /// Expected number of keys in a block of an index, i.e. sharing the mask of a searched key.
+    pub expected_block_size: f64,
+    /// Expected number of candidates compared to a searched key, across all indexes.
+    pub expected_candidates: f64,
+    /// Memory taken by keys of all indexes, in bytes. Values take `n_indexes * expected_n * size_of::<T>()` more.
+    pub memory: u64,
+    /// Probability of finding a key at distance `max_distance`, if differing bits are at random positions. This is
+    /// exactly 1 when `max_distance <= r - k`: at least `k` blocks are then equal, and some index has them in its mask.
+    pub recall: f64,
+}
+
+/// Evaluate parameters `r` and `k` of lookups over `f`-bit keys, searched for keys within `max_distance`, which hold
+/// `expected_n` keys in at most `memory_budget` bytes.
+///
+/// Options which fit into the budget are returned from the best to the worst: by decreasing recall, then by
+/// increasing number of candidates a search compares.
+pub fn recommend_params(
+    f: usize,
+    max_distance: usize,
+    expected_n: usize,
+    memory_budget: u64,
+) -> Vec<ParamsRecommendation> {
+    let mut options = Vec::new();
+    for r in 2..=f.min(max_distance + 1 + MAX_EXTRA_BLOCKS) {
+        for k in 1..r {
+            let Some(n_indexes) = binomial(r, k) else {
+                break;
+            };
+            let memory = (n_indexes as u128 * expected_n as u128 * f.div_ceil(8) as u128).min(u64::MAX as u128);
+            if memory > memory_budget as u128 {
+                continue;
+            }
+            let mask_bits = (k * f) as f64 / r as f64;
+            let expected_block_size = expected_n as f64 / mask_bits.exp2();
+            options.push(ParamsRecommendation {
+                r,
+                k,
+                n_indexes,
+                expected_block_size,
+                expected_candidates: expected_block_size * n_indexes as f64,
+                memory: memory as u64,
+                recall: estimate_recall(f, r, k, max_distance),
+            });
+        }
+    }
+    options.sort_by(|a, b| {
+        b.recall
+            .total_cmp(&a.recall)
+            .then(a.expected_candidates.total_cmp(&b.expected_candidates))
+    });
+    options
+}
+
+/// Probability that at least `k` of `r` equal blocks of `f` bits are left intact when `d` random bits are changed.
+fn estimate_recall(f: usize, r: usize, k: usize, d: usize) -> f64 {
+    if d + k <= r {
+        return 1.0;
+    }
+    if d > f {
+        return 0.0;
+    }
+    // probability that given `j` blocks are intact: all changed bits are in the other blocks
+    let intact = |j: usize| {
+        let other_bits = f as f64 * (r - j) as f64 / r as f64;
+        (0..d)
+            .map(|i| ((other_bits - i as f64) / (f - i) as f64).max(0.0))
+            .product::<f64>()
+    };
+    // inclusion-exclusion: P(at least k intact) = sum over j >= k of (-1)^(j-k) C(j-1, k-1) C(r, j) P(j given intact)
+    let recall: f64 = (k..=r)
+        .map(|j| {
+            let sign = if (j - k).is_multiple_of(2) { 1.0 } else { -1.0 };
+            sign * binomial_f64(j - 1, k - 1) * binomial_f64(r, j) * intact(j)
+        })
+        .sum();
+    recall.clamp(0.0, 1.0)
+}
+
+/// `n` choose `k`, or `None` if it overflows.
+fn binomial(n: usize, k: usize) -> Option<usize> {
+    let mut result: usize = 1;
+    for i in 0..k.min(n - k) {
+        // the product of i + 1 consecutive numbers is divisible by (i + 1)!
+        result = result.checked_mul(n - i)? / (i + 1);
+    }
+    Some(result)
+}
+
+fn binomial_f64(n: usize, k: usize) -> f64 {
+    (0..k.min(n - k)).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn signature_params_can_be_recovered() {
+        let sig = sign_type::<u64>(256, 8, 1, 64);
+        let params = SignatureParams::decode(sig).expect("parameters should fit");
+        assert_eq!(params.to_string(), "f=256,r=8,k=1,w=64 with 8-byte values");
+        assert_eq!(sig, 0xC100_0814_0008_CC43, "signatures are stable across builds");
+        assert_ne!(sig, sign_type::<[u32; 2]>(256, 8, 1, 64), "different alignment");
+        assert_ne!(sig, sign_type_with_schema::<i64>(1, 256, 8, 1, 64), "different schema");
+        assert_eq!(
+            SignatureParams::decode(sign_type::<u64>(1 << 20, 8, 1, 64)),
+            None,
+            "f does not fit"
+        );
+        assert_eq!(SignatureParams::decode(42), None, "arbitrary signature");
+    }
+
+    #[test]
+    fn legacy_signatures_are_recognized() {
+        let sig = sign_type::<u64>(256, 8, 1, 64);
+        let legacy = (sig & !(0xF << 60) & !0xFFFF) | LEGACY_SIGNATURE_TAG << 60 | 0x1234;
+        assert!(is_legacy_signature(legacy, sig));
+        assert!(!is_legacy_signature(sig, sig), "current signatures are not legacy");
+        assert!(
+            !is_legacy_signature(legacy, sign_type::<u32>(256, 8, 1, 64)),
+            "different value size"
+        );
+        assert_eq!(SignatureParams::decode(legacy), SignatureParams::decode(sig));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn signature_mismatches_are_described() {
+        let sig = sign_type::<u64>(256, 8, 1, 64);
+        assert_eq!(
+            describe_signature_mismatch(sign_type::<u64>(256, 5, 1, 64), sig),
+            "file was built with f=256,r=8,k=1,w=64 with 8-byte values, but f=256,r=5,k=1,w=64 with 8-byte values was \
+             requested"
+        );
+        assert_eq!(describe_signature_mismatch(1, 2), "expected: 1, got: 2");
+    }
+
+    #[test]
+    fn recommended_params_fit_the_budget() {
+        let options = recommend_params(64, 3, 1_000_000, 1 << 30);
+        assert!(
+            options
+                .iter()
+                .all(|o| o.memory <= 1 << 30 && o.n_indexes * 8_000_000 == o.memory as usize)
+        );
+        let best = options[0];
+        assert_eq!(best.recall, 1.0);
+        assert!(best.r >= best.k + 3);
+        let r5k2 = options.iter().find(|o| (o.r, o.k) == (5, 2)).expect("r=5 k=2 fits");
+        assert_eq!((r5k2.n_indexes, r5k2.recall), (10, 1.0));
+        let r4k2 = options.iter().find(|o| (o.r, o.k) == (4, 2)).expect("r=4 k=2 fits");
+        assert!(0.0 < r4k2.recall && r4k2.recall < 1.0, "recall {}", r4k2.recall);
+        // one of 2 blocks is intact only if all 3 changed bits are in the other one
+        let r2k1 = options.iter().find(|o| (o.r, o.k) == (2, 1)).expect("r=2 k=1 fits");
+        let expected = 2.0 * (32.0 * 31.0 * 30.0) / (64.0 * 63.0 * 62.0);
+        assert!((r2k1.recall - expected).abs() < 1e-9, "recall {}", r2k1.recall);
+        assert!(recommend_params(64, 3, 1_000_000, 1000).is_empty());
+    }
+
+    #[test]
+    fn merge_sorted_by_key_merges_runs() {
+        let a = [1, 2, 2, 5, 8, 13, 21];
+        let b = [0, 2, 9, 9, 30];
+        let mut out = [0; 12];
+        merge_sorted_by_key(&a, &b, &mut out, |x| *x);
+        assert_eq!(out, [0, 1, 2, 2, 2, 5, 8, 9, 9, 13, 21, 30]);
+
+        // equal items from the first slice go first
+        let a = [(1, 'a'), (2, 'a')];
+        let b = [(1, 'b')];
+        let mut out = [(0, ' '); 3];
+        merge_sorted_by_key(&a, &b, &mut out, |x| x.0);
+        assert_eq!(out, [(1, 'a'), (1, 'b'), (2, 'a')]);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn merge_ranks_by_key_matches_merge() {
+        let a = [1, 2, 2, 5, 8, 13, 21];
+        let b = [0, 2, 9, 9, 30];
+        let mut ranks = [0; 5];
+        merge_ranks_by_key(&a, &b, &mut ranks, |x| *x);
+        assert_eq!(ranks, [0, 3, 5, 5, 7]);
+        for (item, rank) in b.iter().zip(ranks) {
+            assert_eq!(rank as usize, a.partition_point(|el| el <= item), "rank of {item}");
+        }
+    }
+
     #[test]
     fn partition_vector() {
         let mut data = vec![0, 3, 4, 6, 3];
@@ -130,6 +602,10 @@ mod tests {
         let res = extended_binary_search_by(&data, |(k, _)| k.cmp(&0));
         assert_eq!(res.len(), 0, "key = 0");
         assert_eq!(res, &data[0..0], "key = 0 - data");
+        let res = extended_binary_search_by(&data[0..0], |(k, _)| k.cmp(&0));
+        assert_eq!(res.len(), 0, "empty");
+        let res = extended_binary_search_range_by(&data, |(k, _)| k.cmp(&4));
+        assert_eq!(res, 4..7, "key = 4 - range");
     }
 
     #[test]
@@ -148,4 +624,38 @@ mod tests {
         let res = exponential_search_by(&data[0..0], |_| panic!("this should not be called"));
         assert_eq!(res, Err(0), "empty");
     }
+
+    #[test]
+    fn sort_unstable_by_key_sorts_large_slices() {
+        let mut data: Vec<u64> = (0..(PARALLEL_SORT_THRESHOLD as u64 + 1000))
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .collect();
+        sort_unstable_by_key(&mut data, |x| *x);
+        assert!(data.is_sorted(), "large slice");
+        let mut data = vec![3, 1, 2];
+        sort_unstable_by_key(&mut data, |x| *x);
+        assert_eq!(data, vec![1, 2, 3], "small slice");
+    }
+
+    #[test]
+    fn extended_binary_search_finds_blocks_spanning_the_middle() {
+        let data = [1, 2, 3, 3];
+        assert_eq!(
+            extended_binary_search_range_by(&data, |x| x.cmp(&3)),
+            2..4,
+            "block starting at mid"
+        );
+        let data = [1, 3, 3, 3, 3];
+        assert_eq!(
+            extended_binary_search_range_by(&data, |x| x.cmp(&3)),
+            1..5,
+            "block containing mid"
+        );
+        let data = [3, 3, 3, 3, 4];
+        assert_eq!(
+            extended_binary_search_range_by(&data, |x| x.cmp(&3)),
+            0..4,
+            "block ending after mid"
+        );
+    }
 }
@@ -1,33 +1,40 @@
 use std::collections::HashSet;
 
-use hloo::index::{Candidates, SearchResultItem};
+use hloo::{
+    hloo_core::{
+        BitContainer,
+        proptest::{prop_assert, prop_assert_eq, proptest},
+    },
+    index::{Candidates, Index, ScanBound, SearchResultItem},
+    Lookup,
+};
 
 // 7 7 6 6 6
 hloo::init_lookup!(LookupUtil, 32, 5, 1, 32);
+hloo::init_lookup!(mod wide_lookup, 128, 5, 2, 64);
+hloo::init_lookup!(mod narrow_lookup, 32, 4, 2, 32);
+
+mod configs {
+    hloo::init_lookup!(Hashes {
+        Bits64 = (64, 4, 1, 64),
+        Bits256 = (256, 6, 2, 64),
+    });
+}
 
 fn generate_data(n: usize) -> Vec<(Bits, i64)> {
     let mut data = Vec::new();
     for i in 0..n {
-        let bits = Bits::new(data_gen::random());
+        let bits = data_gen::random::<Bits>();
         data.push((bits, i as i64));
     }
     data
 }
 
-fn flip_bits(mut bits: Bits, n: usize) -> Bits {
-    for _ in 0..n {
-        let pos = (data_gen::random::<f32>() * 31f32) as usize;
-        let bit = (bits.data[0] & (1 << pos)) >> pos;
-        if bit == 0 {
-            bits.data[0] |= 1 << pos;
-        } else {
-            bits.data[0] &= !(1 << pos);
-        }
-    }
-    bits
-}
-
-fn naive_search<K: BitContainer, V: Clone>(data: &[(K, V)], key: K, distance: u32) -> Vec<SearchResultItem<V>> {
+fn naive_search<K: BitContainer + ScanBound, V: Clone + ScanBound>(
+    data: &[(K, V)],
+    key: K,
+    distance: u32,
+) -> Vec<SearchResultItem<V>> {
     Candidates::new(key, data).scan(distance)
 }
 
@@ -62,11 +69,50 @@ fn memmap_lookup_compiles_and_runs_without_errors() {
     );
 }
 
+#[test]
+fn lookups_can_be_initialized_in_modules() {
+    let mut lookup = wide_lookup::LookupUtil::create_mem_lookup::<i64>();
+    assert_eq!(lookup.indexes().len(), 10);
+    let key = wide_lookup::Bits::from(u128::MAX);
+    lookup.insert(&[(key, 1)]).unwrap();
+    let result = lookup.search(&key.random_within_distance(3), 3).unwrap();
+    assert!(result.flat_iter().all(|item| *item.data() == 1));
+    assert!(result.flat_iter().next().is_some());
+}
+
+#[test]
+fn lookups_can_be_initialized_with_several_configs() {
+    use configs::{Bits256, Bits64, Hashes};
+
+    let mut short = Hashes::create_mem_lookup::<Bits64, i64>();
+    let mut long = Hashes::create_mem_lookup::<Bits256, i64>();
+    assert_eq!(short.indexes().len(), 4);
+    assert_eq!(long.indexes().len(), 15);
+
+    let (short_key, long_key) = (Bits64::from(u64::MAX), Bits256::default());
+    short.insert(&[(short_key, 1)]).unwrap();
+    long.insert(&[(long_key, 2)]).unwrap();
+    let result = short.search_simple(&short_key.random_within_distance(3), 3);
+    assert_eq!(result.iter().map(|item| *item.data()).collect::<Vec<_>>(), vec![1]);
+    let result = long.search_simple(&long_key.random_within_distance(4), 4);
+    assert_eq!(result.iter().map(|item| *item.data()).collect::<Vec<_>>(), vec![2]);
+}
+
+#[test]
+fn lookup_reports_mask_bits() {
+    let lookup = LookupUtil::create_mem_lookup::<i64>();
+    // blocks are 7, 7, 6, 6, 6 bits long, and each permutation moves one of them to the front
+    let mask_bits: Vec<_> = lookup.indexes().iter().map(|i| i.permuter().mask_bits()).collect();
+    assert_eq!(mask_bits, vec![7, 7, 6, 6, 6]);
+    assert_eq!(lookup.mask_bits(), 6);
+    assert_eq!(Permutations0::MASK_BITS, 7);
+}
+
 #[test]
 fn mem_lookup_works_correctly() {
     let mut lookup = LookupUtil::create_mem_lookup::<i64>();
     let data = generate_data(10);
-    let target = flip_bits(data[0].0, 3);
+    let target = data[0].0.random_within_distance(3);
     lookup.insert(&data).unwrap();
     let expected = naive_search(&data, target, 3).into_iter().collect::<HashSet<_>>();
     let result = lookup.search_simple(&target, 3);
@@ -87,7 +133,7 @@ fn memmap_lookup_works_correctly() {
     let tmp_path = tempfile::tempdir().unwrap();
     let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
     let data = generate_data(10);
-    let target = flip_bits(data[0].0, 3);
+    let target = data[0].0.random_within_distance(3);
     lookup.insert(&data).unwrap();
     let expected = naive_search(&data, target, 3).into_iter().collect::<HashSet<_>>();
     let result = lookup.search_simple(&target, 3);
@@ -103,6 +149,56 @@ fn memmap_lookup_works_correctly() {
     }
 }
 
+#[test]
+fn split_mem_lookup_works_correctly() {
+    let mut lookup = LookupUtil::create_split_mem_lookup::<i64>();
+    let data = generate_data(100);
+    let target = data[0].0.random_within_distance(3);
+    lookup.insert(&data).unwrap();
+    let expected = naive_search(&data, target, 3).into_iter().collect::<HashSet<_>>();
+    let result = lookup.search_simple(&target, 3);
+    assert_eq!(
+        result, expected,
+        "split layout lookup should produce the same results as naive search"
+    );
+}
+
+#[test]
+fn lookup_search_with_custom_metric() {
+    let mut lookup = LookupUtil::create_mem_lookup::<i64>();
+    let key = Bits::from(0xF000_0000u32);
+    lookup.insert(&[(key, 1), (Bits::from(0x0000_000Fu32), 2)]).unwrap();
+    // differences in the first 4 bits are ignored
+    let masked = |a: &Bits, b: &Bits| (u32::from(*a) ^ u32::from(*b)).wrapping_shl(4).count_ones();
+    let result = lookup.search_with(&Bits::from(0x3000_0000u32), 2, masked).unwrap();
+    let result: Vec<_> = result.flat_iter().map(|item| (*item.data(), item.distance())).collect();
+    assert!(!result.is_empty());
+    assert!(result.iter().all(|item| *item == (1, 0)));
+    assert!(
+        lookup
+            .search(&Bits::from(0x3000_0000u32), 1)
+            .unwrap()
+            .flat_iter()
+            .next()
+            .is_none()
+    );
+}
+
+#[test]
+fn lookup_searches_beyond_max_distance_without_guarantees() {
+    let mut lookup = LookupUtil::create_mem_lookup::<i64>();
+    assert_eq!(lookup.max_search_distance(), 4);
+    // 6 bits differ, all within the first block
+    let key = Bits::from(0u32);
+    lookup.insert(&[(Bits::from(0xFC00_0000u32), 1)]).unwrap();
+    assert!(lookup.search(&key, 6).is_err());
+    let result = lookup.search_unchecked_distance(&key, 6);
+    assert!(!result.recall_guaranteed);
+    assert!(result.flat_iter().all(|item| (*item.data(), item.distance()) == (1, 6)));
+    assert!(result.flat_iter().next().is_some());
+    assert!(lookup.search(&key, 4).unwrap().recall_guaranteed);
+}
+
 #[test]
 fn mem_lookup_single_entry() {
     let init_data = vec![(Bits { data: [851899373] }, 0)];
@@ -121,7 +217,7 @@ fn mem_lookup_single_entry() {
 #[test]
 fn naive_results_correspond_to_hloo() {
     let data = generate_data(1000);
-    let target = flip_bits(data[0].0, 3);
+    let target = data[0].0.random_within_distance(3);
 
     let mut lookup_mem = LookupUtil::create_mem_lookup::<i64>();
     lookup_mem.insert(&data).unwrap();
@@ -164,7 +260,7 @@ fn naive_results_correspond_to_hloo() {
 fn memmap_lookup_can_be_saved_and_loaded() {
     let tmp_path = tempfile::tempdir().unwrap();
     let data = generate_data(10);
-    let target = flip_bits(data[0].0, 3);
+    let target = data[0].0.random_within_distance(3);
     let expected = naive_search(&data, target, 5).into_iter().collect::<HashSet<_>>();
 
     {
@@ -186,6 +282,7 @@ fn memmap_lookup_can_be_saved_and_loaded() {
 
     {
         let lookup = LookupUtil::load_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+        lookup.warmup();
         let result = lookup.search_simple(&target, 3);
         assert_eq!(
             result.len(),
@@ -199,3 +296,671 @@ fn memmap_lookup_can_be_saved_and_loaded() {
         }
     }
 }
+
+#[test]
+fn persistent_lookups_can_be_destroyed() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data = generate_data(10);
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    lookup.insert(&data).unwrap();
+    lookup.destroy().unwrap();
+    let mut file_lookup = LookupUtil::create_file_lookup::<i64>(tmp_path.path()).unwrap();
+    file_lookup.insert(&data).unwrap();
+    file_lookup.destroy().unwrap();
+    assert_eq!(
+        std::fs::read_dir(tmp_path.path()).unwrap().count(),
+        0,
+        "all files should be removed"
+    );
+}
+
+#[test]
+fn memmap_lookup_can_be_backed_up() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let path = tmp_path.path().join("lookup");
+    std::fs::create_dir(&path).unwrap();
+    let data = generate_data(100);
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(&path).unwrap();
+    lookup.insert(&data[..50]).unwrap();
+    lookup.remove(&[data[0].0]).unwrap();
+
+    let backup = tmp_path.path().join("backup");
+    let entries = lookup.backup_to(&backup).unwrap();
+    assert_eq!(entries.len(), 2 * lookup.indexes().len(), "index and tombstone files");
+    assert_eq!(
+        hloo::lookup::backup::verify(&backup).unwrap(),
+        entries,
+        "backup matches its manifest"
+    );
+    // changes made after the backup do not affect it
+    lookup.insert(&data[50..]).unwrap();
+
+    let restored = LookupUtil::load_memmap_lookup::<i64>(&backup).unwrap();
+    assert!(
+        restored.search_simple(&data[0].0, 0).is_empty(),
+        "removed item should stay removed"
+    );
+    assert_eq!(
+        restored.search_simple(&data[1].0, 0).len(),
+        1,
+        "backed up item should be found"
+    );
+    assert!(
+        restored.search_simple(&data[99].0, 0).is_empty(),
+        "item inserted later should not be found"
+    );
+    assert!(restored.validate().unwrap().is_ok(), "restored lookup should be valid");
+}
+
+#[test]
+fn memmap_lookup_imports_raw_dumps() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let path = tmp_path.path().join("lookup");
+    std::fs::create_dir(&path).unwrap();
+    let data = generate_data(200);
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(&path).unwrap();
+    lookup.insert(&data[..100]).unwrap();
+
+    let mut dump = Vec::new();
+    for (key, value) in &data[100..] {
+        for word in key.data {
+            dump.extend_from_slice(&word.to_le_bytes());
+        }
+        dump.extend_from_slice(&value.to_le_bytes());
+    }
+    let dump_path = tmp_path.path().join("dump.bin");
+    std::fs::write(&dump_path, &dump).unwrap();
+    assert_eq!(lookup.import_raw(&dump_path).unwrap(), 100, "imported records");
+
+    for (key, value) in &data {
+        let result = lookup.search_simple(key, 0);
+        assert!(
+            result.iter().any(|item| item.data() == value),
+            "item {value} should be found"
+        );
+    }
+    assert!(lookup.validate().unwrap().is_ok(), "lookup should stay sorted");
+
+    std::fs::write(&dump_path, &dump[..dump.len() - 1]).unwrap();
+    assert!(
+        matches!(
+            lookup.import_raw(&dump_path),
+            Err(hloo::mmvec::MmVecError::InvalidRecordDump { .. })
+        ),
+        "truncated dumps should be rejected"
+    );
+
+    // only the last index runs out of space, and the dump must not end up in any index
+    std::fs::write(&dump_path, &dump).unwrap();
+    let lens: Vec<_> = lookup.indexes().iter().map(|index| index.data().len()).collect();
+    lookup.indexes_mut().last_mut().unwrap().set_max_len(Some(lens[0]));
+    assert!(
+        matches!(
+            lookup.import_raw(&dump_path),
+            Err(hloo::mmvec::MmVecError::TooLarge { .. })
+        ),
+        "imports beyond the maximum length should be rejected"
+    );
+    for (index, len) in lookup.indexes().iter().zip(lens) {
+        assert_eq!(
+            index.data().len(),
+            len,
+            "failed imports should leave every index unchanged"
+        );
+    }
+}
+
+#[test]
+fn lookups_are_exported_and_imported() {
+    use hloo::{export::ExportError, util::KeyBytes};
+
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data = generate_data(100);
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    lookup.insert(&data).unwrap();
+    lookup.remove(&[data[0].0]).unwrap();
+    let export_path = tmp_path.path().join("export.bin");
+    assert_eq!(
+        lookup.export(&export_path).unwrap(),
+        99,
+        "removed item should not be exported"
+    );
+
+    // keys are stored in their original form, so lookups with other parameters can import them
+    let mut imported = narrow_lookup::LookupUtil::create_mem_lookup::<i64>();
+    assert_eq!(imported.import(&export_path).unwrap(), 99, "imported items");
+    for (key, value) in &data[1..] {
+        let key = narrow_lookup::Bits::from_be_slice(&key.to_be_vec());
+        let result = imported.search_simple(&key, 0);
+        assert!(
+            result.iter().any(|item| item.data() == value),
+            "item {value} should be found"
+        );
+    }
+    assert!(
+        imported
+            .search_simple(&narrow_lookup::Bits::from_be_slice(&data[0].0.to_be_vec()), 0)
+            .is_empty()
+    );
+
+    let mut other_values = LookupUtil::create_mem_lookup::<i32>();
+    assert!(
+        matches!(
+            other_values.import(&export_path),
+            Err(hloo::export::ImportError::Export(ExportError::SizeMismatch { .. }))
+        ),
+        "values of another size should be rejected"
+    );
+}
+
+#[test]
+fn memmap_lookup_validates_correctly() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    lookup.insert(&generate_data(100)).unwrap();
+    let report = lookup.validate().unwrap();
+    assert_eq!(report.indexes.len(), lookup.indexes().len(), "one report per index");
+    assert!(report.is_ok(), "freshly built lookup should be valid: {report:?}");
+}
+
+#[test]
+fn memmap_lookup_retain_works_correctly() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    let data = generate_data(100);
+    lookup.insert(&data).unwrap();
+    lookup.retain(|_, v| v % 2 == 0).unwrap();
+    for index in lookup.indexes() {
+        assert_eq!(index.data().len(), 50, "every index should retain the same items");
+        assert!(index.data().iter().all(|(_, v)| v % 2 == 0), "wrong items retained");
+    }
+    let result = lookup.search_simple(&data[1].0, 0);
+    assert!(result.is_empty(), "removed item should not be found");
+    let result = lookup.search_simple(&data[2].0, 0);
+    assert_eq!(result.len(), 1, "retained item should be found");
+}
+
+#[test]
+fn memmap_lookup_drain_works_correctly() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    let data = generate_data(100);
+    lookup.insert(&data).unwrap();
+    lookup.remove(&[data[0].0]).unwrap();
+
+    let mut drained = lookup.drain().unwrap();
+    let mut expected = data[1..].to_vec();
+    drained.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(drained, expected, "drained items should have original keys");
+    for index in lookup.indexes() {
+        assert!(index.data().is_empty(), "every index should be empty after drain");
+    }
+
+    let mut mem_lookup = LookupUtil::create_mem_lookup::<i64>();
+    mem_lookup.insert(&drained).unwrap();
+    let result = mem_lookup.search_simple(&data[1].0, 0);
+    assert_eq!(result.len(), 1, "drained item should be searchable after re-insertion");
+}
+
+#[test]
+fn lookups_dedup_works_correctly() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data = generate_data(100);
+    let mut duplicated = data.clone();
+    duplicated.extend_from_slice(&data[..50]);
+    duplicated.extend_from_slice(&data[..10]);
+    // same key, different value: not a duplicate
+    duplicated.push((data[0].0, -1));
+
+    let mut mem_lookup = LookupUtil::create_mem_lookup::<i64>();
+    let mut memmap_lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    mem_lookup.insert(&duplicated).unwrap();
+    memmap_lookup.insert(&duplicated).unwrap();
+    mem_lookup.dedup().unwrap();
+    memmap_lookup.dedup().unwrap();
+
+    for index in mem_lookup.indexes() {
+        assert_eq!(index.data().len(), 101, "mem lookup should have no duplicates");
+    }
+    for index in memmap_lookup.indexes() {
+        assert_eq!(index.data().len(), 101, "memmap lookup should have no duplicates");
+    }
+    let result = memmap_lookup.search(&data[0].0, 0).unwrap();
+    assert!(result.result.iter().all(|r| r.len() == 2), "search after dedup");
+}
+
+#[test]
+fn lookups_skip_present_pairs_on_insert() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data = generate_data(100);
+    let mut rerun = data[..50].to_vec();
+    rerun.extend_from_slice(&data[..10]);
+    // same key, different value: not a duplicate
+    rerun.push((data[0].0, -1));
+
+    let mut mem_lookup = LookupUtil::create_mem_lookup::<i64>();
+    let mut memmap_lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    assert_eq!(mem_lookup.insert_new(&data).unwrap(), 0);
+    assert_eq!(memmap_lookup.insert_new(&data).unwrap(), 0);
+    assert_eq!(mem_lookup.insert_new(&rerun).unwrap(), 60);
+    assert_eq!(memmap_lookup.insert_new(&rerun).unwrap(), 60);
+
+    for index in mem_lookup.indexes() {
+        assert_eq!(index.data().len(), 101, "mem lookup should have no duplicates");
+    }
+    for index in memmap_lookup.indexes() {
+        assert_eq!(index.data().len(), 101, "memmap lookup should have no duplicates");
+    }
+    memmap_lookup.remove(&[data[1].0]).unwrap();
+    assert_eq!(
+        memmap_lookup.insert_new(&data[1..2]).unwrap(),
+        0,
+        "removed pairs are inserted again"
+    );
+    let result = memmap_lookup.search(&data[1].0, 0).unwrap();
+    assert!(result.result.iter().all(|r| r.len() == 1), "search after re-insertion");
+}
+
+#[test]
+fn mem_lookup_predicts_candidates_scanned() {
+    let mut lookup = LookupUtil::create_mem_lookup::<i64>();
+    let data = generate_data(1000);
+    lookup.insert(&data).unwrap();
+    for (key, _) in data.iter().take(10) {
+        let predicted = lookup.predict_candidates_scanned(key);
+        let result = lookup.search(key, 2).unwrap();
+        assert_eq!(
+            predicted, result.candidates_scanned,
+            "prediction should match the actual search"
+        );
+    }
+}
+
+#[test]
+fn memmap_lookup_readers_follow_writer() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data = generate_data(100);
+    let mut writer = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    writer.insert(&data[..50]).unwrap();
+    writer.persist().unwrap();
+
+    let mut reader = LookupUtil::load_memmap_lookup_read_only::<i64>(tmp_path.path()).unwrap();
+    assert_eq!(
+        reader.search_simple(&data[0].0, 0).len(),
+        1,
+        "reader should see persisted items"
+    );
+    assert!(
+        reader.search_simple(&data[50].0, 0).is_empty(),
+        "item is not inserted yet"
+    );
+    assert!(!reader.reload().unwrap(), "nothing has changed yet");
+
+    writer.insert(&data[50..]).unwrap();
+    assert!(
+        reader.is_stale(),
+        "searches which overlapped the insert should be repeated"
+    );
+    assert!(reader.reload().unwrap(), "writer has inserted items");
+    assert!(!reader.is_stale(), "reader should be current after reloading");
+    assert_eq!(
+        reader.search_simple(&data[50].0, 0).len(),
+        1,
+        "reader should see new items"
+    );
+
+    writer.remove(&[data[0].0]).unwrap();
+    assert!(reader.reload().unwrap(), "writer has removed items");
+    assert!(
+        reader.search_simple(&data[0].0, 0).is_empty(),
+        "reader should not see removed items"
+    );
+
+    assert!(
+        reader.insert(&data[..1]).is_err(),
+        "reader should not be able to insert"
+    );
+    assert!(
+        reader.remove(&[data[1].0]).is_err(),
+        "reader should not be able to remove"
+    );
+}
+
+#[test]
+fn memmap_lookup_can_be_shipped_as_container() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let source = tmp_path.path().join("source");
+    std::fs::create_dir(&source).unwrap();
+    let data = generate_data(100);
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(&source).unwrap();
+    lookup.insert(&data).unwrap();
+    lookup.remove(&[data[0].0]).unwrap();
+
+    let container = tmp_path.path().join("lookup.hloo");
+    let entries = lookup.pack(&source, &container).unwrap();
+    // one data file and one tombstone file per index
+    assert_eq!(entries.len(), 2 * 5, "container entries");
+
+    let target = tmp_path.path().join("target");
+    let lookup = LookupUtil::unpack_memmap_lookup::<i64>(&container, &target).unwrap();
+    assert!(
+        lookup.search_simple(&data[0].0, 0).is_empty(),
+        "removed item should stay removed"
+    );
+    for (key, value) in &data[1..] {
+        let result = lookup.search_simple(key, 0);
+        assert!(
+            result.contains(&SearchResultItem::new(*value, 0)),
+            "item {value} should be found"
+        );
+    }
+    assert!(lookup.validate().unwrap().is_ok(), "unpacked lookup should be valid");
+}
+
+#[test]
+fn file_lookup_works_correctly() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data = generate_data(100);
+    let mut lookup = LookupUtil::create_file_lookup::<i64>(tmp_path.path()).unwrap();
+    lookup.insert(&data).unwrap();
+    lookup.remove(&[data[0].0]).unwrap();
+    drop(lookup);
+
+    let lookup = LookupUtil::load_file_lookup::<i64>(tmp_path.path()).unwrap();
+    assert!(
+        lookup.search_simple(&data[0].0, 0).is_empty(),
+        "removed item should stay removed"
+    );
+    for (key, value) in &data[1..] {
+        let result = lookup.search_simple(key, 0);
+        assert!(
+            result.contains(&SearchResultItem::new(*value, 0)),
+            "item {value} should be found"
+        );
+    }
+    drop(lookup);
+
+    // files are compatible with memory-mapped lookups
+    let lookup = LookupUtil::load_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    assert_eq!(lookup.indexes()[0].data().len(), 99, "items in memory-mapped lookup");
+}
+
+#[cfg(feature = "redb")]
+#[test]
+fn redb_lookup_works_correctly() {
+    use configs::Bits64;
+    use hloo::lookup::factory::{create_redb_lookup, load_redb_lookup};
+
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data: Vec<_> = (0..100).map(|i| (data_gen::random::<Bits64>(), i as i64)).collect();
+    let mut lookup = create_redb_lookup::<Bits64, i64>(tmp_path.path()).unwrap();
+    lookup.insert(&data).unwrap();
+    lookup.remove(&[data[0].0]).unwrap();
+    drop(lookup);
+
+    let lookup = load_redb_lookup::<Bits64, i64>(tmp_path.path()).unwrap();
+    assert!(
+        lookup.search_simple(&data[0].0, 0).is_empty(),
+        "removed item should stay removed"
+    );
+    for (key, value) in &data[1..] {
+        let result = lookup.search_simple(key, 0);
+        assert!(
+            result.contains(&SearchResultItem::new(*value, 0)),
+            "item {value} should be found"
+        );
+    }
+    assert!(lookup.validate().unwrap().is_ok(), "indexes should agree");
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocks_lookup_works_correctly() {
+    use configs::Bits64;
+    use hloo::lookup::factory::{create_rocks_lookup, load_rocks_lookup, load_rocks_lookup_read_only};
+
+    let tmp_path = tempfile::tempdir().unwrap();
+    let data: Vec<_> = (0..100).map(|i| (data_gen::random::<Bits64>(), i as i64)).collect();
+    let mut lookup = create_rocks_lookup::<Bits64, i64>(tmp_path.path()).unwrap();
+    lookup.insert(&data[..50]).unwrap();
+    let mut reader = load_rocks_lookup_read_only::<Bits64, i64>(tmp_path.path()).unwrap();
+    lookup.insert(&data[50..]).unwrap();
+    lookup.remove(&[data[0].0]).unwrap();
+    lookup.persist().unwrap();
+    assert!(reader.reload().unwrap(), "reader should pick up changes");
+    assert_eq!(reader.indexes()[0].data().len(), 99, "items seen by reader");
+    drop(reader);
+    drop(lookup);
+
+    let lookup = load_rocks_lookup::<Bits64, i64>(tmp_path.path()).unwrap();
+    assert!(
+        lookup.search_simple(&data[0].0, 0).is_empty(),
+        "removed item should stay removed"
+    );
+    for (key, value) in &data[1..] {
+        let result = lookup.search_simple(key, 0);
+        assert!(
+            result.contains(&SearchResultItem::new(*value, 0)),
+            "item {value} should be found"
+        );
+    }
+    assert!(lookup.validate().unwrap().is_ok(), "indexes should agree");
+}
+
+#[test]
+fn lookup_stats_snapshot_includes_storage() {
+    let data = generate_data(100);
+    let mut mem_lookup = LookupUtil::create_mem_lookup::<i64>();
+    mem_lookup.insert(&data).unwrap();
+    let stats = mem_lookup.stats_snapshot();
+    assert_eq!(stats.n_items, 100, "items");
+    assert_eq!(stats.indexes.len(), mem_lookup.indexes().len(), "indexes");
+    assert!(
+        stats
+            .indexes
+            .iter()
+            .all(|index| index.n_blocks > 0 && index.max_block_size >= index.avg_block_size)
+    );
+    assert_eq!(
+        (stats.bytes_on_disk, stats.last_flush_unix_ms),
+        (0, None),
+        "in-memory lookup"
+    );
+
+    let tmp_path = tempfile::tempdir().unwrap();
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    lookup.insert(&data).unwrap();
+    lookup.persist().unwrap();
+    let stats = lookup.stats_snapshot();
+    assert_eq!(stats.n_items, 100, "items");
+    assert!(
+        stats.bytes_on_disk >= 100 * 12 * 5,
+        "size of files: {}",
+        stats.bytes_on_disk
+    );
+    assert!(stats.last_flush_unix_ms.is_some(), "flushed");
+    assert_eq!(
+        stats.last_flush_unix_ms,
+        stats.indexes.iter().filter_map(|index| index.last_flush_unix_ms).min(),
+        "oldest flush"
+    );
+}
+
+#[test]
+fn memmap_lookup_files_can_be_spread_over_directories() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let dirs = vec![tmp_path.path().join("disk0"), tmp_path.path().join("disk1")];
+    for dir in &dirs {
+        std::fs::create_dir(dir).unwrap();
+    }
+    let layout = hloo::lookup::StripedLayout::new(dirs.clone());
+    let sig = hloo::util::sign_type::<i64>(32, 5, 1, 32);
+    let data = generate_data(10);
+    let mut lookup = MemMapLookup::<i64>::create(Permutations::get_all_variants(), sig, &layout).unwrap();
+    lookup.insert(&data).unwrap();
+    drop(lookup);
+
+    let files = |dir: &std::path::Path| std::fs::read_dir(dir).unwrap().count();
+    assert!(files(&dirs[0]) > 0 && files(&dirs[1]) > 0, "files should be spread");
+    let lookup = MemMapLookup::<i64>::load(Permutations::get_all_variants(), sig, &layout).unwrap();
+    assert_eq!(
+        lookup.search_simple(&data[3].0, 0).len(),
+        1,
+        "items should be found after load"
+    );
+    lookup.destroy().unwrap();
+    assert_eq!(files(&dirs[0]) + files(&dirs[1]), 0, "files should be removed");
+}
+
+#[test]
+fn memmap_lookup_ingests_streams_in_batches() {
+    let tmp_path = tempfile::tempdir().unwrap();
+    let mut lookup = LookupUtil::create_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    let data = generate_data(25);
+    let mut reports = Vec::new();
+    let progress = lookup
+        .ingest(data.iter().copied(), 10, 2, |progress| reports.push(*progress))
+        .unwrap();
+    let expected = |items, batches, flushes| hloo::lookup::IngestProgress {
+        items,
+        batches,
+        flushes,
+    };
+    assert_eq!(progress, expected(25, 3, 2));
+    assert_eq!(
+        reports,
+        [
+            expected(10, 1, 0),
+            expected(20, 2, 0),
+            expected(20, 2, 1),
+            expected(25, 3, 1),
+            expected(25, 3, 2)
+        ],
+        "progress is reported after every batch and flush"
+    );
+    drop(lookup);
+
+    let lookup = LookupUtil::load_memmap_lookup::<i64>(tmp_path.path()).unwrap();
+    assert_eq!(lookup.indexes()[0].iter().count(), 25, "all items are persisted");
+}
+
+#[test]
+fn dyn_width_lookups_dispatch_by_key_size() {
+    use hloo::lookup::lookup_impl::{DynWidthError, DynWidthMemLookup, DynWidthMemMapLookup};
+
+    assert!(matches!(
+        DynWidthMemLookup::<i64>::new(12),
+        Err(DynWidthError::UnsupportedKeySize(12))
+    ));
+    for key_size in [8, 16, 24, 32] {
+        let mut lookup = DynWidthMemLookup::<i64>::new(key_size).unwrap();
+        assert_eq!(lookup.key_size(), key_size);
+        let keys: Vec<Vec<u8>> = (0..10u8).map(|i| vec![i.wrapping_mul(37); key_size]).collect();
+        let items: Vec<_> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.as_slice(), i as i64))
+            .collect();
+        lookup.insert(&items).unwrap();
+        let result = lookup.search(&keys[3], 0).unwrap();
+        assert_eq!(
+            result.flat_iter().map(|item| *item.data()).collect::<HashSet<_>>(),
+            HashSet::from([3])
+        );
+        lookup.remove(&[&keys[3]]).unwrap();
+        assert!(
+            lookup.search(&keys[3], 0).unwrap().flat_iter().next().is_none(),
+            "removed key is not found"
+        );
+        assert!(matches!(
+            lookup.search(&[0; 40], 0),
+            Err(DynWidthError::KeySizeMismatch { expected, actual: 40 }) if expected == key_size
+        ));
+    }
+
+    let tmp_path = tempfile::tempdir().unwrap();
+    let mut lookup = DynWidthMemMapLookup::<i64>::create(16, tmp_path.path()).unwrap();
+    lookup.insert(&[(&[7; 16], 1)]).unwrap();
+    lookup.persist().unwrap();
+    drop(lookup);
+    let lookup = DynWidthMemMapLookup::<i64>::load(16, tmp_path.path()).unwrap();
+    let result = lookup.search(&[7; 16], 0).unwrap();
+    assert_eq!(
+        result.flat_iter().map(|item| *item.data()).collect::<HashSet<_>>(),
+        HashSet::from([1])
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn mem_lookup_can_be_serialized() {
+    let mut lookup = LookupUtil::create_mem_lookup::<i64>();
+    let data = generate_data(100);
+    lookup.insert(&data).unwrap();
+
+    let json = serde_json::to_value(&lookup).unwrap();
+    let restored: MemLookup<i64> = serde_json::from_value(json.clone()).unwrap();
+    for (index, restored) in lookup.indexes().iter().zip(restored.indexes()) {
+        assert_eq!(restored.data().to_vec(), index.data().to_vec(), "index data");
+    }
+    let target = data[7].0.random_within_distance(2);
+    assert_eq!(restored.search_simple(&target, 2), lookup.search_simple(&target, 2));
+
+    let mut other_params = json;
+    other_params["params"][1] = 4.into();
+    assert!(serde_json::from_value::<MemLookup<i64>>(other_params).is_err());
+}
+
+proptest! {
+    #[test]
+    fn lookup_finds_keys_within_distance((key, target) in Bits::pair_within_distance(4)) {
+        let mut lookup = LookupUtil::create_mem_lookup::<i64>();
+        lookup.insert(&[(key, 1)]).unwrap();
+        let result = lookup.search_simple(&target, 4);
+        prop_assert_eq!(result.len(), 1);
+        prop_assert!(result.iter().all(|item| item.distance() == key.xor_dist(&target)));
+    }
+}
+
+#[test]
+fn legacy_lookup_files_are_migrated() {
+    use hloo::lookup::lookup_impl::lookup64::{self, MemMapLookup};
+
+    let tmp_path = tempfile::tempdir().unwrap();
+    let dir = tmp_path.path();
+    let data: Vec<_> = (0..10)
+        .map(|i| (lookup64::Bits::from(i * 0x0101_0101_0101u64), i as i64))
+        .collect();
+    let mut lookup = MemMapLookup::<i64>::create(dir).unwrap();
+    lookup.insert(&data).unwrap();
+    lookup.persist().unwrap();
+    drop(lookup);
+
+    // files of older versions carry the parameters in their signatures, but a hash of the `TypeId` of values
+    let sig = hloo::util::sign_type::<i64>(64, 4, 1, 64);
+    let legacy = (sig & !(0xF << 60) & !0xFFFF) | 0xB << 60 | 0x1234;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let name = name.replace(&format!("{sig:016x}"), &format!("{legacy:016x}"));
+        let mut contents = std::fs::read(&path).unwrap();
+        contents[..8].copy_from_slice(&legacy.to_ne_bytes());
+        std::fs::write(dir.join(name), contents).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+    assert!(MemMapLookup::<i64>::load(dir).is_err(), "legacy files are not loaded");
+
+    assert_eq!(MemMapLookup::<i64>::migrate_legacy_signatures(dir).unwrap(), 4);
+    assert_eq!(
+        MemMapLookup::<i64>::migrate_legacy_signatures(dir).unwrap(),
+        0,
+        "nothing is left to migrate"
+    );
+    let lookup = MemMapLookup::<i64>::load(dir).unwrap();
+    assert_eq!(
+        lookup.search_simple(&data[3].0, 0).len(),
+        1,
+        "items should be found after migration"
+    );
+}